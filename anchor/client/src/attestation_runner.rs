@@ -0,0 +1,1019 @@
+//! Runs the attester duties of our validators from the attestation data to the submitted
+//! attestation.
+//!
+//! The operators of a committee first agree on a vote for a slot through a QBFT instance,
+//! started with the attestation data requested from our beacon node. The instance is shared by
+//! the attester duties of the validators of the committee at the slot, and we only agree to a
+//! vote of the epoch of the slot that the first of them can attest to without being slashed. The
+//! attestation data of each validator is the vote at the committee index of its duty. Each
+//! operator then signs the attestation data of its validators with its key shares, broadcasting
+//! its partial signatures in a single message, and each attestation is submitted once a threshold
+//! of the partial signatures reconstructs the signature of its validator.
+//!
+//! From Electra on, the attestation data of every committee has the index 0, and each
+//! attestation is submitted as the attestation of a single attester with its committee index.
+
+use crate::beacon_node::{
+    Attestation, AttestationData, BeaconNodes, ChainSpec, Checkpoint, SingleAttestation,
+};
+use crate::duties_service::{AttesterDuty, ScheduledDuties};
+use crate::partial_signer::PartialSigner;
+use crate::pre_consensus::OutboundPartialSignatures;
+use crate::signature_rounds::{RoundKey, SignatureRounds};
+use crate::signing::SigningContext;
+use blst::min_pk::SecretKey;
+use parking_lot::Mutex;
+use qbft::{
+    validate_data, Completed, Config, DataValidator, InMessage, InstanceHeight, LeaderFunction,
+    OutMessage, Qbft, Round,
+};
+use signature_collector::ValidatorCommittee;
+use slot_clock::{Slot, SlotClock};
+use ssv_types::{
+    CommitteeId, PartialSignatureKind, PartialSignatureMessage, PartialSignatureMessages, Role,
+};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// The QBFT messages of an instance not started yet kept for each signer, enough for a few
+/// rounds.
+pub const MAX_EARLY_CONSENSUS_MESSAGES_PER_SIGNER: usize = 32;
+
+/// The QBFT messages of the instances not started yet kept for all signers.
+pub const MAX_EARLY_CONSENSUS_MESSAGES: usize = 16 * 1024;
+
+/// An attester duty of one of our validators.
+#[derive(Clone)]
+pub struct AttestationDuty {
+    pub duty: AttesterDuty,
+    /// Our share of the validator key.
+    pub share: SecretKey,
+    pub committee: Arc<ValidatorCommittee>,
+}
+
+/// What the operators of a committee agree on for the attestations of its validators at a slot:
+/// the attestation data without its slot and committee index.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BeaconVote {
+    pub beacon_block_root: Vec<u8>,
+    pub source: Checkpoint,
+    pub target: Checkpoint,
+}
+
+impl BeaconVote {
+    pub fn from_data(data: AttestationData) -> Self {
+        Self {
+            beacon_block_root: data.beacon_block_root,
+            source: data.source,
+            target: data.target,
+        }
+    }
+
+    /// The attestation data of a validator attesting with the vote in the committee at `index`
+    /// of the slot.
+    pub fn attestation_data(&self, slot: u64, index: u64) -> AttestationData {
+        AttestationData {
+            slot,
+            index,
+            beacon_block_root: self.beacon_block_root.clone(),
+            source: self.source.clone(),
+            target: self.target.clone(),
+        }
+    }
+}
+
+/// A QBFT message of ours, to broadcast to the operators of the committee.
+#[derive(Clone, Debug)]
+pub struct OutboundConsensusMessage {
+    pub committee_id: CommitteeId,
+    pub slot: u64,
+    pub message: OutMessage<BeaconVote>,
+}
+
+/// Identifies the QBFT instance of a committee at a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct InstanceKey {
+    committee_id: CommitteeId,
+    slot: u64,
+}
+
+/// The attestation data of a duty and our partial signature of it, or why it was not signed.
+type SignedAttestation = Result<(AttestationData, PartialSignatureMessage), String>;
+
+/// A duty waiting for the vote of its committee to be decided.
+struct Attester {
+    duty: AttestationDuty,
+    signed: oneshot::Sender<SignedAttestation>,
+}
+
+/// The QBFT instance of a committee at a slot.
+struct Instance {
+    /// The inputs of the instance, once it started and until it decides.
+    messages: Option<mpsc::UnboundedSender<InMessage<BeaconVote>>>,
+    /// The duties attesting with the vote, until it is decided.
+    attesters: Option<Vec<Attester>>,
+}
+
+#[derive(Default)]
+struct State {
+    instances: HashMap<InstanceKey, Instance>,
+    /// The QBFT messages of the instances not started yet.
+    early: HashMap<InstanceKey, Vec<InMessage<BeaconVote>>>,
+}
+
+impl State {
+    /// Keeps a QBFT message of an instance not started yet, unless it is already kept or its
+    /// signer or all signers have too many of them.
+    fn add_early(&mut self, key: InstanceKey, message: InMessage<BeaconVote>) {
+        let early = self.early.get(&key).map(Vec::as_slice).unwrap_or_default();
+        if early.contains(&message) {
+            return;
+        }
+        let signer = signer(&message);
+        let from_signer = early
+            .iter()
+            .filter(|early| self::signer(early) == signer)
+            .count();
+        let total: usize = self.early.values().map(Vec::len).sum();
+        if from_signer >= MAX_EARLY_CONSENSUS_MESSAGES_PER_SIGNER
+            || total >= MAX_EARLY_CONSENSUS_MESSAGES
+        {
+            debug!(
+                signer = *signer,
+                slot = key.slot,
+                "Too many early consensus messages, dropping"
+            );
+            return;
+        }
+        self.early.entry(key).or_default().push(message);
+    }
+}
+
+/// Leads the rounds of a QBFT instance with the operators of the committee in turn.
+#[derive(Clone, Debug)]
+struct CommitteeLeader {
+    operators: Vec<qbft::OperatorId>,
+}
+
+impl LeaderFunction for CommitteeLeader {
+    fn leader_function(
+        &self,
+        operator_id: &qbft::OperatorId,
+        round: Round,
+        instance_height: InstanceHeight,
+        _committee_size: usize,
+    ) -> bool {
+        (*round + *instance_height)
+            .checked_rem(self.operators.len())
+            .and_then(|index| self.operators.get(index))
+            == Some(operator_id)
+    }
+}
+
+/// Accepts the votes of the epoch of a duty that its validator can attest to without being
+/// slashed. The validators of the other duties sharing the instance are only checked when
+/// signing.
+struct BeaconVoteValidator {
+    slot: u64,
+    /// The index of the attestation data of the duty.
+    index: u64,
+    slots_per_epoch: u64,
+    validator_public_key: Vec<u8>,
+    signer: Arc<PartialSigner>,
+    context: SigningContext,
+}
+
+impl DataValidator<BeaconVote> for BeaconVoteValidator {
+    fn validate(&self, vote: &BeaconVote) -> bool {
+        let epoch = self.slot / self.slots_per_epoch;
+        if vote.target.epoch != epoch || vote.source.epoch > vote.target.epoch {
+            debug!(
+                slot = self.slot,
+                source_epoch = vote.source.epoch,
+                target_epoch = vote.target.epoch,
+                "Vote of another epoch"
+            );
+            return false;
+        }
+        let data = vote.attestation_data(self.slot, self.index);
+        let result = self
+            .context
+            .attestation_root(&data)
+            .and_then(|signing_root| {
+                self.signer.check_attestation(
+                    &self.validator_public_key,
+                    data.source.epoch,
+                    data.target.epoch,
+                    &signing_root,
+                )
+            });
+        if let Err(error) = result {
+            warn!(error, slot = self.slot, "Refusing vote");
+            return false;
+        }
+        true
+    }
+}
+
+pub struct AttestationRunner<T> {
+    signer: Arc<PartialSigner>,
+    slot_clock: T,
+    spec: ChainSpec,
+    consensus_outbound: mpsc::Sender<OutboundConsensusMessage>,
+    partial_signatures_outbound: mpsc::Sender<OutboundPartialSignatures>,
+    /// The duties of our validators, the messages of instances not started yet being only kept
+    /// for their committees.
+    duties: Arc<dyn ScheduledDuties>,
+    state: Mutex<State>,
+    rounds: SignatureRounds,
+}
+
+impl<T: SlotClock> AttestationRunner<T> {
+    pub fn new(
+        signer: Arc<PartialSigner>,
+        slot_clock: T,
+        spec: ChainSpec,
+        consensus_outbound: mpsc::Sender<OutboundConsensusMessage>,
+        partial_signatures_outbound: mpsc::Sender<OutboundPartialSignatures>,
+        duties: Arc<dyn ScheduledDuties>,
+    ) -> Self {
+        Self {
+            signer,
+            slot_clock,
+            spec,
+            consensus_outbound,
+            partial_signatures_outbound,
+            rounds: SignatureRounds::new(duties.clone()),
+            duties,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Decides on the vote of the slot with the other operators of the committee, signs the
+    /// attestation data of the duty and submits the reconstructed attestation.
+    ///
+    /// The first duty of a committee at a slot runs its QBFT instance, and the duties of the
+    /// other validators of the committee join it until the vote is decided.
+    pub async fn attest(
+        &self,
+        beacon_nodes: &BeaconNodes,
+        duty: &AttestationDuty,
+        context: &SigningContext,
+    ) -> Result<(), String> {
+        let AttesterDuty {
+            validator_index,
+            committee_index,
+            slot,
+            ..
+        } = duty.duty;
+        let aggregation_bits = aggregation_bits(&duty.duty)?;
+        let key = InstanceKey {
+            committee_id: duty.committee.committee_id(),
+            slot,
+        };
+        let (signed, on_signed) = oneshot::channel();
+        let attester = Attester {
+            duty: duty.clone(),
+            signed,
+        };
+        let leads = {
+            let mut state = self.state.lock();
+            // The instances of the previous slot may still be running.
+            let oldest = slot.saturating_sub(1);
+            state.instances.retain(|key, _| key.slot >= oldest);
+            state.early.retain(|key, _| key.slot >= oldest);
+            match state.instances.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    let attesters = entry.get_mut().attesters.as_mut().ok_or_else(|| {
+                        format!(
+                            "The vote of slot {slot} was decided before the duty of validator \
+                             {validator_index}"
+                        )
+                    })?;
+                    attesters.push(attester);
+                    false
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Instance {
+                        messages: None,
+                        attesters: Some(vec![attester]),
+                    });
+                    true
+                }
+            }
+        };
+        if leads {
+            let vote = self.decide(beacon_nodes, key, duty, context).await;
+            self.sign(key, vote, context);
+        }
+
+        let (data, message) = on_signed
+            .await
+            .map_err(|_| format!("The consensus instance of slot {slot} was dropped"))??;
+        let round = RoundKey {
+            validator_index,
+            kind: PartialSignatureKind::PostConsensus,
+            slot,
+            signing_root: message.signing_root,
+        };
+        let signature = self
+            .rounds
+            .collect(round, &duty.committee, &message, self.time_left(slot))
+            .await
+            .ok_or_else(|| {
+                format!(
+                    "Timed out collecting the attestation of validator {validator_index} at slot \
+                     {slot}"
+                )
+            })?;
+
+        let signature = signature.to_bytes().to_vec();
+        if self.spec.is_electra(slot) {
+            let attestation = SingleAttestation {
+                committee_index,
+                attester_index: validator_index,
+                data,
+                signature,
+            };
+            beacon_nodes
+                .submit_single_attestations(std::slice::from_ref(&attestation))
+                .await?;
+        } else {
+            let attestation = Attestation {
+                aggregation_bits,
+                data,
+                signature,
+            };
+            beacon_nodes
+                .submit_attestations(std::slice::from_ref(&attestation))
+                .await?;
+        }
+        info!(validator_index, slot, "Submitted attestation");
+        Ok(())
+    }
+
+    /// Runs the QBFT instance of a committee at a slot, proposing the vote of the attestation
+    /// data of `duty` when we lead a round.
+    async fn decide(
+        &self,
+        beacon_nodes: &BeaconNodes,
+        key: InstanceKey,
+        duty: &AttestationDuty,
+        context: &SigningContext,
+    ) -> Result<BeaconVote, String> {
+        let AttesterDuty {
+            committee_index,
+            slot,
+            ..
+        } = duty.duty;
+        let data = beacon_nodes.attestation_data(slot, committee_index).await?;
+        let validator = BeaconVoteValidator {
+            slot,
+            index: self.spec.attestation_data_index(slot, committee_index),
+            slots_per_epoch: self.spec.slots_per_epoch,
+            validator_public_key: duty.committee.validator_public_key.to_bytes().to_vec(),
+            signer: self.signer.clone(),
+            context: context.clone(),
+        };
+        let start_data = validate_data(BeaconVote::from_data(data), &validator)
+            .map_err(|_| format!("Refusing to propose the vote of slot {slot}"))?;
+
+        let operators: Vec<qbft::OperatorId> = duty
+            .committee
+            .share_public_keys
+            .keys()
+            .map(|operator_id| qbft::OperatorId::from(**operator_id as usize))
+            .collect();
+        let defaults = Config::default();
+        let config = Config {
+            operator_id: qbft::OperatorId::from(*self.signer.operator_id() as usize),
+            instance_height: InstanceHeight::from(slot as usize),
+            round: Round::default(),
+            pr: defaults.pr,
+            committee_size: operators.len(),
+            committee_members: operators.iter().copied().collect(),
+            quorum_size: duty.committee.threshold(),
+            round_time: defaults.round_time,
+            max_rounds: defaults.max_rounds,
+            leader_fn: CommitteeLeader { operators },
+        };
+        let (sender, mut receiver, instance) = Qbft::new(config, start_data, validator);
+        {
+            let mut state = self.state.lock();
+            for message in state.early.remove(&key).unwrap_or_default() {
+                let _ = sender.send(message);
+            }
+            if let Some(instance) = state.instances.get_mut(&key) {
+                instance.messages = Some(sender);
+            }
+        }
+        tokio::spawn(instance.start_instance());
+
+        loop {
+            match receiver.recv().await {
+                Some(OutMessage::Completed(Completed::Success(vote))) => return Ok(vote),
+                Some(OutMessage::Completed(Completed::TimedOut)) => {
+                    return Err(format!("Consensus on the vote of slot {slot} timed out"))
+                }
+                Some(message) => {
+                    let outbound = OutboundConsensusMessage {
+                        committee_id: key.committee_id,
+                        slot,
+                        message,
+                    };
+                    if let Err(error) = self.consensus_outbound.try_send(outbound) {
+                        warn!(%error, slot, "Unable to broadcast our consensus message");
+                    }
+                }
+                None => {
+                    return Err(format!(
+                        "The consensus instance of slot {slot} stopped without a decision"
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Signs the attestation data of the duties of an instance with its decided vote, handing
+    /// each duty its data and our partial signature, and broadcasts our partial signatures in a
+    /// single message.
+    fn sign(&self, key: InstanceKey, vote: Result<BeaconVote, String>, context: &SigningContext) {
+        let attesters = self
+            .state
+            .lock()
+            .instances
+            .get_mut(&key)
+            .and_then(|instance| {
+                instance.messages = None;
+                instance.attesters.take()
+            })
+            .unwrap_or_default();
+        let mut messages = vec![];
+        for Attester { duty, signed } in attesters {
+            let result = vote
+                .clone()
+                .and_then(|vote| self.sign_attestation(&duty, &vote, context));
+            if let Ok((_, message)) = &result {
+                messages.push(message.clone());
+            }
+            let _ = signed.send(result);
+        }
+        if messages.is_empty() {
+            return;
+        }
+
+        let outbound = OutboundPartialSignatures {
+            role: Role::Committee,
+            duty_executor_id: key.committee_id.0.to_vec(),
+            committee_id: key.committee_id,
+            messages: PartialSignatureMessages {
+                kind: PartialSignatureKind::PostConsensus as u64,
+                slot: key.slot,
+                messages,
+            },
+        };
+        if let Err(error) = self.partial_signatures_outbound.try_send(outbound) {
+            warn!(%error, slot = key.slot, "Unable to broadcast our partial signatures");
+        }
+    }
+
+    /// Signs the attestation data of a duty with our share, unless it could get the validator
+    /// slashed.
+    fn sign_attestation(
+        &self,
+        duty: &AttestationDuty,
+        vote: &BeaconVote,
+        context: &SigningContext,
+    ) -> SignedAttestation {
+        let AttesterDuty {
+            slot,
+            committee_index,
+            ..
+        } = duty.duty;
+        let data = vote.attestation_data(
+            slot,
+            self.spec.attestation_data_index(slot, committee_index),
+        );
+        let signing_root = context.attestation_root(&data)?;
+        let message = self.signer.sign_attestation(
+            &duty.share,
+            &duty.committee.validator_public_key.to_bytes(),
+            duty.duty.validator_index,
+            data.source.epoch,
+            data.target.epoch,
+            signing_root,
+        )?;
+        Ok((data, message))
+    }
+
+    /// Adds a QBFT message of another operator of a committee to its instance of the slot.
+    pub fn on_consensus_message(
+        &self,
+        committee_id: &CommitteeId,
+        slot: u64,
+        message: InMessage<BeaconVote>,
+    ) {
+        if self.is_stale(slot) {
+            debug!(slot, "Ignoring stale consensus message");
+            return;
+        }
+        let key = InstanceKey {
+            committee_id: *committee_id,
+            slot,
+        };
+        let mut state = self.state.lock();
+        match state.instances.get(&key) {
+            Some(Instance {
+                messages: Some(instance),
+                ..
+            }) => {
+                let _ = instance.send(message);
+            }
+            Some(Instance {
+                attesters: None, ..
+            }) => debug!(slot, "Ignoring consensus message of a decided instance"),
+            _ if !self.duties.has_committee_duty(committee_id, slot) => {
+                debug!(
+                    slot,
+                    "Ignoring consensus message of a committee without duty"
+                )
+            }
+            _ => state.add_early(key, message),
+        }
+    }
+
+    /// Adds the partial signatures of attestations broadcast by another operator.
+    pub fn on_partial_signatures(&self, messages: &PartialSignatureMessages) {
+        if messages.kind() != Ok(PartialSignatureKind::PostConsensus) {
+            debug!(
+                kind = messages.kind,
+                "Not a post-consensus partial signature"
+            );
+            return;
+        }
+        if self.is_stale(messages.slot) {
+            debug!(slot = messages.slot, "Ignoring stale partial signatures");
+            return;
+        }
+        self.rounds
+            .add(PartialSignatureKind::PostConsensus, messages);
+    }
+
+    fn is_stale(&self, slot: u64) -> bool {
+        self.slot_clock
+            .now()
+            .is_some_and(|now| slot + 1 < now.as_u64())
+    }
+
+    /// The time left to reconstruct the attestation of `slot`, which is only worth submitting
+    /// until the end of its slot.
+    fn time_left(&self, slot: u64) -> Duration {
+        self.slot_clock
+            .start_of(Slot::new(slot + 1))
+            .zip(self.slot_clock.now_duration())
+            .map(|(end, now)| end.saturating_sub(now))
+            .unwrap_or_default()
+    }
+}
+
+/// The operator who sent a QBFT message.
+fn signer(message: &InMessage<BeaconVote>) -> qbft::OperatorId {
+    match message {
+        InMessage::Propose(signer, _)
+        | InMessage::Prepare(signer, _)
+        | InMessage::Commit(signer, _)
+        | InMessage::RoundChange(signer, _, _) => *signer,
+    }
+}
+
+/// The aggregation bits of the attestation of one attester: the SSZ bit list of its committee,
+/// with only its bit and the length delimiter set.
+fn aggregation_bits(duty: &AttesterDuty) -> Result<Vec<u8>, String> {
+    let length = duty.committee_length as usize;
+    let index = duty.validator_committee_index as usize;
+    if index >= length {
+        return Err(format!(
+            "Invalid index {index} in a committee of {length} attesters"
+        ));
+    }
+    let mut bits = vec![0; length / 8 + 1];
+    bits[index / 8] |= 1 << (index % 8);
+    bits[length / 8] |= 1 << (length % 8);
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon_node::mock::MockBeaconNode;
+    use crate::beacon_node::DEFAULT_REQUEST_TIMEOUT;
+    use crate::test_utils::{committee, context, AllDuties, Committee, SPEC};
+    use futures::future::join_all;
+    use qbft::ConsensusData;
+    use serde_json::json;
+    use signature_collector::ETH_DST;
+    use slashing_protection::SlashingDatabase;
+    use slot_clock::ManualSlotClock;
+    use ssv_types::OperatorId;
+
+    const SLOT: u64 = 100;
+    const COMMITTEE_INDEX: u64 = 3;
+    const VALIDATOR_INDEX: u64 = 7;
+
+    fn attestation_data() -> AttestationData {
+        AttestationData {
+            slot: SLOT,
+            index: COMMITTEE_INDEX,
+            beacon_block_root: vec![0xbb; 32],
+            source: Checkpoint {
+                epoch: 2,
+                root: vec![0x22; 32],
+            },
+            target: Checkpoint {
+                epoch: 3,
+                root: vec![0x33; 32],
+            },
+        }
+    }
+
+    /// The duty of the validator of `committee` at index `validator_index`, the attester at
+    /// `validator_committee_index` in a committee of 10.
+    fn duty(
+        share: &SecretKey,
+        committee: &Committee,
+        validator_index: u64,
+        validator_committee_index: u64,
+    ) -> AttestationDuty {
+        AttestationDuty {
+            duty: AttesterDuty {
+                pubkey: committee.committee.validator_public_key.to_bytes().to_vec(),
+                validator_index,
+                committee_index: COMMITTEE_INDEX,
+                committee_length: 10,
+                committees_at_slot: 4,
+                validator_committee_index,
+                slot: SLOT,
+            },
+            share: share.clone(),
+            committee: committee.committee.clone(),
+        }
+    }
+
+    struct Operator {
+        runner: AttestationRunner<ManualSlotClock>,
+        signer: Arc<PartialSigner>,
+        consensus: mpsc::Receiver<OutboundConsensusMessage>,
+        partial_signatures: mpsc::Receiver<OutboundPartialSignatures>,
+    }
+
+    fn operator(operator_id: OperatorId, spec: ChainSpec) -> Operator {
+        let slot_clock = ManualSlotClock::new(
+            Slot::new(0),
+            Duration::from_secs(0),
+            Duration::from_secs(SPEC.seconds_per_slot),
+        );
+        slot_clock.set_current_time(Duration::from_secs(SLOT * SPEC.seconds_per_slot));
+        let signer = Arc::new(PartialSigner::new(
+            operator_id,
+            Arc::new(SlashingDatabase::open_in_memory().unwrap()),
+        ));
+        let (consensus_outbound, consensus) = mpsc::channel(1024);
+        let (partial_signatures_outbound, partial_signatures) = mpsc::channel(1024);
+        Operator {
+            runner: AttestationRunner::new(
+                signer.clone(),
+                slot_clock,
+                spec,
+                consensus_outbound,
+                partial_signatures_outbound,
+                Arc::new(AllDuties),
+            ),
+            signer,
+            consensus,
+            partial_signatures,
+        }
+    }
+
+    async fn beacon_node() -> (Arc<MockBeaconNode>, BeaconNodes) {
+        let node = MockBeaconNode::new();
+        node.set_response(
+            "/eth/v1/validator/attestation_data",
+            json!({ "data": attestation_data() }),
+        );
+        let url = node.spawn().await;
+        let beacon_nodes =
+            BeaconNodes::new(vec![url], &[], false, DEFAULT_REQUEST_TIMEOUT).unwrap();
+        (node, beacon_nodes)
+    }
+
+    /// Runs the duties of two validators of the same committee with every operator of the
+    /// committee, checking that each operator broadcast its partial signatures of both
+    /// attestations at once. Returns the validators and the beacon node the attestations were
+    /// submitted to.
+    async fn attest_with_every_operator(spec: ChainSpec) -> ([Committee; 2], Arc<MockBeaconNode>) {
+        let validators = [committee(1), committee(2)];
+        let context = context();
+        let (node, beacon_nodes) = beacon_node().await;
+
+        // Every operator receives the broadcasts of the others.
+        let operator_ids: Vec<OperatorId> =
+            validators[0].shares.iter().map(|(id, _)| *id).collect();
+        let mut runners = vec![];
+        let mut broadcasts = vec![];
+        for operator_id in &operator_ids {
+            let operator = operator(*operator_id, spec);
+            runners.push(Arc::new(operator.runner));
+            broadcasts.push((
+                *operator_id,
+                operator.consensus,
+                operator.partial_signatures,
+            ));
+        }
+        let bundles = Arc::new(Mutex::new(vec![]));
+        for (operator_id, mut consensus, mut partial_signatures) in broadcasts {
+            let (consensus_runners, partial_signature_runners) = (runners.clone(), runners.clone());
+            let operator_ids = operator_ids.clone();
+            tokio::spawn(async move {
+                while let Some(outbound) = consensus.recv().await {
+                    let from = qbft::OperatorId::from(*operator_id as usize);
+                    let message = match outbound.message {
+                        OutMessage::Propose(data) => InMessage::Propose(from, data),
+                        OutMessage::Prepare(data) => InMessage::Prepare(from, data),
+                        OutMessage::Commit(data) => InMessage::Commit(from, data),
+                        OutMessage::RoundChange(round, data) => {
+                            InMessage::RoundChange(from, round, data)
+                        }
+                        OutMessage::Completed(_) => continue,
+                    };
+                    for (runner, to) in consensus_runners.iter().zip(&operator_ids) {
+                        if *to != operator_id {
+                            runner.on_consensus_message(
+                                &outbound.committee_id,
+                                outbound.slot,
+                                message.clone(),
+                            );
+                        }
+                    }
+                }
+            });
+            let bundles = bundles.clone();
+            tokio::spawn(async move {
+                while let Some(outbound) = partial_signatures.recv().await {
+                    for runner in &partial_signature_runners {
+                        runner.on_partial_signatures(&outbound.messages);
+                    }
+                    bundles.lock().push(outbound);
+                }
+            });
+        }
+
+        let attestations = join_all(runners.iter().enumerate().flat_map(|(operator, runner)| {
+            let (beacon_nodes, context) = (&beacon_nodes, &context);
+            [(&validators[0], VALIDATOR_INDEX, 9), (&validators[1], 8, 4)].map(
+                |(validator, validator_index, validator_committee_index)| {
+                    let duty = duty(
+                        &validator.shares[operator].1,
+                        validator,
+                        validator_index,
+                        validator_committee_index,
+                    );
+                    async move { runner.attest(beacon_nodes, &duty, context).await }
+                },
+            )
+        }))
+        .await;
+
+        for attestation in attestations {
+            attestation.unwrap();
+        }
+
+        // Each operator broadcast its partial signatures of both attestations at once.
+        while bundles.lock().len() < operator_ids.len() {
+            tokio::task::yield_now().await;
+        }
+        let mut signers = vec![];
+        for bundle in bundles.lock().iter() {
+            assert_eq!(bundle.role, Role::Committee);
+            assert_eq!(bundle.committee_id, validators[0].committee.committee_id());
+            let [first, second] = &bundle.messages.messages[..] else {
+                panic!("Not a bundle of both partial signatures");
+            };
+            assert_eq!(first.signer, second.signer);
+            signers.push(first.signer);
+        }
+        signers.sort();
+        assert_eq!(signers, operator_ids);
+        (validators, node)
+    }
+
+    #[tokio::test]
+    async fn operators_decide_sign_and_submit_the_attestations() {
+        let (validators, node) = attest_with_every_operator(SPEC).await;
+        let data = attestation_data();
+        let signing_root = context().attestation_root(&data).unwrap();
+        let expected = |validator: &Committee, aggregation_bits| Attestation {
+            aggregation_bits,
+            data: data.clone(),
+            signature: validator
+                .secret_key
+                .sign(&signing_root, ETH_DST, &[])
+                .to_bytes()
+                .to_vec(),
+        };
+        // The 10th and the 5th bits of a committee of 10, and the length delimiter.
+        let expected = [
+            expected(&validators[0], vec![0b0000_0000, 0b0000_0110]),
+            expected(&validators[1], vec![0b0001_0000, 0b0000_0100]),
+        ];
+        let posted = node.posted("/eth/v1/beacon/pool/attestations");
+        assert_eq!(posted.len(), 8);
+        for expected in &expected {
+            let count = posted
+                .iter()
+                .filter(|posted| **posted == json!([expected]))
+                .count();
+            assert_eq!(count, 4);
+        }
+        assert!(node.posted("/eth/v2/beacon/pool/attestations").is_empty());
+    }
+
+    #[tokio::test]
+    async fn attestations_of_single_attesters_are_submitted_from_electra() {
+        let spec = ChainSpec {
+            electra_fork_epoch: Some(SLOT / SPEC.slots_per_epoch),
+            ..SPEC
+        };
+        let (validators, node) = attest_with_every_operator(spec).await;
+        // The committee is only given next to the data.
+        let data = AttestationData {
+            index: 0,
+            ..attestation_data()
+        };
+        let signing_root = context().attestation_root(&data).unwrap();
+        let expected = |validator: &Committee, attester_index| SingleAttestation {
+            committee_index: COMMITTEE_INDEX,
+            attester_index,
+            data: data.clone(),
+            signature: validator
+                .secret_key
+                .sign(&signing_root, ETH_DST, &[])
+                .to_bytes()
+                .to_vec(),
+        };
+        let expected = [
+            expected(&validators[0], VALIDATOR_INDEX),
+            expected(&validators[1], 8),
+        ];
+        let posted = node.posted("/eth/v2/beacon/pool/attestations");
+        assert_eq!(posted.len(), 8);
+        for expected in &expected {
+            let count = posted
+                .iter()
+                .filter(|posted| **posted == json!([expected]))
+                .count();
+            assert_eq!(count, 4);
+        }
+        assert!(node.posted("/eth/v1/beacon/pool/attestations").is_empty());
+    }
+
+    #[tokio::test]
+    async fn votes_are_checked_before_agreeing_to_them() {
+        let committee = committee(1);
+        let context = context();
+        let operator = operator(committee.shares[0].0, SPEC);
+        let validator_public_key = committee.committee.validator_public_key.to_bytes().to_vec();
+        let validator = BeaconVoteValidator {
+            slot: SLOT,
+            index: COMMITTEE_INDEX,
+            slots_per_epoch: SPEC.slots_per_epoch,
+            validator_public_key: validator_public_key.clone(),
+            signer: operator.signer.clone(),
+            context: context.clone(),
+        };
+        let vote = BeaconVote::from_data(attestation_data());
+        assert!(validator.validate(&vote));
+        let mut other_epoch = vote.clone();
+        other_epoch.target.epoch += 1;
+        assert!(!validator.validate(&other_epoch));
+        let mut future_source = vote.clone();
+        future_source.source.epoch = future_source.target.epoch + 1;
+        assert!(!validator.validate(&future_source));
+
+        // Once the validator attested to another vote of the target epoch, voting again would
+        // get it slashed.
+        let mut signed = vote.attestation_data(SLOT, COMMITTEE_INDEX);
+        signed.beacon_block_root = vec![0xcc; 32];
+        operator
+            .signer
+            .sign_attestation(
+                &committee.shares[0].1,
+                &validator_public_key,
+                VALIDATOR_INDEX,
+                signed.source.epoch,
+                signed.target.epoch,
+                context.attestation_root(&signed).unwrap(),
+            )
+            .unwrap();
+        assert!(validator.validate(&BeaconVote::from_data(signed)));
+        assert!(!validator.validate(&vote));
+
+        // The duty fails without proposing the vote, broadcasting or submitting anything.
+        let (node, beacon_nodes) = beacon_node().await;
+        let Operator {
+            runner,
+            mut consensus,
+            mut partial_signatures,
+            ..
+        } = operator;
+        let duty = duty(&committee.shares[0].1, &committee, VALIDATOR_INDEX, 9);
+        assert!(runner.attest(&beacon_nodes, &duty, &context).await.is_err());
+        assert!(consensus.try_recv().is_err());
+        assert!(partial_signatures.try_recv().is_err());
+        assert!(node.posted("/eth/v1/beacon/pool/attestations").is_empty());
+    }
+
+    /// Only the validators of one committee have duties, at the slot.
+    struct CommitteeDuties(CommitteeId);
+
+    impl ScheduledDuties for CommitteeDuties {
+        fn has_duty(&self, _validator_index: u64, slot: u64) -> bool {
+            slot == SLOT
+        }
+
+        fn has_committee_duty(&self, committee_id: &CommitteeId, slot: u64) -> bool {
+            *committee_id == self.0 && slot == SLOT
+        }
+    }
+
+    #[test]
+    fn early_consensus_messages_are_bounded_to_our_duties() {
+        let committee_id = committee(1).committee.committee_id();
+        let Operator { mut runner, .. } = operator(OperatorId(3), SPEC);
+        runner.duties = Arc::new(CommitteeDuties(committee_id));
+        let prepare = |signer: usize, round: usize| {
+            InMessage::Prepare(
+                qbft::OperatorId::from(signer),
+                ConsensusData {
+                    round: Round::from(round),
+                    data: BeaconVote::from_data(attestation_data()),
+                },
+            )
+        };
+        let early_count = |runner: &AttestationRunner<ManualSlotClock>| -> usize {
+            runner.state.lock().early.values().map(Vec::len).sum()
+        };
+
+        // The messages of other committees or slots are dropped.
+        runner.on_consensus_message(&CommitteeId([9; 32]), SLOT, prepare(8, 1));
+        runner.on_consensus_message(&committee_id, SLOT + 1, prepare(8, 1));
+        assert_eq!(early_count(&runner), 0);
+
+        // A message is only kept once.
+        runner.on_consensus_message(&committee_id, SLOT, prepare(8, 1));
+        runner.on_consensus_message(&committee_id, SLOT, prepare(8, 1));
+        assert_eq!(early_count(&runner), 1);
+
+        // A signer can not crowd out the others.
+        for round in 2..=MAX_EARLY_CONSENSUS_MESSAGES_PER_SIGNER + 1 {
+            runner.on_consensus_message(&committee_id, SLOT, prepare(8, round));
+        }
+        assert_eq!(
+            early_count(&runner),
+            MAX_EARLY_CONSENSUS_MESSAGES_PER_SIGNER
+        );
+        runner.on_consensus_message(&committee_id, SLOT, prepare(21, 1));
+        assert_eq!(
+            early_count(&runner),
+            MAX_EARLY_CONSENSUS_MESSAGES_PER_SIGNER + 1
+        );
+    }
+
+    #[tokio::test]
+    async fn duties_joining_a_decided_instance_are_refused() {
+        let committee = committee(1);
+        let Operator { runner, .. } = operator(committee.shares[0].0, SPEC);
+        let key = InstanceKey {
+            committee_id: committee.committee.committee_id(),
+            slot: SLOT,
+        };
+        runner.state.lock().instances.insert(
+            key,
+            Instance {
+                messages: None,
+                attesters: None,
+            },
+        );
+
+        let (node, beacon_nodes) = beacon_node().await;
+        let duty = duty(&committee.shares[0].1, &committee, VALIDATOR_INDEX, 9);
+        assert!(runner
+            .attest(&beacon_nodes, &duty, &context())
+            .await
+            .is_err());
+        assert!(node.requests().is_empty());
+    }
+}
@@ -0,0 +1,730 @@
+//! A client of the beacon node HTTP APIs, falling back to the next beacon node in priority order
+//! when one fails or is not synced.
+
+#[cfg(test)]
+pub mod mock;
+
+use futures::future::join_all;
+use parking_lot::RwLock;
+use reqwest::{Certificate, Client, RequestBuilder, Url};
+use sensitive_url::SensitiveUrl;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// The time a beacon node has to answer a request.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(12);
+/// The interval at which the sync status of the beacon nodes is checked.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(12);
+
+/// The health of a beacon node, as of its last check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BeaconNodeHealth {
+    /// The node is synced to the head of the chain.
+    Synced,
+    /// The node has not been checked yet.
+    Unknown,
+    /// The node is syncing, or only optimistically synced as its execution node is not.
+    Unsynced,
+    /// The node failed to answer.
+    Offline,
+}
+
+/// The sync status returned by `/eth/v1/node/syncing`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncingData {
+    #[serde(with = "quoted_u64")]
+    pub head_slot: u64,
+    #[serde(with = "quoted_u64")]
+    pub sync_distance: u64,
+    pub is_syncing: bool,
+    #[serde(default)]
+    pub is_optimistic: bool,
+    #[serde(default)]
+    pub el_offline: bool,
+}
+
+impl SyncingData {
+    fn health(&self) -> BeaconNodeHealth {
+        if self.is_syncing || self.is_optimistic || self.el_offline {
+            BeaconNodeHealth::Unsynced
+        } else {
+            BeaconNodeHealth::Synced
+        }
+    }
+}
+
+/// The envelope of the beacon API responses.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenericResponse<T> {
+    pub data: T,
+}
+
+/// The genesis returned by `/eth/v1/beacon/genesis`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisData {
+    #[serde(with = "quoted_u64")]
+    pub genesis_time: u64,
+    #[serde(with = "hex_bytes")]
+    pub genesis_validators_root: Vec<u8>,
+}
+
+/// The fork of a state, returned by `/eth/v1/beacon/states/{state_id}/fork`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fork {
+    #[serde(with = "hex_bytes")]
+    pub previous_version: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub current_version: Vec<u8>,
+    /// The epoch of the fork, from which the current version applies.
+    #[serde(with = "quoted_u64")]
+    pub epoch: u64,
+}
+
+/// A block to propose, returned by `/eth/v3/validator/blocks/{slot}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProducedBlock {
+    /// The fork of the block.
+    pub version: String,
+    /// Whether the block only commits to the execution payload of a builder.
+    pub execution_payload_blinded: bool,
+    /// The block, or the blinded block, in the JSON encoding of its fork.
+    pub data: serde_json::Value,
+}
+
+/// A checkpoint of the chain, at the first slot of an epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(with = "quoted_u64")]
+    pub epoch: u64,
+    #[serde(with = "hex_bytes")]
+    pub root: Vec<u8>,
+}
+
+/// The data attested to by the attesters of a committee, from
+/// `/eth/v1/validator/attestation_data`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AttestationData {
+    #[serde(with = "quoted_u64")]
+    pub slot: u64,
+    /// The index of the committee.
+    #[serde(with = "quoted_u64")]
+    pub index: u64,
+    #[serde(with = "hex_bytes")]
+    pub beacon_block_root: Vec<u8>,
+    pub source: Checkpoint,
+    pub target: Checkpoint,
+}
+
+/// A signed attestation before Electra, submitted to `/eth/v1/beacon/pool/attestations`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// The bit list of the attesters of the committee whose signatures are aggregated.
+    #[serde(with = "hex_bytes")]
+    pub aggregation_bits: Vec<u8>,
+    pub data: AttestationData,
+    #[serde(with = "hex_bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// The signed attestation of a single attester from Electra on, submitted to
+/// `/eth/v2/beacon/pool/attestations`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SingleAttestation {
+    #[serde(with = "quoted_u64")]
+    pub committee_index: u64,
+    #[serde(with = "quoted_u64")]
+    pub attester_index: u64,
+    pub data: AttestationData,
+    #[serde(with = "hex_bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// The parameters of the chain the duties depend on, from `/eth/v1/config/spec`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainSpec {
+    pub seconds_per_slot: u64,
+    pub slots_per_epoch: u64,
+    pub epochs_per_sync_committee_period: u64,
+    /// The epoch of the Electra fork, unless it is not scheduled.
+    pub electra_fork_epoch: Option<u64>,
+}
+
+impl ChainSpec {
+    /// Reads the parameters from the configuration of a beacon node, whose values are strings.
+    pub fn from_config(config: &HashMap<String, serde_json::Value>) -> Result<Self, String> {
+        let parameter = |name: &str| -> Result<u64, String> {
+            config
+                .get(name)
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok())
+                .filter(|value| *value > 0)
+                .ok_or_else(|| format!("Missing or invalid spec parameter {name}"))
+        };
+        Ok(Self {
+            seconds_per_slot: parameter("SECONDS_PER_SLOT")?,
+            slots_per_epoch: parameter("SLOTS_PER_EPOCH")?,
+            epochs_per_sync_committee_period: parameter("EPOCHS_PER_SYNC_COMMITTEE_PERIOD")?,
+            // Forks not scheduled are at the far future epoch.
+            electra_fork_epoch: config
+                .get("ELECTRA_FORK_EPOCH")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok())
+                .filter(|epoch| *epoch != u64::MAX),
+        })
+    }
+
+    /// Whether the attestations of `slot` are made with the rules of Electra.
+    pub fn is_electra(&self, slot: u64) -> bool {
+        self.electra_fork_epoch
+            .is_some_and(|epoch| slot / self.slots_per_epoch >= epoch)
+    }
+
+    /// The index of the attestation data of the committee at `committee_index` of `slot`. From
+    /// Electra on, the index is always 0 and the committee is given next to the data.
+    pub fn attestation_data_index(&self, slot: u64, committee_index: u64) -> u64 {
+        if self.is_electra(slot) {
+            0
+        } else {
+            committee_index
+        }
+    }
+}
+
+/// The body of the beacon API error responses.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+struct CandidateNode {
+    url: SensitiveUrl,
+    health: RwLock<BeaconNodeHealth>,
+}
+
+/// The beacon nodes, in priority order.
+pub struct BeaconNodes {
+    http: Client,
+    candidates: Vec<CandidateNode>,
+    /// Whether unsynced beacon nodes may be used, once the synced ones failed.
+    allow_unsynced: bool,
+}
+
+impl BeaconNodes {
+    /// Creates a client of the beacon nodes, trusting the given PEM certificates in addition to
+    /// the OS trust store.
+    pub fn new(
+        urls: Vec<SensitiveUrl>,
+        tls_certs: &[PathBuf],
+        allow_unsynced: bool,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        if urls.is_empty() {
+            return Err("No beacon node configured".to_string());
+        }
+        let mut builder = Client::builder().timeout(timeout);
+        for path in tls_certs {
+            let pem =
+                fs::read(path).map_err(|e| format!("Unable to read certificate {path:?}: {e}"))?;
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Invalid certificate {path:?}: {e}"))?;
+            if certificates.is_empty() {
+                return Err(format!("No PEM certificate in {path:?}"));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        let http = builder
+            .build()
+            .map_err(|e| format!("Unable to create the HTTP client: {e}"))?;
+
+        Ok(Self {
+            http,
+            candidates: urls
+                .into_iter()
+                .map(|url| CandidateNode {
+                    url,
+                    health: RwLock::new(BeaconNodeHealth::Unknown),
+                })
+                .collect(),
+            allow_unsynced,
+        })
+    }
+
+    /// The beacon nodes with their health, in priority order.
+    pub fn health(&self) -> Vec<(SensitiveUrl, BeaconNodeHealth)> {
+        self.candidates
+            .iter()
+            .map(|candidate| (candidate.url.clone(), *candidate.health.read()))
+            .collect()
+    }
+
+    /// Checks the sync status of every beacon node, returning the number of synced nodes.
+    pub async fn check_health(&self) -> usize {
+        let checks = self.candidates.iter().map(|candidate| async move {
+            let health = match self.syncing(&candidate.url).await {
+                Ok(syncing) => syncing.health(),
+                Err(error) => {
+                    debug!(endpoint = %candidate.url, error, "Beacon node health check failed");
+                    BeaconNodeHealth::Offline
+                }
+            };
+            let previous = std::mem::replace(&mut *candidate.health.write(), health);
+            if previous != health {
+                info!(endpoint = %candidate.url, ?health, "Beacon node health changed");
+            }
+            health
+        });
+        join_all(checks)
+            .await
+            .into_iter()
+            .filter(|health| *health == BeaconNodeHealth::Synced)
+            .count()
+    }
+
+    /// Checks the health of the beacon nodes periodically.
+    pub async fn run_health_checks(&self) {
+        loop {
+            if self.check_health().await == 0 {
+                warn!("No synced beacon node available");
+            }
+            tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn syncing(&self, endpoint: &SensitiveUrl) -> Result<SyncingData, String> {
+        let request = self.http.get(url(endpoint, "eth/v1/node/syncing")?);
+        let response: GenericResponse<SyncingData> = self.send(endpoint, request).await?;
+        Ok(response.data)
+    }
+
+    pub async fn genesis(&self) -> Result<GenesisData, String> {
+        let response: GenericResponse<GenesisData> = self.get("eth/v1/beacon/genesis").await?;
+        Ok(response.data)
+    }
+
+    pub async fn fork(&self) -> Result<Fork, String> {
+        let response: GenericResponse<Fork> = self.get("eth/v1/beacon/states/head/fork").await?;
+        Ok(response.data)
+    }
+
+    /// Requests a block to propose at `slot`, revealing the RANDAO signature of the proposer.
+    pub async fn produce_block(
+        &self,
+        slot: u64,
+        randao_reveal: &[u8],
+    ) -> Result<ProducedBlock, String> {
+        self.get(&format!(
+            "eth/v3/validator/blocks/{slot}?randao_reveal=0x{}",
+            hex::encode(randao_reveal)
+        ))
+        .await
+    }
+
+    /// Requests the data for the attesters of the committee of `committee_index` at `slot` to
+    /// attest to.
+    pub async fn attestation_data(
+        &self,
+        slot: u64,
+        committee_index: u64,
+    ) -> Result<AttestationData, String> {
+        let response: GenericResponse<AttestationData> = self
+            .get(&format!(
+                "eth/v1/validator/attestation_data?slot={slot}&committee_index={committee_index}"
+            ))
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn submit_attestations(&self, attestations: &[Attestation]) -> Result<(), String> {
+        self.post_empty("eth/v1/beacon/pool/attestations", &attestations)
+            .await
+    }
+
+    /// Submits attestations of the Electra fork, which the beacon nodes of later forks may
+    /// refuse.
+    pub async fn submit_single_attestations(
+        &self,
+        attestations: &[SingleAttestation],
+    ) -> Result<(), String> {
+        self.first_success::<IgnoredAny>("eth/v2/beacon/pool/attestations", |url| {
+            self.http
+                .post(url)
+                .header("Eth-Consensus-Version", "electra")
+                .json(&attestations)
+        })
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn spec(&self) -> Result<ChainSpec, String> {
+        let response: GenericResponse<HashMap<String, serde_json::Value>> =
+            self.get("eth/v1/config/spec").await?;
+        ChainSpec::from_config(&response.data)
+    }
+
+    /// Requests `GET path` from the beacon nodes until one answers.
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.first_success(path, |url| self.http.get(url)).await
+    }
+
+    /// Requests `POST path` with a JSON body from the beacon nodes until one answers.
+    pub async fn post<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, String> {
+        self.first_success(path, |url| self.http.post(url).json(body))
+            .await
+    }
+
+    /// Requests `POST path` with a JSON body from the beacon nodes until one accepts it, ignoring
+    /// the body of the response.
+    pub async fn post_empty<B: Serialize>(&self, path: &str, body: &B) -> Result<(), String> {
+        self.first_success::<IgnoredAny>(path, |url| self.http.post(url).json(body))
+            .await
+            .map(|_| ())
+    }
+
+    /// The candidates to send a request to, in order: the synced nodes, the nodes not checked
+    /// yet, the unsynced nodes if allowed and, as a last resort, the offline nodes, each in
+    /// priority order.
+    fn ordered_candidates(&self) -> Vec<&CandidateNode> {
+        let mut candidates: Vec<(BeaconNodeHealth, &CandidateNode)> = self
+            .candidates
+            .iter()
+            .map(|candidate| (*candidate.health.read(), candidate))
+            .filter(|(health, _)| self.allow_unsynced || *health != BeaconNodeHealth::Unsynced)
+            .collect();
+        candidates.sort_by_key(|(health, _)| *health);
+        candidates
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+
+    async fn first_success<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        request: impl Fn(Url) -> RequestBuilder,
+    ) -> Result<T, String> {
+        let mut errors = vec![];
+        for candidate in self.ordered_candidates() {
+            let result = match url(&candidate.url, path) {
+                Ok(url) => self.send(&candidate.url, request(url)).await,
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    warn!(endpoint = %candidate.url, path, error, "Beacon node request failed");
+                    errors.push(format!("{}: {error}", candidate.url));
+                }
+            }
+        }
+        if errors.is_empty() {
+            return Err("No synced beacon node available".to_string());
+        }
+        Err(format!(
+            "All beacon nodes failed to answer {path}: {}",
+            errors.join(", ")
+        ))
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        endpoint: &SensitiveUrl,
+        request: RequestBuilder,
+    ) -> Result<T, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response
+                .json::<ErrorResponse>()
+                .await
+                .map(|error| error.message)
+                .unwrap_or_default();
+            return Err(format!("Error {status}: {message}"));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Invalid response: {e}"))?;
+        // Some endpoints answer without a body.
+        let bytes = if bytes.is_empty() {
+            &b"null"[..]
+        } else {
+            &bytes
+        };
+        serde_json::from_slice(bytes).map_err(|e| format!("Invalid response of {endpoint}: {e}"))
+    }
+}
+
+/// Appends the path, with an optional query, to the URL of the beacon node. The path of the URL
+/// is kept, e.g. for a beacon node served under a prefix by a proxy.
+fn url(endpoint: &SensitiveUrl, path: &str) -> Result<Url, String> {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut url = endpoint.full.clone();
+    url.path_segments_mut()
+        .map_err(|()| format!("The beacon node URL {endpoint} can't have a path"))?
+        .pop_if_empty()
+        .extend(path.split('/'));
+    url.set_query(query);
+    Ok(url)
+}
+
+/// Serializes integers as decimal strings, as the beacon API does.
+pub mod quoted_u64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes lists of integers as lists of decimal strings, as the beacon API does.
+pub mod quoted_u64_vec {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(u64::to_string))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| value.parse().map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+/// Serializes bytes as `0x` prefixed hex strings, as the beacon API does.
+pub mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        hex::decode(value.strip_prefix("0x").unwrap_or(&value)).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockBeaconNode;
+    use super::*;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    async fn beacon_nodes(nodes: &[&Arc<MockBeaconNode>], allow_unsynced: bool) -> BeaconNodes {
+        let mut urls = vec![];
+        for node in nodes {
+            urls.push(node.spawn().await);
+        }
+        BeaconNodes::new(urls, &[], allow_unsynced, DEFAULT_REQUEST_TIMEOUT).unwrap()
+    }
+
+    fn genesis(genesis_time: u64) -> Value {
+        json!({"data": {
+            "genesis_time": genesis_time.to_string(),
+            "genesis_validators_root": format!("0x{}", "4b".repeat(32)),
+            "genesis_fork_version": "0x00000000",
+        }})
+    }
+
+    #[tokio::test]
+    async fn failing_nodes_fall_back_in_priority_order() {
+        let failing = MockBeaconNode::new();
+        failing.set_failing(true);
+        let first = MockBeaconNode::new();
+        first.set_response("/eth/v1/beacon/genesis", genesis(1));
+        let second = MockBeaconNode::new();
+        second.set_response("/eth/v1/beacon/genesis", genesis(2));
+        let nodes = beacon_nodes(&[&failing, &first, &second], false).await;
+
+        let response: Value = nodes.get("eth/v1/beacon/genesis").await.unwrap();
+        assert_eq!(response, genesis(1));
+        assert_eq!(failing.requests(), vec!["/eth/v1/beacon/genesis"]);
+        assert!(second.requests().is_empty());
+
+        // Once checked, the offline node is only tried after the healthy ones.
+        assert_eq!(nodes.check_health().await, 2);
+        let _: Value = nodes.get("eth/v1/beacon/genesis").await.unwrap();
+        assert_eq!(
+            failing.requests(),
+            vec!["/eth/v1/beacon/genesis", "/eth/v1/node/syncing"]
+        );
+    }
+
+    #[tokio::test]
+    async fn unsynced_nodes_are_only_used_when_allowed() {
+        let unsynced = MockBeaconNode::new();
+        unsynced.set_syncing(true);
+        unsynced.set_response("/eth/v1/beacon/genesis", genesis(1));
+        let synced = MockBeaconNode::new();
+        synced.set_response("/eth/v1/beacon/genesis", genesis(2));
+
+        let nodes = beacon_nodes(&[&unsynced, &synced], false).await;
+        assert_eq!(nodes.check_health().await, 1);
+        assert_eq!(
+            nodes
+                .health()
+                .into_iter()
+                .map(|(_, health)| health)
+                .collect::<Vec<_>>(),
+            vec![BeaconNodeHealth::Unsynced, BeaconNodeHealth::Synced]
+        );
+        let response: Value = nodes.get("eth/v1/beacon/genesis").await.unwrap();
+        assert_eq!(response, genesis(2));
+
+        // Without a synced node, the request fails unless unsynced nodes are allowed.
+        synced.set_syncing(true);
+        nodes.check_health().await;
+        assert!(nodes.get::<Value>("eth/v1/beacon/genesis").await.is_err());
+
+        let nodes = beacon_nodes(&[&unsynced], true).await;
+        nodes.check_health().await;
+        let response: Value = nodes.get("eth/v1/beacon/genesis").await.unwrap();
+        assert_eq!(response, genesis(1));
+    }
+
+    #[tokio::test]
+    async fn posted_bodies_reach_the_node() {
+        let node = MockBeaconNode::new();
+        let nodes = beacon_nodes(&[&node], false).await;
+        nodes
+            .post_empty("eth/v1/beacon/pool/attestations", &json!([{"slot": "1"}]))
+            .await
+            .unwrap();
+        assert_eq!(
+            node.posted("/eth/v1/beacon/pool/attestations"),
+            vec![json!([{"slot": "1"}])]
+        );
+    }
+
+    #[tokio::test]
+    async fn genesis_and_spec_are_parsed() {
+        let node = MockBeaconNode::new();
+        node.set_response("/eth/v1/beacon/genesis", genesis(1606824023));
+        node.set_response(
+            "/eth/v1/config/spec",
+            json!({"data": {
+                "CONFIG_NAME": "mainnet",
+                "SECONDS_PER_SLOT": "12",
+                "SLOTS_PER_EPOCH": "32",
+                "EPOCHS_PER_SYNC_COMMITTEE_PERIOD": "256",
+                "ELECTRA_FORK_EPOCH": "364032",
+            }}),
+        );
+        let nodes = beacon_nodes(&[&node], false).await;
+        assert_eq!(
+            nodes.genesis().await.unwrap(),
+            GenesisData {
+                genesis_time: 1606824023,
+                genesis_validators_root: vec![0x4b; 32],
+            }
+        );
+        assert_eq!(
+            nodes.spec().await.unwrap(),
+            ChainSpec {
+                seconds_per_slot: 12,
+                slots_per_epoch: 32,
+                epochs_per_sync_committee_period: 256,
+                electra_fork_epoch: Some(364032),
+            }
+        );
+
+        // Forks not scheduled yet are at the far future epoch.
+        node.set_response(
+            "/eth/v1/config/spec",
+            json!({"data": {
+                "SECONDS_PER_SLOT": "12",
+                "SLOTS_PER_EPOCH": "32",
+                "EPOCHS_PER_SYNC_COMMITTEE_PERIOD": "256",
+                "ELECTRA_FORK_EPOCH": "18446744073709551615",
+            }}),
+        );
+        assert_eq!(nodes.spec().await.unwrap().electra_fork_epoch, None);
+
+        node.set_response(
+            "/eth/v1/config/spec",
+            json!({"data": {"SECONDS_PER_SLOT": "12"}}),
+        );
+        assert!(nodes.spec().await.is_err());
+    }
+
+    #[test]
+    fn paths_are_appended_to_the_url() {
+        let url = |endpoint: &str, path: &str| {
+            url(&SensitiveUrl::parse(endpoint).unwrap(), path)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            url("http://localhost:5052", "eth/v1/node/syncing"),
+            "http://localhost:5052/eth/v1/node/syncing"
+        );
+        assert_eq!(
+            url("http://localhost/beacon", "eth/v1/node/syncing"),
+            "http://localhost/beacon/eth/v1/node/syncing"
+        );
+        assert_eq!(
+            url("http://localhost/beacon/", "eth/v1/node/syncing"),
+            "http://localhost/beacon/eth/v1/node/syncing"
+        );
+        assert_eq!(
+            url(
+                "http://localhost/beacon",
+                "eth/v1/validator/attestation_data?slot=1&committee_index=2"
+            ),
+            "http://localhost/beacon/eth/v1/validator/attestation_data?slot=1&committee_index=2"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_certificates_are_trusted() {
+        let node = MockBeaconNode::new();
+        node.set_response("/eth/v1/beacon/genesis", genesis(1));
+        let url = node.spawn_tls().await;
+
+        // The self-signed certificate is only trusted once configured.
+        let untrusted =
+            BeaconNodes::new(vec![url.clone()], &[], false, DEFAULT_REQUEST_TIMEOUT).unwrap();
+        assert!(untrusted.genesis().await.is_err());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("cert.pem");
+        fs::write(&path, super::mock::TLS_CERTIFICATE).unwrap();
+        let nodes = BeaconNodes::new(vec![url], &[path], false, DEFAULT_REQUEST_TIMEOUT).unwrap();
+        assert_eq!(nodes.genesis().await.unwrap().genesis_time, 1);
+    }
+
+    #[test]
+    fn invalid_certificates_are_rejected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("cert.pem");
+        fs::write(&path, "not a certificate").unwrap();
+        let url = SensitiveUrl::parse("https://localhost:5052/").unwrap();
+        assert!(BeaconNodes::new(vec![url], &[path], false, DEFAULT_REQUEST_TIMEOUT).is_err());
+    }
+}
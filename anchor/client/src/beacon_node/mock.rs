@@ -0,0 +1,161 @@
+//! A beacon node serving canned responses, to test the components requesting beacon nodes.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use parking_lot::Mutex;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use sensitive_url::SensitiveUrl;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+/// A self-signed certificate of `localhost` and `127.0.0.1`, and its key, generated with:
+/// `openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -days 36500
+/// -subj /CN=localhost -addext basicConstraints=critical,CA:FALSE
+/// -addext subjectAltName=DNS:localhost,IP:127.0.0.1`
+pub const TLS_CERTIFICATE: &str = include_str!("localhost_cert.pem");
+const TLS_KEY: &str = include_str!("localhost_key.pem");
+
+#[derive(Default)]
+pub struct MockBeaconNode {
+    /// The responses to the requests of each path.
+    responses: Mutex<HashMap<String, Value>>,
+    /// The paths requested with their queries, in order.
+    requests: Mutex<Vec<String>>,
+    /// The JSON bodies posted to each path, in order.
+    posted: Mutex<HashMap<String, Vec<Value>>>,
+    syncing: Mutex<bool>,
+    failing: Mutex<bool>,
+}
+
+impl MockBeaconNode {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Serves the node on a local port, returning its URL.
+    pub async fn spawn(self: &Arc<Self>) -> SensitiveUrl {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().fallback(handle).with_state(self.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        SensitiveUrl::parse(&format!("http://{address}/")).unwrap()
+    }
+
+    /// Serves the node over TLS with the self-signed `TLS_CERTIFICATE`, returning its URL.
+    pub async fn spawn_tls(self: &Arc<Self>) -> SensitiveUrl {
+        let certificates = CertificateDer::pem_slice_iter(TLS_CERTIFICATE.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(TLS_KEY.as_bytes()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().fallback(handle).with_state(self.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (acceptor, app) = (acceptor.clone(), app.clone());
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app))
+                        .await;
+                });
+            }
+        });
+        SensitiveUrl::parse(&format!("https://{address}/")).unwrap()
+    }
+
+    pub fn set_response(&self, path: &str, response: Value) {
+        self.responses.lock().insert(path.to_string(), response);
+    }
+
+    pub fn set_syncing(&self, syncing: bool) {
+        *self.syncing.lock() = syncing;
+    }
+
+    /// Makes every request fail with an internal error.
+    pub fn set_failing(&self, failing: bool) {
+        *self.failing.lock() = failing;
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().clone()
+    }
+
+    pub fn posted(&self, path: &str) -> Vec<Value> {
+        self.posted.lock().get(path).cloned().unwrap_or_default()
+    }
+}
+
+async fn handle(
+    State(node): State<Arc<MockBeaconNode>>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().to_string();
+    let path_and_query = uri
+        .path_and_query()
+        .map_or_else(|| path.clone(), |path_and_query| path_and_query.to_string());
+    node.requests.lock().push(path_and_query);
+    if *node.failing.lock() {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, "unavailable");
+    }
+    if method == Method::POST {
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        node.posted
+            .lock()
+            .entry(path.clone())
+            .or_default()
+            .push(body);
+    }
+
+    if let Some(response) = node.responses.lock().get(&path) {
+        return Json(response.clone()).into_response();
+    }
+    match (method, path.as_str()) {
+        (Method::GET, "/eth/v1/node/syncing") => {
+            let syncing = *node.syncing.lock();
+            Json(json!({
+                "data": {
+                    "head_slot": "100",
+                    "sync_distance": if syncing { "10" } else { "0" },
+                    "is_syncing": syncing,
+                    "is_optimistic": false,
+                    "el_offline": false,
+                }
+            }))
+            .into_response()
+        }
+        (Method::POST, _) => StatusCode::OK.into_response(),
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(json!({"code": status.as_u16(), "message": message})),
+    )
+        .into_response()
+}
@@ -1,11 +1,13 @@
 use clap::builder::styling::*;
 use clap::builder::{ArgAction, ArgPredicate};
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use strum::Display;
 // use clap_utils::{get_color_style, FLAG_HEADER};
 use ethereum_hashing::have_sha_extensions;
+use network::Quota;
 use std::net::IpAddr;
+use std::num::NonZeroU16;
 use std::path::PathBuf;
 use std::sync::LazyLock;
 use version::VERSION;
@@ -106,6 +108,26 @@ pub struct Anchor {
     )]
     pub secrets_dir: Option<PathBuf>,
 
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Path to the encrypted keystore of the operator's RSA key, in the \
+                encrypted_private_key.json format of the SSV node, see the operator-key generate \
+                command to create one. Defaults to encrypted_private_key.json in the data \
+                directory.",
+        display_order = 0
+    )]
+    pub operator_keystore: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Path to a file containing the password of the operator keystore. Defaults to \
+                the operator_password file in the secrets directory.",
+        display_order = 0
+    )]
+    pub operator_password_file: Option<PathBuf>,
+
     /* External APIs */
     #[clap(
         long,
@@ -125,6 +147,35 @@ pub struct Anchor {
     )]
     pub execution_nodes: Option<Vec<String>>,
 
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        help = "The address of the SSVNetwork contract whose events are synced. Defaults to \
+                the mainnet contract.",
+        requires = "ssv_deployment_block",
+        display_order = 0
+    )]
+    pub ssv_contract_address: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "BLOCK",
+        help = "The block the SSVNetwork contract was deployed at, where the sync of its events \
+                starts.",
+        requires = "ssv_contract_address",
+        display_order = 0
+    )]
+    pub ssv_deployment_block: Option<u64>,
+
+    #[clap(
+        long,
+        value_name = "BLOCKS",
+        help = "The number of blocks behind the execution head that the SSV contract events \
+                are synced up to, to not be affected by reorgs.",
+        display_order = 0
+    )]
+    pub execution_follow_distance: Option<u64>,
+
     #[clap(
         long,
         value_name = "CERTIFICATE-FILES",
@@ -147,6 +198,15 @@ pub struct Anchor {
     )]
     pub execution_nodes_tls_certs: Option<Vec<PathBuf>>,
 
+    #[clap(
+        long,
+        help = "Allow using beacon nodes that are not synced when no synced beacon node is \
+                available.",
+        help_heading = FLAG_HEADER,
+        display_order = 0
+    )]
+    pub allow_unsynced: bool,
+
     /* REST API related arguments */
     #[clap(
         long,
@@ -283,6 +343,73 @@ pub struct Anchor {
     )]
     pub quic_port6: Option<u16>,
 
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        help = "The IP address to broadcast to other peers on how to reach this node. Set this only if you are sure other nodes can connect to your local \
+                      node on this address. This will update the `ip4` or `ip6` ENR fields \
+                      accordingly. To update both, set this flag twice with the different values.",
+        num_args(1..=2),
+        action = ArgAction::Append,
+    )]
+    pub enr_address: Option<Vec<IpAddr>>,
+
+    #[clap(
+        long,
+        value_name = "PORT",
+        help = "The TCP4 port of the local ENR. Set this only if you are sure other nodes \
+                      can connect to your local node on this port over IPv4. The --port flag is \
+                      used if this is not set.",
+        action = ArgAction::Set,
+    )]
+    pub enr_tcp_port: Option<NonZeroU16>,
+
+    #[clap(
+        long,
+        value_name = "PORT",
+        help = "The UDP4 port of the local ENR. Set this only if you are sure other nodes \
+                      can connect to your local node on this port over IPv4.",
+        action = ArgAction::Set,
+    )]
+    pub enr_udp_port: Option<NonZeroU16>,
+
+    #[clap(
+        long,
+        value_name = "PORT",
+        help = "The quic UDP4 port of the local ENR. Set this only if you are sure other \
+                      nodes can connect to your local node on this port over IPv4.",
+        action = ArgAction::Set,
+    )]
+    pub enr_quic_port: Option<NonZeroU16>,
+
+    #[clap(
+        long,
+        value_name = "PORT",
+        help = "The TCP6 port of the local ENR. Set this only if you are sure other nodes \
+                      can connect to your local node on this port over IPv6. The --port6 flag \
+                      is used if this is not set.",
+        action = ArgAction::Set,
+    )]
+    pub enr_tcp6_port: Option<NonZeroU16>,
+
+    #[clap(
+        long,
+        value_name = "PORT",
+        help = "The UDP6 port of the local ENR. Set this only if you are sure other nodes \
+                      can connect to your local node on this port over IPv6.",
+        action = ArgAction::Set,
+    )]
+    pub enr_udp6_port: Option<NonZeroU16>,
+
+    #[clap(
+        long,
+        value_name = "PORT",
+        help = "The quic UDP6 port of the local ENR. Set this only if you are sure other \
+                      nodes can connect to your local node on this port over IPv6.",
+        action = ArgAction::Set,
+    )]
+    pub enr_quic6_port: Option<NonZeroU16>,
+
     #[clap(
         long,
         help = "Sets all listening TCP/UDP ports to 0, allowing the OS to choose some \
@@ -292,6 +419,206 @@ pub struct Anchor {
     )]
     pub use_zero_ports: bool,
 
+    #[clap(
+        long,
+        value_name = "ENR/MULTIADDR LIST",
+        help = "One or more comma-delimited base64-encoded ENR's or multiaddr strings of peers \
+                to initially connect to.",
+        value_delimiter = ',',
+        display_order = 0
+    )]
+    pub boot_nodes: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        value_name = "MULTIADDR LIST",
+        help = "One or more comma-delimited multiaddrs of peers to stay connected to at all \
+                times. Each multiaddr must end with the /p2p/<peer-id> of the peer. \
+                Disconnected static peers are redialed with an exponential backoff.",
+        value_delimiter = ',',
+        display_order = 0
+    )]
+    pub static_peers: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        value_name = "PEER_ID LIST",
+        help = "One or more comma-delimited trusted peer ids which are never disconnected \
+                or scored down.",
+        value_delimiter = ',',
+        display_order = 0
+    )]
+    pub trusted_peers: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        value_name = "CIDR LIST",
+        help = "One or more comma-delimited subnets in CIDR notation, e.g. 10.0.0.0/8. If set, \
+                only connections to and from IPs in these subnets are allowed.",
+        value_delimiter = ',',
+        display_order = 0
+    )]
+    pub allowed_subnets: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        value_name = "CIDR LIST",
+        help = "One or more comma-delimited subnets in CIDR notation whose IPs are never \
+                connected to. A single IP denies that IP only.",
+        value_delimiter = ',',
+        display_order = 0
+    )]
+    pub denied_subnets: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        value_name = "PEER_ID LIST",
+        help = "One or more comma-delimited peer ids that are never connected to.",
+        value_delimiter = ',',
+        display_order = 0
+    )]
+    pub denied_peers: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Path to a file containing the secp256k1 network key of this node, either hex \
+                encoded, PEM encoded (SEC1) or as an encrypted keystore. Defaults to the key \
+                stored in the network directory, which is generated on first start.",
+        display_order = 0
+    )]
+    pub network_key_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Path to a file containing the password of the network key. When set, the key \
+                stored in the network directory is written as an encrypted keystore.",
+        display_order = 0
+    )]
+    pub network_key_password_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Disables UPnP support. Setting this will prevent Anchor from attempting to \
+                automatically establish external port mappings with the gateway. The ENR is \
+                still updated with the external address observed by peers, unless it is set \
+                with --enr-address.",
+        display_order = 0,
+        help_heading = FLAG_HEADER,
+    )]
+    pub disable_upnp: bool,
+
+    #[clap(
+        long,
+        help = "Subscribe to all subnets instead of only those of the committees of our \
+                validators. This is useful for exporters and nodes relaying all messages.",
+        display_order = 0,
+        help_heading = FLAG_HEADER,
+    )]
+    pub subscribe_all_subnets: bool,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Path to a JSON file containing the gossipsub parameters, e.g. \
+                {\"mesh_n\": 4, \"heartbeat_interval_ms\": 300}. Missing parameters take the \
+                defaults of the SSV network. The gossipsub flags override the file.",
+        display_order = 0
+    )]
+    pub gossipsub_config: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PEERS",
+        help = "The target number of peers in the gossipsub mesh of a topic (D).",
+        display_order = 0
+    )]
+    pub gossipsub_mesh_n: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "PEERS",
+        help = "The number of gossipsub mesh peers below which more peers are grafted (D_lo).",
+        display_order = 0
+    )]
+    pub gossipsub_mesh_n_low: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "PEERS",
+        help = "The number of gossipsub mesh peers above which peers are pruned (D_hi).",
+        display_order = 0
+    )]
+    pub gossipsub_mesh_n_high: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "MILLISECONDS",
+        help = "The interval between two gossipsub heartbeats.",
+        display_order = 0
+    )]
+    pub gossipsub_heartbeat_interval: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Publish our messages to the gossipsub mesh peers only, instead of to all peers \
+                subscribed to the topic.",
+        display_order = 0,
+        help_heading = FLAG_HEADER,
+    )]
+    pub disable_gossipsub_flood_publish: bool,
+
+    #[clap(
+        long,
+        value_name = "CONNECTIONS",
+        help = "The maximum number of incoming connections performing their handshake.",
+        display_order = 0
+    )]
+    pub max_pending_incoming: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "CONNECTIONS",
+        help = "The maximum number of outgoing connections performing their handshake.",
+        display_order = 0
+    )]
+    pub max_pending_outgoing: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "CONNECTIONS",
+        help = "The maximum number of connections established with a single peer.",
+        display_order = 0
+    )]
+    pub max_connections_per_peer: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "FRACTION",
+        help = "The fraction of the system memory above which new connections are denied, \
+                between 0 and 1.",
+        display_order = 0
+    )]
+    pub max_memory_usage: Option<f64>,
+
+    #[clap(
+        long,
+        value_name = "TOKENS/SECONDS",
+        help = "The number of inbound connections a remote IP may open within a period, e.g. 5/60.",
+        display_order = 0
+    )]
+    pub handshake_rate_limit: Option<Quota>,
+
+    #[clap(
+        long,
+        value_name = "TOKENS/SECONDS",
+        help = "The number of decided history requests a peer may send within a period, e.g. \
+                10/60.",
+        display_order = 0
+    )]
+    pub decided_history_rate_limit: Option<Quota>,
+
     /* Prometheus metrics HTTP server related arguments */
     #[clap(
         long,
@@ -330,6 +657,72 @@ pub struct Anchor {
         help_heading = FLAG_HEADER
     )]
     help: Option<bool>,
+
+    #[clap(subcommand)]
+    pub subcommand: Option<AnchorSubcommand>,
+}
+
+#[derive(Subcommand, Clone, Deserialize, Serialize, Debug)]
+pub enum AnchorSubcommand {
+    #[clap(
+        subcommand,
+        about = "Imports or exports the slashing protection database of the validators in the \
+                 EIP-3076 interchange format."
+    )]
+    SlashingProtection(SlashingProtectionCommand),
+    #[clap(subcommand, about = "Exports the network key of the node.")]
+    NetworkKey(NetworkKeyCommand),
+    #[clap(subcommand, about = "Generates the RSA key of the operator.")]
+    OperatorKey(OperatorKeyCommand),
+}
+
+#[derive(Subcommand, Clone, Deserialize, Serialize, Debug)]
+pub enum OperatorKeyCommand {
+    #[clap(
+        about = "Generates a new operator key and writes it to the operator keystore, encrypted \
+                 with the operator password. An existing keystore is never overwritten. Register \
+                 the logged public key in the SSV network."
+    )]
+    Generate,
+}
+
+#[derive(Subcommand, Clone, Deserialize, Serialize, Debug)]
+pub enum NetworkKeyCommand {
+    #[clap(
+        about = "Exports the network key of the node to a file, e.g. to move its identity to \
+                 another host. Import it with --network-key-file."
+    )]
+    Export {
+        #[clap(value_name = "FILE", help = "The file to write the key to.")]
+        file: PathBuf,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Path to a file containing a password to encrypt the exported key with. \
+                    The key is written hex encoded otherwise."
+        )]
+        password_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Clone, Deserialize, Serialize, Debug)]
+pub enum SlashingProtectionCommand {
+    #[clap(
+        about = "Imports the blocks and attestations signed by the validators from an \
+                 interchange file, e.g. exported by another client."
+    )]
+    Import {
+        #[clap(value_name = "FILE", help = "The interchange file to import.")]
+        file: PathBuf,
+    },
+    #[clap(
+        about = "Exports the blocks and attestations signed by the validators to an \
+                 interchange file."
+    )]
+    Export {
+        #[clap(value_name = "FILE", help = "The interchange file to write.")]
+        file: PathBuf,
+    },
 }
 
 pub fn get_color_style() -> Styles {
@@ -2,11 +2,12 @@
 // use clap_utils::{flags::DISABLE_MALLOC_TUNING_FLAG, parse_optional, parse_required};
 
 use crate::cli::Anchor;
-use network::{ListenAddr, ListenAddress};
+use network::{Enr, IpSubnet, ListenAddr, ListenAddress, Multiaddr, PeerId, Quota};
+use operator_key::OPERATOR_KEYSTORE_FILENAME;
 use sensitive_url::SensitiveUrl;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
 use tracing::warn;
 
@@ -18,6 +19,8 @@ pub const DEFAULT_ROOT_DIR: &str = ".anchor";
 pub const DEFAULT_HARDCODED_NETWORK: &str = "mainnet";
 /// Directory within the network directory where secrets are stored.
 pub const DEFAULT_SECRETS_DIR: &str = "secrets";
+/// File within the secrets directory holding the password of the operator keystore.
+pub const DEFAULT_OPERATOR_PASSWORD_FILENAME: &str = "operator_password";
 
 /// Stores the core configuration for this Anchor instance.
 #[derive(Clone, Serialize, Deserialize)]
@@ -26,13 +29,21 @@ pub struct Config {
     pub data_dir: PathBuf,
     /// The directory containing the passwords to unlock validator keystores.
     pub secrets_dir: PathBuf,
+    /// The keystore of the operator's RSA key, generated on first start if it does not exist.
+    pub operator_keystore: PathBuf,
+    /// The file containing the password of the operator keystore.
+    pub operator_password_file: PathBuf,
     /// The http endpoints of the beacon node APIs.
     ///
     /// Should be similar to `["http://localhost:8080"]`
     pub beacon_nodes: Vec<SensitiveUrl>,
     /// The http endpoints of the execution node APIs.
     pub execution_nodes: Vec<SensitiveUrl>,
-    /// beacon node is not synced at startup.
+    /// Configuration for syncing the SSV contract events from the execution nodes.
+    pub execution: execution::Config,
+    /// Configuration of the queues and workers processing the messages of other operators.
+    pub processor: processor::Config,
+    /// Whether beacon nodes that are not synced may be used when no synced one is available.
     pub allow_unsynced_beacon_node: bool,
     /// Configuration for the HTTP REST API.
     pub http_api: http_api::Config,
@@ -58,6 +69,8 @@ impl Default for Config {
             .join(DEFAULT_ROOT_DIR)
             .join(DEFAULT_HARDCODED_NETWORK);
         let secrets_dir = data_dir.join(DEFAULT_SECRETS_DIR);
+        let operator_keystore = data_dir.join(OPERATOR_KEYSTORE_FILENAME);
+        let operator_password_file = secrets_dir.join(DEFAULT_OPERATOR_PASSWORD_FILENAME);
 
         let beacon_nodes = vec![SensitiveUrl::parse(DEFAULT_BEACON_NODE)
             .expect("beacon_nodes must always be a valid url.")];
@@ -66,8 +79,12 @@ impl Default for Config {
         Self {
             data_dir,
             secrets_dir,
+            operator_keystore,
+            operator_password_file,
             beacon_nodes,
             execution_nodes,
+            execution: <_>::default(),
+            processor: <_>::default(),
             allow_unsynced_beacon_node: false,
             http_api: <_>::default(),
             http_metrics: <_>::default(),
@@ -102,6 +119,15 @@ pub fn from_cli(cli_args: &Anchor) -> Result<Config, String> {
 
     config.secrets_dir = secrets_dir.unwrap_or_else(|| default_root_dir.join(DEFAULT_SECRETS_DIR));
 
+    config.operator_keystore = cli_args
+        .operator_keystore
+        .clone()
+        .unwrap_or_else(|| config.data_dir.join(OPERATOR_KEYSTORE_FILENAME));
+    config.operator_password_file = cli_args
+        .operator_password_file
+        .clone()
+        .unwrap_or_else(|| config.secrets_dir.join(DEFAULT_OPERATOR_PASSWORD_FILENAME));
+
     if !config.data_dir.exists() {
         fs::create_dir_all(&config.data_dir)
             .map_err(|e| format!("Failed to create {:?}: {:?}", config.data_dir, e))?;
@@ -123,12 +149,157 @@ pub fn from_cli(cli_args: &Anchor) -> Result<Config, String> {
             .map_err(|e| format!("Unable to parse execution node URL: {:?}", e))?;
     }
 
+    if let Some(contract_address) = &cli_args.ssv_contract_address {
+        config.execution.contract_address = contract_address.parse()?;
+    }
+    if let Some(deployment_block) = cli_args.ssv_deployment_block {
+        config.execution.deployment_block = deployment_block;
+    }
+    if let Some(follow_distance) = cli_args.execution_follow_distance {
+        config.execution.follow_distance = follow_distance;
+    }
+
     /*
      * Network related
      */
     config.network.listen_addresses = parse_listening_addresses(cli_args)?;
 
+    if let Some(enr_addresses) = &cli_args.enr_address {
+        config.network.enr_address = parse_enr_addresses(enr_addresses)?;
+    }
+    config.network.enr_tcp4_port = cli_args.enr_tcp_port;
+    config.network.enr_udp4_port = cli_args.enr_udp_port;
+    config.network.enr_quic4_port = cli_args.enr_quic_port;
+    config.network.enr_tcp6_port = cli_args.enr_tcp6_port;
+    config.network.enr_udp6_port = cli_args.enr_udp6_port;
+    config.network.enr_quic6_port = cli_args.enr_quic6_port;
+
+    if let Some(boot_nodes) = &cli_args.boot_nodes {
+        for addr in boot_nodes {
+            if addr.starts_with("enr:") {
+                let enr = addr
+                    .parse::<Enr>()
+                    .map_err(|e| format!("Invalid ENR boot node {addr}: {e}"))?;
+                config.network.boot_nodes_enr.push(enr);
+            } else {
+                let multiaddr = addr
+                    .parse::<Multiaddr>()
+                    .map_err(|e| format!("Invalid Multiaddr boot node {addr}: {e}"))?;
+                config.network.boot_nodes_multiaddr.push(multiaddr);
+            }
+        }
+    }
+
+    if let Some(static_peers) = &cli_args.static_peers {
+        config.network.static_peers = static_peers
+            .iter()
+            .map(|addr| {
+                addr.parse::<Multiaddr>()
+                    .map_err(|e| format!("Invalid static peer {addr}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    config.network.upnp_enabled = !cli_args.disable_upnp;
+    config.network.subscribe_all_subnets = cli_args.subscribe_all_subnets;
+
+    config.network.network_key_file = cli_args.network_key_file.clone();
+    config.network.network_key_password_file = cli_args.network_key_password_file.clone();
+
+    if let Some(trusted_peers) = &cli_args.trusted_peers {
+        config.network.trusted_peers = trusted_peers
+            .iter()
+            .map(|peer_id| {
+                peer_id
+                    .parse::<PeerId>()
+                    .map_err(|e| format!("Invalid trusted peer id {peer_id}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(allowed_subnets) = &cli_args.allowed_subnets {
+        config.network.allowed_subnets = allowed_subnets
+            .iter()
+            .map(|subnet| {
+                subnet
+                    .parse::<IpSubnet>()
+                    .map_err(|e| format!("Invalid allowed subnet {subnet}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(denied_subnets) = &cli_args.denied_subnets {
+        config.network.denied_subnets = denied_subnets
+            .iter()
+            .map(|subnet| {
+                subnet
+                    .parse::<IpSubnet>()
+                    .map_err(|e| format!("Invalid denied subnet {subnet}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(denied_peers) = &cli_args.denied_peers {
+        config.network.denied_peers = denied_peers
+            .iter()
+            .map(|peer_id| {
+                peer_id
+                    .parse::<PeerId>()
+                    .map_err(|e| format!("Invalid denied peer id {peer_id}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(path) = &cli_args.gossipsub_config {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read gossipsub config {}: {e}", path.display()))?;
+        config.network.gossipsub = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid gossipsub config {}: {e}", path.display()))?;
+    }
+    let gossipsub = &mut config.network.gossipsub;
+    if let Some(mesh_n) = cli_args.gossipsub_mesh_n {
+        gossipsub.mesh_n = mesh_n;
+    }
+    if let Some(mesh_n_low) = cli_args.gossipsub_mesh_n_low {
+        gossipsub.mesh_n_low = mesh_n_low;
+    }
+    if let Some(mesh_n_high) = cli_args.gossipsub_mesh_n_high {
+        gossipsub.mesh_n_high = mesh_n_high;
+    }
+    if let Some(heartbeat_interval) = cli_args.gossipsub_heartbeat_interval {
+        gossipsub.heartbeat_interval_ms = heartbeat_interval;
+    }
+    if cli_args.disable_gossipsub_flood_publish {
+        gossipsub.flood_publish = false;
+    }
+    gossipsub.validate()?;
+
+    if let Some(max_pending_incoming) = cli_args.max_pending_incoming {
+        config.network.max_pending_incoming = max_pending_incoming;
+    }
+    if let Some(max_pending_outgoing) = cli_args.max_pending_outgoing {
+        config.network.max_pending_outgoing = max_pending_outgoing;
+    }
+    if let Some(max_connections_per_peer) = cli_args.max_connections_per_peer {
+        config.network.max_connections_per_peer = max_connections_per_peer;
+    }
+    if let Some(max_memory_usage) = cli_args.max_memory_usage {
+        if !(max_memory_usage > 0.0 && max_memory_usage <= 1.0) {
+            return Err(format!(
+                "Invalid --max-memory-usage {max_memory_usage}, expected a fraction between 0 and 1"
+            ));
+        }
+        config.network.max_memory_usage = max_memory_usage;
+    }
+    if let Some(quota) = cli_args.handshake_rate_limit {
+        config.network.rate_limits.handshake_quota = quota;
+    }
+    if let Some(quota) = cli_args.decided_history_rate_limit {
+        config.network.rate_limits.decided_history_quota = quota;
+    }
+
     config.beacon_nodes_tls_certs = cli_args.beacon_nodes_tls_certs.clone();
+    config.allow_unsynced_beacon_node = cli_args.allow_unsynced;
     config.execution_nodes_tls_certs = cli_args.execution_nodes_tls_certs.clone();
 
     /*
@@ -179,6 +350,34 @@ pub fn from_cli(cli_args: &Anchor) -> Result<Config, String> {
     Ok(config)
 }
 
+/// Parses the ENR addresses, which can be at most one IPv4 and one IPv6 address.
+fn parse_enr_addresses(
+    addresses: &[IpAddr],
+) -> Result<(Option<Ipv4Addr>, Option<Ipv6Addr>), String> {
+    let mut enr_address = (None, None);
+    for address in addresses {
+        match address {
+            IpAddr::V4(v4_addr) => {
+                if let Some(first_ipv4_addr) = enr_address.0.replace(*v4_addr) {
+                    return Err(format!(
+                        "When setting the --enr-address option twice, use an IPv4 address and an \
+                         IPv6 address. Got two IPv4 addresses {first_ipv4_addr} and {v4_addr}"
+                    ));
+                }
+            }
+            IpAddr::V6(v6_addr) => {
+                if let Some(first_ipv6_addr) = enr_address.1.replace(*v6_addr) {
+                    return Err(format!(
+                        "When setting the --enr-address option twice, use an IPv4 address and an \
+                         IPv6 address. Got two IPv6 addresses {first_ipv6_addr} and {v6_addr}"
+                    ));
+                }
+            }
+        }
+    }
+    Ok(enr_address)
+}
+
 /// Gets the listening_addresses for lighthouse based on the cli options.
 pub fn parse_listening_addresses(cli_args: &Anchor) -> Result<ListenAddress, String> {
     // parse the possible ips
@@ -317,6 +516,7 @@ pub fn parse_listening_addresses(cli_args: &Anchor) -> Result<ListenAddress, Str
                 .use_zero_ports
                 .then(unused_port::unused_tcp6_port)
                 .transpose()?
+                .or(cli_args.port6)
                 .unwrap_or(cli_args.port);
             let ipv6_disc_port = cli_args
                 .use_zero_ports
@@ -358,10 +558,204 @@ pub fn parse_listening_addresses(cli_args: &Anchor) -> Result<ListenAddress, Str
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::AnchorSubcommand;
+    use clap::Parser;
+    use std::path::Path;
 
     #[test]
     // Ensures the default config does not panic.
     fn default_config() {
         Config::default();
     }
+
+    #[test]
+    fn dns_boot_nodes_are_accepted() {
+        let datadir = tempfile::tempdir().unwrap();
+        let boot_node = format!("/dns4/boot.example.com/tcp/9100/p2p/{}", PeerId::random());
+        let cli_args = Anchor::try_parse_from([
+            "anchor",
+            "--datadir",
+            datadir.path().to_str().unwrap(),
+            "--boot-nodes",
+            &boot_node,
+        ])
+        .unwrap();
+
+        let config = from_cli(&cli_args).unwrap();
+        assert_eq!(
+            config.network.boot_nodes_multiaddr,
+            vec![boot_node.parse::<Multiaddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn dual_stack_addresses() {
+        let datadir = tempfile::tempdir().unwrap();
+        let cli_args = Anchor::try_parse_from([
+            "anchor",
+            "--datadir",
+            datadir.path().to_str().unwrap(),
+            "--listen-addresses",
+            "0.0.0.0",
+            "--listen-addresses",
+            "::",
+            "--port",
+            "9000",
+            "--port6",
+            "9010",
+            "--enr-address",
+            "2001:db8::1",
+            "--enr-tcp6-port",
+            "9020",
+        ])
+        .unwrap();
+
+        let config = from_cli(&cli_args).unwrap();
+        let listen_addresses = &config.network.listen_addresses;
+        assert_eq!(listen_addresses.v4().unwrap().tcp_port, 9000);
+        let v6 = listen_addresses.v6().unwrap();
+        assert_eq!(v6.addr, Ipv6Addr::UNSPECIFIED);
+        assert_eq!(
+            (v6.tcp_port, v6.disc_port, v6.quic_port),
+            (9010, 9010, 9011)
+        );
+        assert_eq!(
+            config.network.enr_address,
+            (None, Some("2001:db8::1".parse().unwrap()))
+        );
+        assert_eq!(config.network.enr_tcp6_port.unwrap().get(), 9020);
+    }
+
+    #[test]
+    fn gossipsub_flags_override_the_config_file() {
+        let datadir = tempfile::tempdir().unwrap();
+        let config_file = datadir.path().join("gossipsub.json");
+        fs::write(
+            &config_file,
+            r#"{"mesh_n": 4, "mesh_n_low": 3, "mesh_n_high": 6, "heartbeat_interval_ms": 300}"#,
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec![
+                "anchor",
+                "--datadir",
+                datadir.path().to_str().unwrap(),
+                "--gossipsub-config",
+                config_file.to_str().unwrap(),
+            ];
+            args.extend_from_slice(extra);
+            Anchor::try_parse_from(args).unwrap()
+        };
+
+        let config = from_cli(&args(&["--gossipsub-mesh-n", "5"])).unwrap();
+        let gossipsub = &config.network.gossipsub;
+        assert_eq!(
+            (
+                gossipsub.mesh_n_low,
+                gossipsub.mesh_n,
+                gossipsub.mesh_n_high
+            ),
+            (3, 5, 6)
+        );
+        assert_eq!(gossipsub.heartbeat_interval_ms, 300);
+        assert!(gossipsub.flood_publish);
+
+        // The resulting parameters are validated.
+        assert!(from_cli(&args(&["--gossipsub-mesh-n", "8"])).is_err());
+    }
+
+    #[test]
+    fn connection_limit_flags() {
+        let datadir = tempfile::tempdir().unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["anchor", "--datadir", datadir.path().to_str().unwrap()];
+            args.extend_from_slice(extra);
+            Anchor::try_parse_from(args)
+        };
+
+        let config = from_cli(
+            &args(&[
+                "--max-pending-incoming",
+                "8",
+                "--max-connections-per-peer",
+                "2",
+                "--max-memory-usage",
+                "0.5",
+                "--handshake-rate-limit",
+                "3/30",
+            ])
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config.network.max_pending_incoming, 8);
+        assert_eq!(config.network.max_connections_per_peer, 2);
+        assert_eq!(config.network.max_memory_usage, 0.5);
+        assert_eq!(
+            config.network.rate_limits.handshake_quota,
+            Quota::n_every(3, 30)
+        );
+
+        assert!(from_cli(&args(&["--max-memory-usage", "1.5"]).unwrap()).is_err());
+        assert!(args(&["--handshake-rate-limit", "3"]).is_err());
+    }
+
+    #[test]
+    fn duplicate_enr_addresses_are_rejected() {
+        assert!(
+            parse_enr_addresses(&["1.1.1.1".parse().unwrap(), "8.8.8.8".parse().unwrap()]).is_err()
+        );
+        assert_eq!(
+            parse_enr_addresses(&["::1".parse().unwrap(), "1.1.1.1".parse().unwrap()]),
+            Ok((Some(Ipv4Addr::new(1, 1, 1, 1)), Some(Ipv6Addr::LOCALHOST)))
+        );
+    }
+
+    #[test]
+    fn slashing_protection_interchange_round_trips() {
+        let datadir = tempfile::tempdir().unwrap();
+        let interchange = datadir.path().join("interchange.json");
+        fs::write(
+            &interchange,
+            format!(
+                r#"{{
+                    "metadata": {{
+                        "interchange_format_version": "5",
+                        "genesis_validators_root": "0x{}"
+                    }},
+                    "data": [{{
+                        "pubkey": "0x{}",
+                        "signed_blocks": [{{"slot": "81952"}}],
+                        "signed_attestations": [{{"source_epoch": "2290", "target_epoch": "3007"}}]
+                    }}]
+                }}"#,
+                "04".repeat(32),
+                "b8".repeat(48)
+            ),
+        )
+        .unwrap();
+        let run = |command: &str, file: &Path| {
+            let cli_args = Anchor::try_parse_from([
+                "anchor",
+                "--datadir",
+                datadir.path().to_str().unwrap(),
+                "slashing-protection",
+                command,
+                file.to_str().unwrap(),
+            ])
+            .unwrap();
+            let Some(AnchorSubcommand::SlashingProtection(command)) = &cli_args.subcommand else {
+                panic!("Missing the slashing protection subcommand");
+            };
+            crate::run_slashing_protection(command, &from_cli(&cli_args).unwrap())
+        };
+
+        run("import", &interchange).unwrap();
+        let exported = datadir.path().join("exported.json");
+        run("export", &exported).unwrap();
+
+        let read = |file: &Path| -> serde_json::Value {
+            serde_json::from_slice(&fs::read(file).unwrap()).unwrap()
+        };
+        assert_eq!(read(&exported), read(&interchange));
+    }
 }
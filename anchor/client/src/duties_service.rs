@@ -0,0 +1,957 @@
+//! Finds the attester, proposer and sync committee duties of the validators we hold shares of,
+//! and emits each duty when it is due in its slot.
+//!
+//! The duties of the current and next epochs are fetched from the beacon nodes at each epoch,
+//! in a task of their own so that a slow beacon node does not delay the duties already known.
+//! The attester and proposer duties depend on the block at the end of an earlier epoch, the
+//! dependent root, which is checked at every slot: duties are fetched again when a reorg changed
+//! it. The proposers of an epoch depend on the same block as the attesters of the next epoch,
+//! whose dependent root is checked for both.
+//!
+//! The network is subscribed to the subnets of the committees of our validators, which are
+//! looked up in the registry at every slot.
+
+use crate::beacon_node::{
+    hex_bytes, quoted_u64, quoted_u64_vec, BeaconNodes, ChainSpec, GenericResponse,
+};
+use database::Database;
+use network::NetworkCommand;
+use operator_key::OperatorKey;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use slot_clock::{Slot, SlotClock};
+use ssv_types::{CommitteeId, OperatorId};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, info, warn};
+
+/// The number of duty events waiting for the processor before new ones are dropped.
+pub const DUTY_CHANNEL_SIZE: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttesterDuty {
+    #[serde(with = "hex_bytes")]
+    pub pubkey: Vec<u8>,
+    #[serde(with = "quoted_u64")]
+    pub validator_index: u64,
+    #[serde(with = "quoted_u64")]
+    pub committee_index: u64,
+    #[serde(with = "quoted_u64")]
+    pub committee_length: u64,
+    #[serde(with = "quoted_u64")]
+    pub committees_at_slot: u64,
+    #[serde(with = "quoted_u64")]
+    pub validator_committee_index: u64,
+    #[serde(with = "quoted_u64")]
+    pub slot: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposerDuty {
+    #[serde(with = "hex_bytes")]
+    pub pubkey: Vec<u8>,
+    #[serde(with = "quoted_u64")]
+    pub validator_index: u64,
+    #[serde(with = "quoted_u64")]
+    pub slot: u64,
+}
+
+/// The membership of a validator in the sync committee, for a whole sync committee period.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncDuty {
+    #[serde(with = "hex_bytes")]
+    pub pubkey: Vec<u8>,
+    #[serde(with = "quoted_u64")]
+    pub validator_index: u64,
+    #[serde(with = "quoted_u64_vec")]
+    pub validator_sync_committee_indices: Vec<u64>,
+}
+
+/// The attester and proposer duties responses, with the root they depend on.
+#[derive(Deserialize)]
+struct DutiesResponse<T> {
+    #[serde(with = "hex_bytes")]
+    dependent_root: Vec<u8>,
+    data: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct ValidatorData {
+    #[serde(with = "quoted_u64")]
+    index: u64,
+    validator: ValidatorPubkey,
+}
+
+#[derive(Deserialize)]
+struct ValidatorPubkey {
+    #[serde(with = "hex_bytes")]
+    pubkey: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Duty {
+    Proposer(ProposerDuty),
+    /// The selection of an attester as an aggregator of its committee.
+    Aggregator(AttesterDuty),
+    Attester(AttesterDuty),
+    SyncCommittee(SyncDuty),
+}
+
+/// A duty to perform now, in `slot`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DutyEvent {
+    pub slot: Slot,
+    pub duty: Duty,
+}
+
+struct EpochDuties<T> {
+    dependent_root: Vec<u8>,
+    duties: Vec<T>,
+}
+
+#[derive(Default)]
+struct Duties {
+    operator_id: Option<OperatorId>,
+    /// The epoch at which the indices were last updated.
+    indices_epoch: Option<u64>,
+    /// The beacon chain index of our validators, by public key.
+    indices: HashMap<Vec<u8>, u64>,
+    /// The committee of the operators of our validators, by public key.
+    committees: HashMap<Vec<u8>, CommitteeId>,
+    /// The committees the network was last subscribed to.
+    subscribed_committees: Option<Vec<CommitteeId>>,
+    /// The duties by epoch.
+    attesters: BTreeMap<u64, EpochDuties<AttesterDuty>>,
+    proposers: BTreeMap<u64, EpochDuties<ProposerDuty>>,
+    /// The duties by sync committee period.
+    sync_committees: BTreeMap<u64, Vec<SyncDuty>>,
+}
+
+/// Whether our validators have a duty at a slot, to only keep the messages of the other
+/// operators about the duties we perform.
+pub trait ScheduledDuties: Send + Sync {
+    fn has_duty(&self, validator_index: u64, slot: u64) -> bool;
+
+    /// Whether a validator of the committee has an attester duty at `slot`.
+    fn has_committee_duty(&self, committee_id: &CommitteeId, slot: u64) -> bool;
+}
+
+pub struct DutiesService<T> {
+    beacon_nodes: Arc<BeaconNodes>,
+    database: Arc<Database>,
+    operator_key: Arc<OperatorKey>,
+    slot_clock: T,
+    spec: ChainSpec,
+    duties: RwLock<Duties>,
+    sender: Sender<DutyEvent>,
+    network_commands: Sender<NetworkCommand>,
+}
+
+impl<T: SlotClock> DutiesService<T> {
+    pub fn new(
+        beacon_nodes: Arc<BeaconNodes>,
+        database: Arc<Database>,
+        operator_key: Arc<OperatorKey>,
+        slot_clock: T,
+        spec: ChainSpec,
+        sender: Sender<DutyEvent>,
+        network_commands: Sender<NetworkCommand>,
+    ) -> Self {
+        Self {
+            beacon_nodes,
+            database,
+            operator_key,
+            slot_clock,
+            spec,
+            duties: RwLock::new(Duties::default()),
+            sender,
+            network_commands,
+        }
+    }
+
+    /// Emits the duties of each slot from the duties known at its start, see `run_updates`.
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            match self.slot_clock.now() {
+                Some(slot) => self.emit(slot).await,
+                None => debug!("Waiting for genesis"),
+            }
+            self.sleep_until_next_slot().await;
+        }
+    }
+
+    /// Updates the duties at the start of each slot.
+    pub async fn run_updates(self: Arc<Self>) {
+        loop {
+            if let Some(slot) = self.slot_clock.now() {
+                if let Err(error) = self.poll(slot).await {
+                    warn!(%slot, error, "Unable to update duties");
+                }
+            }
+            self.sleep_until_next_slot().await;
+        }
+    }
+
+    async fn sleep_until_next_slot(&self) {
+        let delay = self
+            .slot_clock
+            .duration_to_next_slot()
+            .unwrap_or_else(|| self.slot_clock.slot_duration());
+        tokio::time::sleep(delay).await;
+    }
+
+    /// Fetches the duties of the epoch of `slot` and of the next epoch which are not known yet
+    /// or whose dependent root changed.
+    pub async fn poll(&self, slot: Slot) -> Result<(), String> {
+        let epoch = slot.as_u64() / self.spec.slots_per_epoch;
+        let mut errors = vec![];
+        if let Err(error) = self.update_committees() {
+            errors.push(error);
+        }
+        if let Err(error) = self.update_indices(epoch).await {
+            errors.push(error);
+        }
+        let mut indices: Vec<u64> = self.duties.read().indices.values().copied().collect();
+        indices.sort_unstable();
+        if !indices.is_empty() {
+            for epoch in [epoch, epoch + 1] {
+                if let Err(error) = self.poll_attesters(epoch, &indices).await {
+                    errors.push(error);
+                }
+            }
+            if let Err(error) = self.poll_proposers(epoch).await {
+                errors.push(error);
+            }
+            if let Err(error) = self.poll_sync_committees(epoch, &indices).await {
+                errors.push(error);
+            }
+        }
+        self.prune(epoch);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    /// The duties to perform in `slot`, in the order they are due: proposals and aggregator
+    /// selections, then attestations and sync committee messages.
+    pub fn duties_at(&self, slot: Slot) -> Vec<Duty> {
+        let epoch = slot.as_u64() / self.spec.slots_per_epoch;
+        let period = epoch / self.spec.epochs_per_sync_committee_period;
+        let duties = self.duties.read();
+        let proposers = duties.proposers.get(&epoch).into_iter().flat_map(|epoch| {
+            epoch
+                .duties
+                .iter()
+                .filter(|duty| duty.slot == slot.as_u64())
+                .cloned()
+                .map(Duty::Proposer)
+        });
+        let attesters: Vec<AttesterDuty> = duties
+            .attesters
+            .get(&epoch)
+            .into_iter()
+            .flat_map(|epoch| {
+                epoch
+                    .duties
+                    .iter()
+                    .filter(|duty| duty.slot == slot.as_u64())
+                    .cloned()
+            })
+            .collect();
+        let sync_committees = duties
+            .sync_committees
+            .get(&period)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .map(Duty::SyncCommittee);
+        proposers
+            .chain(attesters.iter().cloned().map(Duty::Aggregator))
+            .chain(attesters.iter().cloned().map(Duty::Attester))
+            .chain(sync_committees)
+            .collect()
+    }
+
+    pub fn attester_count(&self, epoch: u64) -> usize {
+        self.duties
+            .read()
+            .attesters
+            .get(&epoch)
+            .map_or(0, |epoch| epoch.duties.len())
+    }
+
+    pub fn proposer_count(&self, epoch: u64) -> usize {
+        self.duties
+            .read()
+            .proposers
+            .get(&epoch)
+            .map_or(0, |epoch| epoch.duties.len())
+    }
+
+    /// Sends the duties of `slot` to the processor, each at the time it is due: proposals and
+    /// aggregator selections at the start of the slot, attestations and sync committee messages a
+    /// third into the slot.
+    async fn emit(&self, slot: Slot) {
+        for duty in self.duties_at(slot) {
+            let delay = match duty {
+                Duty::Proposer(_) | Duty::Aggregator(_) => Duration::ZERO,
+                Duty::Attester(_) => self.slot_clock.unagg_attestation_production_delay(),
+                Duty::SyncCommittee(_) => self.slot_clock.sync_committee_message_production_delay(),
+            };
+            if let (Some(start), Some(now)) = (
+                self.slot_clock.start_of(slot),
+                self.slot_clock.now_duration(),
+            ) {
+                tokio::time::sleep((start + delay).saturating_sub(now)).await;
+            }
+            match self.sender.try_send(DutyEvent { slot, duty }) {
+                Ok(()) => {}
+                Err(TrySendError::Full(event)) => {
+                    warn!(%slot, duty = ?event.duty, "Duty queue full, dropping duty")
+                }
+                Err(TrySendError::Closed(_)) => debug!(%slot, "Duty queue closed"),
+            }
+        }
+    }
+
+    /// Updates the beacon chain indices of the validators we operate, once per epoch. The
+    /// attester and sync committee duties are fetched again if the validators changed.
+    async fn update_indices(&self, epoch: u64) -> Result<(), String> {
+        if self.duties.read().indices_epoch == Some(epoch) {
+            return Ok(());
+        }
+        let Some(operator_id) = self.operator_id()? else {
+            debug!("Our operator is not registered yet");
+            return Ok(());
+        };
+        let validators = self
+            .database
+            .active_validators_of(operator_id)
+            .map_err(|e| format!("Unable to read our validators: {e}"))?;
+        let indices: HashMap<Vec<u8>, u64> = if validators.is_empty() {
+            HashMap::new()
+        } else {
+            let ids: Vec<String> = validators
+                .iter()
+                .map(|validator| format!("0x{}", hex::encode(&validator.public_key)))
+                .collect();
+            let response: GenericResponse<Vec<ValidatorData>> = self
+                .beacon_nodes
+                .post(
+                    "eth/v1/beacon/states/head/validators",
+                    &json!({ "ids": ids }),
+                )
+                .await?;
+            response
+                .data
+                .into_iter()
+                .map(|data| (data.validator.pubkey, data.index))
+                .collect()
+        };
+
+        let mut duties = self.duties.write();
+        duties.indices_epoch = Some(epoch);
+        if duties.indices != indices {
+            debug!(
+                validators = validators.len(),
+                known = indices.len(),
+                "Validators changed"
+            );
+            duties.indices = indices;
+            duties.attesters.clear();
+            duties.sync_committees.clear();
+        }
+        Ok(())
+    }
+
+    /// Looks up the committees of our validators in the registry, and subscribes the network to
+    /// their subnets when they changed, e.g. when a share of a new validator was added.
+    fn update_committees(&self) -> Result<(), String> {
+        let Some(operator_id) = self.operator_id()? else {
+            return Ok(());
+        };
+        let validators = self
+            .database
+            .active_validators_of(operator_id)
+            .map_err(|e| format!("Unable to read our validators: {e}"))?;
+        let committees: HashMap<Vec<u8>, CommitteeId> = validators
+            .iter()
+            .map(|validator| {
+                (
+                    validator.public_key.clone(),
+                    CommitteeId::from_operators(&validator.operator_ids),
+                )
+            })
+            .collect();
+        let mut committee_ids: Vec<CommitteeId> = committees.values().copied().collect();
+        committee_ids.sort();
+        committee_ids.dedup();
+
+        let mut duties = self.duties.write();
+        duties.committees = committees;
+        if duties.subscribed_committees.as_ref() == Some(&committee_ids) {
+            return Ok(());
+        }
+        let command = NetworkCommand::UpdateCommittees(committee_ids.clone());
+        match self.network_commands.try_send(command) {
+            Ok(()) => {
+                info!(
+                    committees = committee_ids.len(),
+                    "Subscribing to the subnets of our committees"
+                );
+                duties.subscribed_committees = Some(committee_ids);
+            }
+            // The subscriptions are sent again at the next slot.
+            Err(TrySendError::Full(_)) => {
+                warn!("Network command queue full, delaying subscriptions")
+            }
+            Err(TrySendError::Closed(_)) => debug!("Network command queue closed"),
+        }
+        Ok(())
+    }
+
+    /// The id of our operator, found by its public key once it is registered.
+    fn operator_id(&self) -> Result<Option<OperatorId>, String> {
+        if let Some(operator_id) = self.duties.read().operator_id {
+            return Ok(Some(operator_id));
+        }
+        let operators = self
+            .database
+            .operators()
+            .map_err(|e| format!("Unable to read the operators: {e}"))?;
+        let operator_id = operators
+            .into_iter()
+            .find(|operator| self.operator_key.matches(&operator.public_key))
+            .map(|operator| operator.id);
+        if let Some(operator_id) = operator_id {
+            info!(%operator_id, "Found our operator in the registry");
+            self.duties.write().operator_id = Some(operator_id);
+        }
+        Ok(operator_id)
+    }
+
+    /// Fetches the proposers of the epoch, which are returned for all validators, if they are
+    /// unknown or their dependent root changed. It is the dependent root of the attesters of the
+    /// next epoch, which are polled first.
+    async fn poll_proposers(&self, epoch: u64) -> Result<(), String> {
+        {
+            let duties = self.duties.read();
+            if let Some(known) = duties.proposers.get(&epoch) {
+                let dependent_root = duties
+                    .attesters
+                    .get(&(epoch + 1))
+                    .map(|attesters| &attesters.dependent_root);
+                if dependent_root.is_none_or(|root| *root == known.dependent_root) {
+                    return Ok(());
+                }
+                info!(epoch, "Proposer duties changed by a reorg");
+            }
+        }
+
+        let response: DutiesResponse<ProposerDuty> = self
+            .beacon_nodes
+            .get(&format!("eth/v1/validator/duties/proposer/{epoch}"))
+            .await?;
+        let mut duties = self.duties.write();
+        let ours: Vec<ProposerDuty> = response
+            .data
+            .into_iter()
+            .filter(|duty| duties.indices.get(&duty.pubkey) == Some(&duty.validator_index))
+            .collect();
+        duties.proposers.insert(
+            epoch,
+            EpochDuties {
+                dependent_root: response.dependent_root,
+                duties: ours,
+            },
+        );
+        Ok(())
+    }
+
+    /// Fetches the attesters of the epoch if they are unknown or their dependent root changed,
+    /// which is checked by requesting the duties of a single validator.
+    async fn poll_attesters(&self, epoch: u64, indices: &[u64]) -> Result<(), String> {
+        let path = format!("eth/v1/validator/duties/attester/{epoch}");
+        let known_root = self
+            .duties
+            .read()
+            .attesters
+            .get(&epoch)
+            .map(|epoch| epoch.dependent_root.clone());
+        if let Some(known_root) = &known_root {
+            let response: DutiesResponse<AttesterDuty> = self
+                .beacon_nodes
+                .post(&path, &quoted(&indices[..1]))
+                .await?;
+            if response.dependent_root == *known_root {
+                return Ok(());
+            }
+            info!(epoch, "Attester duties changed by a reorg");
+        }
+
+        let response: DutiesResponse<AttesterDuty> =
+            self.beacon_nodes.post(&path, &quoted(indices)).await?;
+        debug!(
+            epoch,
+            duties = response.data.len(),
+            "Fetched attester duties"
+        );
+        self.duties.write().attesters.insert(
+            epoch,
+            EpochDuties {
+                dependent_root: response.dependent_root,
+                duties: response.data,
+            },
+        );
+        Ok(())
+    }
+
+    /// Fetches the sync committee duties of the current and next periods, if unknown.
+    async fn poll_sync_committees(&self, epoch: u64, indices: &[u64]) -> Result<(), String> {
+        let period = epoch / self.spec.epochs_per_sync_committee_period;
+        for period in [period, period + 1] {
+            if self.duties.read().sync_committees.contains_key(&period) {
+                continue;
+            }
+            let first_epoch = period * self.spec.epochs_per_sync_committee_period;
+            let response: GenericResponse<Vec<SyncDuty>> = self
+                .beacon_nodes
+                .post(
+                    &format!("eth/v1/validator/duties/sync/{}", first_epoch.max(epoch)),
+                    &quoted(indices),
+                )
+                .await?;
+            debug!(
+                period,
+                duties = response.data.len(),
+                "Fetched sync committee duties"
+            );
+            self.duties
+                .write()
+                .sync_committees
+                .insert(period, response.data);
+        }
+        Ok(())
+    }
+
+    /// Forgets the duties of past epochs and periods.
+    fn prune(&self, epoch: u64) {
+        let period = epoch / self.spec.epochs_per_sync_committee_period;
+        let mut duties = self.duties.write();
+        duties
+            .attesters
+            .retain(|duty_epoch, _| *duty_epoch >= epoch);
+        duties
+            .proposers
+            .retain(|duty_epoch, _| *duty_epoch >= epoch);
+        duties
+            .sync_committees
+            .retain(|duty_period, _| *duty_period >= period);
+    }
+}
+
+impl<T: SlotClock> ScheduledDuties for DutiesService<T> {
+    fn has_duty(&self, validator_index: u64, slot: u64) -> bool {
+        let epoch = slot / self.spec.slots_per_epoch;
+        let period = epoch / self.spec.epochs_per_sync_committee_period;
+        let duties = self.duties.read();
+        let proposer = duties.proposers.get(&epoch).is_some_and(|epoch| {
+            epoch
+                .duties
+                .iter()
+                .any(|duty| duty.validator_index == validator_index && duty.slot == slot)
+        });
+        let attester = duties.attesters.get(&epoch).is_some_and(|epoch| {
+            epoch
+                .duties
+                .iter()
+                .any(|duty| duty.validator_index == validator_index && duty.slot == slot)
+        });
+        let sync_committee = duties.sync_committees.get(&period).is_some_and(|duties| {
+            duties
+                .iter()
+                .any(|duty| duty.validator_index == validator_index)
+        });
+        proposer || attester || sync_committee
+    }
+
+    fn has_committee_duty(&self, committee_id: &CommitteeId, slot: u64) -> bool {
+        let epoch = slot / self.spec.slots_per_epoch;
+        let duties = self.duties.read();
+        duties.attesters.get(&epoch).is_some_and(|epoch| {
+            epoch.duties.iter().any(|duty| {
+                duty.slot == slot && duties.committees.get(&duty.pubkey) == Some(committee_id)
+            })
+        })
+    }
+}
+
+/// The validator indices as the decimal strings of the beacon API.
+fn quoted(indices: &[u64]) -> Vec<String> {
+    indices.iter().map(u64::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon_node::mock::MockBeaconNode;
+    use crate::beacon_node::DEFAULT_REQUEST_TIMEOUT;
+    use crate::test_utils::SPEC;
+    use serde_json::Value;
+    use slot_clock::ManualSlotClock;
+    use ssv_types::{Address, Operator, Share, Validator};
+    use std::sync::LazyLock;
+    use tokio::sync::mpsc::{self, Receiver};
+
+    /// Generating RSA keys is slow, so the tests share a key.
+    static OPERATOR_KEY: LazyLock<Arc<OperatorKey>> =
+        LazyLock::new(|| Arc::new(OperatorKey::generate().unwrap()));
+
+    /// Our validators, with indices 7 and 9, and a validator we do not operate, with index 8.
+    const OURS: [[u8; 48]; 2] = [[1; 48], [3; 48]];
+    const OTHER: [u8; 48] = [2; 48];
+
+    fn pubkey(public_key: &[u8]) -> String {
+        format!("0x{}", hex::encode(public_key))
+    }
+
+    fn root(byte: u8) -> String {
+        pubkey(&[byte; 32])
+    }
+
+    /// Our operator 1 operates our validators, while operator 2 only operates the other one.
+    fn database() -> Arc<Database> {
+        let database = Database::open_in_memory().unwrap();
+        let our_public_key = OPERATOR_KEY.public_key_base64().unwrap().into_bytes();
+        database
+            .write(|tx| {
+                for (id, public_key) in [(1, our_public_key.clone()), (2, vec![])] {
+                    tx.insert_operator(&Operator {
+                        id: OperatorId(id),
+                        owner: Address([1; 20]),
+                        public_key,
+                        fee: 0,
+                    })?;
+                }
+                for (public_key, operator_id) in [(OURS[0], 1), (OURS[1], 1), (OTHER, 2)] {
+                    let operator_id = OperatorId(operator_id);
+                    tx.insert_validator(
+                        &Validator {
+                            public_key: public_key.to_vec(),
+                            owner: Address([1; 20]),
+                            operator_ids: vec![operator_id],
+                        },
+                        &[Share {
+                            validator_public_key: public_key.to_vec(),
+                            operator_id,
+                            public_key: vec![0xaa; 48],
+                            encrypted_key: vec![0xee; 256],
+                        }],
+                    )?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        Arc::new(database)
+    }
+
+    fn attesters(dependent_root: u8, slot: u64) -> Value {
+        json!({
+            "dependent_root": root(dependent_root),
+            "execution_optimistic": false,
+            "data": [{
+                "pubkey": pubkey(&OURS[0]),
+                "validator_index": "7",
+                "committee_index": "3",
+                "committee_length": "128",
+                "committees_at_slot": "64",
+                "validator_committee_index": "12",
+                "slot": slot.to_string(),
+            }]
+        })
+    }
+
+    async fn mock_beacon_node() -> (Arc<MockBeaconNode>, Arc<BeaconNodes>) {
+        let node = MockBeaconNode::new();
+        let validators = [(OURS[0], 7), (OURS[1], 9)].map(|(public_key, index)| {
+            json!({
+                "index": index.to_string(),
+                "balance": "32000000000",
+                "status": "active_ongoing",
+                "validator": { "pubkey": pubkey(&public_key) },
+            })
+        });
+        node.set_response(
+            "/eth/v1/beacon/states/head/validators",
+            json!({ "execution_optimistic": false, "data": validators }),
+        );
+        node.set_response(
+            "/eth/v1/validator/duties/proposer/1",
+            json!({
+                "dependent_root": root(0x11),
+                "execution_optimistic": false,
+                "data": [
+                    { "pubkey": pubkey(&OURS[1]), "validator_index": "9", "slot": "33" },
+                    { "pubkey": pubkey(&OTHER), "validator_index": "8", "slot": "34" },
+                ]
+            }),
+        );
+        // The proposers of epoch 1 depend on the same block as the attesters of epoch 2.
+        node.set_response("/eth/v1/validator/duties/attester/1", attesters(0x10, 33));
+        node.set_response("/eth/v1/validator/duties/attester/2", attesters(0x11, 70));
+        let sync_duties = json!({
+            "execution_optimistic": false,
+            "data": [{
+                "pubkey": pubkey(&OURS[1]),
+                "validator_index": "9",
+                "validator_sync_committee_indices": ["5", "300"],
+            }]
+        });
+        node.set_response("/eth/v1/validator/duties/sync/1", sync_duties.clone());
+        node.set_response("/eth/v1/validator/duties/sync/256", sync_duties);
+
+        let url = node.spawn().await;
+        let beacon_nodes =
+            BeaconNodes::new(vec![url], &[], false, DEFAULT_REQUEST_TIMEOUT).unwrap();
+        (node, Arc::new(beacon_nodes))
+    }
+
+    fn duties_service(
+        beacon_nodes: Arc<BeaconNodes>,
+    ) -> (
+        DutiesService<ManualSlotClock>,
+        ManualSlotClock,
+        Receiver<DutyEvent>,
+        Receiver<NetworkCommand>,
+    ) {
+        let slot_clock = ManualSlotClock::new(
+            Slot::new(0),
+            Duration::from_secs(1_606_824_023),
+            Duration::from_secs(SPEC.seconds_per_slot),
+        );
+        let (sender, receiver) = mpsc::channel(DUTY_CHANNEL_SIZE);
+        let (network_commands, network_receiver) = mpsc::channel(16);
+        let service = DutiesService::new(
+            beacon_nodes,
+            database(),
+            OPERATOR_KEY.clone(),
+            slot_clock.clone(),
+            SPEC,
+            sender,
+            network_commands,
+        );
+        (service, slot_clock, receiver, network_receiver)
+    }
+
+    fn kinds(duties: &[Duty]) -> Vec<&'static str> {
+        duties
+            .iter()
+            .map(|duty| match duty {
+                Duty::Proposer(_) => "proposer",
+                Duty::Aggregator(_) => "aggregator",
+                Duty::Attester(_) => "attester",
+                Duty::SyncCommittee(_) => "sync_committee",
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn duties_of_our_validators_are_fetched() {
+        let (node, beacon_nodes) = mock_beacon_node().await;
+        let (service, _, _, _) = duties_service(beacon_nodes);
+        service.poll(Slot::new(33)).await.unwrap();
+
+        assert_eq!(
+            node.posted("/eth/v1/beacon/states/head/validators"),
+            vec![json!({ "ids": [pubkey(&OURS[0]), pubkey(&OURS[1])] })]
+        );
+        assert_eq!(
+            node.posted("/eth/v1/validator/duties/attester/1"),
+            vec![json!(["7", "9"])]
+        );
+        assert_eq!(
+            kinds(&service.duties_at(Slot::new(33))),
+            vec!["proposer", "aggregator", "attester", "sync_committee"]
+        );
+        // The proposal of the validator we do not operate is ignored.
+        assert_eq!(
+            kinds(&service.duties_at(Slot::new(34))),
+            vec!["sync_committee"]
+        );
+        assert_eq!(service.proposer_count(1), 1);
+        assert_eq!(service.attester_count(1), 1);
+        assert_eq!(service.attester_count(2), 1);
+        let Duty::SyncCommittee(duty) = &service.duties_at(Slot::new(34))[0] else {
+            panic!("Expected a sync committee duty");
+        };
+        assert_eq!(duty.validator_sync_committee_indices, vec![5, 300]);
+
+        assert!(service.has_duty(7, 33));
+        assert!(!service.has_duty(7, 34));
+        // Validator 9 is in the sync committee for the whole period.
+        assert!(service.has_duty(9, 34));
+        assert!(!service.has_duty(8, 34));
+        let ours = CommitteeId::from_operators(&[OperatorId(1)]);
+        assert!(service.has_committee_duty(&ours, 33));
+        assert!(!service.has_committee_duty(&ours, 34));
+        let other = CommitteeId::from_operators(&[OperatorId(2)]);
+        assert!(!service.has_committee_duty(&other, 33));
+    }
+
+    #[tokio::test]
+    async fn duties_are_fetched_again_when_the_dependent_root_changes() {
+        let (node, beacon_nodes) = mock_beacon_node().await;
+        let (service, _, _, _) = duties_service(beacon_nodes);
+        service.poll(Slot::new(33)).await.unwrap();
+
+        // Only the dependent root is checked while it does not change.
+        service.poll(Slot::new(34)).await.unwrap();
+        assert_eq!(
+            node.posted("/eth/v1/validator/duties/attester/1"),
+            vec![json!(["7", "9"]), json!(["7"])]
+        );
+
+        node.set_response("/eth/v1/validator/duties/attester/1", attesters(0x21, 35));
+        service.poll(Slot::new(35)).await.unwrap();
+        assert_eq!(
+            node.posted("/eth/v1/validator/duties/attester/1"),
+            vec![
+                json!(["7", "9"]),
+                json!(["7"]),
+                json!(["7"]),
+                json!(["7", "9"])
+            ]
+        );
+        assert_eq!(
+            kinds(&service.duties_at(Slot::new(35))),
+            vec!["aggregator", "attester", "sync_committee"]
+        );
+        assert_eq!(
+            kinds(&service.duties_at(Slot::new(33))),
+            vec!["proposer", "sync_committee"]
+        );
+        // The validators are only looked up once per epoch.
+        assert_eq!(
+            node.posted("/eth/v1/beacon/states/head/validators").len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn proposers_are_fetched_again_when_the_dependent_root_changes() {
+        let (node, beacon_nodes) = mock_beacon_node().await;
+        let (service, _, _, _) = duties_service(beacon_nodes);
+        let proposer_requests = || {
+            node.requests()
+                .iter()
+                .filter(|path| *path == "/eth/v1/validator/duties/proposer/1")
+                .count()
+        };
+        service.poll(Slot::new(33)).await.unwrap();
+        service.poll(Slot::new(34)).await.unwrap();
+        assert_eq!(proposer_requests(), 1);
+
+        // A reorg changes the block the proposers of epoch 1 and the attesters of epoch 2
+        // depend on.
+        node.set_response(
+            "/eth/v1/validator/duties/proposer/1",
+            json!({
+                "dependent_root": root(0x22),
+                "execution_optimistic": false,
+                "data": [{ "pubkey": pubkey(&OURS[1]), "validator_index": "9", "slot": "35" }]
+            }),
+        );
+        node.set_response("/eth/v1/validator/duties/attester/2", attesters(0x22, 70));
+        service.poll(Slot::new(35)).await.unwrap();
+        assert_eq!(proposer_requests(), 2);
+        assert_eq!(
+            kinds(&service.duties_at(Slot::new(35))),
+            vec!["proposer", "sync_committee"]
+        );
+        service.poll(Slot::new(36)).await.unwrap();
+        assert_eq!(proposer_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn duties_are_emitted_when_due() {
+        let (_node, beacon_nodes) = mock_beacon_node().await;
+        let (service, slot_clock, mut receiver, _) = duties_service(beacon_nodes);
+        service.poll(Slot::new(33)).await.unwrap();
+
+        // A third into the slot, every duty of the slot is due.
+        slot_clock.set_slot(33);
+        slot_clock.advance_time(slot_clock.unagg_attestation_production_delay());
+        service.emit(Slot::new(33)).await;
+        let mut events = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            assert_eq!(event.slot, Slot::new(33));
+            events.push(event.duty);
+        }
+        assert_eq!(
+            kinds(&events),
+            vec!["proposer", "aggregator", "attester", "sync_committee"]
+        );
+    }
+
+    #[tokio::test]
+    async fn no_duties_before_our_operator_is_registered() {
+        let (node, beacon_nodes) = mock_beacon_node().await;
+        let (mut service, _, _, mut network_commands) = duties_service(beacon_nodes);
+        service.database = Arc::new(Database::open_in_memory().unwrap());
+        service.poll(Slot::new(33)).await.unwrap();
+        assert!(service.duties_at(Slot::new(33)).is_empty());
+        assert!(node.requests().is_empty());
+        assert!(network_commands.try_recv().is_err());
+    }
+
+    fn subscribed_committees(network_commands: &mut Receiver<NetworkCommand>) -> Vec<CommitteeId> {
+        match network_commands.try_recv() {
+            Ok(NetworkCommand::UpdateCommittees(committees)) => committees,
+            command => panic!("Expected a subscription, got {command:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn network_is_subscribed_to_the_committees_of_our_shares() {
+        let (_node, beacon_nodes) = mock_beacon_node().await;
+        let (service, _, _, mut network_commands) = duties_service(beacon_nodes);
+        service.poll(Slot::new(33)).await.unwrap();
+        let ours = CommitteeId::from_operators(&[OperatorId(1)]);
+        assert_eq!(subscribed_committees(&mut network_commands), vec![ours]);
+
+        // Nothing is sent while the committees do not change.
+        service.poll(Slot::new(34)).await.unwrap();
+        assert!(network_commands.try_recv().is_err());
+
+        // A share of a validator of another committee is added to the registry.
+        let operator_ids = vec![OperatorId(1), OperatorId(2)];
+        service
+            .database
+            .write(|tx| {
+                tx.insert_validator(
+                    &Validator {
+                        public_key: vec![4; 48],
+                        owner: Address([1; 20]),
+                        operator_ids: operator_ids.clone(),
+                    },
+                    &[Share {
+                        validator_public_key: vec![4; 48],
+                        operator_id: OperatorId(1),
+                        public_key: vec![0xaa; 48],
+                        encrypted_key: vec![0xee; 256],
+                    }],
+                )
+            })
+            .unwrap();
+        service.poll(Slot::new(35)).await.unwrap();
+        let mut expected = vec![ours, CommitteeId::from_operators(&operator_ids)];
+        expected.sort();
+        assert_eq!(subscribed_committees(&mut network_commands), expected);
+    }
+}
@@ -0,0 +1,404 @@
+//! Performs the duties emitted by the duties service with the other operators of each validator.
+//!
+//! Our key share of a validator is decrypted on its first duty and kept with the public keys of
+//! its committee, until the shares registered for the validator change.
+
+use crate::attestation_runner::{AttestationDuty, AttestationRunner};
+use crate::beacon_node::{BeaconNodes, ChainSpec};
+use crate::duties_service::{Duty, DutyEvent};
+use crate::pre_consensus::{PreConsensus, PreConsensusDuty, PreConsensusKind};
+use crate::signing::SigningContext;
+use blst::min_pk::SecretKey;
+use database::Database;
+use operator_key::OperatorKey;
+use parking_lot::Mutex;
+use signature_collector::ValidatorCommittee;
+use slot_clock::SlotClock;
+use ssv_types::{OperatorId, Share};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Our key share of a validator, with the registered shares it was decrypted from.
+struct ValidatorShare {
+    shares: Vec<Share>,
+    share: SecretKey,
+    committee: Arc<ValidatorCommittee>,
+}
+
+pub struct DutyRunner<T> {
+    beacon_nodes: Arc<BeaconNodes>,
+    database: Arc<Database>,
+    operator_key: Arc<OperatorKey>,
+    operator_id: OperatorId,
+    spec: ChainSpec,
+    attestations: Arc<AttestationRunner<T>>,
+    pre_consensus: Arc<PreConsensus<T>>,
+    /// Our key share of each validator, by public key.
+    shares: Mutex<HashMap<Vec<u8>, ValidatorShare>>,
+    /// The signing context of the epoch of the last duty, which only changes at forks.
+    context: Mutex<Option<(u64, SigningContext)>>,
+}
+
+impl<T: SlotClock + 'static> DutyRunner<T> {
+    pub fn new(
+        beacon_nodes: Arc<BeaconNodes>,
+        database: Arc<Database>,
+        operator_key: Arc<OperatorKey>,
+        operator_id: OperatorId,
+        spec: ChainSpec,
+        attestations: Arc<AttestationRunner<T>>,
+        pre_consensus: Arc<PreConsensus<T>>,
+    ) -> Self {
+        Self {
+            beacon_nodes,
+            database,
+            operator_key,
+            operator_id,
+            spec,
+            attestations,
+            pre_consensus,
+            shares: Mutex::new(HashMap::new()),
+            context: Mutex::new(None),
+        }
+    }
+
+    /// Performs the duties as they are received, each concurrently with the others.
+    pub async fn run(self: Arc<Self>, mut events: mpsc::Receiver<DutyEvent>) {
+        while let Some(event) = events.recv().await {
+            let runner = self.clone();
+            tokio::spawn(async move {
+                if let Err(error) = runner.perform(&event).await {
+                    warn!(slot = %event.slot, duty = ?event.duty, error, "Unable to perform duty");
+                }
+            });
+        }
+    }
+
+    /// Performs a duty with the other operators of its validator.
+    pub async fn perform(&self, event: &DutyEvent) -> Result<(), String> {
+        let slot = event.slot.as_u64();
+        match &event.duty {
+            Duty::Attester(duty) => {
+                let (share, committee) = self.share(&duty.pubkey)?;
+                let context = self.context(slot).await?;
+                let duty = AttestationDuty {
+                    duty: duty.clone(),
+                    share,
+                    committee,
+                };
+                self.attestations
+                    .attest(&self.beacon_nodes, &duty, &context)
+                    .await?;
+                Ok(())
+            }
+            Duty::Aggregator(duty) => {
+                let (share, committee) = self.share(&duty.pubkey)?;
+                let context = self.context(slot).await?;
+                let duty = PreConsensusDuty {
+                    kind: PreConsensusKind::SelectionProof {
+                        committee_length: duty.committee_length,
+                    },
+                    slot,
+                    validator_index: duty.validator_index,
+                    share,
+                    committee,
+                };
+                let selection_proof = self
+                    .pre_consensus
+                    .aggregator_selection(&duty, &context)
+                    .await?;
+                if selection_proof.is_some() {
+                    info!(
+                        slot,
+                        validator_index = duty.validator_index,
+                        "Selected to aggregate attestations, which is not supported yet"
+                    );
+                }
+                Ok(())
+            }
+            Duty::Proposer(duty) => {
+                // The RANDAO reveal is not signed for a block we could not propose.
+                debug!(
+                    slot,
+                    validator_index = duty.validator_index,
+                    "Block proposals are not supported yet"
+                );
+                Ok(())
+            }
+            Duty::SyncCommittee(duty) => {
+                debug!(
+                    slot,
+                    validator_index = duty.validator_index,
+                    "Sync committee duties are not supported yet"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Our key share of a validator and the public keys of its committee. The share is only
+    /// decrypted again if the shares registered for the validator changed.
+    fn share(
+        &self,
+        validator_public_key: &[u8],
+    ) -> Result<(SecretKey, Arc<ValidatorCommittee>), String> {
+        let validator = self
+            .database
+            .validator(validator_public_key)
+            .map_err(|e| format!("Unable to read the validator: {e}"))?
+            .ok_or("Unknown validator")?;
+        let mut shares = vec![];
+        for operator_id in &validator.operator_ids {
+            let share = self
+                .database
+                .share(validator_public_key, *operator_id)
+                .map_err(|e| format!("Unable to read the shares of the validator: {e}"))?;
+            shares.extend(share);
+        }
+        if let Some(cached) = self
+            .shares
+            .lock()
+            .get(validator_public_key)
+            .filter(|cached| cached.shares == shares)
+        {
+            return Ok((cached.share.clone(), cached.committee.clone()));
+        }
+
+        let ours = shares
+            .iter()
+            .find(|share| share.operator_id == self.operator_id)
+            .ok_or("We are not an operator of the validator")?;
+        let share = self.operator_key.decrypt_share(ours)?;
+        let committee = Arc::new(ValidatorCommittee::from_shares(
+            validator_public_key,
+            &shares,
+        )?);
+        self.shares.lock().insert(
+            validator_public_key.to_vec(),
+            ValidatorShare {
+                shares,
+                share: share.clone(),
+                committee: committee.clone(),
+            },
+        );
+        Ok((share, committee))
+    }
+
+    /// The signing context of the duties of `slot`, requested once per epoch.
+    async fn context(&self, slot: u64) -> Result<SigningContext, String> {
+        let epoch = slot / self.spec.slots_per_epoch;
+        if let Some((context_epoch, context)) = &*self.context.lock() {
+            if *context_epoch == epoch {
+                return Ok(context.clone());
+            }
+        }
+        let context = SigningContext::fetch(&self.beacon_nodes).await?;
+        *self.context.lock() = Some((epoch, context.clone()));
+        Ok(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon_node::DEFAULT_REQUEST_TIMEOUT;
+    use crate::duties_service::{AttesterDuty, ProposerDuty};
+    use crate::partial_signer::PartialSigner;
+    use crate::pre_consensus::OutboundPartialSignatures;
+    use crate::test_utils::{context, AllDuties, SPEC};
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
+    use sensitive_url::SensitiveUrl;
+    use signature_collector::split_secret_key;
+    use slashing_protection::SlashingDatabase;
+    use slot_clock::{ManualSlotClock, Slot};
+    use ssv_types::{Address, PartialSignatureKind, Role, Validator};
+    use std::time::Duration;
+
+    const OPERATOR_ID: OperatorId = OperatorId(2);
+
+    /// A runner of operator 2, with the receiver of the partial signatures it broadcasts.
+    fn runner(
+        database: Arc<Database>,
+        operator_key: Arc<OperatorKey>,
+    ) -> (
+        DutyRunner<ManualSlotClock>,
+        mpsc::Receiver<OutboundPartialSignatures>,
+    ) {
+        let slot_clock =
+            ManualSlotClock::new(Slot::new(0), Duration::ZERO, Duration::from_secs(12));
+        let signer = Arc::new(PartialSigner::new(
+            OPERATOR_ID,
+            Arc::new(SlashingDatabase::open_in_memory().unwrap()),
+        ));
+        let (consensus_outbound, _) = mpsc::channel(1);
+        let (partial_signatures_outbound, partial_signatures) = mpsc::channel(1);
+        let beacon_nodes = BeaconNodes::new(
+            vec![SensitiveUrl::parse("http://localhost:5052").unwrap()],
+            &[],
+            false,
+            DEFAULT_REQUEST_TIMEOUT,
+        )
+        .unwrap();
+        let runner = DutyRunner::new(
+            Arc::new(beacon_nodes),
+            database,
+            operator_key,
+            OPERATOR_ID,
+            SPEC,
+            Arc::new(AttestationRunner::new(
+                signer.clone(),
+                slot_clock.clone(),
+                SPEC,
+                consensus_outbound,
+                partial_signatures_outbound.clone(),
+                Arc::new(AllDuties),
+            )),
+            Arc::new(PreConsensus::new(
+                signer,
+                slot_clock,
+                SPEC,
+                partial_signatures_outbound,
+                Arc::new(AllDuties),
+            )),
+        );
+        (runner, partial_signatures)
+    }
+
+    /// Registers a validator shared with operators 1 to 4, encrypting the share of each operator
+    /// with `operator_key`. Returns the shares.
+    fn register_validator(
+        database: &Database,
+        operator_key: &OperatorKey,
+        public_key: &[u8],
+        secret_key: &SecretKey,
+    ) -> Vec<SecretKey> {
+        let operator_ids: Vec<OperatorId> = (1..=4).map(OperatorId).collect();
+        let shares = split_secret_key(secret_key, &operator_ids, 3).unwrap();
+        let rsa_key = RsaPrivateKey::from_pkcs1_pem(
+            std::str::from_utf8(&operator_key.to_pem().unwrap()).unwrap(),
+        )
+        .unwrap()
+        .to_public_key();
+        let registered: Vec<Share> = operator_ids
+            .iter()
+            .zip(&shares)
+            .map(|(operator_id, share)| Share {
+                validator_public_key: public_key.to_vec(),
+                operator_id: *operator_id,
+                public_key: share.sk_to_pk().to_bytes().to_vec(),
+                encrypted_key: rsa_key
+                    .encrypt(
+                        &mut rand::thread_rng(),
+                        Pkcs1v15Encrypt,
+                        hex::encode(share.to_bytes()).as_bytes(),
+                    )
+                    .unwrap(),
+            })
+            .collect();
+        let validator = Validator {
+            public_key: public_key.to_vec(),
+            owner: Address::default(),
+            operator_ids,
+        };
+        database
+            .write(|registry| registry.insert_validator(&validator, &registered))
+            .unwrap();
+        shares
+    }
+
+    #[test]
+    fn our_shares_are_decrypted_and_kept() {
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let operator_key = Arc::new(OperatorKey::generate().unwrap());
+        let (runner, _) = runner(database.clone(), operator_key.clone());
+        let secret_key = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let public_key = secret_key.sk_to_pk().to_bytes();
+        let shares = register_validator(&database, &operator_key, &public_key, &secret_key);
+
+        let (share, committee) = runner.share(&public_key).unwrap();
+        assert_eq!(share.to_bytes(), shares[1].to_bytes());
+        assert_eq!(committee.validator_public_key, secret_key.sk_to_pk());
+        assert_eq!(committee.share_public_keys.len(), 4);
+        assert_eq!(runner.shares.lock().len(), 1);
+
+        // The validator is registered again with other shares.
+        let other_shares = register_validator(&database, &operator_key, &public_key, &secret_key);
+        assert_ne!(other_shares[1].to_bytes(), shares[1].to_bytes());
+        assert_eq!(
+            runner.share(&public_key).unwrap().0.to_bytes(),
+            other_shares[1].to_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn aggregator_duties_broadcast_our_selection_proof() {
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let operator_key = Arc::new(OperatorKey::generate().unwrap());
+        let (runner, mut partial_signatures) = runner(database.clone(), operator_key.clone());
+        let secret_key = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let public_key = secret_key.sk_to_pk().to_bytes();
+        register_validator(&database, &operator_key, &public_key, &secret_key);
+        *runner.context.lock() = Some((0, context()));
+
+        let event = DutyEvent {
+            slot: Slot::new(1),
+            duty: Duty::Aggregator(AttesterDuty {
+                pubkey: public_key.to_vec(),
+                validator_index: 9,
+                committee_index: 3,
+                committee_length: 128,
+                committees_at_slot: 64,
+                validator_committee_index: 12,
+                slot: 1,
+            }),
+        };
+        // The other operators do not sign, so the duty only ends once the selection is due.
+        let runner = Arc::new(runner);
+        let task = tokio::spawn(async move { runner.perform(&event).await });
+        let outbound = partial_signatures.recv().await.unwrap();
+        assert_eq!(outbound.role, Role::Aggregator);
+        assert_eq!(
+            outbound.messages.kind,
+            PartialSignatureKind::SelectionProofPartialSig as u64
+        );
+        assert_eq!(outbound.messages.slot, 1);
+        assert_eq!(outbound.messages.messages[0].signer, OPERATOR_ID);
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn proposer_duties_are_skipped() {
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let operator_key = Arc::new(OperatorKey::generate().unwrap());
+        let (runner, _) = runner(database, operator_key);
+        // The validator is not registered and the beacon node is not running, so the duty would
+        // fail if it was performed.
+        let event = DutyEvent {
+            slot: Slot::new(33),
+            duty: Duty::Proposer(ProposerDuty {
+                pubkey: vec![1; 48],
+                validator_index: 9,
+                slot: 33,
+            }),
+        };
+        assert_eq!(runner.perform(&event).await, Ok(()));
+    }
+
+    #[test]
+    fn duties_of_other_validators_are_refused() {
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let operator_key = Arc::new(OperatorKey::generate().unwrap());
+        let (runner, _) = runner(database.clone(), operator_key);
+        let secret_key = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let public_key = secret_key.sk_to_pk().to_bytes();
+        assert!(runner.share(&public_key).is_err());
+
+        // A share encrypted for another operator key can not be decrypted.
+        let other_key = OperatorKey::generate().unwrap();
+        register_validator(&database, &other_key, &public_key, &secret_key);
+        assert!(runner.share(&public_key).is_err());
+    }
+}
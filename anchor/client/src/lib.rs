@@ -1,17 +1,56 @@
 // use tracing::{debug, info};
 
+pub mod attestation_runner;
+pub mod beacon_node;
 mod cli;
 pub mod config;
+pub mod duties_service;
+pub mod duty_runner;
+pub mod message_router;
+pub mod partial_signer;
+pub mod pre_consensus;
+pub mod signature_rounds;
+pub mod signing;
+#[cfg(test)]
+mod test_utils;
+pub mod validation_context;
 
-pub use cli::Anchor;
+use attestation_runner::AttestationRunner;
+use beacon_node::{
+    BeaconNodes, ChainSpec, GenesisData, DEFAULT_REQUEST_TIMEOUT, HEALTH_CHECK_INTERVAL,
+};
+pub use cli::{
+    Anchor, AnchorSubcommand, NetworkKeyCommand, OperatorKeyCommand, SlashingProtectionCommand,
+};
 use config::Config;
-use network::Network;
+use database::{Database, DATABASE_FILENAME};
+use duties_service::{DutiesService, DUTY_CHANNEL_SIZE};
+use duty_runner::DutyRunner;
+use execution::ExecutionService;
+use message_router::{Justifications, MessageRouter, Publisher};
+use network::{Network, Registry};
+use operator_key::{generate_operator_key, load_operator_key, OperatorKey};
 use parking_lot::RwLock;
+use partial_signer::PartialSigner;
+use pre_consensus::{PreConsensus, OUTBOUND_CHANNEL_SIZE};
+use processor::Processor;
+use slashing_protection::interchange::Interchange;
+use slashing_protection::{SlashingDatabase, SLASHING_PROTECTION_FILENAME};
+use slot_clock::{Slot, SlotClock, SystemTimeSlotClock};
+use ssv_types::OperatorId;
+use std::fs;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
-use task_executor::TaskExecutor;
+use std::time::Duration;
+use task_executor::{ShutdownReason, TaskExecutor};
 use tokio::net::TcpListener;
-use tracing::{debug, error, info};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+use validation_context::RegistryValidationContext;
+
+/// The interval at which the registry is checked for our operator until it is registered.
+const OPERATOR_POLL_INTERVAL: Duration = Duration::from_secs(12);
 
 pub struct Client {}
 
@@ -42,14 +81,104 @@ impl Client {
             "Starting the Anchor client"
         );
 
+        // Create the client of the beacon nodes and keep checking their health.
+        let beacon_nodes = Arc::new(BeaconNodes::new(
+            config.beacon_nodes.clone(),
+            config.beacon_nodes_tls_certs.as_deref().unwrap_or_default(),
+            config.allow_unsynced_beacon_node,
+            DEFAULT_REQUEST_TIMEOUT,
+        )?);
+        executor.spawn(
+            {
+                let beacon_nodes = beacon_nodes.clone();
+                async move { beacon_nodes.run_health_checks().await }
+            },
+            "beacon_node_health",
+        );
+
+        // The registry for the libp2p, network and processor metrics, which is only needed if the
+        // metrics server is enabled.
+        let mut libp2p_registry = config.http_metrics.enabled.then(Registry::default);
+
+        // Load the operator key, which decrypts our key shares.
+        let operator_key = Arc::new(load_operator_key(
+            &config.operator_keystore,
+            &config.operator_password_file,
+        )?);
+
+        // Open the database, holding the SSV registry synced from the contract events.
+        let database_path = config.data_dir.join(DATABASE_FILENAME);
+        let database = Arc::new(
+            Database::open(&database_path)
+                .map_err(|e| format!("Unable to open the database {database_path:?}: {e}"))?,
+        );
+        let execution_service = ExecutionService::new(
+            config.execution_nodes.clone(),
+            config.execution.clone(),
+            database.clone(),
+            Some(operator_key.clone()),
+        )?;
+        executor.spawn(execution_service.run(), "execution");
+
+        // Open the slashing protection database of our validators.
+        let slashing_protection = Arc::new(open_slashing_protection(&config.data_dir)?);
+
+        // Wait for the genesis of the chain, which the duties are timed from.
+        let (genesis, spec) = wait_for_genesis(&beacon_nodes).await;
+        let slot_clock = SystemTimeSlotClock::new(
+            Slot::new(0),
+            Duration::from_secs(genesis.genesis_time),
+            Duration::from_secs(spec.seconds_per_slot),
+        );
+        // Refuse to sign for the validators of another chain than the one protected.
+        let genesis_validators_root: [u8; 32] = genesis
+            .genesis_validators_root
+            .as_slice()
+            .try_into()
+            .map_err(|_| "Invalid genesis validators root".to_string())?;
+        slashing_protection
+            .check_genesis_validators_root(&genesis_validators_root)
+            .map_err(|e| e.to_string())?;
+
+        // Process the CPU intensive work in priority order, registering the queue metrics.
+        let (processor_sender, processor) = Processor::new(
+            config.processor.clone(),
+            slot_clock.clone(),
+            libp2p_registry.as_mut(),
+        );
+        executor.spawn(processor.run(), "processor");
+
+        // Build the p2p network, registering its metrics. Gossip messages are validated by the
+        // processor against the committees and operator keys of the registry, kept up to date at
+        // every slot.
+        let validation_context = Arc::new(RegistryValidationContext::new(
+            database.clone(),
+            slot_clock.clone(),
+        )?);
+        executor.spawn(validation_context.clone().run(), "validation_context");
+        let (network, network_messages) = Network::try_new(
+            &config.network,
+            validation_context,
+            processor_sender.clone(),
+            libp2p_registry.as_mut(),
+            executor.clone(),
+        )
+        .await?;
+
         // Optionally start the metrics server.
         let _http_metrics_shared_state = if config.http_metrics.enabled {
-            let shared_state = Arc::new(RwLock::new(http_metrics::Shared { genesis_time: None }));
+            let shared_state = Arc::new(RwLock::new(http_metrics::Shared {
+                genesis_time: Some(genesis.genesis_time),
+                libp2p_registry,
+            }));
 
             let exit = executor.exit();
 
             // Attempt to bind to the socket
-            let socket = SocketAddr::new(config.http_api.listen_addr, config.http_api.listen_port);
+            let socket = SocketAddr::new(
+                config.http_metrics.listen_addr,
+                config.http_metrics.listen_port,
+            );
             let listener = TcpListener::bind(socket)
                 .await
                 .map_err(|e| format!("Unable to bind to metrics server port: {}", e))?;
@@ -63,17 +192,205 @@ impl Client {
             None
         };
 
-        // Optionally run the http_api server
-        if let Err(error) = http_api::run(config.http_api).await {
-            error!(error, "Failed to run HTTP API");
-            return Err("HTTP API Failed".to_string());
-        }
-
-        // Start the p2p network
-        let network = Network::try_new(&config.network, executor.clone()).await?;
         // Spawn the network listening task
+        let network_info = network.info();
+        let network_commands = network.command_sender();
         executor.spawn(network.run(), "network");
 
+        // Optionally run the http_api server, shutting down if it fails.
+        executor.spawn(
+            {
+                let http_api_config = config.http_api.clone();
+                let network_commands = network_commands.clone();
+                let shutdown_executor = executor.clone();
+                async move {
+                    if let Err(error) =
+                        http_api::run(http_api_config, network_info, network_commands).await
+                    {
+                        error!(error, "Failed to run HTTP API");
+                        let _ = shutdown_executor
+                            .shutdown_sender()
+                            .try_send(ShutdownReason::Failure("HTTP API Failed"));
+                    }
+                }
+            },
+            "http_api",
+        );
+
+        // Sign with the key shares of our operator once it is registered, refusing to sign what
+        // could get our validators slashed. The node joins the network meanwhile.
+        let operator_id = wait_for_operator(&database, &operator_key).await?;
+        let signer = Arc::new(PartialSigner::new(operator_id, slashing_protection));
+
+        // The duties of our validators, which the runners only keep the early messages of the
+        // other operators for. The network is subscribed to the subnets of their committees.
+        let (duty_sender, duty_events) = mpsc::channel(DUTY_CHANNEL_SIZE);
+        let duties_service = Arc::new(DutiesService::new(
+            beacon_nodes.clone(),
+            database.clone(),
+            operator_key.clone(),
+            slot_clock.clone(),
+            spec,
+            duty_sender,
+            network_commands.clone(),
+        ));
+        let (consensus_outbound, consensus_messages) = mpsc::channel(OUTBOUND_CHANNEL_SIZE);
+        let (partial_signatures_outbound, partial_signature_messages) =
+            mpsc::channel(OUTBOUND_CHANNEL_SIZE);
+        let attestation_runner = Arc::new(AttestationRunner::new(
+            signer.clone(),
+            slot_clock.clone(),
+            spec,
+            consensus_outbound,
+            partial_signatures_outbound.clone(),
+            duties_service.clone(),
+        ));
+        let pre_consensus = Arc::new(PreConsensus::new(
+            signer,
+            slot_clock,
+            spec,
+            partial_signatures_outbound,
+            duties_service.clone(),
+        ));
+
+        // Hand the messages of the other operators to the runners of our duties through the
+        // processor, and publish ours with the messages justifying them.
+        let justifications = Arc::new(Justifications::default());
+        let message_router = Arc::new(MessageRouter::new(
+            operator_id,
+            attestation_runner.clone(),
+            pre_consensus.clone(),
+            justifications.clone(),
+            processor_sender,
+        ));
+        executor.spawn(message_router.run(network_messages), "message_router");
+        let publisher = Publisher::new(
+            operator_key.clone(),
+            operator_id,
+            config.network.fork_schedule.clone(),
+            spec,
+            justifications,
+            network_commands,
+        );
+        executor.spawn(
+            publisher.run(consensus_messages, partial_signature_messages),
+            "publisher",
+        );
+
+        // Find the duties of our validators and perform them when they are due.
+        executor.spawn(duties_service.clone().run_updates(), "duties_updates");
+        executor.spawn(duties_service.run(), "duties");
+        let duty_runner = Arc::new(DutyRunner::new(
+            beacon_nodes,
+            database,
+            operator_key,
+            operator_id,
+            spec,
+            attestation_runner,
+            pre_consensus,
+        ));
+        executor.spawn(duty_runner.run(duty_events), "duty_runner");
+
         Ok(())
     }
 }
+
+/// Requests the genesis and the parameters of the chain until a beacon node answers.
+async fn wait_for_genesis(beacon_nodes: &BeaconNodes) -> (GenesisData, ChainSpec) {
+    loop {
+        match futures::future::try_join(beacon_nodes.genesis(), beacon_nodes.spec()).await {
+            Ok(chain) => return chain,
+            Err(error) => warn!(error, "Unable to fetch the genesis, retrying"),
+        }
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    }
+}
+
+/// Waits for our operator to be registered in the registry synced from the contract, returning
+/// its id.
+async fn wait_for_operator(
+    database: &Database,
+    operator_key: &OperatorKey,
+) -> Result<OperatorId, String> {
+    let public_key = operator_key.public_key_base64()?;
+    loop {
+        let operators = database
+            .operators()
+            .map_err(|e| format!("Unable to read the operators: {e}"))?;
+        if let Some(operator) = operators
+            .into_iter()
+            .find(|operator| operator_key.matches(&operator.public_key))
+        {
+            info!(operator_id = %operator.id, "Found our operator in the registry");
+            return Ok(operator.id);
+        }
+        info!(public_key, "Waiting for our operator to be registered");
+        tokio::time::sleep(OPERATOR_POLL_INTERVAL).await;
+    }
+}
+
+/// Runs a `slashing-protection` subcommand on the slashing protection database of the data
+/// directory.
+pub fn run_slashing_protection(
+    command: &SlashingProtectionCommand,
+    config: &Config,
+) -> Result<(), String> {
+    fs::create_dir_all(&config.data_dir)
+        .map_err(|e| format!("Unable to create the data directory: {e}"))?;
+    let slashing_protection = open_slashing_protection(&config.data_dir)?;
+    match command {
+        SlashingProtectionCommand::Import { file } => {
+            let json = fs::read(file).map_err(|e| format!("Unable to read {file:?}: {e}"))?;
+            let interchange: Interchange = serde_json::from_slice(&json)
+                .map_err(|e| format!("Invalid interchange file {file:?}: {e}"))?;
+            slashing_protection
+                .import_interchange(&interchange)
+                .map_err(|e| format!("Unable to import {file:?}: {e}"))?;
+            info!(
+                validators = interchange.data.len(),
+                ?file,
+                "Imported slashing protection interchange"
+            );
+        }
+        SlashingProtectionCommand::Export { file } => {
+            let interchange = slashing_protection
+                .export_interchange()
+                .map_err(|e| format!("Unable to export the slashing protection database: {e}"))?;
+            let json = serde_json::to_vec_pretty(&interchange)
+                .map_err(|e| format!("Unable to encode the interchange: {e}"))?;
+            fs::write(file, json).map_err(|e| format!("Unable to write {file:?}: {e}"))?;
+            info!(
+                validators = interchange.data.len(),
+                ?file,
+                "Exported slashing protection interchange"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Runs a command of the network key of the node.
+pub fn run_network_key(command: &NetworkKeyCommand, config: &Config) -> Result<(), String> {
+    match command {
+        NetworkKeyCommand::Export {
+            file,
+            password_file,
+        } => network::export_network_key(&config.network, file, password_file.as_deref()),
+    }
+}
+
+/// Runs a command of the operator key.
+pub fn run_operator_key(command: &OperatorKeyCommand, config: &Config) -> Result<(), String> {
+    match command {
+        OperatorKeyCommand::Generate => {
+            generate_operator_key(&config.operator_keystore, &config.operator_password_file)?;
+            Ok(())
+        }
+    }
+}
+
+fn open_slashing_protection(data_dir: &Path) -> Result<SlashingDatabase, String> {
+    let path = data_dir.join(SLASHING_PROTECTION_FILENAME);
+    SlashingDatabase::open(&path)
+        .map_err(|e| format!("Unable to open the slashing protection database {path:?}: {e}"))
+}
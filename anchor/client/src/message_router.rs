@@ -0,0 +1,878 @@
+//! Routes the messages exchanged with the other operators over the network.
+//!
+//! The messages received from the network, whose operator signatures were verified when they were
+//! validated, are queued in the processor, which decodes and verifies their content on its
+//! blocking thread pool in priority order before handing them to the runner of their duty. Our
+//! own messages are signed with the operator key and published on the subnet of their committee,
+//! with the message id of the fork of their slot.
+//!
+//! The prepares and round changes of the instances, ours and those of the other operators, are
+//! kept to justify our proposals and round changes to the other operators, who refuse them
+//! without the messages of a quorum.
+//!
+//! The QBFT rounds start at 1 on the network, while the rounds of our instances start at 0.
+
+use crate::attestation_runner::{AttestationRunner, BeaconVote, OutboundConsensusMessage};
+use crate::beacon_node::{ChainSpec, Checkpoint};
+use crate::pre_consensus::{OutboundPartialSignatures, PreConsensus};
+use crate::signing::beacon_vote_root;
+use network::{ForkSchedule, NetworkCommand, SubnetId};
+use operator_key::OperatorKey;
+use parking_lot::Mutex;
+use processor::{Work, WorkKind};
+use qbft::{ConsensusData, InMessage, OutMessage, Round};
+use slot_clock::SlotClock;
+use ssv_types::{
+    CommitteeId, MessageId, MsgType, OperatorId, PartialSignatureKind, PartialSignatureMessages,
+    QbftMessage, QbftMessageType, Role, SSVMessage, SignedSSVMessage,
+};
+use ssz::{Decode, Encode};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// The size of the SSZ encoding of a vote: a root and two checkpoints.
+const VOTE_SSZ_LEN: usize = 32 + 2 * (8 + 32);
+
+pub struct MessageRouter<T> {
+    operator_id: OperatorId,
+    attestation_runner: Arc<AttestationRunner<T>>,
+    pre_consensus: Arc<PreConsensus<T>>,
+    justifications: Arc<Justifications>,
+    processor: processor::Sender,
+}
+
+impl<T: SlotClock + 'static> MessageRouter<T> {
+    pub fn new(
+        operator_id: OperatorId,
+        attestation_runner: Arc<AttestationRunner<T>>,
+        pre_consensus: Arc<PreConsensus<T>>,
+        justifications: Arc<Justifications>,
+        processor: processor::Sender,
+    ) -> Self {
+        Self {
+            operator_id,
+            attestation_runner,
+            pre_consensus,
+            justifications,
+            processor,
+        }
+    }
+
+    /// Routes the messages received from the network to the processor until the network stops.
+    pub async fn run(self: Arc<Self>, mut messages: mpsc::Receiver<SignedSSVMessage>) {
+        while let Some(message) = messages.recv().await {
+            self.on_message(message);
+        }
+    }
+
+    /// Queues the processing of a message of another operator, with the priority of its kind.
+    pub fn on_message(self: &Arc<Self>, message: SignedSSVMessage) {
+        let work = match self.work(message) {
+            Ok(work) => work,
+            Err(error) => {
+                debug!(error, "Ignoring network message");
+                return;
+            }
+        };
+        if let Err(error) = self.processor.send(work) {
+            debug!(error, "Unable to queue network message");
+        }
+    }
+
+    fn work(self: &Arc<Self>, message: SignedSSVMessage) -> Result<Work, String> {
+        let router = self.clone();
+        match message.ssv_message.msg_type() {
+            Ok(MsgType::Consensus) => {
+                let qbft_message = QbftMessage::from_ssz_bytes(&message.ssv_message.data)
+                    .map_err(|e| format!("Undecodable consensus message: {e:?}"))?;
+                let kind = if message.operator_ids.len() > 1 {
+                    WorkKind::Decided
+                } else {
+                    match qbft_message.qbft_message_type() {
+                        Ok(QbftMessageType::Proposal) => WorkKind::Proposal,
+                        Ok(QbftMessageType::Prepare) => WorkKind::Prepare,
+                        Ok(QbftMessageType::Commit) => WorkKind::Commit,
+                        Ok(QbftMessageType::RoundChange) => WorkKind::RoundChange,
+                        Err(msg_type) => {
+                            return Err(format!("Unknown QBFT message type {msg_type}"))
+                        }
+                    }
+                };
+                Ok(Work::new(kind, qbft_message.height, move || {
+                    if let Err(error) = router.on_consensus_message(&message, &qbft_message) {
+                        debug!(
+                            error,
+                            slot = qbft_message.height,
+                            "Invalid consensus message"
+                        );
+                    }
+                }))
+            }
+            Ok(MsgType::PartialSignature) => {
+                let messages = PartialSignatureMessages::from_ssz_bytes(&message.ssv_message.data)
+                    .map_err(|e| format!("Undecodable partial signatures: {e:?}"))?;
+                Ok(Work::new(
+                    WorkKind::PartialSignature,
+                    messages.slot,
+                    move || router.on_partial_signatures(&messages),
+                ))
+            }
+            Err(msg_type) => Err(format!("Unknown message type {msg_type}")),
+        }
+    }
+
+    /// Verifies the vote of a consensus message of another operator against its root, and hands
+    /// it to the instance of its committee.
+    fn on_consensus_message(
+        &self,
+        message: &SignedSSVMessage,
+        qbft_message: &QbftMessage,
+    ) -> Result<(), String> {
+        let msg_id = message.ssv_message.msg_id;
+        if msg_id.role() != Some(Role::Committee) {
+            return Err(format!(
+                "Consensus of the {:?} role is not supported yet",
+                msg_id.role()
+            ));
+        }
+        let committee_id = committee_id(&msg_id)?;
+        let messages = consensus_messages(message, qbft_message)?;
+        self.justifications.record(qbft_message, message);
+        for (signer, message) in messages {
+            if signer != self.operator_id {
+                self.attestation_runner.on_consensus_message(
+                    &committee_id,
+                    qbft_message.height,
+                    message,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands partial signatures to the runner of their duty, which verifies them.
+    fn on_partial_signatures(&self, messages: &PartialSignatureMessages) {
+        match messages.kind() {
+            Ok(PartialSignatureKind::PostConsensus) => {
+                self.attestation_runner.on_partial_signatures(messages)
+            }
+            _ => self.pre_consensus.on_partial_signatures(messages),
+        }
+    }
+}
+
+/// Signs our messages and publishes them on the network.
+pub struct Publisher {
+    operator_key: Arc<OperatorKey>,
+    operator_id: OperatorId,
+    fork_schedule: ForkSchedule,
+    spec: ChainSpec,
+    justifications: Arc<Justifications>,
+    network_commands: mpsc::Sender<NetworkCommand>,
+}
+
+impl Publisher {
+    pub fn new(
+        operator_key: Arc<OperatorKey>,
+        operator_id: OperatorId,
+        fork_schedule: ForkSchedule,
+        spec: ChainSpec,
+        justifications: Arc<Justifications>,
+        network_commands: mpsc::Sender<NetworkCommand>,
+    ) -> Self {
+        Self {
+            operator_key,
+            operator_id,
+            fork_schedule,
+            spec,
+            justifications,
+            network_commands,
+        }
+    }
+
+    /// Publishes the messages of the runners of our duties until they stop.
+    pub async fn run(
+        self,
+        mut consensus_messages: mpsc::Receiver<OutboundConsensusMessage>,
+        mut partial_signature_messages: mpsc::Receiver<OutboundPartialSignatures>,
+    ) {
+        loop {
+            tokio::select! {
+                Some(outbound) = consensus_messages.recv() => self.publish_consensus(outbound),
+                Some(outbound) = partial_signature_messages.recv() => {
+                    self.publish_partial_signatures(outbound)
+                }
+                else => return,
+            }
+        }
+    }
+
+    fn publish_consensus(&self, outbound: OutboundConsensusMessage) {
+        let msg_id = self
+            .fork(outbound.slot)
+            .message_id(Role::Committee, &outbound.committee_id.0);
+        let (mut qbft_message, full_data) =
+            match encode_consensus(&msg_id, outbound.slot, &outbound.message) {
+                Ok(Some(encoded)) => encoded,
+                Ok(None) => return,
+                Err(error) => {
+                    warn!(error, slot = outbound.slot, "Unable to encode our vote");
+                    return;
+                }
+            };
+        self.justifications.justify(&mut qbft_message);
+        let Some(message) = self.sign(
+            MsgType::Consensus,
+            msg_id,
+            qbft_message.as_ssz_bytes(),
+            full_data,
+        ) else {
+            return;
+        };
+        self.justifications.record(&qbft_message, &message);
+        self.publish(outbound.committee_id, message);
+    }
+
+    fn publish_partial_signatures(&self, outbound: OutboundPartialSignatures) {
+        let msg_id = self
+            .fork(outbound.messages.slot)
+            .message_id(outbound.role, &outbound.duty_executor_id);
+        if let Some(message) = self.sign(
+            MsgType::PartialSignature,
+            msg_id,
+            outbound.messages.as_ssz_bytes(),
+            vec![],
+        ) {
+            self.publish(outbound.committee_id, message);
+        }
+    }
+
+    /// Signs a message of ours with the operator key.
+    fn sign(
+        &self,
+        msg_type: MsgType,
+        msg_id: MessageId,
+        data: Vec<u8>,
+        full_data: Vec<u8>,
+    ) -> Option<SignedSSVMessage> {
+        let ssv_message = SSVMessage {
+            msg_type: msg_type as u64,
+            msg_id,
+            data,
+        };
+        let signature = match self.operator_key.sign(&ssv_message.as_ssz_bytes()) {
+            Ok(signature) => signature,
+            Err(error) => {
+                warn!(error, "Unable to sign our message");
+                return None;
+            }
+        };
+        Some(SignedSSVMessage {
+            signatures: vec![signature],
+            operator_ids: vec![self.operator_id],
+            ssv_message,
+            full_data,
+        })
+    }
+
+    /// Publishes a signed message of ours on the subnet of its committee.
+    fn publish(&self, committee_id: CommitteeId, message: SignedSSVMessage) {
+        let command = NetworkCommand::Publish {
+            subnet: SubnetId::from_committee(&committee_id),
+            message,
+        };
+        if let Err(error) = self.network_commands.try_send(command) {
+            warn!(%error, "Unable to publish our message");
+        }
+    }
+
+    /// The fork of the epoch of `slot`, whose message ids the message is published with.
+    fn fork(&self, slot: u64) -> &network::Fork {
+        self.fork_schedule.fork_at(slot / self.spec.slots_per_epoch)
+    }
+}
+
+/// The prepare and round change messages of the recent instances, signed by a single operator.
+#[derive(Default)]
+pub struct Justifications {
+    messages: Mutex<BTreeMap<u64, Vec<(QbftMessage, SignedSSVMessage)>>>,
+}
+
+impl Justifications {
+    /// Keeps a prepare or round change message to justify our messages of its instance.
+    pub fn record(&self, qbft_message: &QbftMessage, message: &SignedSSVMessage) {
+        if message.operator_ids.len() != 1
+            || !matches!(
+                qbft_message.qbft_message_type(),
+                Ok(QbftMessageType::Prepare | QbftMessageType::RoundChange)
+            )
+        {
+            return;
+        }
+        let mut messages = self.messages.lock();
+        messages
+            .entry(qbft_message.height)
+            .or_default()
+            .push((qbft_message.clone(), message.clone()));
+        // The instances of the previous height may still be running.
+        if let Some((&newest, _)) = messages.last_key_value() {
+            let oldest = newest.saturating_sub(1);
+            messages.retain(|height, _| *height >= oldest);
+        }
+    }
+
+    /// Adds the justifications of a message of ours: the prepares of its value to a prepared
+    /// round change, and the round changes of its round, with the prepares of the highest value
+    /// they prepared, to a proposal after the first round.
+    pub fn justify(&self, qbft_message: &mut QbftMessage) {
+        let messages = self.messages.lock();
+        let Some(instance) = messages.get(&qbft_message.height) else {
+            return;
+        };
+        // The messages of a step, round and value of the instance, one per signer.
+        let find = |qbft_message_type, round, root: Option<[u8; 32]>| {
+            let mut signers = HashSet::new();
+            instance
+                .iter()
+                .filter(|(message, signed)| {
+                    message.identifier == qbft_message.identifier
+                        && message.qbft_message_type() == Ok(qbft_message_type)
+                        && message.round == round
+                        && root.is_none_or(|root| message.root == root)
+                        && signers.insert(signed.operator_ids[0])
+                })
+                .collect::<Vec<_>>()
+        };
+        let encode = |messages: &[&(QbftMessage, SignedSSVMessage)]| {
+            messages
+                .iter()
+                .map(|(_, signed)| signed.as_ssz_bytes())
+                .collect::<Vec<_>>()
+        };
+        let (round_change_justification, prepare_justification) = match qbft_message
+            .qbft_message_type()
+        {
+            Ok(QbftMessageType::RoundChange) if qbft_message.data_round > 0 => {
+                let prepares = find(
+                    QbftMessageType::Prepare,
+                    qbft_message.data_round,
+                    Some(qbft_message.root),
+                );
+                (encode(&prepares), vec![])
+            }
+            Ok(QbftMessageType::Proposal) if qbft_message.round > 1 => {
+                let round_changes = find(QbftMessageType::RoundChange, qbft_message.round, None);
+                let prepared = round_changes
+                    .iter()
+                    .map(|(round_change, _)| round_change)
+                    .filter(|round_change| round_change.data_round > 0)
+                    .max_by_key(|round_change| round_change.data_round);
+                let prepares = match prepared {
+                    Some(prepared) => find(
+                        QbftMessageType::Prepare,
+                        prepared.data_round,
+                        Some(prepared.root),
+                    ),
+                    None => vec![],
+                };
+                (encode(&round_changes), encode(&prepares))
+            }
+            _ => return,
+        };
+        qbft_message.round_change_justification = round_change_justification;
+        qbft_message.prepare_justification = prepare_justification;
+    }
+}
+
+/// The committee id of a message id of the committee role, right aligned in its duty executor id.
+fn committee_id(msg_id: &MessageId) -> Result<CommitteeId, String> {
+    let duty_executor_id = msg_id.duty_executor_id();
+    let (padding, committee_id) = duty_executor_id.split_at(duty_executor_id.len() - 32);
+    if padding.iter().any(|byte| *byte != 0) {
+        return Err("Not the message id of a committee".to_string());
+    }
+    Ok(CommitteeId(
+        committee_id
+            .try_into()
+            .expect("The committee id is 32 bytes long"),
+    ))
+}
+
+/// Encodes a QBFT message of ours with the vote it carries, if it is to be published.
+fn encode_consensus(
+    msg_id: &MessageId,
+    height: u64,
+    message: &OutMessage<BeaconVote>,
+) -> Result<Option<(QbftMessage, Vec<u8>)>, String> {
+    let (qbft_message_type, round, data) = match message {
+        OutMessage::Propose(data) => (QbftMessageType::Proposal, data.round, Some(data)),
+        OutMessage::Prepare(data) => (QbftMessageType::Prepare, data.round, Some(data)),
+        OutMessage::Commit(data) => (QbftMessageType::Commit, data.round, Some(data)),
+        OutMessage::RoundChange(round, data) => {
+            (QbftMessageType::RoundChange, *round, data.as_ref())
+        }
+        OutMessage::Completed(_) => return Ok(None),
+    };
+    let (root, data_round, full_data) = match data {
+        Some(data) => (
+            beacon_vote_root(&data.data)?,
+            network_round(data.round),
+            encode_vote(&data.data)?,
+        ),
+        None => ([0; 32], 0, vec![]),
+    };
+    let qbft_message = QbftMessage {
+        qbft_message_type: qbft_message_type as u64,
+        height,
+        round: network_round(round),
+        identifier: msg_id.as_bytes().to_vec(),
+        root,
+        data_round,
+        round_change_justification: vec![],
+        prepare_justification: vec![],
+    };
+    Ok(Some((qbft_message, full_data)))
+}
+
+/// The QBFT messages of the signers of a consensus message: a decided message holds the commits
+/// of a quorum. The vote is checked against the root the operators signed.
+fn consensus_messages(
+    message: &SignedSSVMessage,
+    qbft_message: &QbftMessage,
+) -> Result<Vec<(OperatorId, InMessage<BeaconVote>)>, String> {
+    let vote = if message.full_data.is_empty() {
+        None
+    } else {
+        let vote = decode_vote(&message.full_data)?;
+        if beacon_vote_root(&vote)? != qbft_message.root {
+            return Err("The vote does not match the signed root".to_string());
+        }
+        Some(vote)
+    };
+    let round = instance_round(qbft_message.round)?;
+    let data = |round| {
+        vote.clone()
+            .map(|data| ConsensusData { round, data })
+            .ok_or_else(|| "Missing vote".to_string())
+    };
+    let qbft_message_type = qbft_message
+        .qbft_message_type()
+        .map_err(|msg_type| format!("Unknown QBFT message type {msg_type}"))?;
+    message
+        .operator_ids
+        .iter()
+        .map(|signer| {
+            let from = qbft::OperatorId::from(**signer as usize);
+            let message = match qbft_message_type {
+                QbftMessageType::Proposal => InMessage::Propose(from, data(round)?),
+                QbftMessageType::Prepare => InMessage::Prepare(from, data(round)?),
+                QbftMessageType::Commit => InMessage::Commit(from, data(round)?),
+                QbftMessageType::RoundChange if qbft_message.data_round == 0 => {
+                    InMessage::RoundChange(from, round, None)
+                }
+                QbftMessageType::RoundChange => InMessage::RoundChange(
+                    from,
+                    round,
+                    Some(data(instance_round(qbft_message.data_round)?)?),
+                ),
+            };
+            Ok((*signer, message))
+        })
+        .collect()
+}
+
+/// The round of a message on the network, from the round of our instance.
+fn network_round(round: Round) -> u64 {
+    *round as u64 + 1
+}
+
+/// The round of our instance, from the round of a message on the network.
+fn instance_round(round: u64) -> Result<Round, String> {
+    round
+        .checked_sub(1)
+        .map(|round| Round::from(round as usize))
+        .ok_or_else(|| format!("Invalid round {round}"))
+}
+
+/// The SSZ encoding of a vote.
+fn encode_vote(vote: &BeaconVote) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(VOTE_SSZ_LEN);
+    bytes.extend_from_slice(&vote.beacon_block_root);
+    for checkpoint in [&vote.source, &vote.target] {
+        bytes.extend_from_slice(&checkpoint.epoch.to_le_bytes());
+        bytes.extend_from_slice(&checkpoint.root);
+    }
+    if bytes.len() != VOTE_SSZ_LEN {
+        return Err("Invalid roots in the vote".to_string());
+    }
+    Ok(bytes)
+}
+
+fn decode_vote(bytes: &[u8]) -> Result<BeaconVote, String> {
+    if bytes.len() != VOTE_SSZ_LEN {
+        return Err(format!("Invalid vote length {}", bytes.len()));
+    }
+    let checkpoint = |bytes: &[u8]| Checkpoint {
+        epoch: u64::from_le_bytes(bytes[..8].try_into().expect("The epoch is 8 bytes long")),
+        root: bytes[8..].to_vec(),
+    };
+    Ok(BeaconVote {
+        beacon_block_root: bytes[..32].to_vec(),
+        source: checkpoint(&bytes[32..72]),
+        target: checkpoint(&bytes[72..]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partial_signer::PartialSigner;
+    use crate::pre_consensus::{PreConsensusDuty, PreConsensusKind, OUTBOUND_CHANNEL_SIZE};
+    use crate::test_utils::{committee, context, AllDuties, Committee, SPEC};
+    use network::{MessageValidator, ValidationContext, ValidationResult};
+    use operator_key::OperatorPublicKey;
+    use processor::Processor;
+    use signature_collector::ETH_DST;
+    use slashing_protection::SlashingDatabase;
+    use slot_clock::{ManualSlotClock, Slot};
+    use ssv_types::{PartialSignature, PartialSignatureMessage};
+    use std::time::Duration;
+
+    const SLOT: u64 = 100;
+
+    fn vote() -> BeaconVote {
+        BeaconVote {
+            beacon_block_root: vec![0xbb; 32],
+            source: Checkpoint {
+                epoch: 2,
+                root: vec![0x22; 32],
+            },
+            target: Checkpoint {
+                epoch: 3,
+                root: vec![0x33; 32],
+            },
+        }
+    }
+
+    /// A message of our instance as received by the other operators from `signers`.
+    fn received(
+        message: &OutMessage<BeaconVote>,
+        signers: &[OperatorId],
+    ) -> Result<Vec<(OperatorId, InMessage<BeaconVote>)>, String> {
+        let msg_id = MessageId::new([0, 0, 0, 1], Role::Committee, &[7; 32]);
+        let (qbft_message, full_data) = encode_consensus(&msg_id, SLOT, message)?.unwrap();
+        assert_eq!(qbft_message.identifier, msg_id.as_bytes());
+        let message = SignedSSVMessage {
+            signatures: vec![vec![0; 256]; signers.len()],
+            operator_ids: signers.to_vec(),
+            ssv_message: SSVMessage {
+                msg_type: MsgType::Consensus as u64,
+                msg_id,
+                data: qbft_message.as_ssz_bytes(),
+            },
+            full_data,
+        };
+        consensus_messages(&message, &qbft_message)
+    }
+
+    #[test]
+    fn consensus_messages_survive_the_network_encoding() {
+        let data = |round| ConsensusData {
+            round: Round::from(round),
+            data: vote(),
+        };
+        let from = qbft::OperatorId::from(5);
+        let messages = [
+            (
+                OutMessage::Propose(data(0)),
+                InMessage::Propose(from, data(0)),
+            ),
+            (
+                OutMessage::Prepare(data(1)),
+                InMessage::Prepare(from, data(1)),
+            ),
+            (
+                OutMessage::Commit(data(2)),
+                InMessage::Commit(from, data(2)),
+            ),
+            (
+                OutMessage::RoundChange(Round::from(3), Some(data(1))),
+                InMessage::RoundChange(from, Round::from(3), Some(data(1))),
+            ),
+            (
+                OutMessage::RoundChange(Round::from(3), None),
+                InMessage::RoundChange(from, Round::from(3), None),
+            ),
+        ];
+        for (sent, expected) in messages {
+            let [(signer, message)] = received(&sent, &[OperatorId(5)])
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert_eq!(signer, OperatorId(5));
+            assert_eq!(format!("{message:?}"), format!("{expected:?}"));
+        }
+
+        // A decided message holds the commits of each of its signers.
+        let signers = [1, 2, 4].map(OperatorId);
+        let commits = received(&OutMessage::Commit(data(0)), &signers).unwrap();
+        assert_eq!(commits.len(), 3);
+        for ((signer, commit), expected) in commits.iter().zip(signers) {
+            assert_eq!(*signer, expected);
+            assert!(matches!(commit, InMessage::Commit(from, _) if **from == *expected as usize));
+        }
+        assert!(encode_consensus(
+            &MessageId::new([0; 4], Role::Committee, &[7; 32]),
+            SLOT,
+            &OutMessage::Completed(qbft::Completed::TimedOut)
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn votes_must_match_their_root() {
+        let msg_id = MessageId::new([0, 0, 0, 1], Role::Committee, &[7; 32]);
+        let message = OutMessage::Prepare(ConsensusData {
+            round: Round::default(),
+            data: vote(),
+        });
+        let (qbft_message, mut full_data) =
+            encode_consensus(&msg_id, SLOT, &message).unwrap().unwrap();
+        full_data[0] ^= 1;
+        let message = SignedSSVMessage {
+            signatures: vec![vec![0; 256]],
+            operator_ids: vec![OperatorId(5)],
+            ssv_message: SSVMessage {
+                msg_type: MsgType::Consensus as u64,
+                msg_id,
+                data: qbft_message.as_ssz_bytes(),
+            },
+            full_data,
+        };
+        assert!(consensus_messages(&message, &qbft_message).is_err());
+        assert!(decode_vote(&[0; VOTE_SSZ_LEN - 1]).is_err());
+        let mut invalid = vote();
+        invalid.target.root.pop();
+        assert!(encode_vote(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn our_messages_are_signed_and_published_on_the_subnet_of_their_committee() {
+        let operator_key = Arc::new(OperatorKey::generate().unwrap());
+        let fork_schedule = ForkSchedule::genesis([0, 0, 3, 1]);
+        let (network_commands, mut published) = mpsc::channel(1);
+        let publisher = Publisher::new(
+            operator_key.clone(),
+            OperatorId(3),
+            fork_schedule.clone(),
+            SPEC,
+            Arc::new(Justifications::default()),
+            network_commands,
+        );
+        let committee_id = CommitteeId([9; 32]);
+        let messages = PartialSignatureMessages {
+            kind: PartialSignatureKind::RandaoPartialSig as u64,
+            slot: SLOT,
+            messages: vec![],
+        };
+        publisher.publish_partial_signatures(OutboundPartialSignatures {
+            role: Role::Proposer,
+            duty_executor_id: vec![4; 48],
+            committee_id,
+            messages: messages.clone(),
+        });
+
+        let Some(NetworkCommand::Publish { subnet, message }) = published.recv().await else {
+            panic!("Nothing published");
+        };
+        assert_eq!(subnet, SubnetId::from_committee(&committee_id));
+        assert_eq!(message.operator_ids, vec![OperatorId(3)]);
+        assert_eq!(
+            message.ssv_message.msg_id,
+            fork_schedule
+                .fork_at(0)
+                .message_id(Role::Proposer, &[4; 48])
+        );
+        assert_eq!(message.ssv_message.data, messages.as_ssz_bytes());
+        assert!(OperatorPublicKey::from(&*operator_key)
+            .verify(&message.ssv_message.as_ssz_bytes(), &message.signatures[0]));
+    }
+
+    /// Knows a committee of four operators sharing a key, at `SLOT`.
+    struct TestContext(OperatorPublicKey);
+
+    impl ValidationContext for TestContext {
+        fn current_slot(&self) -> Option<u64> {
+            Some(SLOT)
+        }
+
+        fn committee(&self, _message_id: &MessageId) -> Option<Vec<OperatorId>> {
+            Some((1..=4).map(OperatorId).collect())
+        }
+
+        fn operator_public_key(&self, _operator_id: OperatorId) -> Option<Arc<OperatorPublicKey>> {
+            Some(Arc::new(self.0.clone()))
+        }
+    }
+
+    #[test]
+    fn our_messages_are_justified_to_the_other_operators() {
+        let operator_key = Arc::new(OperatorKey::generate().unwrap());
+        let fork_schedule = ForkSchedule::genesis([0, 0, 3, 1]);
+        let validator = MessageValidator::new(
+            Arc::new(TestContext(OperatorPublicKey::from(&*operator_key))),
+            fork_schedule.fork_at(0).domain_type,
+        );
+        let (network_commands, mut published) = mpsc::channel(1);
+        let operators: Vec<_> = (1..=4)
+            .map(|operator_id| {
+                let justifications = Arc::new(Justifications::default());
+                let publisher = Publisher::new(
+                    operator_key.clone(),
+                    OperatorId(operator_id),
+                    fork_schedule.clone(),
+                    SPEC,
+                    justifications.clone(),
+                    network_commands.clone(),
+                );
+                (publisher, justifications)
+            })
+            .collect();
+
+        // Publishes a message of an operator, which the other operators validate and keep.
+        let mut publish = |operator: usize, message| {
+            operators[operator]
+                .0
+                .publish_consensus(OutboundConsensusMessage {
+                    committee_id: CommitteeId([9; 32]),
+                    slot: SLOT,
+                    message,
+                });
+            let Ok(NetworkCommand::Publish { message, .. }) = published.try_recv() else {
+                panic!("Nothing published");
+            };
+            let ValidationResult::Accept(message) = validator.validate(&message.as_ssz_bytes())
+            else {
+                panic!("Our message is not valid");
+            };
+            let qbft_message = QbftMessage::from_ssz_bytes(&message.ssv_message.data).unwrap();
+            for (_, justifications) in operators
+                .iter()
+                .filter(|(publisher, _)| publisher.operator_id != message.operator_ids[0])
+            {
+                justifications.record(&qbft_message, &message);
+            }
+            qbft_message
+        };
+
+        // A quorum prepares the vote of the first round, which does not decide in time.
+        let data = |round| ConsensusData {
+            round: Round::from(round),
+            data: vote(),
+        };
+        for operator in 0..3 {
+            publish(operator, OutMessage::Prepare(data(0)));
+        }
+        for operator in 0..3 {
+            let round_change = publish(
+                operator,
+                OutMessage::RoundChange(Round::from(1), Some(data(0))),
+            );
+            assert_eq!(round_change.round_change_justification.len(), 3);
+        }
+
+        // The leader of the second round proposes the prepared vote again.
+        let proposal = publish(1, OutMessage::Propose(data(1)));
+        assert_eq!(proposal.round, 2);
+        assert_eq!(proposal.round_change_justification.len(), 3);
+        assert_eq!(proposal.prepare_justification.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn partial_signatures_of_the_other_operators_are_processed() {
+        let Committee {
+            secret_key,
+            shares,
+            committee,
+        } = committee(1);
+        let context = context();
+
+        let slot_clock = ManualSlotClock::new(
+            Slot::new(0),
+            Duration::ZERO,
+            Duration::from_secs(SPEC.seconds_per_slot),
+        );
+        slot_clock.set_current_time(Duration::from_secs(SLOT * SPEC.seconds_per_slot));
+        let signer = Arc::new(PartialSigner::new(
+            shares[0].0,
+            Arc::new(SlashingDatabase::open_in_memory().unwrap()),
+        ));
+        let (consensus_outbound, _) = mpsc::channel(1);
+        let (partial_signatures_outbound, _) = mpsc::channel(OUTBOUND_CHANNEL_SIZE);
+        let pre_consensus = Arc::new(PreConsensus::new(
+            signer.clone(),
+            slot_clock.clone(),
+            SPEC,
+            partial_signatures_outbound.clone(),
+            Arc::new(AllDuties),
+        ));
+        let attestation_runner = Arc::new(AttestationRunner::new(
+            signer,
+            slot_clock.clone(),
+            SPEC,
+            consensus_outbound,
+            partial_signatures_outbound,
+            Arc::new(AllDuties),
+        ));
+        let (processor, processor_task) =
+            Processor::new(processor::Config::default(), slot_clock, None);
+        tokio::spawn(processor_task.run());
+        let router = Arc::new(MessageRouter::new(
+            shares[0].0,
+            attestation_runner,
+            pre_consensus.clone(),
+            Arc::new(Justifications::default()),
+            processor,
+        ));
+
+        // The other operators broadcast their partial signatures of the RANDAO reveal.
+        let signing_root = context.randao_root(SLOT / SPEC.slots_per_epoch);
+        for (operator_id, share) in shares.iter().skip(1) {
+            let messages = PartialSignatureMessages {
+                kind: PartialSignatureKind::RandaoPartialSig as u64,
+                slot: SLOT,
+                messages: vec![PartialSignatureMessage {
+                    partial_signature: PartialSignature(
+                        share.sign(&signing_root, ETH_DST, &[]).to_bytes(),
+                    ),
+                    signing_root,
+                    signer: *operator_id,
+                    validator_index: 7,
+                }],
+            };
+            router.on_message(SignedSSVMessage {
+                signatures: vec![vec![0; 256]],
+                operator_ids: vec![*operator_id],
+                ssv_message: SSVMessage {
+                    msg_type: MsgType::PartialSignature as u64,
+                    msg_id: MessageId::new([0; 4], Role::Proposer, &[4; 48]),
+                    data: messages.as_ssz_bytes(),
+                },
+                full_data: vec![],
+            });
+        }
+
+        let duty = PreConsensusDuty {
+            kind: PreConsensusKind::Randao,
+            slot: SLOT,
+            validator_index: 7,
+            share: shares[0].1.clone(),
+            committee,
+        };
+        let signature = pre_consensus.sign(&duty, &context).await.unwrap();
+        assert_eq!(signature, secret_key.sign(&signing_root, ETH_DST, &[]));
+    }
+}
@@ -0,0 +1,134 @@
+//! Produces our partial signatures with our shares of the validator keys.
+//!
+//! Blocks and attestations are only signed once the slashing protection database recorded them,
+//! refusing the ones conflicting with what the validator signed before.
+
+use blst::min_pk::SecretKey;
+use signature_collector::ETH_DST;
+use slashing_protection::SlashingDatabase;
+use ssv_types::{OperatorId, PartialSignature, PartialSignatureMessage};
+use std::sync::Arc;
+
+pub struct PartialSigner {
+    operator_id: OperatorId,
+    slashing_protection: Arc<SlashingDatabase>,
+}
+
+impl PartialSigner {
+    pub fn new(operator_id: OperatorId, slashing_protection: Arc<SlashingDatabase>) -> Self {
+        Self {
+            operator_id,
+            slashing_protection,
+        }
+    }
+
+    pub fn operator_id(&self) -> OperatorId {
+        self.operator_id
+    }
+
+    /// Signs an object that can not get the validator slashed, such as a RANDAO reveal or a
+    /// selection proof.
+    pub fn sign(
+        &self,
+        share: &SecretKey,
+        validator_index: u64,
+        signing_root: [u8; 32],
+    ) -> PartialSignatureMessage {
+        PartialSignatureMessage {
+            partial_signature: PartialSignature(share.sign(&signing_root, ETH_DST, &[]).to_bytes()),
+            signing_root,
+            signer: self.operator_id,
+            validator_index,
+        }
+    }
+
+    /// Signs the block of a validator at `slot`, unless it signed another block at that slot.
+    pub fn sign_block(
+        &self,
+        share: &SecretKey,
+        validator_public_key: &[u8],
+        validator_index: u64,
+        slot: u64,
+        signing_root: [u8; 32],
+    ) -> Result<PartialSignatureMessage, String> {
+        self.slashing_protection
+            .check_and_insert_block(validator_public_key, slot, &signing_root)
+            .map_err(|e| format!("Not signing the block at slot {slot}: {e}"))?;
+        Ok(self.sign(share, validator_index, signing_root))
+    }
+
+    /// Checks that the attestation of a validator would be signed, e.g. before agreeing to it.
+    pub fn check_attestation(
+        &self,
+        validator_public_key: &[u8],
+        source_epoch: u64,
+        target_epoch: u64,
+        signing_root: &[u8; 32],
+    ) -> Result<(), String> {
+        self.slashing_protection
+            .check_attestation(
+                validator_public_key,
+                source_epoch,
+                target_epoch,
+                signing_root,
+            )
+            .map_err(|e| format!("Slashable attestation of epoch {target_epoch}: {e}"))
+    }
+
+    /// Signs an attestation of a validator, unless it conflicts with a signed attestation.
+    pub fn sign_attestation(
+        &self,
+        share: &SecretKey,
+        validator_public_key: &[u8],
+        validator_index: u64,
+        source_epoch: u64,
+        target_epoch: u64,
+        signing_root: [u8; 32],
+    ) -> Result<PartialSignatureMessage, String> {
+        self.slashing_protection
+            .check_and_insert_attestation(
+                validator_public_key,
+                source_epoch,
+                target_epoch,
+                &signing_root,
+            )
+            .map_err(|e| format!("Not signing the attestation of epoch {target_epoch}: {e}"))?;
+        Ok(self.sign(share, validator_index, signing_root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slashable_signatures_are_refused() {
+        let signer = PartialSigner::new(
+            OperatorId(1),
+            Arc::new(SlashingDatabase::open_in_memory().unwrap()),
+        );
+        let share = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let validator = [0xaa; 48];
+
+        let message = signer
+            .sign_block(&share, &validator, 7, 100, [1; 32])
+            .unwrap();
+        assert_eq!(message, signer.sign(&share, 7, [1; 32]));
+        assert!(signer
+            .sign_block(&share, &validator, 7, 100, [2; 32])
+            .is_err());
+
+        signer
+            .check_attestation(&validator, 10, 20, &[3; 32])
+            .unwrap();
+        signer
+            .sign_attestation(&share, &validator, 7, 10, 20, [3; 32])
+            .unwrap();
+        assert!(signer
+            .check_attestation(&validator, 9, 21, &[4; 32])
+            .is_err());
+        assert!(signer
+            .sign_attestation(&share, &validator, 7, 9, 21, [4; 32])
+            .is_err());
+    }
+}
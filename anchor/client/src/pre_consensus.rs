@@ -0,0 +1,486 @@
+//! The pre-consensus round of the duties needing a signature of the validator before the
+//! committee can decide on their data: the RANDAO reveal of a block proposal, and the selection
+//! proofs telling whether the validator aggregates attestations or sync committee contributions.
+//!
+//! Each operator signs with its key share and broadcasts its partial signature to the operators
+//! of the validator. Once a threshold of the partial signatures is verified, the signature of the
+//! validator is reconstructed.
+
+use crate::beacon_node::{BeaconNodes, ChainSpec, ProducedBlock};
+use crate::duties_service::ScheduledDuties;
+use crate::partial_signer::PartialSigner;
+use crate::signature_rounds::{RoundKey, SignatureRounds};
+use crate::signing::{is_aggregator, is_sync_committee_aggregator, SigningContext};
+use blst::min_pk::{SecretKey, Signature};
+use signature_collector::ValidatorCommittee;
+use slot_clock::{Slot, SlotClock};
+use ssv_types::{CommitteeId, PartialSignatureKind, PartialSignatureMessages, Role};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// The number of our partial signature messages waiting to be broadcast before new ones are
+/// dropped.
+pub const OUTBOUND_CHANNEL_SIZE: usize = 1024;
+
+/// What a pre-consensus round signs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreConsensusKind {
+    /// The RANDAO reveal of a block proposal.
+    Randao,
+    /// The selection proof of an attester, in a committee of `committee_length` attesters.
+    SelectionProof { committee_length: u64 },
+    /// The selection proof of a sync committee member, for one of its subcommittees.
+    SyncSelectionProof { subcommittee_index: u64 },
+}
+
+impl PreConsensusKind {
+    /// The duty the round is part of.
+    pub fn role(&self) -> Role {
+        match self {
+            Self::Randao => Role::Proposer,
+            Self::SelectionProof { .. } => Role::Aggregator,
+            Self::SyncSelectionProof { .. } => Role::SyncCommitteeContribution,
+        }
+    }
+
+    pub fn partial_signature_kind(&self) -> PartialSignatureKind {
+        match self {
+            Self::Randao => PartialSignatureKind::RandaoPartialSig,
+            Self::SelectionProof { .. } => PartialSignatureKind::SelectionProofPartialSig,
+            Self::SyncSelectionProof { .. } => PartialSignatureKind::ContributionProofs,
+        }
+    }
+}
+
+/// A duty of a validator needing a pre-consensus signature.
+#[derive(Clone)]
+pub struct PreConsensusDuty {
+    pub kind: PreConsensusKind,
+    pub slot: u64,
+    pub validator_index: u64,
+    /// Our share of the validator key.
+    pub share: SecretKey,
+    pub committee: Arc<ValidatorCommittee>,
+}
+
+impl PreConsensusDuty {
+    fn signing_root(&self, context: &SigningContext, spec: &ChainSpec) -> [u8; 32] {
+        match self.kind {
+            PreConsensusKind::Randao => context.randao_root(self.slot / spec.slots_per_epoch),
+            PreConsensusKind::SelectionProof { .. } => {
+                context.selection_proof_root(self.slot, spec.slots_per_epoch)
+            }
+            PreConsensusKind::SyncSelectionProof { subcommittee_index } => context
+                .sync_selection_proof_root(self.slot, subcommittee_index, spec.slots_per_epoch),
+        }
+    }
+}
+
+/// Our partial signatures for a duty, to broadcast to the operators of the validator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutboundPartialSignatures {
+    pub role: Role,
+    /// The public key of the validator, or the committee id for the committee role.
+    pub duty_executor_id: Vec<u8>,
+    /// The committee of the operators, whose subnet the signatures are published on.
+    pub committee_id: CommitteeId,
+    pub messages: PartialSignatureMessages,
+}
+
+pub struct PreConsensus<T> {
+    signer: Arc<PartialSigner>,
+    slot_clock: T,
+    spec: ChainSpec,
+    outbound: mpsc::Sender<OutboundPartialSignatures>,
+    rounds: SignatureRounds,
+}
+
+impl<T: SlotClock> PreConsensus<T> {
+    pub fn new(
+        signer: Arc<PartialSigner>,
+        slot_clock: T,
+        spec: ChainSpec,
+        outbound: mpsc::Sender<OutboundPartialSignatures>,
+        duties: Arc<dyn ScheduledDuties>,
+    ) -> Self {
+        Self {
+            signer,
+            slot_clock,
+            spec,
+            outbound,
+            rounds: SignatureRounds::new(duties),
+        }
+    }
+
+    /// Signs the object of the duty with our share, broadcasts the partial signature and waits
+    /// for the signature of the validator to be reconstructed, until the round is due.
+    pub async fn sign(
+        &self,
+        duty: &PreConsensusDuty,
+        context: &SigningContext,
+    ) -> Result<Signature, String> {
+        let signing_root = duty.signing_root(context, &self.spec);
+        let kind = duty.kind.partial_signature_kind();
+        let key = RoundKey {
+            validator_index: duty.validator_index,
+            kind,
+            slot: duty.slot,
+            signing_root,
+        };
+        let message = self
+            .signer
+            .sign(&duty.share, duty.validator_index, signing_root);
+
+        let outbound = OutboundPartialSignatures {
+            role: duty.kind.role(),
+            duty_executor_id: duty.committee.validator_public_key.to_bytes().to_vec(),
+            committee_id: duty.committee.committee_id(),
+            messages: PartialSignatureMessages {
+                kind: kind as u64,
+                slot: duty.slot,
+                messages: vec![message.clone()],
+            },
+        };
+        if let Err(error) = self.outbound.try_send(outbound) {
+            warn!(%error, slot = duty.slot, "Unable to broadcast our partial signature");
+        }
+
+        self.rounds
+            .collect(key, &duty.committee, &message, self.time_left(duty))
+            .await
+            .ok_or_else(|| {
+                format!(
+                    "Timed out collecting the {kind:?} of validator {} at slot {}",
+                    duty.validator_index, duty.slot
+                )
+            })
+    }
+
+    /// Reconstructs the RANDAO reveal of a proposer duty, and requests the block to propose with
+    /// it.
+    pub async fn produce_block(
+        &self,
+        beacon_nodes: &BeaconNodes,
+        duty: &PreConsensusDuty,
+        context: &SigningContext,
+    ) -> Result<ProducedBlock, String> {
+        if duty.kind != PreConsensusKind::Randao {
+            return Err(format!("{:?} is not a RANDAO reveal", duty.kind));
+        }
+        let randao_reveal = self.sign(duty, context).await?;
+        beacon_nodes
+            .produce_block(duty.slot, &randao_reveal.to_bytes())
+            .await
+    }
+
+    /// Reconstructs the selection proof of an aggregator duty, returning it if the validator is
+    /// selected to aggregate.
+    pub async fn aggregator_selection(
+        &self,
+        duty: &PreConsensusDuty,
+        context: &SigningContext,
+    ) -> Result<Option<Signature>, String> {
+        if duty.kind == PreConsensusKind::Randao {
+            return Err("A RANDAO reveal is not a selection proof".to_string());
+        }
+        let proof = self.sign(duty, context).await?;
+        let selected = match duty.kind {
+            PreConsensusKind::SelectionProof { committee_length } => {
+                is_aggregator(committee_length, &proof)
+            }
+            _ => is_sync_committee_aggregator(&proof),
+        };
+        debug!(
+            validator_index = duty.validator_index,
+            slot = duty.slot,
+            kind = ?duty.kind,
+            selected,
+            "Aggregator selection"
+        );
+        Ok(selected.then_some(proof))
+    }
+
+    /// Adds the partial signatures broadcast by another operator of our validators.
+    pub fn on_partial_signatures(&self, messages: &PartialSignatureMessages) {
+        let kind = match messages.kind() {
+            Ok(
+                kind @ (PartialSignatureKind::RandaoPartialSig
+                | PartialSignatureKind::SelectionProofPartialSig
+                | PartialSignatureKind::ContributionProofs),
+            ) => kind,
+            _ => {
+                debug!(
+                    kind = messages.kind,
+                    "Not a pre-consensus partial signature"
+                );
+                return;
+            }
+        };
+        let is_stale = self
+            .slot_clock
+            .now()
+            .is_some_and(|now| messages.slot + 1 < now.as_u64());
+        if is_stale {
+            debug!(slot = messages.slot, "Ignoring stale partial signatures");
+            return;
+        }
+
+        self.rounds.add(kind, messages);
+    }
+
+    /// The time left until the round of a duty is due: a block is proposed in the first third of
+    /// its slot so that it is attested to, and aggregates are published two thirds into it.
+    fn time_left(&self, duty: &PreConsensusDuty) -> Duration {
+        let delay = match duty.kind {
+            PreConsensusKind::Randao => self.slot_clock.slot_duration() / 3,
+            PreConsensusKind::SelectionProof { .. } => {
+                self.slot_clock.agg_attestation_production_delay()
+            }
+            PreConsensusKind::SyncSelectionProof { .. } => self
+                .slot_clock
+                .sync_committee_contribution_production_delay(),
+        };
+        self.slot_clock
+            .start_of(Slot::new(duty.slot))
+            .zip(self.slot_clock.now_duration())
+            .map(|(start, now)| (start + delay).saturating_sub(now))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon_node::mock::MockBeaconNode;
+    use crate::beacon_node::DEFAULT_REQUEST_TIMEOUT;
+    use crate::test_utils::{committee, context, AllDuties, Committee, SPEC};
+    use futures::future::join_all;
+    use serde_json::json;
+    use signature_collector::ETH_DST;
+    use slashing_protection::SlashingDatabase;
+    use slot_clock::ManualSlotClock;
+    use ssv_types::{OperatorId, PartialSignature, PartialSignatureMessage};
+
+    const SLOT: u64 = 100;
+    const VALIDATOR_INDEX: u64 = 7;
+
+    /// A slot clock at `offset` into the slot of the duties.
+    fn slot_clock(offset: Duration) -> ManualSlotClock {
+        let slot_clock = ManualSlotClock::new(
+            Slot::new(0),
+            Duration::from_secs(0),
+            Duration::from_secs(SPEC.seconds_per_slot),
+        );
+        slot_clock.set_current_time(Duration::from_secs(SLOT * SPEC.seconds_per_slot) + offset);
+        slot_clock
+    }
+
+    fn pre_consensus(
+        operator_id: OperatorId,
+        slot_clock: ManualSlotClock,
+    ) -> (
+        PreConsensus<ManualSlotClock>,
+        mpsc::Receiver<OutboundPartialSignatures>,
+    ) {
+        let signer = PartialSigner::new(
+            operator_id,
+            Arc::new(SlashingDatabase::open_in_memory().unwrap()),
+        );
+        let (outbound, broadcast) = mpsc::channel(OUTBOUND_CHANNEL_SIZE);
+        let pre_consensus = PreConsensus::new(
+            Arc::new(signer),
+            slot_clock,
+            SPEC,
+            outbound,
+            Arc::new(AllDuties),
+        );
+        (pre_consensus, broadcast)
+    }
+
+    fn duty(kind: PreConsensusKind, share: &SecretKey, committee: &Committee) -> PreConsensusDuty {
+        PreConsensusDuty {
+            kind,
+            slot: SLOT,
+            validator_index: VALIDATOR_INDEX,
+            share: share.clone(),
+            committee: committee.committee.clone(),
+        }
+    }
+
+    /// The partial signature messages of an operator.
+    fn partial_signatures(
+        kind: PartialSignatureKind,
+        (operator_id, share): &(OperatorId, SecretKey),
+        signing_root: [u8; 32],
+    ) -> PartialSignatureMessages {
+        PartialSignatureMessages {
+            kind: kind as u64,
+            slot: SLOT,
+            messages: vec![PartialSignatureMessage {
+                partial_signature: PartialSignature(
+                    share.sign(&signing_root, ETH_DST, &[]).to_bytes(),
+                ),
+                signing_root,
+                signer: *operator_id,
+                validator_index: VALIDATOR_INDEX,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn operators_reconstruct_the_signature_together() {
+        let committee = committee(1);
+        let context = context();
+        let expected = committee.secret_key.sign(
+            &context.randao_root(SLOT / SPEC.slots_per_epoch),
+            ETH_DST,
+            &[],
+        );
+
+        // Every operator receives the broadcasts of the others.
+        let mut operators = vec![];
+        let mut broadcasts = vec![];
+        for (operator_id, _) in &committee.shares {
+            let (pre_consensus, broadcast) =
+                pre_consensus(*operator_id, slot_clock(Duration::ZERO));
+            operators.push(Arc::new(pre_consensus));
+            broadcasts.push(broadcast);
+        }
+        for mut broadcast in broadcasts {
+            let operators = operators.clone();
+            tokio::spawn(async move {
+                while let Some(outbound) = broadcast.recv().await {
+                    for operator in &operators {
+                        operator.on_partial_signatures(&outbound.messages);
+                    }
+                }
+            });
+        }
+
+        let signatures = join_all(operators.iter().zip(&committee.shares).map(
+            |(operator, (_, share))| {
+                let duty = duty(PreConsensusKind::Randao, share, &committee);
+                let context = context.clone();
+                async move { operator.sign(&duty, &context).await }
+            },
+        ))
+        .await;
+        for signature in signatures {
+            assert_eq!(signature.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn early_partial_signatures_count_toward_the_threshold() {
+        let committee = committee(1);
+        let context = context();
+        let kind = PreConsensusKind::SelectionProof {
+            committee_length: 1,
+        };
+        let signing_root = context.selection_proof_root(SLOT, SPEC.slots_per_epoch);
+        // The round is due, so it only completes with the partial signatures already received.
+        let (operator, mut broadcast) = pre_consensus(
+            committee.shares[0].0,
+            slot_clock(Duration::from_secs(SPEC.seconds_per_slot)),
+        );
+        for shares in &committee.shares[2..] {
+            operator.on_partial_signatures(&partial_signatures(
+                kind.partial_signature_kind(),
+                shares,
+                signing_root,
+            ));
+        }
+
+        let duty = duty(kind, &committee.shares[0].1, &committee);
+        let proof = operator
+            .aggregator_selection(&duty, &context)
+            .await
+            .unwrap();
+        assert_eq!(
+            proof,
+            Some(committee.secret_key.sign(&signing_root, ETH_DST, &[]))
+        );
+        // Our partial signature was broadcast.
+        let outbound = broadcast.recv().await.unwrap();
+        assert_eq!(outbound.role, Role::Aggregator);
+        assert_eq!(
+            outbound.messages,
+            partial_signatures(
+                kind.partial_signature_kind(),
+                &committee.shares[0],
+                signing_root
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn rounds_time_out_when_due() {
+        let committee = committee(1);
+        let (operator, _broadcast) = pre_consensus(
+            committee.shares[0].0,
+            slot_clock(Duration::from_secs(SPEC.seconds_per_slot / 3)),
+        );
+        // Below the threshold.
+        let signing_root = context().randao_root(SLOT / SPEC.slots_per_epoch);
+        operator.on_partial_signatures(&partial_signatures(
+            PartialSignatureKind::RandaoPartialSig,
+            &committee.shares[1],
+            signing_root,
+        ));
+
+        let duty = duty(PreConsensusKind::Randao, &committee.shares[0].1, &committee);
+        assert!(operator.sign(&duty, &context()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn blocks_are_produced_with_the_randao_reveal() {
+        let committee = committee(1);
+        let context = context();
+        let signing_root = context.randao_root(SLOT / SPEC.slots_per_epoch);
+        let randao_reveal = committee.secret_key.sign(&signing_root, ETH_DST, &[]);
+        let block = json!({
+            "version": "deneb",
+            "execution_payload_blinded": false,
+            "execution_payload_value": "1",
+            "consensus_block_value": "1",
+            "data": {"block": {"slot": SLOT.to_string()}},
+        });
+        let node = MockBeaconNode::new();
+        node.set_response(&format!("/eth/v3/validator/blocks/{SLOT}"), block.clone());
+        let url = node.spawn().await;
+        let beacon_nodes =
+            BeaconNodes::new(vec![url], &[], false, DEFAULT_REQUEST_TIMEOUT).unwrap();
+
+        let (operator, _broadcast) =
+            pre_consensus(committee.shares[0].0, slot_clock(Duration::ZERO));
+        for shares in &committee.shares[1..3] {
+            operator.on_partial_signatures(&partial_signatures(
+                PartialSignatureKind::RandaoPartialSig,
+                shares,
+                signing_root,
+            ));
+        }
+        let duty = duty(PreConsensusKind::Randao, &committee.shares[0].1, &committee);
+        let produced = operator
+            .produce_block(&beacon_nodes, &duty, &context)
+            .await
+            .unwrap();
+        assert_eq!(produced.version, "deneb");
+        assert_eq!(produced.data, block["data"]);
+        assert_eq!(
+            node.requests(),
+            vec![format!(
+                "/eth/v3/validator/blocks/{SLOT}?randao_reveal=0x{}",
+                hex::encode(randao_reveal.to_bytes())
+            )]
+        );
+
+        // A RANDAO reveal does not select aggregators.
+        assert!(operator
+            .aggregator_selection(&duty, &context)
+            .await
+            .is_err());
+    }
+}
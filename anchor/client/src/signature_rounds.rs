@@ -0,0 +1,362 @@
+//! The rounds in which the operators of a validator exchange their partial signatures of an
+//! object, until a threshold of them reconstructs the signature of the validator.
+//!
+//! Each round collects the partial signatures of one signing root. Partial signatures received
+//! before our round started are kept until it does, only for the duties of our validators and
+//! up to a bound for each signer, as they can not be verified before the round starts.
+
+use crate::duties_service::ScheduledDuties;
+use blst::min_pk::Signature;
+use parking_lot::Mutex;
+use signature_collector::{CollectionKey, SignatureCollector, ValidatorCommittee};
+use ssv_types::{
+    OperatorId, PartialSignatureKind, PartialSignatureMessage, PartialSignatureMessages,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+/// The number of partial signatures of rounds not started yet kept for each signer.
+pub const MAX_EARLY_PARTIAL_SIGNATURES_PER_SIGNER: usize = 1024;
+
+/// The number of partial signatures of rounds not started yet kept for all signers.
+pub const MAX_EARLY_PARTIAL_SIGNATURES: usize = 16 * 1024;
+
+/// Identifies a round from the partial signature messages of the other operators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RoundKey {
+    pub validator_index: u64,
+    pub kind: PartialSignatureKind,
+    pub slot: u64,
+    pub signing_root: [u8; 32],
+}
+
+struct Round {
+    collection_key: CollectionKey,
+    committee: Arc<ValidatorCommittee>,
+    /// Receives the reconstructed signature.
+    done: Option<oneshot::Sender<Signature>>,
+}
+
+#[derive(Default)]
+struct State {
+    collector: SignatureCollector,
+    /// The rounds in progress.
+    rounds: HashMap<RoundKey, Round>,
+    /// The partial signatures of the rounds not started yet.
+    early: HashMap<RoundKey, Vec<PartialSignatureMessage>>,
+    /// The number of early partial signatures of each signer.
+    early_counts: HashMap<OperatorId, usize>,
+    early_total: usize,
+}
+
+impl State {
+    fn add(&mut self, key: &RoundKey, message: &PartialSignatureMessage) {
+        let Some(round) = self.rounds.get_mut(key) else {
+            return;
+        };
+        let result = self.collector.add(
+            round.collection_key.clone(),
+            &round.committee,
+            message.signer,
+            &message.partial_signature,
+        );
+        match result {
+            Ok(Some(signature)) => {
+                if let Some(done) = round.done.take() {
+                    let _ = done.send(signature);
+                }
+            }
+            Ok(None) => {}
+            Err(error) => warn!(
+                error,
+                signer = *message.signer,
+                slot = key.slot,
+                kind = ?key.kind,
+                "Invalid partial signature"
+            ),
+        }
+    }
+
+    /// Keeps a partial signature of a round not started yet, unless it is already kept or its
+    /// signer or all signers have too many of them.
+    fn add_early(&mut self, key: RoundKey, message: &PartialSignatureMessage) {
+        if self
+            .early
+            .get(&key)
+            .is_some_and(|early| early.contains(message))
+        {
+            return;
+        }
+        let count = self.early_counts.get(&message.signer).copied().unwrap_or(0);
+        if count >= MAX_EARLY_PARTIAL_SIGNATURES_PER_SIGNER
+            || self.early_total >= MAX_EARLY_PARTIAL_SIGNATURES
+        {
+            debug!(
+                signer = *message.signer,
+                slot = key.slot,
+                "Too many early partial signatures, dropping"
+            );
+            return;
+        }
+        self.early_counts.insert(message.signer, count + 1);
+        self.early_total += 1;
+        self.early.entry(key).or_default().push(message.clone());
+    }
+
+    /// Takes the early partial signatures of a round starting.
+    fn take_early(&mut self, key: &RoundKey) -> Vec<PartialSignatureMessage> {
+        let early = self.early.remove(key).unwrap_or_default();
+        self.forget_early(&early);
+        early
+    }
+
+    fn forget_early(&mut self, messages: &[PartialSignatureMessage]) {
+        for message in messages {
+            self.early_total -= 1;
+            if let Some(count) = self.early_counts.get_mut(&message.signer) {
+                *count -= 1;
+                if *count == 0 {
+                    self.early_counts.remove(&message.signer);
+                }
+            }
+        }
+    }
+
+    /// Forgets the partial signatures of the slots before `slot`.
+    fn prune(&mut self, slot: u64) {
+        self.collector.prune(slot);
+        let pruned: Vec<RoundKey> = self
+            .early
+            .keys()
+            .filter(|key| key.slot < slot)
+            .copied()
+            .collect();
+        for key in pruned {
+            self.take_early(&key);
+        }
+    }
+}
+
+pub struct SignatureRounds {
+    /// The duties of our validators, the partial signatures of rounds not started yet being only
+    /// kept for them.
+    duties: Arc<dyn ScheduledDuties>,
+    state: Mutex<State>,
+}
+
+impl SignatureRounds {
+    pub fn new(duties: Arc<dyn ScheduledDuties>) -> Self {
+        Self {
+            duties,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Runs the round of `key` with our partial signature, waiting for at most `timeout` for the
+    /// signature of the validator to be reconstructed.
+    pub async fn collect(
+        &self,
+        key: RoundKey,
+        committee: &Arc<ValidatorCommittee>,
+        message: &PartialSignatureMessage,
+        timeout: Duration,
+    ) -> Option<Signature> {
+        let (done, reconstructed) = oneshot::channel();
+        {
+            let mut state = self.state.lock();
+            // The rounds of the previous slot may still be running.
+            state.prune(key.slot.saturating_sub(1));
+            state.rounds.insert(
+                key,
+                Round {
+                    collection_key: CollectionKey {
+                        validator_public_key: committee.validator_public_key.to_bytes().to_vec(),
+                        kind: key.kind,
+                        slot: key.slot,
+                        signing_root: key.signing_root,
+                    },
+                    committee: committee.clone(),
+                    done: Some(done),
+                },
+            );
+            let early = state.take_early(&key);
+            for message in std::iter::once(message).chain(&early) {
+                state.add(&key, message);
+            }
+        }
+
+        let result = tokio::time::timeout(timeout, reconstructed).await;
+        self.state.lock().rounds.remove(&key);
+        result.ok()?.ok()
+    }
+
+    /// Adds the partial signatures of `kind` broadcast by another operator.
+    pub fn add(&self, kind: PartialSignatureKind, messages: &PartialSignatureMessages) {
+        let mut state = self.state.lock();
+        for message in &messages.messages {
+            let key = RoundKey {
+                validator_index: message.validator_index,
+                kind,
+                slot: messages.slot,
+                signing_root: message.signing_root,
+            };
+            if state.rounds.contains_key(&key) {
+                state.add(&key, message);
+                continue;
+            }
+            if self.duties.has_duty(key.validator_index, key.slot) {
+                state.add_early(key, message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{committee, Committee};
+    use blst::min_pk::SecretKey;
+    use signature_collector::ETH_DST;
+    use ssv_types::{CommitteeId, PartialSignature};
+
+    const SLOT: u64 = 100;
+    const VALIDATOR_INDEX: u64 = 7;
+    const KIND: PartialSignatureKind = PartialSignatureKind::RandaoPartialSig;
+
+    /// Validator 7 has a duty at slot 100.
+    struct Duties;
+
+    impl ScheduledDuties for Duties {
+        fn has_duty(&self, validator_index: u64, slot: u64) -> bool {
+            validator_index == VALIDATOR_INDEX && slot == SLOT
+        }
+
+        fn has_committee_duty(&self, _committee_id: &CommitteeId, _slot: u64) -> bool {
+            false
+        }
+    }
+
+    fn key(validator_index: u64, slot: u64, signing_root: [u8; 32]) -> RoundKey {
+        RoundKey {
+            validator_index,
+            kind: KIND,
+            slot,
+            signing_root,
+        }
+    }
+
+    /// The partial signature of `signer` made with `share`.
+    fn message(key: &RoundKey, signer: OperatorId, share: &SecretKey) -> PartialSignatureMessage {
+        PartialSignatureMessage {
+            partial_signature: PartialSignature(
+                share.sign(&key.signing_root, ETH_DST, &[]).to_bytes(),
+            ),
+            signing_root: key.signing_root,
+            signer,
+            validator_index: key.validator_index,
+        }
+    }
+
+    fn messages(
+        key: &RoundKey,
+        messages: Vec<PartialSignatureMessage>,
+    ) -> PartialSignatureMessages {
+        PartialSignatureMessages {
+            kind: key.kind as u64,
+            slot: key.slot,
+            messages,
+        }
+    }
+
+    fn early_count(rounds: &SignatureRounds) -> usize {
+        rounds.state.lock().early.values().map(Vec::len).sum()
+    }
+
+    #[tokio::test]
+    async fn distinct_early_partial_signatures_of_a_signer_are_kept() {
+        let Committee {
+            secret_key,
+            shares,
+            committee,
+        } = committee(1);
+        let rounds = SignatureRounds::new(Arc::new(Duties));
+        let key = key(VALIDATOR_INDEX, SLOT, [0x33; 32]);
+
+        // An operator first sends a partial signature made with another share, then its own.
+        let invalid = message(&key, shares[1].0, &shares[2].1);
+        let valid = message(&key, shares[1].0, &shares[1].1);
+        rounds.add(KIND, &messages(&key, vec![invalid.clone(), valid.clone()]));
+        rounds.add(KIND, &messages(&key, vec![valid]));
+        rounds.add(
+            KIND,
+            &messages(&key, vec![message(&key, shares[2].0, &shares[2].1)]),
+        );
+        assert_eq!(early_count(&rounds), 3);
+
+        let ours = message(&key, shares[0].0, &shares[0].1);
+        let signature = rounds
+            .collect(key, &committee, &ours, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(signature, secret_key.sign(&key.signing_root, ETH_DST, &[]));
+        assert_eq!(early_count(&rounds), 0);
+        assert!(rounds.state.lock().early_counts.is_empty());
+    }
+
+    #[test]
+    fn early_partial_signatures_are_only_kept_for_our_duties() {
+        let shares = committee(1).shares;
+        let rounds = SignatureRounds::new(Arc::new(Duties));
+        for key in [
+            key(VALIDATOR_INDEX + 1, SLOT, [0x33; 32]),
+            key(VALIDATOR_INDEX, SLOT + 1, [0x33; 32]),
+        ] {
+            rounds.add(
+                KIND,
+                &messages(&key, vec![message(&key, shares[1].0, &shares[1].1)]),
+            );
+        }
+        assert_eq!(early_count(&rounds), 0);
+    }
+
+    #[test]
+    fn early_partial_signatures_are_bounded_for_each_signer() {
+        let shares = committee(1).shares;
+        let rounds = SignatureRounds::new(Arc::new(Duties));
+        let flood = (0..=MAX_EARLY_PARTIAL_SIGNATURES_PER_SIGNER)
+            .map(|i| {
+                let mut signing_root = [0; 32];
+                signing_root[..8].copy_from_slice(&(i as u64).to_le_bytes());
+                message(
+                    &key(VALIDATOR_INDEX, SLOT, signing_root),
+                    shares[1].0,
+                    &shares[1].1,
+                )
+            })
+            .collect();
+        let key = key(VALIDATOR_INDEX, SLOT, [0x33; 32]);
+        rounds.add(KIND, &messages(&key, flood));
+        assert_eq!(
+            early_count(&rounds),
+            MAX_EARLY_PARTIAL_SIGNATURES_PER_SIGNER
+        );
+
+        // The partial signatures of the other signers are still kept.
+        rounds.add(
+            KIND,
+            &messages(&key, vec![message(&key, shares[2].0, &shares[2].1)]),
+        );
+        assert_eq!(
+            early_count(&rounds),
+            MAX_EARLY_PARTIAL_SIGNATURES_PER_SIGNER + 1
+        );
+
+        // They are forgotten with their slot.
+        rounds.state.lock().prune(SLOT + 1);
+        assert_eq!(early_count(&rounds), 0);
+        assert_eq!(rounds.state.lock().early_total, 0);
+    }
+}
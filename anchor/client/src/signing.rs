@@ -0,0 +1,313 @@
+//! The signing roots of the objects signed by validators, and the selection of aggregators from
+//! their selection proofs.
+//!
+//! A validator signs the hash tree root of an object mixed with a domain, which binds the
+//! signature to the kind of the object, the fork and the chain.
+
+use crate::attestation_runner::BeaconVote;
+use crate::beacon_node::{AttestationData, BeaconNodes, Checkpoint, Fork, GenesisData};
+use blst::min_pk::Signature;
+use ethereum_hashing::{hash32_concat, hash_fixed};
+
+/// The kind of signed object, prefixing its domain.
+pub type DomainType = [u8; 4];
+
+pub const DOMAIN_BEACON_ATTESTER: DomainType = [1, 0, 0, 0];
+pub const DOMAIN_RANDAO: DomainType = [2, 0, 0, 0];
+pub const DOMAIN_SELECTION_PROOF: DomainType = [5, 0, 0, 0];
+pub const DOMAIN_SYNC_COMMITTEE_SELECTION_PROOF: DomainType = [8, 0, 0, 0];
+
+/// The number of attesters of a committee expected to aggregate its attestations.
+pub const TARGET_AGGREGATORS_PER_COMMITTEE: u64 = 16;
+/// The number of members of a sync subcommittee expected to aggregate its contributions.
+pub const TARGET_AGGREGATORS_PER_SYNC_SUBCOMMITTEE: u64 = 16;
+pub const SYNC_COMMITTEE_SIZE: u64 = 512;
+pub const SYNC_COMMITTEE_SUBNET_COUNT: u64 = 4;
+
+/// What the signatures of the validators commit to besides the signed objects: the chain and its
+/// fork.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigningContext {
+    genesis_validators_root: [u8; 32],
+    previous_version: [u8; 4],
+    current_version: [u8; 4],
+    /// The epoch from which the current version applies.
+    fork_epoch: u64,
+}
+
+impl SigningContext {
+    pub fn new(genesis: &GenesisData, fork: &Fork) -> Result<Self, String> {
+        let version = |version: &[u8]| {
+            version
+                .try_into()
+                .map_err(|_| format!("Invalid fork version 0x{}", hex::encode(version)))
+        };
+        Ok(Self {
+            genesis_validators_root: genesis
+                .genesis_validators_root
+                .as_slice()
+                .try_into()
+                .map_err(|_| "Invalid genesis validators root".to_string())?,
+            previous_version: version(&fork.previous_version)?,
+            current_version: version(&fork.current_version)?,
+            fork_epoch: fork.epoch,
+        })
+    }
+
+    /// Requests the genesis and the fork of the head state from the beacon nodes.
+    pub async fn fetch(beacon_nodes: &BeaconNodes) -> Result<Self, String> {
+        let (genesis, fork) =
+            futures::future::try_join(beacon_nodes.genesis(), beacon_nodes.fork()).await?;
+        Self::new(&genesis, &fork)
+    }
+
+    /// The domain of the objects of `domain_type` signed at `epoch`.
+    pub fn domain(&self, domain_type: DomainType, epoch: u64) -> [u8; 32] {
+        let version = if epoch < self.fork_epoch {
+            self.previous_version
+        } else {
+            self.current_version
+        };
+        // The hash tree root of the `ForkData` of the version and the chain.
+        let mut padded_version = [0; 32];
+        padded_version[..4].copy_from_slice(&version);
+        let fork_data_root = hash32_concat(&padded_version, &self.genesis_validators_root);
+
+        let mut domain = [0; 32];
+        domain[..4].copy_from_slice(&domain_type);
+        domain[4..].copy_from_slice(&fork_data_root[..28]);
+        domain
+    }
+
+    /// The root signed for the object of root `object_root`: the hash tree root of its
+    /// `SigningData`.
+    pub fn signing_root(
+        &self,
+        object_root: [u8; 32],
+        domain_type: DomainType,
+        epoch: u64,
+    ) -> [u8; 32] {
+        hash32_concat(&object_root, &self.domain(domain_type, epoch))
+    }
+
+    /// The root signed by the attestation of `data`, in the domain of its target epoch.
+    pub fn attestation_root(&self, data: &AttestationData) -> Result<[u8; 32], String> {
+        Ok(self.signing_root(
+            attestation_data_root(data)?,
+            DOMAIN_BEACON_ATTESTER,
+            data.target.epoch,
+        ))
+    }
+
+    /// The root signed by the RANDAO reveal of a block proposed at `epoch`.
+    pub fn randao_root(&self, epoch: u64) -> [u8; 32] {
+        self.signing_root(uint64_root(epoch), DOMAIN_RANDAO, epoch)
+    }
+
+    /// The root signed by the selection proof of an attestation aggregator.
+    pub fn selection_proof_root(&self, slot: u64, slots_per_epoch: u64) -> [u8; 32] {
+        self.signing_root(
+            uint64_root(slot),
+            DOMAIN_SELECTION_PROOF,
+            slot / slots_per_epoch,
+        )
+    }
+
+    /// The root signed by the selection proof of a sync committee contribution aggregator: the
+    /// root of its `SyncAggregatorSelectionData`.
+    pub fn sync_selection_proof_root(
+        &self,
+        slot: u64,
+        subcommittee_index: u64,
+        slots_per_epoch: u64,
+    ) -> [u8; 32] {
+        let selection_data_root =
+            hash32_concat(&uint64_root(slot), &uint64_root(subcommittee_index));
+        self.signing_root(
+            selection_data_root,
+            DOMAIN_SYNC_COMMITTEE_SELECTION_PROOF,
+            slot / slots_per_epoch,
+        )
+    }
+}
+
+/// Whether the validator of `selection_proof` aggregates the attestations of its committee.
+pub fn is_aggregator(committee_length: u64, selection_proof: &Signature) -> bool {
+    let modulo = (committee_length / TARGET_AGGREGATORS_PER_COMMITTEE).max(1);
+    is_selected(selection_proof, modulo)
+}
+
+/// Whether the validator of `selection_proof` aggregates the contributions of its sync
+/// subcommittee.
+pub fn is_sync_committee_aggregator(selection_proof: &Signature) -> bool {
+    let modulo = (SYNC_COMMITTEE_SIZE
+        / SYNC_COMMITTEE_SUBNET_COUNT
+        / TARGET_AGGREGATORS_PER_SYNC_SUBCOMMITTEE)
+        .max(1);
+    is_selected(selection_proof, modulo)
+}
+
+/// Whether the first 8 bytes of the hash of the proof, as a little endian integer, are a
+/// multiple of `modulo`.
+fn is_selected(selection_proof: &Signature, modulo: u64) -> bool {
+    let hash = hash_fixed(&selection_proof.to_bytes());
+    let value = u64::from_le_bytes(hash[..8].try_into().expect("The hash is 32 bytes long"));
+    value % modulo == 0
+}
+
+/// The hash tree root of attestation data, merkleizing its five fields padded to eight leaves.
+fn attestation_data_root(data: &AttestationData) -> Result<[u8; 32], String> {
+    let leaves = [
+        uint64_root(data.slot),
+        uint64_root(data.index),
+        bytes32_root(&data.beacon_block_root)?,
+        checkpoint_root(&data.source)?,
+        checkpoint_root(&data.target)?,
+        [0; 32],
+        [0; 32],
+        [0; 32],
+    ];
+    Ok(merkleize(&leaves))
+}
+
+/// The hash tree root of the vote of a committee, merkleizing its three fields padded to four
+/// leaves.
+pub fn beacon_vote_root(vote: &BeaconVote) -> Result<[u8; 32], String> {
+    let leaves = [
+        bytes32_root(&vote.beacon_block_root)?,
+        checkpoint_root(&vote.source)?,
+        checkpoint_root(&vote.target)?,
+        [0; 32],
+    ];
+    Ok(merkleize(&leaves))
+}
+
+/// The root of a power of two number of leaves.
+fn merkleize(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash32_concat(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer[0]
+}
+
+fn checkpoint_root(checkpoint: &Checkpoint) -> Result<[u8; 32], String> {
+    Ok(hash32_concat(
+        &uint64_root(checkpoint.epoch),
+        &bytes32_root(&checkpoint.root)?,
+    ))
+}
+
+/// The hash tree root of a root, which is the root itself.
+fn bytes32_root(bytes: &[u8]) -> Result<[u8; 32], String> {
+    bytes
+        .try_into()
+        .map_err(|_| format!("Invalid root 0x{}", hex::encode(bytes)))
+}
+
+/// The hash tree root of an integer.
+fn uint64_root(value: u64) -> [u8; 32] {
+    let mut root = [0; 32];
+    root[..8].copy_from_slice(&value.to_le_bytes());
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blst::min_pk::SecretKey;
+    use signature_collector::ETH_DST;
+
+    fn context(genesis_validators_root: u8, fork_epoch: u64) -> SigningContext {
+        let genesis = GenesisData {
+            genesis_time: 0,
+            genesis_validators_root: vec![genesis_validators_root; 32],
+        };
+        let fork = Fork {
+            previous_version: vec![0, 0, 0, 0],
+            current_version: vec![1, 0, 0, 0],
+            epoch: fork_epoch,
+        };
+        SigningContext::new(&genesis, &fork).unwrap()
+    }
+
+    #[test]
+    fn domains_match_the_chain() {
+        // The deposit domain of mainnet, of the genesis fork version and a zero root.
+        let domain = context(0, 10).domain([3, 0, 0, 0], 0);
+        assert_eq!(
+            hex::encode(domain),
+            "03000000f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a9"
+        );
+
+        // The domain changes with the fork and the chain.
+        let before_fork = context(0, 10).domain(DOMAIN_RANDAO, 9);
+        assert_ne!(context(0, 10).domain(DOMAIN_RANDAO, 10), before_fork);
+        assert_ne!(context(1, 10).domain(DOMAIN_RANDAO, 9), before_fork);
+        let invalid_root = GenesisData {
+            genesis_time: 0,
+            genesis_validators_root: vec![0; 31],
+        };
+        let fork = Fork {
+            previous_version: vec![0; 4],
+            current_version: vec![0; 4],
+            epoch: 0,
+        };
+        assert!(SigningContext::new(&invalid_root, &fork).is_err());
+    }
+
+    #[test]
+    fn signing_roots_differ_by_object() {
+        let context = context(7, 0);
+        let checkpoint = |epoch| Checkpoint {
+            epoch,
+            root: vec![epoch as u8; 32],
+        };
+        let mut attestation_data = AttestationData {
+            slot: 64,
+            index: 3,
+            beacon_block_root: vec![1; 32],
+            source: checkpoint(1),
+            target: checkpoint(2),
+        };
+        let attestation_root = context.attestation_root(&attestation_data).unwrap();
+        attestation_data.beacon_block_root = vec![2; 32];
+        let other_attestation_root = context.attestation_root(&attestation_data).unwrap();
+        attestation_data.beacon_block_root = vec![2; 31];
+        assert!(context.attestation_root(&attestation_data).is_err());
+
+        let roots = [
+            attestation_root,
+            other_attestation_root,
+            context.randao_root(1),
+            context.randao_root(2),
+            context.selection_proof_root(32, 32),
+            context.selection_proof_root(33, 32),
+            context.sync_selection_proof_root(32, 0, 32),
+            context.sync_selection_proof_root(32, 1, 32),
+        ];
+        for (i, root) in roots.iter().enumerate() {
+            assert!(!roots[i + 1..].contains(root));
+        }
+    }
+
+    #[test]
+    fn a_share_of_the_validators_are_aggregators() {
+        let secret_key = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let proofs: Vec<Signature> = (0..64u64)
+            .map(|slot| secret_key.sign(&uint64_root(slot), ETH_DST, &[]))
+            .collect();
+
+        // Every member of a small committee aggregates.
+        assert!(proofs.iter().all(|proof| is_aggregator(15, proof)));
+        let aggregators = proofs.iter().filter(|proof| is_aggregator(512, proof));
+        assert!((1..16).contains(&aggregators.count()));
+        let aggregators = proofs
+            .iter()
+            .filter(|proof| is_sync_committee_aggregator(proof));
+        assert!((1..32).contains(&aggregators.count()));
+    }
+}
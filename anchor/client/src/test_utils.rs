@@ -0,0 +1,71 @@
+//! Fixtures shared by the tests of the client.
+
+use crate::beacon_node::{ChainSpec, Fork, GenesisData};
+use crate::duties_service::ScheduledDuties;
+use crate::signing::SigningContext;
+use blst::min_pk::SecretKey;
+use signature_collector::{split_secret_key, ValidatorCommittee};
+use ssv_types::{CommitteeId, OperatorId};
+use std::sync::Arc;
+
+/// The mainnet parameters, before Electra.
+pub const SPEC: ChainSpec = ChainSpec {
+    seconds_per_slot: 12,
+    slots_per_epoch: 32,
+    epochs_per_sync_committee_period: 256,
+    electra_fork_epoch: None,
+};
+
+/// Every validator has a duty at every slot.
+pub struct AllDuties;
+
+impl ScheduledDuties for AllDuties {
+    fn has_duty(&self, _validator_index: u64, _slot: u64) -> bool {
+        true
+    }
+
+    fn has_committee_duty(&self, _committee_id: &CommitteeId, _slot: u64) -> bool {
+        true
+    }
+}
+
+/// A validator shared with 4 operators, with the key shares of each operator.
+pub struct Committee {
+    pub secret_key: SecretKey,
+    pub shares: Vec<(OperatorId, SecretKey)>,
+    pub committee: Arc<ValidatorCommittee>,
+}
+
+/// A validator of the committee of operators 3, 8, 21 and 42, whose key is generated from
+/// `seed`.
+pub fn committee(seed: u8) -> Committee {
+    let secret_key = SecretKey::key_gen(&[seed; 32], &[]).unwrap();
+    let operator_ids: Vec<OperatorId> = [3, 8, 21, 42].map(OperatorId).to_vec();
+    let shares = split_secret_key(&secret_key, &operator_ids, 3).unwrap();
+    let committee = ValidatorCommittee {
+        validator_public_key: secret_key.sk_to_pk(),
+        share_public_keys: operator_ids
+            .iter()
+            .zip(&shares)
+            .map(|(operator_id, share)| (*operator_id, share.sk_to_pk()))
+            .collect(),
+    };
+    Committee {
+        secret_key,
+        shares: operator_ids.into_iter().zip(shares).collect(),
+        committee: Arc::new(committee),
+    }
+}
+
+pub fn context() -> SigningContext {
+    let genesis = GenesisData {
+        genesis_time: 0,
+        genesis_validators_root: vec![0x4b; 32],
+    };
+    let fork = Fork {
+        previous_version: vec![3, 0, 0, 0],
+        current_version: vec![4, 0, 0, 0],
+        epoch: 0,
+    };
+    SigningContext::new(&genesis, &fork).unwrap()
+}
@@ -0,0 +1,206 @@
+//! The chain and registry information the messages received over gossip are validated against.
+//!
+//! The committees and the operator public keys are read from the SSV registry in the database
+//! and kept in memory, as every gossip message is validated against them. They are refreshed at
+//! every slot to follow the contract events.
+
+use database::Database;
+use network::ValidationContext;
+use operator_key::OperatorPublicKey;
+use parking_lot::RwLock;
+use slot_clock::SlotClock;
+use ssv_types::{CommitteeId, MessageId, OperatorId, Role};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// The committees and operators of the registry, indexed for the validation of messages.
+#[derive(Default)]
+struct Registry {
+    /// The operators of each committee.
+    committees: HashMap<CommitteeId, Vec<OperatorId>>,
+    /// The operators of each validator, by public key.
+    validators: HashMap<Vec<u8>, Vec<OperatorId>>,
+    operator_keys: HashMap<OperatorId, Arc<OperatorPublicKey>>,
+}
+
+pub struct RegistryValidationContext<T> {
+    database: Arc<Database>,
+    slot_clock: T,
+    registry: RwLock<Registry>,
+}
+
+impl<T: SlotClock> RegistryValidationContext<T> {
+    /// Creates the context, reading the registry from the database.
+    pub fn new(database: Arc<Database>, slot_clock: T) -> Result<Self, String> {
+        let context = Self {
+            database,
+            slot_clock,
+            registry: RwLock::new(Registry::default()),
+        };
+        context.refresh()?;
+        Ok(context)
+    }
+
+    /// Refreshes the registry at every slot.
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            let delay = self
+                .slot_clock
+                .duration_to_next_slot()
+                .unwrap_or_else(|| self.slot_clock.slot_duration());
+            tokio::time::sleep(delay).await;
+            if let Err(error) = self.refresh() {
+                warn!(
+                    error,
+                    "Unable to refresh the registry of the message validation"
+                );
+            }
+        }
+    }
+
+    /// Reads the committees and operator public keys from the database.
+    pub fn refresh(&self) -> Result<(), String> {
+        let validators = self
+            .database
+            .validators()
+            .map_err(|e| format!("Unable to read the validators: {e}"))?;
+        let operators = self
+            .database
+            .operators()
+            .map_err(|e| format!("Unable to read the operators: {e}"))?;
+
+        let mut registry = Registry::default();
+        for validator in validators {
+            let mut operator_ids = validator.operator_ids;
+            operator_ids.sort();
+            registry.committees.insert(
+                CommitteeId::from_operators(&operator_ids),
+                operator_ids.clone(),
+            );
+            registry
+                .validators
+                .insert(validator.public_key, operator_ids);
+        }
+        for operator in operators {
+            match OperatorPublicKey::from_registered(&operator.public_key) {
+                Ok(public_key) => {
+                    registry
+                        .operator_keys
+                        .insert(operator.id, Arc::new(public_key));
+                }
+                Err(error) => debug!(operator_id = %operator.id, error, "Invalid operator key"),
+            }
+        }
+        *self.registry.write() = registry;
+        Ok(())
+    }
+}
+
+impl<T: SlotClock> ValidationContext for RegistryValidationContext<T> {
+    fn current_slot(&self) -> Option<u64> {
+        self.slot_clock.now().map(|slot| slot.as_u64())
+    }
+
+    fn committee(&self, message_id: &MessageId) -> Option<Vec<OperatorId>> {
+        let registry = self.registry.read();
+        let duty_executor_id = message_id.duty_executor_id();
+        match message_id.role()? {
+            Role::Committee => {
+                // Committee ids are right aligned in the duty executor id.
+                let committee_id = duty_executor_id[duty_executor_id.len() - 32..]
+                    .try_into()
+                    .map(CommitteeId)
+                    .ok()?;
+                registry.committees.get(&committee_id).cloned()
+            }
+            _ => registry.validators.get(duty_executor_id).cloned(),
+        }
+    }
+
+    fn operator_public_key(&self, operator_id: OperatorId) -> Option<Arc<OperatorPublicKey>> {
+        self.registry
+            .read()
+            .operator_keys
+            .get(&operator_id)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use operator_key::OperatorKey;
+    use slot_clock::{ManualSlotClock, Slot};
+    use ssv_types::{Address, Operator, Share, Validator};
+    use std::time::Duration;
+
+    const DOMAIN: [u8; 4] = [0, 0, 5, 2];
+
+    fn slot_clock() -> ManualSlotClock {
+        let slot_clock =
+            ManualSlotClock::new(Slot::new(0), Duration::ZERO, Duration::from_secs(12));
+        slot_clock.set_slot(7);
+        slot_clock
+    }
+
+    #[test]
+    fn committees_and_keys_are_read_from_the_registry() {
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let key = OperatorKey::generate().unwrap();
+        let operator_ids: Vec<OperatorId> = [4, 1, 3, 2].map(OperatorId).to_vec();
+        let validator = Validator {
+            public_key: vec![0xaa; 48],
+            owner: Address::default(),
+            operator_ids: operator_ids.clone(),
+        };
+        database
+            .write(|registry| {
+                for operator_id in &operator_ids {
+                    registry.insert_operator(&Operator {
+                        id: *operator_id,
+                        owner: Address::default(),
+                        public_key: key.public_key_base64().unwrap().into_bytes(),
+                        fee: 0,
+                    })?;
+                }
+                let shares: Vec<Share> = operator_ids
+                    .iter()
+                    .map(|operator_id| Share {
+                        validator_public_key: validator.public_key.clone(),
+                        operator_id: *operator_id,
+                        public_key: vec![0xbb; 48],
+                        encrypted_key: vec![],
+                    })
+                    .collect();
+                registry.insert_validator(&validator, &shares)
+            })
+            .unwrap();
+
+        let context = RegistryValidationContext::new(database, slot_clock()).unwrap();
+        let sorted: Vec<OperatorId> = (1..=4).map(OperatorId).collect();
+        let committee_id = CommitteeId::from_operators(&operator_ids);
+        assert_eq!(
+            context.committee(&MessageId::new(DOMAIN, Role::Committee, &committee_id.0)),
+            Some(sorted.clone())
+        );
+        assert_eq!(
+            context.committee(&MessageId::new(
+                DOMAIN,
+                Role::Proposer,
+                &validator.public_key
+            )),
+            Some(sorted)
+        );
+        assert_eq!(
+            context.committee(&MessageId::new(DOMAIN, Role::Committee, &[1; 32])),
+            None
+        );
+        assert_eq!(
+            context.operator_public_key(OperatorId(1)).as_deref(),
+            Some(&OperatorPublicKey::from(&key))
+        );
+        assert!(context.operator_public_key(OperatorId(5)).is_none());
+        assert_eq!(context.current_slot(), Some(7));
+    }
+}
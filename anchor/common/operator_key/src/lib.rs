@@ -0,0 +1,338 @@
+//! The RSA key of an SSV operator, which the owners of validators encrypt the key shares of the
+//! operator with, and which signs the messages the operator publishes.
+
+mod keystore;
+
+pub use keystore::{EncryptedOperatorKey, Module, DEFAULT_PBKDF2_ITERATIONS};
+
+use base64::prelude::*;
+use blst::min_pk::SecretKey;
+use rsa::pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePublicKey};
+use rsa::{Pkcs1v15Encrypt, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use ssv_types::Share;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::info;
+
+/// The name of the operator keystore written by the SSV node.
+pub const OPERATOR_KEYSTORE_FILENAME: &str = "encrypted_private_key.json";
+
+/// The size of the RSA keys of the operators, in bits.
+const RSA_KEY_BITS: usize = 2048;
+const PEM_RSA_PRIVATE_KEY_TAG: &str = "RSA PRIVATE KEY";
+/// The tag of the PEM encoded public keys registered in the contract. Despite the tag, the PEM
+/// holds a PKIX encoded public key.
+const PEM_RSA_PUBLIC_KEY_TAG: &str = "RSA PUBLIC KEY";
+
+/// The RSA private key of an operator.
+#[derive(Clone)]
+pub struct OperatorKey {
+    private_key: RsaPrivateKey,
+    public_key: RsaPublicKey,
+}
+
+impl OperatorKey {
+    pub fn generate() -> Result<Self, String> {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), RSA_KEY_BITS)
+            .map_err(|e| format!("Unable to generate the operator key: {e}"))?;
+        Ok(private_key.into())
+    }
+
+    /// Parses a PEM encoded private key, either PKCS#1 or PKCS#8, optionally base64 encoded as
+    /// in the configuration of the SSV node.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, String> {
+        let pem = match BASE64_STANDARD.decode(pem.trim_ascii()) {
+            Ok(decoded) => decoded,
+            Err(_) => pem.to_vec(),
+        };
+        let pem = std::str::from_utf8(&pem).map_err(|_| "The operator key is not PEM encoded")?;
+        RsaPrivateKey::from_pkcs1_pem(pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs8_pem(pem))
+            .map(Into::into)
+            .map_err(|e| format!("Invalid operator key: {e}"))
+    }
+
+    /// The PKCS#1 PEM encoding of the private key.
+    pub fn to_pem(&self) -> Result<Vec<u8>, String> {
+        let der = self
+            .private_key
+            .to_pkcs1_der()
+            .map_err(|e| format!("Unable to encode the operator key: {e}"))?;
+        Ok(encode_pem(PEM_RSA_PRIVATE_KEY_TAG, der.as_bytes().to_vec()).into_bytes())
+    }
+
+    /// The public key in the format registered in the contract: the base64 encoded PEM of the
+    /// PKIX encoded key.
+    pub fn public_key_base64(&self) -> Result<String, String> {
+        let der = self
+            .public_key
+            .to_public_key_der()
+            .map_err(|e| format!("Unable to encode the operator public key: {e}"))?;
+        Ok(BASE64_STANDARD.encode(encode_pem(PEM_RSA_PUBLIC_KEY_TAG, der.into_vec())))
+    }
+
+    /// Whether a public key registered in the contract is the public key of this operator.
+    pub fn matches(&self, registered_public_key: &[u8]) -> bool {
+        parse_public_key(registered_public_key)
+            .is_ok_and(|public_key| public_key == self.public_key)
+    }
+
+    /// Signs a message as the SSV node does: a PKCS#1 v1.5 signature of its SHA-256 hash.
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        self.private_key
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(message))
+            .map_err(|e| format!("Unable to sign the message: {e}"))
+    }
+
+    /// Decrypts the key share of this operator and verifies it against the public key of the
+    /// share registered with the validator.
+    pub fn decrypt_share(&self, share: &Share) -> Result<SecretKey, String> {
+        let decrypted = self
+            .private_key
+            .decrypt(Pkcs1v15Encrypt, &share.encrypted_key)
+            .map_err(|e| format!("Unable to decrypt the key share: {e}"))?;
+        // The share is the hex encoded BLS secret key.
+        let decrypted = std::str::from_utf8(&decrypted)
+            .map_err(|_| "The decrypted key share is not hex encoded")?;
+        let decrypted = decrypted.trim();
+        let bytes = hex::decode(decrypted.strip_prefix("0x").unwrap_or(decrypted))
+            .map_err(|e| format!("The decrypted key share is not hex encoded: {e}"))?;
+        let secret_key =
+            SecretKey::from_bytes(&bytes).map_err(|e| format!("Invalid BLS key share: {e:?}"))?;
+        if secret_key.sk_to_pk().to_bytes().as_slice() != share.public_key.as_slice() {
+            return Err("The key share does not match its registered public key".to_string());
+        }
+        Ok(secret_key)
+    }
+}
+
+impl From<RsaPrivateKey> for OperatorKey {
+    fn from(private_key: RsaPrivateKey) -> Self {
+        Self {
+            public_key: private_key.to_public_key(),
+            private_key,
+        }
+    }
+}
+
+/// The RSA public key of an operator, which the signatures of its messages are verified with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperatorPublicKey(RsaPublicKey);
+
+impl OperatorPublicKey {
+    /// Parses a public key in the format registered in the contract.
+    pub fn from_registered(registered_public_key: &[u8]) -> Result<Self, String> {
+        parse_public_key(registered_public_key).map(Self)
+    }
+
+    /// Whether `signature` is the signature of `message` by this operator.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        self.0
+            .verify(
+                Pkcs1v15Sign::new::<Sha256>(),
+                &Sha256::digest(message),
+                signature,
+            )
+            .is_ok()
+    }
+}
+
+impl From<&OperatorKey> for OperatorPublicKey {
+    fn from(key: &OperatorKey) -> Self {
+        Self(key.public_key.clone())
+    }
+}
+
+/// Loads the operator key from its keystore, decrypting it with the password in
+/// `password_file`. A missing keystore is an error, see `generate_operator_key`.
+pub fn load_operator_key(keystore: &Path, password_file: &Path) -> Result<OperatorKey, String> {
+    let password = read_password(password_file)?;
+    if !keystore.exists() {
+        return Err(format!(
+            "Operator keystore {keystore:?} not found, generate a key with \
+             `anchor operator-key generate`"
+        ));
+    }
+    let contents = fs::read(keystore)
+        .map_err(|e| format!("Unable to read operator keystore {keystore:?}: {e}"))?;
+    let encrypted: EncryptedOperatorKey = serde_json::from_slice(&contents)
+        .map_err(|e| format!("Invalid operator keystore {keystore:?}: {e}"))?;
+    OperatorKey::from_pem(&encrypted.decrypt(&password)?)
+}
+
+/// Generates a new operator key and saves it to `keystore`, encrypted with the password in
+/// `password_file`. An existing keystore is never overwritten.
+pub fn generate_operator_key(keystore: &Path, password_file: &Path) -> Result<OperatorKey, String> {
+    let password = read_password(password_file)?;
+    if keystore.exists() {
+        return Err(format!("Operator keystore {keystore:?} already exists"));
+    }
+    let key = OperatorKey::generate()?;
+    let public_key = key.public_key_base64()?;
+    export_operator_key(&key, keystore, &password, DEFAULT_PBKDF2_ITERATIONS)?;
+    info!(
+        file = ?keystore,
+        public_key,
+        "New operator key generated, register its public key in the SSV network"
+    );
+    Ok(key)
+}
+
+fn read_password(password_file: &Path) -> Result<String, String> {
+    let password = fs::read_to_string(password_file)
+        .map_err(|e| format!("Unable to read operator password file {password_file:?}: {e}"))?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Writes the operator key to a keystore that is only readable by the current user.
+pub fn export_operator_key(
+    key: &OperatorKey,
+    keystore: &Path,
+    password: &str,
+    iterations: u32,
+) -> Result<(), String> {
+    let encrypted = EncryptedOperatorKey::encrypt(
+        &key.to_pem()?,
+        key.public_key_base64()?,
+        password,
+        iterations,
+    )?;
+    let contents = serde_json::to_vec_pretty(&encrypted)
+        .map_err(|e| format!("Unable to serialize operator keystore: {e}"))?;
+
+    if let Some(dir) = keystore.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Unable to create {dir:?}: {e}"))?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(keystore)
+        .and_then(|mut file| file.write_all(&contents))
+        .map_err(|e| format!("Unable to write operator keystore {keystore:?}: {e}"))
+}
+
+fn parse_public_key(registered_public_key: &[u8]) -> Result<RsaPublicKey, String> {
+    let pem = BASE64_STANDARD
+        .decode(registered_public_key)
+        .map_err(|e| format!("Public key is not base64 encoded: {e}"))?;
+    let pem = pem::parse(pem).map_err(|e| format!("Public key is not PEM encoded: {e}"))?;
+    RsaPublicKey::from_public_key_der(pem.contents())
+        .map_err(|e| format!("Invalid RSA public key: {e}"))
+}
+
+/// Encodes a PEM with the line endings of the SSV node.
+fn encode_pem(tag: &str, contents: Vec<u8>) -> String {
+    pem::encode_config(
+        &pem::Pem::new(tag, contents),
+        pem::EncodeConfig::new().set_line_ending(pem::LineEnding::LF),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssv_types::OperatorId;
+    use std::sync::LazyLock;
+    use tempfile::TempDir;
+
+    /// Generating RSA keys is slow, so the tests share a key.
+    static KEY: LazyLock<OperatorKey> = LazyLock::new(|| OperatorKey::generate().unwrap());
+
+    fn share(key_share: &SecretKey, plaintext: &[u8]) -> Share {
+        let encrypted_key = KEY
+            .public_key
+            .encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, plaintext)
+            .unwrap();
+        Share {
+            validator_public_key: vec![0xaa; 48],
+            operator_id: OperatorId(1),
+            public_key: key_share.sk_to_pk().to_bytes().to_vec(),
+            encrypted_key,
+        }
+    }
+
+    #[test]
+    fn keystore_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let keystore = dir.path().join(OPERATOR_KEYSTORE_FILENAME);
+        export_operator_key(&KEY, &keystore, "password", 16).unwrap();
+
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "password\n").unwrap();
+        let loaded = load_operator_key(&keystore, &password_file).unwrap();
+        assert_eq!(loaded.public_key, KEY.public_key);
+
+        fs::write(&password_file, "wrong").unwrap();
+        assert!(load_operator_key(&keystore, &password_file).is_err());
+    }
+
+    #[test]
+    fn keys_are_only_generated_explicitly() {
+        let dir = TempDir::new().unwrap();
+        let keystore = dir.path().join(OPERATOR_KEYSTORE_FILENAME);
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "password\n").unwrap();
+        assert!(load_operator_key(&keystore, &password_file).is_err());
+        assert!(!keystore.exists());
+
+        let generated = generate_operator_key(&keystore, &password_file).unwrap();
+        let loaded = load_operator_key(&keystore, &password_file).unwrap();
+        assert_eq!(loaded.public_key, generated.public_key);
+        // The generated key is never overwritten.
+        assert!(generate_operator_key(&keystore, &password_file).is_err());
+        let loaded = load_operator_key(&keystore, &password_file).unwrap();
+        assert_eq!(loaded.public_key, generated.public_key);
+    }
+
+    #[test]
+    fn public_key_matches_its_registration() {
+        let registered = KEY.public_key_base64().unwrap();
+        assert!(KEY.matches(registered.as_bytes()));
+        assert!(!KEY.matches(b"not a key"));
+
+        let pem = KEY.to_pem().unwrap();
+        let reloaded = OperatorKey::from_pem(BASE64_STANDARD.encode(pem).as_bytes()).unwrap();
+        assert!(reloaded.matches(registered.as_bytes()));
+    }
+
+    #[test]
+    fn signatures_are_verified_with_the_registered_key() {
+        let public_key =
+            OperatorPublicKey::from_registered(KEY.public_key_base64().unwrap().as_bytes())
+                .unwrap();
+        assert_eq!(public_key, OperatorPublicKey::from(&*KEY));
+
+        let signature = KEY.sign(b"message").unwrap();
+        assert_eq!(signature.len(), RSA_KEY_BITS / 8);
+        assert!(public_key.verify(b"message", &signature));
+        assert!(!public_key.verify(b"other message", &signature));
+        assert!(!public_key.verify(b"message", &signature[1..]));
+    }
+
+    #[test]
+    fn shares_are_decrypted_and_verified() {
+        let key_share = SecretKey::key_gen(&[7; 32], &[]).unwrap();
+        let plaintext = hex::encode(key_share.to_bytes());
+        let decrypted = KEY
+            .decrypt_share(&share(&key_share, plaintext.as_bytes()))
+            .unwrap();
+        assert_eq!(decrypted.to_bytes(), key_share.to_bytes());
+
+        let other_share = SecretKey::key_gen(&[8; 32], &[]).unwrap();
+        let mut mismatching = share(&key_share, plaintext.as_bytes());
+        mismatching.public_key = other_share.sk_to_pk().to_bytes().to_vec();
+        assert!(KEY.decrypt_share(&mismatching).is_err());
+
+        assert!(KEY
+            .decrypt_share(&share(&key_share, b"not a key share"))
+            .is_err());
+    }
+}
@@ -0,0 +1,46 @@
+/// Implements SSZ encoding for a newtype around a byte array, which `ethereum_ssz` only provides
+/// for a few array lengths.
+macro_rules! impl_ssz_fixed_bytes {
+    ($name:ident, $len:expr) => {
+        impl ssz::Encode for $name {
+            fn is_ssz_fixed_len() -> bool {
+                true
+            }
+
+            fn ssz_fixed_len() -> usize {
+                $len
+            }
+
+            fn ssz_bytes_len(&self) -> usize {
+                $len
+            }
+
+            fn ssz_append(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.0)
+            }
+        }
+
+        impl ssz::Decode for $name {
+            fn is_ssz_fixed_len() -> bool {
+                true
+            }
+
+            fn ssz_fixed_len() -> usize {
+                $len
+            }
+
+            fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, ssz::DecodeError> {
+                let fixed: [u8; $len] =
+                    bytes
+                        .try_into()
+                        .map_err(|_| ssz::DecodeError::InvalidByteLength {
+                            len: bytes.len(),
+                            expected: $len,
+                        })?;
+                Ok(Self(fixed))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_ssz_fixed_bytes;
@@ -0,0 +1,76 @@
+//! Types of the SSV protocol shared between the Anchor components.
+
+mod address;
+mod fixed_bytes;
+pub mod message;
+pub mod partial_sig;
+pub mod registry;
+
+pub use address::Address;
+pub use message::{
+    MessageId, MsgType, QbftMessage, QbftMessageType, Role, SSVMessage, SignedSSVMessage,
+};
+pub use partial_sig::{
+    PartialSignature, PartialSignatureKind, PartialSignatureMessage, PartialSignatureMessages,
+};
+pub use registry::{Cluster, ClusterKey, Operator, Share, Validator};
+
+use derive_more::{Deref, Display, From};
+use sha2::{Digest, Sha256};
+use ssz_derive::{Decode, Encode};
+
+/// The id of an operator registered in the SSV network contract.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deref,
+    Display,
+    From,
+    Encode,
+    Decode,
+)]
+#[ssz(struct_behaviour = "transparent")]
+pub struct OperatorId(pub u64);
+
+/// Identifies a committee of operators. It is the SHA-256 hash of the committee's sorted
+/// operator ids, each encoded as a little endian `u32`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, From, Encode, Decode)]
+#[ssz(struct_behaviour = "transparent")]
+pub struct CommitteeId(pub [u8; 32]);
+
+impl CommitteeId {
+    pub fn from_operators(operator_ids: &[OperatorId]) -> Self {
+        let mut operator_ids = operator_ids.to_vec();
+        operator_ids.sort();
+        let mut hasher = Sha256::new();
+        for operator_id in operator_ids {
+            hasher.update((*operator_id as u32).to_le_bytes());
+        }
+        Self(hasher.finalize().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committee_id_ignores_the_operator_order() {
+        let committee = CommitteeId::from_operators(&[1, 2, 3, 4].map(OperatorId));
+        assert_eq!(
+            committee,
+            CommitteeId::from_operators(&[4, 2, 1, 3].map(OperatorId))
+        );
+        assert_ne!(
+            committee,
+            CommitteeId::from_operators(&[1, 2, 3, 5].map(OperatorId))
+        );
+    }
+}
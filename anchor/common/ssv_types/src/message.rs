@@ -0,0 +1,236 @@
+//! The messages exchanged between operators over gossipsub.
+
+use crate::fixed_bytes::impl_ssz_fixed_bytes;
+use crate::OperatorId;
+use ssz_derive::{Decode, Encode};
+
+/// The maximum number of operators in a committee, and so of signers of a message.
+pub const MAX_SIGNATURES: usize = 13;
+/// The size of an operator's RSA signature.
+pub const RSA_SIGNATURE_SIZE: usize = 256;
+/// The maximum size of the full data (e.g. the proposed block) carried by a message.
+pub const MAX_FULL_DATA_SIZE: usize = 4_194_532;
+/// The maximum size of the data of an `SSVMessage`.
+pub const MAX_SSV_MESSAGE_DATA_SIZE: usize = 722_412;
+/// The maximum size of an SSZ encoded `SignedSSVMessage`.
+pub const MAX_SIGNED_SSV_MESSAGE_SIZE: usize = 4 * OFFSET_LEN
+    + MAX_SIGNATURES * (OFFSET_LEN + RSA_SIGNATURE_SIZE)
+    + MAX_SIGNATURES * 8
+    + MAX_SSV_MESSAGE_SIZE
+    + MAX_FULL_DATA_SIZE;
+/// The maximum size of an SSZ encoded `SSVMessage`.
+const MAX_SSV_MESSAGE_SIZE: usize = 8 + MESSAGE_ID_LEN + OFFSET_LEN + MAX_SSV_MESSAGE_DATA_SIZE;
+const OFFSET_LEN: usize = ssz::BYTES_PER_LENGTH_OFFSET;
+
+/// The length of a `MessageId`.
+pub const MESSAGE_ID_LEN: usize = 56;
+const DOMAIN_LEN: usize = 4;
+const ROLE_LEN: usize = 4;
+/// The length of a duty executor id: a validator public key or a committee id.
+pub const DUTY_EXECUTOR_ID_LEN: usize = 48;
+
+/// Identifies the duty executor, role and network domain a message belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MessageId([u8; MESSAGE_ID_LEN]);
+
+impl_ssz_fixed_bytes!(MessageId, MESSAGE_ID_LEN);
+
+impl MessageId {
+    /// Creates the id from the network domain, the role and the duty executor. Committee ids are
+    /// 32 bytes long and right aligned.
+    pub fn new(domain: [u8; DOMAIN_LEN], role: Role, duty_executor_id: &[u8]) -> Self {
+        let mut id = [0; MESSAGE_ID_LEN];
+        id[..DOMAIN_LEN].copy_from_slice(&domain);
+        id[DOMAIN_LEN..DOMAIN_LEN + ROLE_LEN].copy_from_slice(&(role as u32).to_le_bytes());
+        let executor_len = duty_executor_id.len().min(DUTY_EXECUTOR_ID_LEN);
+        id[MESSAGE_ID_LEN - executor_len..]
+            .copy_from_slice(&duty_executor_id[duty_executor_id.len() - executor_len..]);
+        Self(id)
+    }
+
+    pub fn domain(&self) -> [u8; DOMAIN_LEN] {
+        self.0[..DOMAIN_LEN].try_into().expect("domain has 4 bytes")
+    }
+
+    /// The role of the message, `None` if it is unknown.
+    pub fn role(&self) -> Option<Role> {
+        let role = self.0[DOMAIN_LEN..DOMAIN_LEN + ROLE_LEN]
+            .try_into()
+            .expect("role has 4 bytes");
+        Role::from_u32(u32::from_le_bytes(role))
+    }
+
+    pub fn duty_executor_id(&self) -> &[u8] {
+        &self.0[DOMAIN_LEN + ROLE_LEN..]
+    }
+
+    pub fn as_bytes(&self) -> &[u8; MESSAGE_ID_LEN] {
+        &self.0
+    }
+}
+
+impl From<[u8; MESSAGE_ID_LEN]> for MessageId {
+    fn from(id: [u8; MESSAGE_ID_LEN]) -> Self {
+        Self(id)
+    }
+}
+
+/// The duty a message is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Role {
+    /// Attestations and sync committee messages, executed by the whole committee.
+    Committee = 0,
+    Aggregator = 1,
+    Proposer = 2,
+    SyncCommitteeContribution = 3,
+    ValidatorRegistration = 4,
+    VoluntaryExit = 5,
+}
+
+impl Role {
+    pub fn from_u32(role: u32) -> Option<Self> {
+        match role {
+            0 => Some(Self::Committee),
+            1 => Some(Self::Aggregator),
+            2 => Some(Self::Proposer),
+            3 => Some(Self::SyncCommitteeContribution),
+            4 => Some(Self::ValidatorRegistration),
+            5 => Some(Self::VoluntaryExit),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of data carried by an `SSVMessage`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MsgType {
+    /// A `QbftMessage`.
+    Consensus,
+    /// A `PartialSignatureMessages`.
+    PartialSignature,
+}
+
+impl TryFrom<u64> for MsgType {
+    type Error = u64;
+
+    fn try_from(msg_type: u64) -> Result<Self, Self::Error> {
+        match msg_type {
+            0 => Ok(Self::Consensus),
+            1 => Ok(Self::PartialSignature),
+            unknown => Err(unknown),
+        }
+    }
+}
+
+/// A message of an operator, along with the duty it belongs to.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct SSVMessage {
+    pub msg_type: u64,
+    pub msg_id: MessageId,
+    pub data: Vec<u8>,
+}
+
+impl SSVMessage {
+    /// The type of the message, or the unknown type value.
+    pub fn msg_type(&self) -> Result<MsgType, u64> {
+        MsgType::try_from(self.msg_type)
+    }
+}
+
+/// An `SSVMessage` signed by one or more operators, as published on gossipsub.
+///
+/// Messages with several signers are decided messages aggregating the operators' commits.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct SignedSSVMessage {
+    /// The RSA signatures, in the order of `operator_ids`.
+    pub signatures: Vec<Vec<u8>>,
+    pub operator_ids: Vec<OperatorId>,
+    pub ssv_message: SSVMessage,
+    pub full_data: Vec<u8>,
+}
+
+/// The steps of a QBFT instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QbftMessageType {
+    Proposal,
+    Prepare,
+    Commit,
+    RoundChange,
+}
+
+impl TryFrom<u64> for QbftMessageType {
+    type Error = u64;
+
+    fn try_from(msg_type: u64) -> Result<Self, Self::Error> {
+        match msg_type {
+            0 => Ok(Self::Proposal),
+            1 => Ok(Self::Prepare),
+            2 => Ok(Self::Commit),
+            3 => Ok(Self::RoundChange),
+            unknown => Err(unknown),
+        }
+    }
+}
+
+/// A QBFT consensus message, the data of a consensus `SSVMessage`.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct QbftMessage {
+    pub qbft_message_type: u64,
+    /// The height of the instance, which is the slot of the duty.
+    pub height: u64,
+    pub round: u64,
+    pub identifier: Vec<u8>,
+    /// The root of the full data being agreed on.
+    pub root: [u8; 32],
+    pub data_round: u64,
+    /// SSZ encoded `SignedSSVMessage`s justifying a round change.
+    pub round_change_justification: Vec<Vec<u8>>,
+    /// SSZ encoded `SignedSSVMessage`s justifying a prepared value.
+    pub prepare_justification: Vec<Vec<u8>>,
+}
+
+impl QbftMessage {
+    /// The type of the message, or the unknown type value.
+    pub fn qbft_message_type(&self) -> Result<QbftMessageType, u64> {
+        QbftMessageType::try_from(self.qbft_message_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssz::{Decode, Encode};
+
+    #[test]
+    fn message_id_layout() {
+        let validator_pubkey = [7; DUTY_EXECUTOR_ID_LEN];
+        let id = MessageId::new([0, 0, 5, 2], Role::Proposer, &validator_pubkey);
+        assert_eq!(id.domain(), [0, 0, 5, 2]);
+        assert_eq!(id.role(), Some(Role::Proposer));
+        assert_eq!(id.duty_executor_id(), &validator_pubkey);
+
+        // Committee ids are shorter than public keys and are padded on the left.
+        let committee_id = [9; 32];
+        let id = MessageId::new([0, 0, 5, 2], Role::Committee, &committee_id);
+        assert_eq!(id.duty_executor_id()[..16], [0; 16]);
+        assert_eq!(id.duty_executor_id()[16..], committee_id);
+    }
+
+    #[test]
+    fn signed_message_ssz_roundtrip() {
+        let message = SignedSSVMessage {
+            signatures: vec![vec![1; RSA_SIGNATURE_SIZE], vec![2; RSA_SIGNATURE_SIZE]],
+            operator_ids: vec![OperatorId(1), OperatorId(3)],
+            ssv_message: SSVMessage {
+                msg_type: 0,
+                msg_id: MessageId::new([0; 4], Role::Committee, &[1; 32]),
+                data: vec![3; 100],
+            },
+            full_data: vec![4; 10],
+        };
+        let bytes = message.as_ssz_bytes();
+        assert!(bytes.len() <= MAX_SIGNED_SSV_MESSAGE_SIZE);
+        assert_eq!(SignedSSVMessage::from_ssz_bytes(&bytes).unwrap(), message);
+    }
+}
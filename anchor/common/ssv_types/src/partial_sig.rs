@@ -0,0 +1,69 @@
+//! Partial BLS signatures exchanged by the operators of a committee.
+
+use crate::fixed_bytes::impl_ssz_fixed_bytes;
+use crate::OperatorId;
+use ssz_derive::{Decode, Encode};
+
+/// The maximum number of partial signatures in a `PartialSignatureMessages`.
+pub const MAX_PARTIAL_SIGNATURE_MESSAGES: usize = 1512;
+/// The size of a BLS signature.
+pub const BLS_SIGNATURE_SIZE: usize = 96;
+
+/// A BLS signature share of an operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PartialSignature(pub [u8; BLS_SIGNATURE_SIZE]);
+
+impl_ssz_fixed_bytes!(PartialSignature, BLS_SIGNATURE_SIZE);
+
+/// What the partial signatures of a `PartialSignatureMessages` sign.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PartialSignatureKind {
+    /// The signed duty, e.g. an attestation or a block.
+    PostConsensus,
+    RandaoPartialSig,
+    SelectionProofPartialSig,
+    ContributionProofs,
+    ValidatorRegistration,
+    VoluntaryExit,
+}
+
+impl TryFrom<u64> for PartialSignatureKind {
+    type Error = u64;
+
+    fn try_from(kind: u64) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(Self::PostConsensus),
+            1 => Ok(Self::RandaoPartialSig),
+            2 => Ok(Self::SelectionProofPartialSig),
+            3 => Ok(Self::ContributionProofs),
+            4 => Ok(Self::ValidatorRegistration),
+            5 => Ok(Self::VoluntaryExit),
+            unknown => Err(unknown),
+        }
+    }
+}
+
+/// The partial signatures of an operator for a duty, the data of a partial signature
+/// `SSVMessage`.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct PartialSignatureMessages {
+    pub kind: u64,
+    pub slot: u64,
+    pub messages: Vec<PartialSignatureMessage>,
+}
+
+impl PartialSignatureMessages {
+    /// The kind of the signatures, or the unknown kind value.
+    pub fn kind(&self) -> Result<PartialSignatureKind, u64> {
+        PartialSignatureKind::try_from(self.kind)
+    }
+}
+
+/// A partial signature of a single validator's signing root.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct PartialSignatureMessage {
+    pub partial_signature: PartialSignature,
+    pub signing_root: [u8; 32],
+    pub signer: OperatorId,
+    pub validator_index: u64,
+}
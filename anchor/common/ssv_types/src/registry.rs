@@ -0,0 +1,48 @@
+//! The operators, validators and clusters registered in the SSVNetwork contract.
+
+use crate::address::Address;
+use crate::OperatorId;
+
+/// The size of a BLS public key.
+pub const BLS_PUBLIC_KEY_SIZE: usize = 48;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Operator {
+    pub id: OperatorId,
+    pub owner: Address,
+    /// The base64 encoded PEM of the operator's RSA public key.
+    pub public_key: Vec<u8>,
+    pub fee: u128,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Validator {
+    pub public_key: Vec<u8>,
+    pub owner: Address,
+    pub operator_ids: Vec<OperatorId>,
+}
+
+/// The key share of a validator held by one of its operators.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    pub validator_public_key: Vec<u8>,
+    pub operator_id: OperatorId,
+    /// The BLS public key of the key share.
+    pub public_key: Vec<u8>,
+    /// The BLS secret key of the key share, encrypted with the RSA public key of the operator.
+    pub encrypted_key: Vec<u8>,
+}
+
+/// The validators of an owner operated by the same operators.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClusterKey {
+    pub owner: Address,
+    pub operator_ids: Vec<OperatorId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cluster {
+    pub validator_count: u32,
+    /// Liquidated clusters ran out of balance and their validators must not perform duties.
+    pub liquidated: bool,
+}
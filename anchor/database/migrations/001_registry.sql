@@ -0,0 +1,42 @@
+CREATE TABLE operators (
+    id INTEGER PRIMARY KEY,
+    owner BLOB NOT NULL,
+    public_key BLOB NOT NULL,
+    -- The fee is a uint256 of the contract, which does not fit in an INTEGER.
+    fee TEXT NOT NULL
+);
+
+CREATE TABLE clusters (
+    owner BLOB NOT NULL,
+    -- The sorted ids of the operators, separated by commas.
+    operator_ids TEXT NOT NULL,
+    validator_count INTEGER NOT NULL,
+    liquidated INTEGER NOT NULL,
+    PRIMARY KEY (owner, operator_ids)
+);
+
+CREATE TABLE validators (
+    public_key BLOB PRIMARY KEY,
+    owner BLOB NOT NULL,
+    operator_ids TEXT NOT NULL
+);
+
+CREATE TABLE shares (
+    validator_public_key BLOB NOT NULL REFERENCES validators (public_key) ON DELETE CASCADE,
+    operator_id INTEGER NOT NULL,
+    public_key BLOB NOT NULL,
+    encrypted_key BLOB NOT NULL,
+    PRIMARY KEY (validator_public_key, operator_id)
+);
+
+CREATE INDEX shares_operator_id ON shares (operator_id);
+
+CREATE TABLE fee_recipients (
+    owner BLOB PRIMARY KEY,
+    recipient BLOB NOT NULL
+);
+
+CREATE TABLE sync_state (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    last_processed_block INTEGER NOT NULL
+);
@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// An error of the database.
+#[derive(Debug)]
+pub enum Error {
+    /// An error of SQLite.
+    Sqlite(rusqlite::Error),
+    /// A stored value could not be decoded.
+    InvalidValue(String),
+    /// The database was created by a more recent version of Anchor.
+    UnknownSchemaVersion(usize),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sqlite(e) => write!(f, "SQLite error: {e}"),
+            Self::InvalidValue(e) => write!(f, "Invalid stored value: {e}"),
+            Self::UnknownSchemaVersion(version) => {
+                write!(f, "Unknown database schema version {version}")
+            }
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
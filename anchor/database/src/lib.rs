@@ -0,0 +1,73 @@
+//! The persistent storage of Anchor: the operators, validators, shares and clusters of the SSV
+//! network, and the progress of the sync of the SSVNetwork contract events.
+
+mod error;
+mod registry;
+
+pub use error::Error;
+pub use registry::RegistryTransaction;
+
+use parking_lot::Mutex;
+use rusqlite::Connection;
+use std::path::Path;
+use tracing::info;
+
+/// The name of the database file within the data directory.
+pub const DATABASE_FILENAME: &str = "anchor.sqlite";
+
+/// The migrations of the schema, in order. The schema version of a database is the number of
+/// migrations applied to it.
+const MIGRATIONS: &[&str] = &[include_str!("../migrations/001_registry.sql")];
+
+/// A SQLite database shared between the components of the client.
+pub struct Database {
+    connection: Mutex<Connection>,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it if needed and migrating it to the latest schema.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Opens a database that only lives in memory.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut connection: Connection) -> Result<Self, Error> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Runs `f` in a transaction, which is committed if `f` succeeds and rolled back otherwise.
+    pub fn write<T>(
+        &self,
+        f: impl FnOnce(&RegistryTransaction) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut connection = self.connection.lock();
+        let transaction = connection.transaction()?;
+        let result = f(&RegistryTransaction(&transaction))?;
+        transaction.commit()?;
+        Ok(result)
+    }
+}
+
+/// Applies the migrations the database has not been migrated with yet.
+fn migrate(connection: &mut Connection) -> Result<(), Error> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(Error::UnknownSchemaVersion(version));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!(version = index + 1, "Migrating the database schema");
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
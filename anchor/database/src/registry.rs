@@ -0,0 +1,488 @@
+//! The typed queries of the SSV registry: operators, validators, shares, clusters, fee recipients
+//! and the last processed block of the contract events.
+
+use crate::{Database, Error};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use ssv_types::{Address, Cluster, ClusterKey, Operator, OperatorId, Share, Validator};
+
+const VALIDATOR_COLUMNS: &str = "validators.public_key, validators.owner, validators.operator_ids";
+const SHARE_COLUMNS: &str = "validator_public_key, operator_id, public_key, encrypted_key";
+
+impl Database {
+    pub fn operator(&self, operator_id: OperatorId) -> Result<Option<Operator>, Error> {
+        operator(&self.connection.lock(), operator_id)
+    }
+
+    pub fn operators(&self) -> Result<Vec<Operator>, Error> {
+        let connection = self.connection.lock();
+        let mut statement =
+            connection.prepare("SELECT id, owner, public_key, fee FROM operators ORDER BY id")?;
+        let rows = statement.query_map([], OperatorRow::read)?;
+        rows.map(|row| row?.decode()).collect()
+    }
+
+    pub fn validator(&self, public_key: &[u8]) -> Result<Option<Validator>, Error> {
+        validator(&self.connection.lock(), public_key)
+    }
+
+    pub fn validators(&self) -> Result<Vec<Validator>, Error> {
+        let connection = self.connection.lock();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {VALIDATOR_COLUMNS} FROM validators ORDER BY public_key"
+        ))?;
+        let rows = statement.query_map([], ValidatorRow::read)?;
+        rows.map(|row| row?.decode()).collect()
+    }
+
+    /// The validators the operator holds a share of, whose cluster is not liquidated.
+    pub fn active_validators_of(&self, operator_id: OperatorId) -> Result<Vec<Validator>, Error> {
+        let connection = self.connection.lock();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {VALIDATOR_COLUMNS} FROM validators
+             JOIN shares ON shares.validator_public_key = validators.public_key
+             LEFT JOIN clusters ON clusters.owner = validators.owner
+                AND clusters.operator_ids = validators.operator_ids
+             WHERE shares.operator_id = ?1 AND clusters.liquidated IS NOT 1
+             ORDER BY validators.public_key"
+        ))?;
+        let rows = statement.query_map([*operator_id], ValidatorRow::read)?;
+        rows.map(|row| row?.decode()).collect()
+    }
+
+    /// The share of a validator held by an operator.
+    pub fn share(
+        &self,
+        validator_public_key: &[u8],
+        operator_id: OperatorId,
+    ) -> Result<Option<Share>, Error> {
+        let connection = self.connection.lock();
+        let share = connection
+            .query_row(
+                &format!(
+                    "SELECT {SHARE_COLUMNS} FROM shares
+                     WHERE validator_public_key = ?1 AND operator_id = ?2"
+                ),
+                params![validator_public_key, *operator_id],
+                read_share,
+            )
+            .optional()?;
+        Ok(share)
+    }
+
+    /// The shares held by an operator.
+    pub fn shares_of(&self, operator_id: OperatorId) -> Result<Vec<Share>, Error> {
+        let connection = self.connection.lock();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {SHARE_COLUMNS} FROM shares WHERE operator_id = ?1
+             ORDER BY validator_public_key"
+        ))?;
+        let shares = statement.query_map([*operator_id], read_share)?;
+        Ok(shares.collect::<Result<_, _>>()?)
+    }
+
+    pub fn cluster(&self, key: &ClusterKey) -> Result<Option<Cluster>, Error> {
+        cluster(&self.connection.lock(), key)
+    }
+
+    /// The fee recipient of the validators of an owner, the owner itself unless updated.
+    pub fn fee_recipient(&self, owner: &Address) -> Result<Address, Error> {
+        let recipient: Option<Vec<u8>> = self
+            .connection
+            .lock()
+            .query_row(
+                "SELECT recipient FROM fee_recipients WHERE owner = ?1",
+                [owner.0],
+                |row| row.get(0),
+            )
+            .optional()?;
+        recipient.map_or(Ok(*owner), |recipient| decode_address(&recipient))
+    }
+
+    pub fn last_processed_block(&self) -> Result<Option<u64>, Error> {
+        last_processed_block(&self.connection.lock())
+    }
+}
+
+/// The writes of the registry, applied atomically by [`Database::write`].
+pub struct RegistryTransaction<'a>(pub(crate) &'a Transaction<'a>);
+
+impl RegistryTransaction<'_> {
+    pub fn operator(&self, operator_id: OperatorId) -> Result<Option<Operator>, Error> {
+        operator(self.0, operator_id)
+    }
+
+    pub fn validator(&self, public_key: &[u8]) -> Result<Option<Validator>, Error> {
+        validator(self.0, public_key)
+    }
+
+    pub fn cluster(&self, key: &ClusterKey) -> Result<Option<Cluster>, Error> {
+        cluster(self.0, key)
+    }
+
+    pub fn last_processed_block(&self) -> Result<Option<u64>, Error> {
+        last_processed_block(self.0)
+    }
+
+    /// Inserts or replaces an operator.
+    pub fn insert_operator(&self, operator: &Operator) -> Result<(), Error> {
+        self.0.execute(
+            "INSERT OR REPLACE INTO operators (id, owner, public_key, fee) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                *operator.id,
+                operator.owner.0,
+                operator.public_key,
+                operator.fee.to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes an operator, returning whether it was known.
+    pub fn remove_operator(&self, operator_id: OperatorId) -> Result<bool, Error> {
+        let removed = self
+            .0
+            .execute("DELETE FROM operators WHERE id = ?1", [*operator_id])?;
+        Ok(removed > 0)
+    }
+
+    /// Inserts or replaces a validator with the shares of its operators.
+    pub fn insert_validator(&self, validator: &Validator, shares: &[Share]) -> Result<(), Error> {
+        self.remove_validator(&validator.public_key)?;
+        self.0.execute(
+            "INSERT INTO validators (public_key, owner, operator_ids) VALUES (?1, ?2, ?3)",
+            params![
+                validator.public_key,
+                validator.owner.0,
+                encode_operator_ids(&validator.operator_ids)
+            ],
+        )?;
+        let mut statement = self.0.prepare_cached(&format!(
+            "INSERT INTO shares ({SHARE_COLUMNS}) VALUES (?1, ?2, ?3, ?4)"
+        ))?;
+        for share in shares {
+            statement.execute(params![
+                share.validator_public_key,
+                *share.operator_id,
+                share.public_key,
+                share.encrypted_key
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Removes a validator and its shares, returning whether it was known.
+    pub fn remove_validator(&self, public_key: &[u8]) -> Result<bool, Error> {
+        let removed = self
+            .0
+            .execute("DELETE FROM validators WHERE public_key = ?1", [public_key])?;
+        Ok(removed > 0)
+    }
+
+    /// Inserts or replaces the state of a cluster.
+    pub fn update_cluster(&self, key: &ClusterKey, cluster: &Cluster) -> Result<(), Error> {
+        self.0.execute(
+            "INSERT OR REPLACE INTO clusters (owner, operator_ids, validator_count, liquidated)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                key.owner.0,
+                encode_operator_ids(&key.operator_ids),
+                cluster.validator_count,
+                cluster.liquidated
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_fee_recipient(&self, owner: &Address, recipient: &Address) -> Result<(), Error> {
+        self.0.execute(
+            "INSERT OR REPLACE INTO fee_recipients (owner, recipient) VALUES (?1, ?2)",
+            [owner.0, recipient.0],
+        )?;
+        Ok(())
+    }
+
+    /// Marks the block as processed, once all its events have been applied.
+    pub fn set_last_processed_block(&self, block: u64) -> Result<(), Error> {
+        self.0.execute(
+            "INSERT OR REPLACE INTO sync_state (id, last_processed_block) VALUES (0, ?1)",
+            [block],
+        )?;
+        Ok(())
+    }
+}
+
+fn operator(connection: &Connection, operator_id: OperatorId) -> Result<Option<Operator>, Error> {
+    let row = connection
+        .query_row(
+            "SELECT id, owner, public_key, fee FROM operators WHERE id = ?1",
+            [*operator_id],
+            OperatorRow::read,
+        )
+        .optional()?;
+    row.map(OperatorRow::decode).transpose()
+}
+
+fn validator(connection: &Connection, public_key: &[u8]) -> Result<Option<Validator>, Error> {
+    let row = connection
+        .query_row(
+            &format!("SELECT {VALIDATOR_COLUMNS} FROM validators WHERE public_key = ?1"),
+            [public_key],
+            ValidatorRow::read,
+        )
+        .optional()?;
+    row.map(ValidatorRow::decode).transpose()
+}
+
+fn cluster(connection: &Connection, key: &ClusterKey) -> Result<Option<Cluster>, Error> {
+    let cluster = connection
+        .query_row(
+            "SELECT validator_count, liquidated FROM clusters
+             WHERE owner = ?1 AND operator_ids = ?2",
+            params![key.owner.0, encode_operator_ids(&key.operator_ids)],
+            |row| {
+                Ok(Cluster {
+                    validator_count: row.get(0)?,
+                    liquidated: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(cluster)
+}
+
+fn last_processed_block(connection: &Connection) -> Result<Option<u64>, Error> {
+    let block = connection
+        .query_row(
+            "SELECT last_processed_block FROM sync_state WHERE id = 0",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(block)
+}
+
+/// The columns of an operator, decoded once the statement is done with SQLite errors.
+struct OperatorRow(u64, Vec<u8>, Vec<u8>, String);
+
+impl OperatorRow {
+    fn read(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    }
+
+    fn decode(self) -> Result<Operator, Error> {
+        let Self(id, owner, public_key, fee) = self;
+        Ok(Operator {
+            id: OperatorId(id),
+            owner: decode_address(&owner)?,
+            public_key,
+            fee: fee
+                .parse()
+                .map_err(|_| Error::InvalidValue(format!("Invalid operator fee {fee}")))?,
+        })
+    }
+}
+
+struct ValidatorRow(Vec<u8>, Vec<u8>, String);
+
+impl ValidatorRow {
+    fn read(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self(row.get(0)?, row.get(1)?, row.get(2)?))
+    }
+
+    fn decode(self) -> Result<Validator, Error> {
+        let Self(public_key, owner, operator_ids) = self;
+        Ok(Validator {
+            public_key,
+            owner: decode_address(&owner)?,
+            operator_ids: decode_operator_ids(&operator_ids)?,
+        })
+    }
+}
+
+fn read_share(row: &Row) -> rusqlite::Result<Share> {
+    Ok(Share {
+        validator_public_key: row.get(0)?,
+        operator_id: OperatorId(row.get(1)?),
+        public_key: row.get(2)?,
+        encrypted_key: row.get(3)?,
+    })
+}
+
+fn decode_address(bytes: &[u8]) -> Result<Address, Error> {
+    bytes
+        .try_into()
+        .map(Address)
+        .map_err(|_| Error::InvalidValue(format!("Invalid address of {} bytes", bytes.len())))
+}
+
+/// Encodes the operator ids of a cluster as a comma separated list, in the order of the contract,
+/// which sorts them.
+fn encode_operator_ids(operator_ids: &[OperatorId]) -> String {
+    operator_ids
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn decode_operator_ids(operator_ids: &str) -> Result<Vec<OperatorId>, Error> {
+    operator_ids
+        .split(',')
+        .map(|operator_id| {
+            operator_id
+                .parse()
+                .map(OperatorId)
+                .map_err(|_| Error::InvalidValue(format!("Invalid operator ids {operator_ids}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const OWNER: Address = Address([1; 20]);
+
+    fn operator_ids() -> Vec<OperatorId> {
+        [1, 2, 3, 4].map(OperatorId).to_vec()
+    }
+
+    fn validator(public_key: u8) -> (Validator, Vec<Share>) {
+        let validator = Validator {
+            public_key: vec![public_key; 48],
+            owner: OWNER,
+            operator_ids: operator_ids(),
+        };
+        let shares = operator_ids()
+            .into_iter()
+            .map(|operator_id| Share {
+                validator_public_key: validator.public_key.clone(),
+                operator_id,
+                public_key: vec![*operator_id as u8; 48],
+                encrypted_key: vec![0xee; 256],
+            })
+            .collect();
+        (validator, shares)
+    }
+
+    fn cluster_key() -> ClusterKey {
+        ClusterKey {
+            owner: OWNER,
+            operator_ids: operator_ids(),
+        }
+    }
+
+    #[test]
+    fn registry_is_persisted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(crate::DATABASE_FILENAME);
+        let operator = Operator {
+            id: OperatorId(1),
+            owner: OWNER,
+            public_key: b"key".to_vec(),
+            fee: u128::MAX,
+        };
+        let (validator, shares) = validator(0xaa);
+
+        let database = Database::open(&path).unwrap();
+        assert_eq!(database.last_processed_block().unwrap(), None);
+        database
+            .write(|tx| {
+                tx.insert_operator(&operator)?;
+                tx.insert_validator(&validator, &shares)?;
+                tx.set_fee_recipient(&OWNER, &Address([2; 20]))?;
+                tx.set_last_processed_block(100)
+            })
+            .unwrap();
+        drop(database);
+
+        let database = Database::open(&path).unwrap();
+        assert_eq!(database.operators().unwrap(), vec![operator]);
+        assert_eq!(database.validator(&[0xaa; 48]).unwrap(), Some(validator));
+        assert_eq!(
+            database.share(&[0xaa; 48], OperatorId(2)).unwrap(),
+            Some(shares[1].clone())
+        );
+        assert_eq!(database.fee_recipient(&OWNER).unwrap(), Address([2; 20]));
+        assert_eq!(
+            database.fee_recipient(&Address([3; 20])).unwrap(),
+            Address([3; 20])
+        );
+        assert_eq!(database.last_processed_block().unwrap(), Some(100));
+    }
+
+    #[test]
+    fn failed_writes_are_rolled_back() {
+        let database = Database::open_in_memory().unwrap();
+        let result = database.write(|tx| {
+            tx.set_last_processed_block(100)?;
+            Err::<(), _>(Error::InvalidValue("failure".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(database.last_processed_block().unwrap(), None);
+    }
+
+    #[test]
+    fn validators_of_liquidated_clusters_are_inactive() {
+        let database = Database::open_in_memory().unwrap();
+        let (validator, shares) = validator(0xaa);
+        database
+            .write(|tx| {
+                tx.insert_validator(&validator, &shares)?;
+                tx.update_cluster(
+                    &cluster_key(),
+                    &Cluster {
+                        validator_count: 1,
+                        liquidated: false,
+                    },
+                )
+            })
+            .unwrap();
+        assert_eq!(
+            database.active_validators_of(OperatorId(1)).unwrap(),
+            vec![validator.clone()]
+        );
+        assert!(database
+            .active_validators_of(OperatorId(5))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            database.shares_of(OperatorId(3)).unwrap(),
+            vec![shares[2].clone()]
+        );
+
+        let liquidated = Cluster {
+            validator_count: 1,
+            liquidated: true,
+        };
+        database
+            .write(|tx| tx.update_cluster(&cluster_key(), &liquidated))
+            .unwrap();
+        assert_eq!(database.cluster(&cluster_key()).unwrap(), Some(liquidated));
+        assert!(database
+            .active_validators_of(OperatorId(1))
+            .unwrap()
+            .is_empty());
+
+        // The shares are removed with their validator.
+        assert!(database
+            .write(|tx| tx.remove_validator(&validator.public_key))
+            .unwrap());
+        assert!(database.shares_of(OperatorId(3)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn newer_schemas_are_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(crate::DATABASE_FILENAME);
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", 100)
+            .unwrap();
+        assert!(matches!(
+            Database::open(&path),
+            Err(Error::UnknownSchemaVersion(100))
+        ));
+    }
+}
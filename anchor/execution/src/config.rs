@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use ssv_types::Address;
+use std::time::Duration;
+
+/// The address of the SSVNetwork contract on mainnet.
+pub const MAINNET_CONTRACT_ADDRESS: &str = "0xDD9BC35aE942eF0cFa76930954a156B3fF30a4E1";
+/// The block the SSVNetwork contract was deployed at on mainnet.
+pub const MAINNET_DEPLOYMENT_BLOCK: u64 = 17_507_487;
+/// The number of blocks behind the head that are considered final enough to be synced.
+pub const DEFAULT_FOLLOW_DISTANCE: u64 = 8;
+
+/// Configuration of the execution service.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// The address of the SSVNetwork contract.
+    pub contract_address: Address,
+    /// The block the contract was deployed at, where the historical sync starts.
+    pub deployment_block: u64,
+    /// Only the blocks this far behind the head are synced, so that reorgs of the recent blocks
+    /// do not affect the registry.
+    pub follow_distance: u64,
+    /// The maximum number of blocks whose logs are requested at once.
+    pub logs_batch_size: u64,
+    /// The interval at which the head is polled once synced.
+    pub poll_interval: Duration,
+    /// The time an execution node has to answer a request.
+    pub request_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            contract_address: MAINNET_CONTRACT_ADDRESS
+                .parse()
+                .expect("Valid contract address"),
+            deployment_block: MAINNET_DEPLOYMENT_BLOCK,
+            follow_distance: DEFAULT_FOLLOW_DISTANCE,
+            logs_batch_size: 10_000,
+            poll_interval: Duration::from_secs(12),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
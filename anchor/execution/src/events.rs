@@ -0,0 +1,450 @@
+//! The events of the SSVNetwork contract and their ABI decoding.
+
+use crate::json_rpc::Log;
+use ssv_types::Address;
+use ssv_types::OperatorId;
+
+/// The events the registry is built from, with the keccak256 hash of their signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    OperatorAdded,
+    OperatorRemoved,
+    ValidatorAdded,
+    ValidatorRemoved,
+    ClusterLiquidated,
+    ClusterReactivated,
+    FeeRecipientAddressUpdated,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 7] = [
+        EventKind::OperatorAdded,
+        EventKind::OperatorRemoved,
+        EventKind::ValidatorAdded,
+        EventKind::ValidatorRemoved,
+        EventKind::ClusterLiquidated,
+        EventKind::ClusterReactivated,
+        EventKind::FeeRecipientAddressUpdated,
+    ];
+
+    /// The Solidity signature of the event, of which `topic` is the hash.
+    pub fn signature(&self) -> &'static str {
+        match self {
+            EventKind::OperatorAdded => "OperatorAdded(uint64,address,bytes,uint256)",
+            EventKind::OperatorRemoved => "OperatorRemoved(uint64)",
+            EventKind::ValidatorAdded => {
+                "ValidatorAdded(address,uint64[],bytes,bytes,(uint32,uint64,uint64,bool,uint256))"
+            }
+            EventKind::ValidatorRemoved => {
+                "ValidatorRemoved(address,uint64[],bytes,(uint32,uint64,uint64,bool,uint256))"
+            }
+            EventKind::ClusterLiquidated => {
+                "ClusterLiquidated(address,uint64[],(uint32,uint64,uint64,bool,uint256))"
+            }
+            EventKind::ClusterReactivated => {
+                "ClusterReactivated(address,uint64[],(uint32,uint64,uint64,bool,uint256))"
+            }
+            EventKind::FeeRecipientAddressUpdated => "FeeRecipientAddressUpdated(address,address)",
+        }
+    }
+
+    /// The first topic of the logs of the event, the keccak256 hash of its signature.
+    pub fn topic(&self) -> [u8; 32] {
+        let hash = match self {
+            EventKind::OperatorAdded => {
+                "d839f31c14bd632f424e307b36abff63ca33684f77f28e35dc13718ef338f7f4"
+            }
+            EventKind::OperatorRemoved => {
+                "0e0ba6c2b04de36d6d509ec5bd155c43a9fe862f8052096dd54f3902a74cca3e"
+            }
+            EventKind::ValidatorAdded => {
+                "48a3ea0796746043948f6341d17ff8200937b99262a0b48c2663b951ed7114e5"
+            }
+            EventKind::ValidatorRemoved => {
+                "ccf4370403e5fbbde0cd3f13426479dcd8a5916b05db424b7a2c04978cf8ce6e"
+            }
+            EventKind::ClusterLiquidated => {
+                "1fce24c373e07f89214e9187598635036111dbb363e99f4ce498488cdc66e688"
+            }
+            EventKind::ClusterReactivated => {
+                "c803f8c01343fcdaf32068f4c283951623ef2b3fa0c547551931356f456b6859"
+            }
+            EventKind::FeeRecipientAddressUpdated => {
+                "259235c230d57def1521657e7c7951d3b385e76193378bc87ef6b56bc2ec3548"
+            }
+        };
+        let mut topic = [0; 32];
+        hex::decode_to_slice(hash, &mut topic).expect("Valid topic hash");
+        topic
+    }
+
+    fn from_topic(topic: &[u8; 32]) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.topic() == *topic)
+    }
+}
+
+/// The state of a cluster after an event, as emitted by the contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClusterSnapshot {
+    pub validator_count: u32,
+    pub active: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SsvEvent {
+    OperatorAdded {
+        operator_id: OperatorId,
+        owner: Address,
+        /// The base64 encoded PEM of the operator's RSA public key.
+        public_key: Vec<u8>,
+        fee: u128,
+    },
+    OperatorRemoved {
+        operator_id: OperatorId,
+    },
+    ValidatorAdded {
+        owner: Address,
+        operator_ids: Vec<OperatorId>,
+        public_key: Vec<u8>,
+        /// The signature of the owner, the public keys of the key shares and the key shares
+        /// encrypted for each operator.
+        shares: Vec<u8>,
+        cluster: ClusterSnapshot,
+    },
+    ValidatorRemoved {
+        owner: Address,
+        operator_ids: Vec<OperatorId>,
+        public_key: Vec<u8>,
+        cluster: ClusterSnapshot,
+    },
+    ClusterLiquidated {
+        owner: Address,
+        operator_ids: Vec<OperatorId>,
+        cluster: ClusterSnapshot,
+    },
+    ClusterReactivated {
+        owner: Address,
+        operator_ids: Vec<OperatorId>,
+        cluster: ClusterSnapshot,
+    },
+    FeeRecipientAddressUpdated {
+        owner: Address,
+        recipient: Address,
+    },
+}
+
+impl SsvEvent {
+    /// Decodes the event of a log, returning `None` for the logs of other events.
+    pub fn decode(log: &Log) -> Result<Option<Self>, String> {
+        let Some(kind) = log.topics.first().and_then(EventKind::from_topic) else {
+            return Ok(None);
+        };
+        let indexed = |index: usize| {
+            log.topics
+                .get(index)
+                .ok_or_else(|| format!("{kind:?} log misses topic {index}"))
+        };
+        let data = AbiData(&log.data);
+
+        let event = match kind {
+            EventKind::OperatorAdded => SsvEvent::OperatorAdded {
+                operator_id: OperatorId(to_u64(indexed(1)?)?),
+                owner: to_address(indexed(2)?)?,
+                public_key: data.bytes(0)?,
+                fee: to_u128(data.word(1)?)?,
+            },
+            EventKind::OperatorRemoved => SsvEvent::OperatorRemoved {
+                operator_id: OperatorId(to_u64(indexed(1)?)?),
+            },
+            EventKind::ValidatorAdded => SsvEvent::ValidatorAdded {
+                owner: to_address(indexed(1)?)?,
+                operator_ids: data.operator_ids(0)?,
+                public_key: data.bytes(1)?,
+                shares: data.bytes(2)?,
+                cluster: data.cluster(3)?,
+            },
+            EventKind::ValidatorRemoved => SsvEvent::ValidatorRemoved {
+                owner: to_address(indexed(1)?)?,
+                operator_ids: data.operator_ids(0)?,
+                public_key: data.bytes(1)?,
+                cluster: data.cluster(2)?,
+            },
+            EventKind::ClusterLiquidated => SsvEvent::ClusterLiquidated {
+                owner: to_address(indexed(1)?)?,
+                operator_ids: data.operator_ids(0)?,
+                cluster: data.cluster(1)?,
+            },
+            EventKind::ClusterReactivated => SsvEvent::ClusterReactivated {
+                owner: to_address(indexed(1)?)?,
+                operator_ids: data.operator_ids(0)?,
+                cluster: data.cluster(1)?,
+            },
+            EventKind::FeeRecipientAddressUpdated => SsvEvent::FeeRecipientAddressUpdated {
+                owner: to_address(indexed(1)?)?,
+                recipient: to_address(data.word(0)?)?,
+            },
+        };
+        Ok(Some(event))
+    }
+}
+
+/// The ABI encoded data of a log: a head of 32 byte words, holding static values and the offsets
+/// of dynamic values.
+struct AbiData<'a>(&'a [u8]);
+
+impl AbiData<'_> {
+    /// The word at a byte offset.
+    fn word_at(&self, offset: usize) -> Result<&[u8; 32], String> {
+        offset
+            .checked_add(32)
+            .and_then(|end| self.0.get(offset..end))
+            .map(|word| word.try_into().expect("32 bytes"))
+            .ok_or_else(|| format!("Log data too short for a word at offset {offset}"))
+    }
+
+    /// The word at an index of the head.
+    fn word(&self, index: usize) -> Result<&[u8; 32], String> {
+        self.word_at(index * 32)
+    }
+
+    /// Returns the length of the dynamic value at an index of the head and the offset of its
+    /// content.
+    fn dynamic(&self, index: usize) -> Result<(usize, usize), String> {
+        let offset = to_usize(self.word(index)?)?;
+        let len = to_usize(self.word_at(offset)?)?;
+        Ok((len, offset + 32))
+    }
+
+    fn bytes(&self, index: usize) -> Result<Vec<u8>, String> {
+        let (len, start) = self.dynamic(index)?;
+        start
+            .checked_add(len)
+            .and_then(|end| self.0.get(start..end))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| format!("Log data too short for {len} bytes at offset {start}"))
+    }
+
+    fn operator_ids(&self, index: usize) -> Result<Vec<OperatorId>, String> {
+        let (len, start) = self.dynamic(index)?;
+        // Bound the allocation by the available data.
+        if len > self.0.len() / 32 {
+            return Err(format!("Invalid array length {len}"));
+        }
+        (0..len)
+            .map(|i| Ok(OperatorId(to_u64(self.word_at(start + i * 32)?)?)))
+            .collect()
+    }
+
+    /// Decodes the static cluster tuple starting at an index of the head: the validator count,
+    /// the network fee index, the index, whether the cluster is active and its balance.
+    fn cluster(&self, index: usize) -> Result<ClusterSnapshot, String> {
+        let validator_count = u32::try_from(to_u64(self.word(index)?)?)
+            .map_err(|_| "Invalid cluster validator count".to_string())?;
+        let active = match to_u64(self.word(index + 3)?)? {
+            0 => false,
+            1 => true,
+            value => return Err(format!("Invalid boolean {value}")),
+        };
+        Ok(ClusterSnapshot {
+            validator_count,
+            active,
+        })
+    }
+}
+
+fn to_u128(word: &[u8; 32]) -> Result<u128, String> {
+    let (high, low) = word.split_at(16);
+    if high.iter().any(|byte| *byte != 0) {
+        return Err("Integer does not fit in 128 bits".to_string());
+    }
+    Ok(u128::from_be_bytes(low.try_into().expect("16 bytes")))
+}
+
+fn to_u64(word: &[u8; 32]) -> Result<u64, String> {
+    u64::try_from(to_u128(word)?).map_err(|_| "Integer does not fit in 64 bits".to_string())
+}
+
+fn to_usize(word: &[u8; 32]) -> Result<usize, String> {
+    usize::try_from(to_u64(word)?).map_err(|_| "Offset does not fit in usize".to_string())
+}
+
+fn to_address(word: &[u8; 32]) -> Result<Address, String> {
+    let (padding, address) = word.split_at(12);
+    if padding.iter().any(|byte| *byte != 0) {
+        return Err("Invalid address padding".to_string());
+    }
+    Ok(Address(address.try_into().expect("20 bytes")))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sha3::{Digest, Keccak256};
+
+    /// ABI encodes values as the data of a log.
+    #[derive(Default)]
+    pub struct AbiEncoder {
+        head: Vec<Vec<u8>>,
+        /// The index of the head word holding the offset of each tail.
+        tails: Vec<(usize, Vec<u8>)>,
+    }
+
+    impl AbiEncoder {
+        pub fn uint(mut self, value: u128) -> Self {
+            self.head.push(uint_word(value));
+            self
+        }
+
+        pub fn bytes(self, bytes: &[u8]) -> Self {
+            let mut tail = uint_word(bytes.len() as u128);
+            tail.extend_from_slice(bytes);
+            tail.resize(32 + bytes.len().div_ceil(32) * 32, 0);
+            self.push_tail(tail)
+        }
+
+        pub fn operator_ids(self, operator_ids: &[u64]) -> Self {
+            let mut tail = uint_word(operator_ids.len() as u128);
+            for operator_id in operator_ids {
+                tail.extend(uint_word(u128::from(*operator_id)));
+            }
+            self.push_tail(tail)
+        }
+
+        pub fn cluster(self, validator_count: u32, active: bool) -> Self {
+            self.uint(validator_count.into())
+                .uint(0)
+                .uint(0)
+                .uint(active.into())
+                .uint(1_000_000)
+        }
+
+        fn push_tail(mut self, tail: Vec<u8>) -> Self {
+            self.tails.push((self.head.len(), tail));
+            self.head.push(vec![]);
+            self
+        }
+
+        pub fn encode(mut self) -> Vec<u8> {
+            let mut offset = self.head.len() * 32;
+            let mut tails = vec![];
+            for (index, tail) in self.tails {
+                self.head[index] = uint_word(offset as u128);
+                offset += tail.len();
+                tails.extend(tail);
+            }
+            self.head.into_iter().flatten().chain(tails).collect()
+        }
+    }
+
+    pub fn uint_word(value: u128) -> Vec<u8> {
+        let mut word = vec![0; 16];
+        word.extend_from_slice(&value.to_be_bytes());
+        word
+    }
+
+    pub fn address_word(address: Address) -> Vec<u8> {
+        let mut word = vec![0; 12];
+        word.extend_from_slice(&address.0);
+        word
+    }
+
+    pub fn log(block_number: u64, kind: EventKind, indexed: &[Vec<u8>], data: Vec<u8>) -> Log {
+        Log {
+            block_number,
+            log_index: 0,
+            topics: std::iter::once(kind.topic())
+                .chain(
+                    indexed
+                        .iter()
+                        .map(|topic| topic.as_slice().try_into().unwrap()),
+                )
+                .collect(),
+            data,
+        }
+    }
+
+    #[test]
+    fn topics_are_the_hashes_of_the_signatures() {
+        for kind in EventKind::ALL {
+            assert_eq!(
+                Keccak256::digest(kind.signature()).as_slice(),
+                kind.topic(),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn validator_added_is_decoded() {
+        let owner = Address([7; 20]);
+        let data = AbiEncoder::default()
+            .operator_ids(&[1, 2, 3, 4])
+            .bytes(&[0xaa; 48])
+            .bytes(&[0xbb; 100])
+            .cluster(3, true)
+            .encode();
+        let log = log(10, EventKind::ValidatorAdded, &[address_word(owner)], data);
+
+        assert_eq!(
+            SsvEvent::decode(&log).unwrap(),
+            Some(SsvEvent::ValidatorAdded {
+                owner,
+                operator_ids: [1, 2, 3, 4].map(OperatorId).to_vec(),
+                public_key: vec![0xaa; 48],
+                shares: vec![0xbb; 100],
+                cluster: ClusterSnapshot {
+                    validator_count: 3,
+                    active: true,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn operator_added_is_decoded() {
+        let owner = Address([9; 20]);
+        let data = AbiEncoder::default()
+            .bytes(b"LS0tLS1CRUdJTiBSU0EgUFVCTElDIEtFWS0tLS0t")
+            .uint(1_000_000_000)
+            .encode();
+        let log = log(
+            10,
+            EventKind::OperatorAdded,
+            &[uint_word(42), address_word(owner)],
+            data,
+        );
+
+        assert_eq!(
+            SsvEvent::decode(&log).unwrap(),
+            Some(SsvEvent::OperatorAdded {
+                operator_id: OperatorId(42),
+                owner,
+                public_key: b"LS0tLS1CRUdJTiBSU0EgUFVCTElDIEtFWS0tLS0t".to_vec(),
+                fee: 1_000_000_000,
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_and_unknown_logs() {
+        let truncated = log(
+            10,
+            EventKind::FeeRecipientAddressUpdated,
+            &[address_word(Address([1; 20]))],
+            vec![0; 16],
+        );
+        assert!(SsvEvent::decode(&truncated).is_err());
+
+        let huge_offset = log(
+            10,
+            EventKind::ClusterLiquidated,
+            &[address_word(Address([1; 20]))],
+            AbiEncoder::default().uint(u64::MAX.into()).encode(),
+        );
+        assert!(SsvEvent::decode(&huge_offset).is_err());
+
+        let mut unknown = truncated;
+        unknown.topics[0] = [0; 32];
+        assert_eq!(SsvEvent::decode(&unknown), Ok(None));
+    }
+}
@@ -0,0 +1,169 @@
+//! A minimal Ethereum JSON-RPC client. Every request is sent to the execution node chosen by the
+//! caller, so that related requests are answered from the same view of the chain.
+
+use reqwest::Client;
+use sensitive_url::SensitiveUrl;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use ssv_types::Address;
+use std::time::Duration;
+
+/// A log emitted by a contract.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Log {
+    pub block_number: u64,
+    pub log_index: u64,
+    pub topics: Vec<[u8; 32]>,
+    pub data: Vec<u8>,
+}
+
+/// A log as returned by `eth_getLogs`, with hex encoded fields.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLog {
+    topics: Vec<String>,
+    data: String,
+    block_number: String,
+    log_index: String,
+    #[serde(default)]
+    removed: bool,
+}
+
+impl TryFrom<RawLog> for Log {
+    type Error = String;
+
+    fn try_from(log: RawLog) -> Result<Self, Self::Error> {
+        let topics = log
+            .topics
+            .iter()
+            .map(|topic| {
+                <[u8; 32]>::try_from(decode_hex(topic)?)
+                    .map_err(|_| format!("Invalid log topic {topic}"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            block_number: parse_quantity(&log.block_number)?,
+            log_index: parse_quantity(&log.log_index)?,
+            topics,
+            data: decode_hex(&log.data)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    result: Option<Value>,
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+pub struct JsonRpcClient {
+    http: Client,
+    endpoints: Vec<SensitiveUrl>,
+}
+
+impl JsonRpcClient {
+    pub fn new(endpoints: Vec<SensitiveUrl>, timeout: Duration) -> Result<Self, String> {
+        if endpoints.is_empty() {
+            return Err("No execution node configured".to_string());
+        }
+        let http = Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| format!("Unable to create the HTTP client: {e}"))?;
+        Ok(Self { http, endpoints })
+    }
+
+    /// The execution nodes, in order of preference.
+    pub fn endpoints(&self) -> &[SensitiveUrl] {
+        &self.endpoints
+    }
+
+    /// The number of the latest block of the node.
+    pub async fn block_number(&self, endpoint: &SensitiveUrl) -> Result<u64, String> {
+        let block_number: String = self.call(endpoint, "eth_blockNumber", json!([])).await?;
+        parse_quantity(&block_number)
+    }
+
+    /// The logs of a contract with one of the topics in the inclusive range of blocks, ordered
+    /// as emitted.
+    pub async fn get_logs(
+        &self,
+        endpoint: &SensitiveUrl,
+        address: Address,
+        topics: &[[u8; 32]],
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<Log>, String> {
+        let topics: Vec<String> = topics
+            .iter()
+            .map(|topic| format!("0x{}", hex::encode(topic)))
+            .collect();
+        let filter = json!([{
+            "address": address.to_string(),
+            "topics": [topics],
+            "fromBlock": format!("{from_block:#x}"),
+            "toBlock": format!("{to_block:#x}"),
+        }]);
+        let raw_logs: Vec<RawLog> = self.call(endpoint, "eth_getLogs", filter).await?;
+
+        let mut logs = raw_logs
+            .into_iter()
+            .filter(|log| !log.removed)
+            .map(Log::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+
+    /// Calls the method on the execution node.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        endpoint: &SensitiveUrl,
+        method: &str,
+        params: Value,
+    ) -> Result<T, String> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let response: Response = self
+            .http
+            .post(endpoint.full.clone())
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?
+            .error_for_status()
+            .map_err(|e| format!("Request failed: {e}"))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid response: {e}"))?;
+        if let Some(error) = response.error {
+            return Err(format!(
+                "Error {} answering {method}: {}",
+                error.code, error.message
+            ));
+        }
+        serde_json::from_value(response.result.unwrap_or(Value::Null))
+            .map_err(|e| format!("Invalid result: {e}"))
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s.strip_prefix("0x").unwrap_or(s)).map_err(|e| format!("Invalid hex {s}: {e}"))
+}
+
+/// Parses a hex encoded quantity, e.g. `0x1b4`.
+fn parse_quantity(s: &str) -> Result<u64, String> {
+    u64::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16)
+        .map_err(|e| format!("Invalid quantity {s}: {e}"))
+}
@@ -0,0 +1,12 @@
+//! The execution service, syncing the state of the SSV network from the events of the
+//! SSVNetwork contract.
+
+mod config;
+mod events;
+mod json_rpc;
+mod registry;
+mod service;
+
+pub use config::{Config, DEFAULT_FOLLOW_DISTANCE, MAINNET_CONTRACT_ADDRESS};
+pub use events::{ClusterSnapshot, EventKind, SsvEvent};
+pub use service::ExecutionService;
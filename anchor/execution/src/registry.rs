@@ -0,0 +1,376 @@
+//! Applies the events of the SSVNetwork contract to the registry of the database.
+
+use crate::events::{ClusterSnapshot, SsvEvent};
+use database::{Error, RegistryTransaction};
+use operator_key::OperatorKey;
+use ssv_types::registry::BLS_PUBLIC_KEY_SIZE;
+use ssv_types::{Address, Cluster, ClusterKey, Operator, OperatorId, Share, Validator};
+use tracing::{debug, warn};
+
+/// The size of the signature of the owner prefixing the shares of a validator.
+const SHARES_SIGNATURE_SIZE: usize = 96;
+/// The size of a key share encrypted with the RSA-2048 public key of an operator.
+const ENCRYPTED_KEY_SIZE: usize = 256;
+
+/// Applies an event. Events inconsistent with the registry are logged and ignored, as the
+/// contract is the source of truth.
+///
+/// With the key of our operator, the key share of validators we operate is decrypted to verify
+/// it, and validators whose share is invalid are ignored, as we could not perform their duties.
+pub fn apply(
+    tx: &RegistryTransaction,
+    event: SsvEvent,
+    operator_key: Option<&OperatorKey>,
+) -> Result<(), Error> {
+    match event {
+        SsvEvent::OperatorAdded {
+            operator_id,
+            owner,
+            public_key,
+            fee,
+        } => {
+            debug!(%operator_id, %owner, "Operator added");
+            tx.insert_operator(&Operator {
+                id: operator_id,
+                owner,
+                public_key,
+                fee,
+            })?;
+        }
+        SsvEvent::OperatorRemoved { operator_id } => {
+            debug!(%operator_id, "Operator removed");
+            if !tx.remove_operator(operator_id)? {
+                warn!(%operator_id, "Removed operator is unknown");
+            }
+        }
+        SsvEvent::ValidatorAdded {
+            owner,
+            operator_ids,
+            public_key,
+            shares,
+            cluster,
+        } => {
+            debug!(public_key = hex::encode(&public_key), %owner, "Validator added");
+            for operator_id in &operator_ids {
+                if tx.operator(*operator_id)?.is_none() {
+                    warn!(%operator_id, "Validator added with an unknown operator");
+                }
+            }
+            update_cluster(tx, owner, operator_ids.clone(), cluster, None)?;
+            let shares = match (
+                parse_shares(&public_key, &operator_ids, &shares),
+                operator_key,
+            ) {
+                (Ok(shares), Some(operator_key)) => match own_share(tx, operator_key, &shares)? {
+                    Some(share) => operator_key.decrypt_share(share).map(|_| ()),
+                    None => Ok(()),
+                }
+                .map(|()| shares),
+                (shares, _) => shares,
+            };
+            match shares {
+                Ok(shares) => tx.insert_validator(
+                    &Validator {
+                        public_key,
+                        owner,
+                        operator_ids,
+                    },
+                    &shares,
+                )?,
+                Err(error) => warn!(
+                    public_key = hex::encode(&public_key),
+                    error, "Ignoring validator with invalid shares"
+                ),
+            }
+        }
+        SsvEvent::ValidatorRemoved {
+            owner,
+            operator_ids,
+            public_key,
+            cluster,
+        } => {
+            debug!(public_key = hex::encode(&public_key), %owner, "Validator removed");
+            if !tx.remove_validator(&public_key)? {
+                warn!(
+                    public_key = hex::encode(&public_key),
+                    "Removed validator is unknown"
+                );
+            }
+            update_cluster(tx, owner, operator_ids, cluster, None)?;
+        }
+        SsvEvent::ClusterLiquidated {
+            owner,
+            operator_ids,
+            cluster,
+        } => {
+            debug!(%owner, ?operator_ids, "Cluster liquidated");
+            update_cluster(tx, owner, operator_ids, cluster, Some(true))?;
+        }
+        SsvEvent::ClusterReactivated {
+            owner,
+            operator_ids,
+            cluster,
+        } => {
+            debug!(%owner, ?operator_ids, "Cluster reactivated");
+            update_cluster(tx, owner, operator_ids, cluster, Some(false))?;
+        }
+        SsvEvent::FeeRecipientAddressUpdated { owner, recipient } => {
+            debug!(%owner, %recipient, "Fee recipient updated");
+            tx.set_fee_recipient(&owner, &recipient)?;
+        }
+    }
+    Ok(())
+}
+
+fn update_cluster(
+    tx: &RegistryTransaction,
+    owner: Address,
+    operator_ids: Vec<OperatorId>,
+    snapshot: ClusterSnapshot,
+    liquidated: Option<bool>,
+) -> Result<(), Error> {
+    let key = ClusterKey {
+        owner,
+        operator_ids,
+    };
+    let liquidated = match liquidated {
+        Some(liquidated) => liquidated,
+        None => tx.cluster(&key)?.is_some_and(|cluster| cluster.liquidated),
+    };
+    tx.update_cluster(
+        &key,
+        &Cluster {
+            validator_count: snapshot.validator_count,
+            liquidated,
+        },
+    )
+}
+
+/// The share held by the operator of `operator_key`, if it operates the validator.
+fn own_share<'a>(
+    tx: &RegistryTransaction,
+    operator_key: &OperatorKey,
+    shares: &'a [Share],
+) -> Result<Option<&'a Share>, Error> {
+    for share in shares {
+        let operator = tx.operator(share.operator_id)?;
+        if operator.is_some_and(|operator| operator_key.matches(&operator.public_key)) {
+            return Ok(Some(share));
+        }
+    }
+    Ok(None)
+}
+
+/// Splits the shares of a validator into the share of each operator. The shares are the
+/// signature of the owner, followed by the public keys of the key shares and the encrypted key
+/// shares, both in the order of the operator ids.
+fn parse_shares(
+    validator_public_key: &[u8],
+    operator_ids: &[OperatorId],
+    shares: &[u8],
+) -> Result<Vec<Share>, String> {
+    let count = operator_ids.len();
+    let expected = SHARES_SIGNATURE_SIZE + count * (BLS_PUBLIC_KEY_SIZE + ENCRYPTED_KEY_SIZE);
+    if shares.len() != expected {
+        return Err(format!(
+            "Expected {expected} bytes of shares for {count} operators, got {}",
+            shares.len()
+        ));
+    }
+    let (public_keys, encrypted_keys) =
+        shares[SHARES_SIGNATURE_SIZE..].split_at(count * BLS_PUBLIC_KEY_SIZE);
+    Ok(operator_ids
+        .iter()
+        .zip(public_keys.chunks_exact(BLS_PUBLIC_KEY_SIZE))
+        .zip(encrypted_keys.chunks_exact(ENCRYPTED_KEY_SIZE))
+        .map(|((operator_id, public_key), encrypted_key)| Share {
+            validator_public_key: validator_public_key.to_vec(),
+            operator_id: *operator_id,
+            public_key: public_key.to_vec(),
+            encrypted_key: encrypted_key.to_vec(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use database::Database;
+
+    const OWNER: Address = Address([1; 20]);
+
+    fn operator_ids() -> Vec<OperatorId> {
+        [1, 2, 3, 4].map(OperatorId).to_vec()
+    }
+
+    fn cluster(validator_count: u32, active: bool) -> ClusterSnapshot {
+        ClusterSnapshot {
+            validator_count,
+            active,
+        }
+    }
+
+    /// Shares for four operators, the public key of each key share filled with its operator id.
+    pub fn shares() -> Vec<u8> {
+        let mut shares = vec![0xcc; SHARES_SIGNATURE_SIZE];
+        for operator_id in 1..=4 {
+            shares.extend([operator_id; BLS_PUBLIC_KEY_SIZE]);
+        }
+        shares.extend([0xee; 4 * ENCRYPTED_KEY_SIZE]);
+        shares
+    }
+
+    fn apply_all(database: &Database, events: Vec<SsvEvent>) {
+        database
+            .write(|tx| {
+                events
+                    .into_iter()
+                    .try_for_each(|event| apply(tx, event, None))
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn validators_of_liquidated_clusters_are_inactive() {
+        let database = Database::open_in_memory().unwrap();
+        let mut events: Vec<SsvEvent> = operator_ids()
+            .into_iter()
+            .map(|operator_id| SsvEvent::OperatorAdded {
+                operator_id,
+                owner: OWNER,
+                public_key: vec![],
+                fee: 0,
+            })
+            .collect();
+        events.push(SsvEvent::ValidatorAdded {
+            owner: OWNER,
+            operator_ids: operator_ids(),
+            public_key: vec![1; 48],
+            shares: shares(),
+            cluster: cluster(1, true),
+        });
+        apply_all(&database, events);
+        assert_eq!(
+            database.active_validators_of(OperatorId(1)).unwrap().len(),
+            1
+        );
+        assert!(database
+            .active_validators_of(OperatorId(5))
+            .unwrap()
+            .is_empty());
+        let share = database.share(&[1; 48], OperatorId(3)).unwrap().unwrap();
+        assert_eq!(share.public_key, vec![3; 48]);
+
+        apply_all(
+            &database,
+            vec![SsvEvent::ClusterLiquidated {
+                owner: OWNER,
+                operator_ids: operator_ids(),
+                cluster: cluster(1, false),
+            }],
+        );
+        assert!(database
+            .active_validators_of(OperatorId(1))
+            .unwrap()
+            .is_empty());
+
+        apply_all(
+            &database,
+            vec![SsvEvent::ClusterReactivated {
+                owner: OWNER,
+                operator_ids: operator_ids(),
+                cluster: cluster(1, true),
+            }],
+        );
+        assert_eq!(
+            database.active_validators_of(OperatorId(1)).unwrap().len(),
+            1
+        );
+
+        apply_all(
+            &database,
+            vec![SsvEvent::ValidatorRemoved {
+                owner: OWNER,
+                operator_ids: operator_ids(),
+                public_key: vec![1; 48],
+                cluster: cluster(0, true),
+            }],
+        );
+        assert!(database.validator(&[1; 48]).unwrap().is_none());
+        let key = ClusterKey {
+            owner: OWNER,
+            operator_ids: operator_ids(),
+        };
+        assert_eq!(database.cluster(&key).unwrap().unwrap().validator_count, 0);
+    }
+
+    #[test]
+    fn validators_with_malformed_shares_are_ignored() {
+        let database = Database::open_in_memory().unwrap();
+        apply_all(
+            &database,
+            vec![SsvEvent::ValidatorAdded {
+                owner: OWNER,
+                operator_ids: operator_ids(),
+                public_key: vec![1; 48],
+                shares: shares()[1..].to_vec(),
+                cluster: cluster(1, true),
+            }],
+        );
+        assert!(database.validator(&[1; 48]).unwrap().is_none());
+    }
+
+    #[test]
+    fn validators_with_an_invalid_own_share_are_ignored() {
+        let database = Database::open_in_memory().unwrap();
+        let operator_key = OperatorKey::generate().unwrap();
+        let our_public_key = operator_key.public_key_base64().unwrap().into_bytes();
+        let validator_added = |public_key: u8, operator_ids: [u64; 4]| SsvEvent::ValidatorAdded {
+            owner: OWNER,
+            operator_ids: operator_ids.map(OperatorId).to_vec(),
+            public_key: vec![public_key; 48],
+            shares: shares(),
+            cluster: cluster(1, true),
+        };
+        let mut events: Vec<SsvEvent> = (1..=5)
+            .map(|operator_id| SsvEvent::OperatorAdded {
+                operator_id: OperatorId(operator_id),
+                owner: OWNER,
+                public_key: if operator_id == 1 {
+                    our_public_key.clone()
+                } else {
+                    vec![]
+                },
+                fee: 0,
+            })
+            .collect();
+        // Our share can not be decrypted, while the other validator is not operated by us.
+        events.push(validator_added(1, [1, 2, 3, 4]));
+        events.push(validator_added(2, [2, 3, 4, 5]));
+        database
+            .write(|tx| {
+                events
+                    .into_iter()
+                    .try_for_each(|event| apply(tx, event, Some(&operator_key)))
+            })
+            .unwrap();
+
+        assert!(database.validator(&[1; 48]).unwrap().is_none());
+        assert!(database.validator(&[2; 48]).unwrap().is_some());
+    }
+
+    #[test]
+    fn fee_recipient_defaults_to_the_owner() {
+        let database = Database::open_in_memory().unwrap();
+        assert_eq!(database.fee_recipient(&OWNER).unwrap(), OWNER);
+
+        apply_all(
+            &database,
+            vec![SsvEvent::FeeRecipientAddressUpdated {
+                owner: OWNER,
+                recipient: Address([2; 20]),
+            }],
+        );
+        assert_eq!(database.fee_recipient(&OWNER).unwrap(), Address([2; 20]));
+    }
+}
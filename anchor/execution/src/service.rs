@@ -0,0 +1,367 @@
+use crate::config::Config;
+use crate::events::{EventKind, SsvEvent};
+use crate::json_rpc::JsonRpcClient;
+use crate::registry;
+use database::Database;
+use operator_key::OperatorKey;
+use sensitive_url::SensitiveUrl;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+/// Syncs the events of the SSVNetwork contract into the database, from the deployment block of
+/// the contract to `follow_distance` blocks behind the head.
+pub struct ExecutionService {
+    client: JsonRpcClient,
+    config: Config,
+    database: Arc<Database>,
+    /// The key of our operator, to verify the key shares of the validators we operate.
+    operator_key: Option<Arc<OperatorKey>>,
+    topics: Vec<[u8; 32]>,
+}
+
+impl ExecutionService {
+    /// Creates the service, requesting the execution nodes in order until one answers. The sync
+    /// resumes from the last block processed into the database.
+    pub fn new(
+        execution_nodes: Vec<SensitiveUrl>,
+        config: Config,
+        database: Arc<Database>,
+        operator_key: Option<Arc<OperatorKey>>,
+    ) -> Result<Self, String> {
+        if config.logs_batch_size == 0 {
+            return Err("The logs batch size must not be zero".to_string());
+        }
+        Ok(Self {
+            client: JsonRpcClient::new(execution_nodes, config.request_timeout)?,
+            config,
+            database,
+            operator_key,
+            topics: EventKind::ALL.iter().map(EventKind::topic).collect(),
+        })
+    }
+
+    /// Syncs the database and keeps following the head.
+    pub async fn run(self) {
+        let mut synced = false;
+        loop {
+            match self.sync().await {
+                Ok(block) if !synced => {
+                    info!(block, "SSV contract events synced");
+                    synced = true;
+                }
+                Ok(_) => {}
+                Err(error) => warn!(error, "Unable to sync the SSV contract events"),
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Applies the events of the blocks up to `follow_distance` behind the head, returning the
+    /// last processed block. The whole pass is synced from the first execution node that
+    /// answers, as a node lagging behind the head of another one would return no logs for the
+    /// blocks it does not have yet.
+    pub async fn sync(&self) -> Result<Option<u64>, String> {
+        let mut errors = vec![];
+        for endpoint in self.client.endpoints() {
+            match self.sync_from(endpoint).await {
+                Ok(block) => return Ok(block),
+                Err(error) => {
+                    warn!(%endpoint, error, "Unable to sync from execution node");
+                    errors.push(format!("{endpoint}: {error}"));
+                }
+            }
+        }
+        Err(format!(
+            "All execution nodes failed to sync: {}",
+            errors.join(", ")
+        ))
+    }
+
+    /// Syncs the events from a single execution node.
+    async fn sync_from(&self, endpoint: &SensitiveUrl) -> Result<Option<u64>, String> {
+        let head = self.client.block_number(endpoint).await?;
+        let target = head.saturating_sub(self.config.follow_distance);
+        loop {
+            let last_processed_block = self
+                .database
+                .last_processed_block()
+                .map_err(|e| format!("Unable to read the last processed block: {e}"))?;
+            let next = last_processed_block.map_or(self.config.deployment_block, |block| block + 1);
+            if next > target {
+                return Ok(last_processed_block);
+            }
+            let to = target.min(next.saturating_add(self.config.logs_batch_size - 1));
+            let logs = self
+                .client
+                .get_logs(
+                    endpoint,
+                    self.config.contract_address,
+                    &self.topics,
+                    next,
+                    to,
+                )
+                .await?;
+            // A node behind a load balancer may be served by another node than the head was,
+            // which may not have the blocks of the batch yet.
+            let head = self.client.block_number(endpoint).await?;
+            if head < to {
+                return Err(format!(
+                    "The head {head} of the node is behind the synced block {to}"
+                ));
+            }
+
+            let events: Vec<SsvEvent> = logs
+                .iter()
+                .filter_map(|log| {
+                    SsvEvent::decode(log)
+                        .map_err(|error| {
+                            warn!(
+                                block = log.block_number,
+                                log_index = log.log_index,
+                                error,
+                                "Ignoring malformed SSV contract event"
+                            )
+                        })
+                        .ok()
+                        .flatten()
+                })
+                .collect();
+            debug!(
+                from = next,
+                to,
+                head,
+                events = events.len(),
+                "Syncing SSV contract events"
+            );
+
+            // The events of the batch and its last block are stored atomically, so that a
+            // restart resumes right after the last applied event.
+            self.database
+                .write(|tx| {
+                    for event in events {
+                        registry::apply(tx, event, self.operator_key.as_deref())?;
+                    }
+                    tx.set_last_processed_block(to)
+                })
+                .map_err(|e| format!("Unable to store the SSV contract events: {e}"))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::tests::{address_word, log, uint_word, AbiEncoder};
+    use crate::json_rpc::Log;
+    use crate::registry::tests::shares;
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use parking_lot::Mutex;
+    use serde_json::{json, Value};
+    use ssv_types::Address;
+    use ssv_types::OperatorId;
+    use tokio::net::TcpListener;
+
+    const OWNER: Address = Address([1; 20]);
+
+    /// A JSON-RPC server serving a fixed set of logs.
+    #[derive(Default)]
+    struct MockNode {
+        head: Mutex<u64>,
+        logs: Vec<Log>,
+        /// The ranges of blocks the logs were requested for.
+        requests: Mutex<Vec<(u64, u64)>>,
+        failing: bool,
+        /// Whether only the logs are not served.
+        failing_logs: bool,
+    }
+
+    async fn handle(State(node): State<Arc<MockNode>>, Json(request): Json<Value>) -> Json<Value> {
+        let quantity = |value: &Value| {
+            u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+        };
+        let result = match request["method"].as_str().unwrap() {
+            _ if node.failing => None,
+            "eth_blockNumber" => Some(json!(format!("{:#x}", *node.head.lock()))),
+            "eth_getLogs" if node.failing_logs => None,
+            "eth_getLogs" => {
+                let filter = &request["params"][0];
+                let (from, to) = (quantity(&filter["fromBlock"]), quantity(&filter["toBlock"]));
+                node.requests.lock().push((from, to));
+                let logs: Vec<Value> = node
+                    .logs
+                    .iter()
+                    .filter(|log| (from..=to).contains(&log.block_number))
+                    .map(|log| {
+                        json!({
+                            "topics": log
+                                .topics
+                                .iter()
+                                .map(|topic| format!("0x{}", hex::encode(topic)))
+                                .collect::<Vec<_>>(),
+                            "data": format!("0x{}", hex::encode(&log.data)),
+                            "blockNumber": format!("{:#x}", log.block_number),
+                            "logIndex": format!("{:#x}", log.log_index),
+                            "removed": false,
+                        })
+                    })
+                    .collect();
+                Some(json!(logs))
+            }
+            _ => None,
+        };
+        Json(match result {
+            Some(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": -32000, "message": "unavailable"},
+            }),
+        })
+    }
+
+    async fn spawn(node: Arc<MockNode>) -> SensitiveUrl {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route("/", post(handle)).with_state(node);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        SensitiveUrl::parse(&format!("http://{address}/")).unwrap()
+    }
+
+    fn config() -> Config {
+        Config {
+            deployment_block: 100,
+            follow_distance: 8,
+            logs_batch_size: 50,
+            ..Config::default()
+        }
+    }
+
+    fn operator_added(block_number: u64, operator_id: u64) -> Log {
+        let data = AbiEncoder::default().bytes(b"key").uint(0).encode();
+        log(
+            block_number,
+            EventKind::OperatorAdded,
+            &[uint_word(operator_id.into()), address_word(OWNER)],
+            data,
+        )
+    }
+
+    fn validator_event(block_number: u64, added: bool) -> Log {
+        let encoder = AbiEncoder::default()
+            .operator_ids(&[1, 2, 3, 4])
+            .bytes(&[0xaa; 48]);
+        let (kind, encoder) = if added {
+            (EventKind::ValidatorAdded, encoder.bytes(&shares()))
+        } else {
+            (EventKind::ValidatorRemoved, encoder)
+        };
+        let data = encoder.cluster(u32::from(added), true).encode();
+        log(block_number, kind, &[address_word(OWNER)], data)
+    }
+
+    fn mock_node(head: u64) -> MockNode {
+        let mut logs: Vec<Log> = (1..=4).map(|id| operator_added(100, id)).collect();
+        for (log_index, log) in logs.iter_mut().enumerate() {
+            log.log_index = log_index as u64;
+        }
+        logs.push(validator_event(150, true));
+        logs.push(validator_event(300, false));
+        MockNode {
+            head: Mutex::new(head),
+            logs,
+            ..MockNode::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn events_are_synced_up_to_the_follow_distance() {
+        let node = Arc::new(mock_node(250));
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let service = ExecutionService::new(
+            vec![spawn(node.clone()).await],
+            config(),
+            database.clone(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(service.sync().await, Ok(Some(242)));
+        assert_eq!(
+            *node.requests.lock(),
+            vec![(100, 149), (150, 199), (200, 242)]
+        );
+        assert_eq!(database.operators().unwrap().len(), 4);
+        assert_eq!(
+            database.active_validators_of(OperatorId(1)).unwrap().len(),
+            1
+        );
+
+        // The removal is applied once it is `follow_distance` blocks behind the head.
+        *node.head.lock() = 307;
+        assert_eq!(service.sync().await, Ok(Some(299)));
+        assert!(database.validator(&[0xaa; 48]).unwrap().is_some());
+        *node.head.lock() = 308;
+        assert_eq!(service.sync().await, Ok(Some(300)));
+        assert!(database.validator(&[0xaa; 48]).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn nothing_is_synced_before_the_deployment_block() {
+        let node = Arc::new(mock_node(105));
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let service =
+            ExecutionService::new(vec![spawn(node.clone()).await], config(), database, None)
+                .unwrap();
+
+        assert_eq!(service.sync().await, Ok(None));
+        assert!(node.requests.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_nodes_fall_back_to_the_next_node() {
+        let failing = Arc::new(MockNode {
+            failing: true,
+            ..MockNode::default()
+        });
+        let node = Arc::new(mock_node(250));
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let endpoints = vec![spawn(failing).await, spawn(node).await];
+        let service = ExecutionService::new(endpoints, config(), database.clone(), None).unwrap();
+
+        assert_eq!(service.sync().await, Ok(Some(242)));
+        assert_eq!(database.operators().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn a_sync_pass_uses_a_single_node() {
+        // The first node knows a later head but fails to serve the logs, the second one lags
+        // behind and must not be asked for the blocks it does not have.
+        let first = Arc::new(MockNode {
+            failing_logs: true,
+            ..mock_node(250)
+        });
+        let second = Arc::new(mock_node(160));
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let endpoints = vec![spawn(first).await, spawn(second.clone()).await];
+        let service = ExecutionService::new(endpoints, config(), database.clone(), None).unwrap();
+
+        assert_eq!(service.sync().await, Ok(Some(152)));
+        assert_eq!(*second.requests.lock(), vec![(100, 149), (150, 152)]);
+    }
+
+    #[tokio::test]
+    async fn malformed_events_are_skipped() {
+        let mut node = mock_node(250);
+        node.logs[0].data.truncate(10);
+        let node = Arc::new(node);
+        let database = Arc::new(Database::open_in_memory().unwrap());
+        let service =
+            ExecutionService::new(vec![spawn(node).await], config(), database.clone(), None)
+                .unwrap();
+
+        assert_eq!(service.sync().await, Ok(Some(242)));
+        assert_eq!(database.operators().unwrap().len(), 3);
+    }
+}
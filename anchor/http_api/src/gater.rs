@@ -0,0 +1,69 @@
+//! The `/v1/node/gater` endpoints, managing the connection gater rules at runtime. Rules added
+//! here are persisted by the network and restored on restart.
+
+use crate::node::{ApiError, GenericResponse};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use network::{GaterRule, NetworkCommand};
+use tokio::sync::{mpsc, oneshot};
+
+/// Sends a command to the network and waits for its reply.
+async fn request<T>(
+    network_commands: &mpsc::Sender<NetworkCommand>,
+    command: impl FnOnce(oneshot::Sender<T>) -> NetworkCommand,
+) -> Result<T, ApiError> {
+    let unavailable = || {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The network is not running".to_string(),
+        )
+    };
+    let (reply_tx, reply_rx) = oneshot::channel();
+    network_commands
+        .send(command(reply_tx))
+        .await
+        .map_err(|_| unavailable())?;
+    reply_rx.await.map_err(|_| unavailable())
+}
+
+/// `GET /v1/node/gater/rules`
+pub async fn rules(
+    State(network_commands): State<mpsc::Sender<NetworkCommand>>,
+) -> Result<Json<GenericResponse<Vec<GaterRule>>>, ApiError> {
+    let mut data = request(&network_commands, NetworkCommand::GaterRules).await?;
+    data.sort_by_key(|rule| rule.to_string());
+    Ok(Json(GenericResponse { data }))
+}
+
+/// `POST /v1/node/gater/rules`, with the rule as body, e.g. `{"deny_subnet": "1.2.3.0/24"}`.
+pub async fn add_rule(
+    State(network_commands): State<mpsc::Sender<NetworkCommand>>,
+    Json(rule): Json<GaterRule>,
+) -> Result<StatusCode, ApiError> {
+    request(&network_commands, |reply| {
+        NetworkCommand::AddGaterRule(rule, reply)
+    })
+    .await?
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(StatusCode::OK)
+}
+
+/// `DELETE /v1/node/gater/rules`, with the rule as body.
+pub async fn remove_rule(
+    State(network_commands): State<mpsc::Sender<NetworkCommand>>,
+    Json(rule): Json<GaterRule>,
+) -> Result<StatusCode, ApiError> {
+    let removed = request(&network_commands, |reply| {
+        NetworkCommand::RemoveGaterRule(rule, reply)
+    })
+    .await?
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if !removed {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "Rule not found".to_string(),
+        ));
+    }
+    Ok(StatusCode::OK)
+}
@@ -1,12 +1,17 @@
 mod config;
+mod gater;
+mod node;
 mod router;
 
 pub use config::Config;
+use network::{NetworkCommand, NetworkInfo};
 use slot_clock::SlotClock;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 use task_executor::TaskExecutor;
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 use tracing::info;
 
 /// A wrapper around all the items required to spawn the HTTP server.
@@ -26,15 +31,20 @@ pub struct Context<T: SlotClock> {
     pub slot_clock: T,
 }
 
-/// Runs the HTTP API server
-pub async fn run(config: Config) -> Result<(), String> {
+/// Runs the HTTP API server, serving the node information from the network and managing its
+/// connection gater rules.
+pub async fn run(
+    config: Config,
+    network_info: Arc<NetworkInfo>,
+    network_commands: mpsc::Sender<NetworkCommand>,
+) -> Result<(), String> {
     if !config.enabled {
         info!("HTTP API Disabled");
         return Ok(());
     }
 
     // Generate the axum routes
-    let router = router::new();
+    let router = router::new(network_info, network_commands);
 
     // Set up a listening address
 
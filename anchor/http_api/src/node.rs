@@ -0,0 +1,219 @@
+//! The `/v1/node` endpoints, describing the node and its peers. The responses follow the shape of
+//! the beacon node API.
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use network::{ConnectionDirection, NetworkInfo, PeerConnectionState, PeerId, PeerInfo};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The response of most endpoints, wrapping the data.
+#[derive(Serialize)]
+pub struct GenericResponse<T> {
+    pub data: T,
+}
+
+/// An error, returned with the matching status code.
+#[derive(Serialize)]
+pub struct ApiError {
+    pub code: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, message: String) -> Self {
+        Self {
+            code: status.as_u16(),
+            message,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, Json(self)).into_response()
+    }
+}
+
+#[derive(Serialize)]
+pub struct IdentityData {
+    pub peer_id: String,
+    pub enr: String,
+    /// The addresses libp2p listens on.
+    pub p2p_addresses: Vec<String>,
+    /// The addresses discovery is reachable on, as advertised in the ENR.
+    pub discovery_addresses: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerState {
+    Connected,
+    Disconnected,
+}
+
+impl From<PeerConnectionState> for PeerState {
+    fn from(state: PeerConnectionState) -> Self {
+        match state {
+            PeerConnectionState::Connected => Self::Connected,
+            PeerConnectionState::Disconnected => Self::Disconnected,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerDirection {
+    Inbound,
+    Outbound,
+}
+
+impl From<ConnectionDirection> for PeerDirection {
+    fn from(direction: ConnectionDirection) -> Self {
+        match direction {
+            ConnectionDirection::Inbound => Self::Inbound,
+            ConnectionDirection::Outbound => Self::Outbound,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PeerData {
+    pub peer_id: String,
+    pub last_seen_p2p_address: String,
+    pub state: PeerState,
+    pub direction: PeerDirection,
+    pub agent_version: Option<String>,
+}
+
+impl From<PeerInfo> for PeerData {
+    fn from(peer: PeerInfo) -> Self {
+        Self {
+            peer_id: peer.peer_id.to_string(),
+            last_seen_p2p_address: peer.last_seen_address.to_string(),
+            state: peer.state.into(),
+            direction: peer.direction.into(),
+            agent_version: peer.agent_version,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PeersMeta {
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct PeersResponse {
+    pub data: Vec<PeerData>,
+    pub meta: PeersMeta,
+}
+
+/// Filters the peers by state and direction.
+#[derive(Deserialize)]
+pub struct PeersQuery {
+    pub state: Option<PeerState>,
+    pub direction: Option<PeerDirection>,
+}
+
+#[derive(Serialize)]
+pub struct TopicData {
+    pub topic: String,
+    /// The peers subscribed to the topic.
+    pub peers: Vec<String>,
+}
+
+/// `GET /v1/node/identity`
+pub async fn identity(
+    State(network_info): State<Arc<NetworkInfo>>,
+) -> Json<GenericResponse<IdentityData>> {
+    let identity = network_info.identity();
+    let peer_id = identity.peer_id;
+    let p2p_addresses = identity
+        .listen_addresses
+        .iter()
+        .map(|address| format!("{address}/p2p/{peer_id}"))
+        .collect();
+    let discovery_addresses = [
+        identity.enr.udp4_socket().map(SocketAddr::V4),
+        identity.enr.udp6_socket().map(SocketAddr::V6),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|socket| {
+        let ip_version = if socket.is_ipv4() { "ip4" } else { "ip6" };
+        format!(
+            "/{ip_version}/{}/udp/{}/p2p/{peer_id}",
+            socket.ip(),
+            socket.port()
+        )
+    })
+    .collect();
+
+    Json(GenericResponse {
+        data: IdentityData {
+            peer_id: peer_id.to_string(),
+            enr: identity.enr.to_base64(),
+            p2p_addresses,
+            discovery_addresses,
+        },
+    })
+}
+
+/// `GET /v1/node/peers`
+pub async fn peers(
+    State(network_info): State<Arc<NetworkInfo>>,
+    Query(query): Query<PeersQuery>,
+) -> Json<PeersResponse> {
+    let mut data: Vec<PeerData> = network_info
+        .peers()
+        .into_iter()
+        .map(PeerData::from)
+        .filter(|peer| query.state.map_or(true, |state| peer.state == state))
+        .filter(|peer| {
+            query
+                .direction
+                .map_or(true, |direction| peer.direction == direction)
+        })
+        .collect();
+    data.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+    Json(PeersResponse {
+        meta: PeersMeta { count: data.len() },
+        data,
+    })
+}
+
+/// `GET /v1/node/peers/{peer_id}`
+pub async fn peer(
+    State(network_info): State<Arc<NetworkInfo>>,
+    Path(peer_id): Path<String>,
+) -> Result<Json<GenericResponse<PeerData>>, ApiError> {
+    let peer_id = PeerId::from_str(&peer_id)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid peer id: {e}")))?;
+    let peer = network_info
+        .peer(&peer_id)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Peer not found".to_string()))?;
+    Ok(Json(GenericResponse { data: peer.into() }))
+}
+
+/// `GET /v1/node/topics`
+pub async fn topics(
+    State(network_info): State<Arc<NetworkInfo>>,
+) -> Json<GenericResponse<Vec<TopicData>>> {
+    let mut data: Vec<TopicData> = network_info
+        .topics()
+        .into_iter()
+        .map(|topic| TopicData {
+            topic: topic.topic,
+            peers: topic.peers.iter().map(PeerId::to_string).collect(),
+        })
+        .collect();
+    data.sort_by(|a, b| a.topic.cmp(&b.topic));
+    Json(GenericResponse { data })
+}
@@ -1,11 +1,34 @@
 //! The routes for the HTTP API
 
+use crate::{gater, node};
 use axum::{routing::get, Router};
+use network::{NetworkCommand, NetworkInfo};
+use std::sync::Arc;
+use tokio::sync::mpsc;
 
 /// Creates all the routes for HTTP API
-pub fn new() -> Router {
+pub fn new(
+    network_info: Arc<NetworkInfo>,
+    network_commands: mpsc::Sender<NetworkCommand>,
+) -> Router {
+    let gater_routes = Router::new()
+        .route(
+            "/v1/node/gater/rules",
+            get(gater::rules)
+                .post(gater::add_rule)
+                .delete(gater::remove_rule),
+        )
+        .with_state(network_commands);
+
     // Default route
-    Router::new().route("/", get(root))
+    Router::new()
+        .route("/", get(root))
+        .route("/v1/node/identity", get(node::identity))
+        .route("/v1/node/peers", get(node::peers))
+        .route("/v1/node/peers/:peer_id", get(node::peer))
+        .route("/v1/node/topics", get(node::topics))
+        .with_state(network_info)
+        .merge(gater_routes)
 }
 
 // Temporary return value.
@@ -13,6 +13,7 @@ use axum::{
     Router,
 };
 use parking_lot::RwLock;
+use prometheus_client::registry::Registry;
 use serde::{Deserialize, Serialize};
 use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr};
@@ -26,6 +27,9 @@ use tracing::error;
 pub struct Shared {
     /// If we know genesis, it is entered here.
     pub genesis_time: Option<u64>,
+    /// The registry containing the libp2p, network and processor metrics, if the network is
+    /// running.
+    pub libp2p_registry: Option<Registry>,
 }
 
 /// Configuration for the HTTP server.
@@ -110,14 +114,29 @@ async fn metrics_handler(State(state): State<Arc<RwLock<Shared>>>) -> Response<B
 
     encoder.encode(&metrics::gather(), &mut buffer).unwrap();
 
-    match String::from_utf8(buffer) {
-        Ok(v) => v.into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to encode promethus data: {}", e),
-        )
-            .into_response(),
+    let mut response = match String::from_utf8(buffer) {
+        Ok(v) => v,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to encode promethus data: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    // Append the libp2p metrics, which are held in a separate registry
+    if let Some(registry) = &state.read().libp2p_registry {
+        if let Err(e) = prometheus_client::encoding::text::encode(&mut response, registry) {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to encode libp2p metrics: {}", e),
+            )
+                .into_response();
+        }
     }
+
+    response.into_response()
 }
 
 /// Creates a server that will serve requests using information from `ctx`.
@@ -1,12 +1,30 @@
+use crate::connection_gater;
+use crate::history_sync::HistorySyncCodec;
+use crate::peer_limits;
+use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::swarm::NetworkBehaviour;
-use libp2p::{identify, ping};
+use libp2p::{
+    connection_limits, gossipsub, identify, memory_connection_limits, ping, request_response, upnp,
+};
 
 #[derive(NetworkBehaviour)]
 pub struct AnchorBehaviour {
+    /// Denies connections to and from denied IPs and peers.
+    pub connection_gater: connection_gater::Behaviour,
+    /// Rate limits inbound handshakes per IP and caps the connections of unprotected peers.
+    pub peer_limits: peer_limits::Behaviour,
+    /// Denies connections exceeding the configured pending and per peer connection counts.
+    pub connection_limits: connection_limits::Behaviour,
+    /// Denies new connections while the process uses too much memory.
+    pub memory_limits: memory_connection_limits::Behaviour,
     /// Provides IP addresses and peer information.
     pub identify: identify::Behaviour,
     /// Used for connection health checks.
     pub ping: ping::Behaviour,
-    // /// The routing pub-sub mechanism for Anchor.
-    // pub gossipsub: gossipsub::Behaviour,
+    /// The routing pub-sub mechanism for Anchor.
+    pub gossipsub: gossipsub::Behaviour,
+    /// Serves and requests the history of decided messages.
+    pub history_sync: request_response::Behaviour<HistorySyncCodec>,
+    /// Maps the listening ports on the gateway, if enabled.
+    pub upnp: Toggle<upnp::tokio::Behaviour>,
 }
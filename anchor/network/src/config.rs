@@ -1,5 +1,9 @@
+use crate::connection_gater::IpSubnet;
+use crate::fork::ForkSchedule;
+use crate::gossipsub_config::GossipsubConfig;
+use crate::rate_limiter::RateLimiterConfig;
 use discv5::Enr;
-use libp2p::Multiaddr;
+use libp2p::{Multiaddr, PeerId};
 use lighthouse_network::types::GossipKind;
 use lighthouse_network::{ListenAddr, ListenAddress};
 use serde::{Deserialize, Serialize};
@@ -21,6 +25,12 @@ pub struct Config {
     /// Data directory where node's keyfile is stored
     pub network_dir: PathBuf,
 
+    /// A file containing the network key to use instead of the one in the network directory.
+    pub network_key_file: Option<PathBuf>,
+
+    /// A file containing the password used to encrypt the network key.
+    pub network_key_password_file: Option<PathBuf>,
+
     /// IP addresses to listen on.
     pub listen_addresses: ListenAddress,
 
@@ -52,17 +62,60 @@ pub struct Config {
     /// List of nodes to initially connect to, on Multiaddr format.
     pub boot_nodes_multiaddr: Vec<Multiaddr>,
 
+    /// List of peers to stay connected to at all times. Each address must contain the peer id.
+    pub static_peers: Vec<Multiaddr>,
+
+    /// List of peers that are never disconnected or scored down.
+    pub trusted_peers: Vec<PeerId>,
+
+    /// If not empty, only connections to and from IPs in these subnets are allowed.
+    pub allowed_subnets: Vec<IpSubnet>,
+
+    /// List of subnets whose IPs are never connected to.
+    pub denied_subnets: Vec<IpSubnet>,
+
+    /// List of peers that are never connected to.
+    pub denied_peers: Vec<PeerId>,
+
     /// Disables peer scoring altogether.
     pub disable_peer_scoring: bool,
 
     /// Disables quic support.
     pub disable_quic_support: bool,
 
+    /// Attempt to map the listening ports on the gateway using UPnP and update the ENR with the
+    /// external address.
+    pub upnp_enabled: bool,
+
+    /// Subscribe to all subnets instead of only those of our committees.
+    pub subscribe_all_subnets: bool,
+
+    /// The forks of the SSV network, determining the domain type and the topic names over time.
+    pub fork_schedule: ForkSchedule,
+
+    /// The gossipsub mesh, gossip and peer score parameters.
+    pub gossipsub: GossipsubConfig,
+
     /// List of extra topics to initially subscribe to as strings.
     pub topics: Vec<GossipKind>,
 
     /// Target number of connected peers.
     pub target_peers: usize,
+
+    /// The maximum number of connections being established from peers at once.
+    pub max_pending_incoming: u32,
+
+    /// The maximum number of connections being established to peers at once.
+    pub max_pending_outgoing: u32,
+
+    /// The maximum number of connections to a single peer.
+    pub max_connections_per_peer: u32,
+
+    /// New connections are denied once the process uses this fraction of the system memory.
+    pub max_memory_usage: f64,
+
+    /// The rate at which a single peer may use each rate limited protocol.
+    pub rate_limits: RateLimiterConfig,
 }
 
 impl Default for Config {
@@ -82,6 +135,8 @@ impl Default for Config {
 
         Self {
             network_dir,
+            network_key_file: None,
+            network_key_password_file: None,
             listen_addresses,
             enr_address: (None, None),
             enr_udp4_port: None,
@@ -91,10 +146,24 @@ impl Default for Config {
             enr_tcp6_port: None,
             enr_quic6_port: None,
             target_peers: 50,
+            max_pending_incoming: 5,
+            max_pending_outgoing: 16,
+            max_connections_per_peer: 1,
+            max_memory_usage: 0.9,
+            rate_limits: RateLimiterConfig::default(),
             boot_nodes_enr: vec![],
             boot_nodes_multiaddr: vec![],
+            static_peers: vec![],
+            trusted_peers: vec![],
+            allowed_subnets: vec![],
+            denied_subnets: vec![],
+            denied_peers: vec![],
             disable_peer_scoring: false,
             disable_quic_support: false,
+            upnp_enabled: true,
+            subscribe_all_subnets: false,
+            fork_schedule: ForkSchedule::default(),
+            gossipsub: GossipsubConfig::default(),
             topics: vec![],
         }
     }
@@ -0,0 +1,485 @@
+//! Denies connections to and from peers by IP subnet and peer id.
+//!
+//! The rules of the config apply on every start. Rules added at runtime, e.g. to ban an abusive
+//! IP, are persisted in the network directory and restored on restart. When a rule is added, the
+//! existing connections it denies are closed.
+
+use crate::nat::ip_from_multiaddr;
+use libp2p::core::transport::PortUse;
+use libp2p::core::Endpoint;
+use libp2p::swarm::behaviour::ConnectionEstablished;
+use libp2p::swarm::{
+    dummy, CloseConnection, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm,
+    NetworkBehaviour, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::task::{Context, Poll, Waker};
+use tracing::debug;
+
+/// The file in the network directory holding the rules added at runtime.
+pub const GATER_RULES_FILENAME: &str = "connection_gater.json";
+
+/// An IP subnet in CIDR notation, e.g. `10.0.0.0/8`. A single IP is a subnet with the full prefix
+/// length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpSubnet {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpSubnet {
+    pub fn new(address: IpAddr, prefix_len: u8) -> Result<Self, String> {
+        let max_prefix_len = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_prefix_len {
+            return Err(format!(
+                "Prefix length {prefix_len} exceeds {max_prefix_len} bits"
+            ));
+        }
+        Ok(Self {
+            address: mask(address, prefix_len),
+            prefix_len,
+        })
+    }
+
+    /// Returns true if the IP is in the subnet. IPv4-mapped IPv6 addresses are matched as IPv4.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+            ip => ip,
+        };
+        ip.is_ipv4() == self.address.is_ipv4() && mask(ip, self.prefix_len) == self.address
+    }
+}
+
+/// Clears the bits of the address after the prefix.
+fn mask(address: IpAddr, prefix_len: u8) -> IpAddr {
+    let host_bits = |bits: u32| bits.saturating_sub(u32::from(prefix_len));
+    match address {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX.checked_shl(host_bits(32)).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX.checked_shl(host_bits(128)).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+        }
+    }
+}
+
+impl FromStr for IpSubnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|e| format!("Invalid IP address {address}: {e}"))?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .map_err(|e| format!("Invalid prefix length {prefix_len}: {e}"))?,
+            None if address.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(address, prefix_len)
+    }
+}
+
+impl fmt::Display for IpSubnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl TryFrom<String> for IpSubnet {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IpSubnet> for String {
+    fn from(subnet: IpSubnet) -> Self {
+        subnet.to_string()
+    }
+}
+
+/// A rule of the connection gater.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GaterRule {
+    /// Allows connections with the IPs of the subnet. Once any subnet is allowed, connections
+    /// with IPs outside of the allowed subnets are denied.
+    AllowSubnet(IpSubnet),
+    /// Denies connections with the IPs of the subnet.
+    DenySubnet(IpSubnet),
+    /// Denies connections with the peer.
+    DenyPeer(PeerId),
+}
+
+impl fmt::Display for GaterRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::AllowSubnet(subnet) => write!(f, "allow subnet {subnet}"),
+            Self::DenySubnet(subnet) => write!(f, "deny subnet {subnet}"),
+            Self::DenyPeer(peer_id) => write!(f, "deny peer {peer_id}"),
+        }
+    }
+}
+
+/// The reason a connection is denied.
+#[derive(Debug)]
+pub struct Gated(String);
+
+impl fmt::Display for Gated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Gated {}
+
+/// Denies the connections to and from peers and IPs according to its rules.
+pub struct Behaviour {
+    rules: HashSet<GaterRule>,
+    /// The rules added at runtime, which are persisted in `rules_file`.
+    persisted_rules: HashSet<GaterRule>,
+    rules_file: PathBuf,
+    /// The established connections, to close those denied by new rules.
+    connections: HashMap<ConnectionId, (PeerId, Option<IpAddr>)>,
+    close_connections: VecDeque<(PeerId, ConnectionId)>,
+    waker: Option<Waker>,
+}
+
+impl Behaviour {
+    /// Creates the gater with the rules of the config and the rules persisted in the network
+    /// directory.
+    pub fn new(rules: Vec<GaterRule>, network_dir: &Path) -> Result<Self, String> {
+        let rules_file = network_dir.join(GATER_RULES_FILENAME);
+        let persisted_rules: HashSet<GaterRule> = if rules_file.exists() {
+            let contents = fs::read(&rules_file).map_err(|e| {
+                format!(
+                    "Unable to read connection gater rules {}: {e}",
+                    rules_file.display()
+                )
+            })?;
+            serde_json::from_slice(&contents).map_err(|e| {
+                format!(
+                    "Invalid connection gater rules {}: {e}",
+                    rules_file.display()
+                )
+            })?
+        } else {
+            HashSet::new()
+        };
+
+        Ok(Self {
+            rules: rules.into_iter().chain(persisted_rules.clone()).collect(),
+            persisted_rules,
+            rules_file,
+            connections: HashMap::new(),
+            close_connections: VecDeque::new(),
+            waker: None,
+        })
+    }
+
+    /// The rules currently applied.
+    pub fn rules(&self) -> Vec<GaterRule> {
+        self.rules.iter().cloned().collect()
+    }
+
+    /// Adds and persists a rule, and closes the existing connections it denies. The rule is only
+    /// applied once persisted.
+    pub fn add_rule(&mut self, rule: GaterRule) -> Result<(), String> {
+        let mut persisted_rules = self.persisted_rules.clone();
+        persisted_rules.insert(rule.clone());
+        self.persist(&persisted_rules)?;
+        self.persisted_rules = persisted_rules;
+        self.rules.insert(rule);
+
+        let denied: Vec<_> = self
+            .connections
+            .iter()
+            .filter(|(_, (peer_id, ip))| self.check(*peer_id, *ip).is_err())
+            .map(|(connection_id, (peer_id, _))| (*peer_id, *connection_id))
+            .collect();
+        for (peer_id, connection_id) in denied {
+            debug!(%peer_id, "Closing connection denied by new rule");
+            self.close_connections.push_back((peer_id, connection_id));
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Removes a rule, returning false if it was not applied. A rule of the config is applied
+    /// again on restart.
+    pub fn remove_rule(&mut self, rule: &GaterRule) -> Result<bool, String> {
+        if !self.rules.contains(rule) {
+            return Ok(false);
+        }
+        if self.persisted_rules.contains(rule) {
+            let mut persisted_rules = self.persisted_rules.clone();
+            persisted_rules.remove(rule);
+            self.persist(&persisted_rules)?;
+            self.persisted_rules = persisted_rules;
+        }
+        self.rules.remove(rule);
+        Ok(true)
+    }
+
+    /// Writes the rules added at runtime to the rules file.
+    fn persist(&self, persisted_rules: &HashSet<GaterRule>) -> Result<(), String> {
+        if let Some(dir) = self.rules_file.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                format!("Unable to create network directory {}: {e}", dir.display())
+            })?;
+        }
+        let contents = serde_json::to_vec_pretty(persisted_rules)
+            .map_err(|e| format!("Unable to encode connection gater rules: {e}"))?;
+        fs::write(&self.rules_file, contents).map_err(|e| {
+            format!(
+                "Unable to write connection gater rules {}: {e}",
+                self.rules_file.display()
+            )
+        })
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> Result<(), Gated> {
+        let mut allowed_subnets = self
+            .rules
+            .iter()
+            .filter_map(|rule| match rule {
+                GaterRule::AllowSubnet(subnet) => Some(subnet),
+                _ => None,
+            })
+            .peekable();
+        if allowed_subnets.peek().is_some() && !allowed_subnets.any(|subnet| subnet.contains(ip)) {
+            return Err(Gated(format!("IP {ip} is not in an allowed subnet")));
+        }
+        match self.rules.iter().find(|rule| match rule {
+            GaterRule::DenySubnet(subnet) => subnet.contains(ip),
+            _ => false,
+        }) {
+            Some(rule) => Err(Gated(format!("IP {ip} is denied by rule: {rule}"))),
+            None => Ok(()),
+        }
+    }
+
+    fn check_peer(&self, peer_id: PeerId) -> Result<(), Gated> {
+        if self.rules.contains(&GaterRule::DenyPeer(peer_id)) {
+            return Err(Gated(format!("Peer {peer_id} is denied")));
+        }
+        Ok(())
+    }
+
+    fn check(&self, peer_id: PeerId, ip: Option<IpAddr>) -> Result<(), Gated> {
+        self.check_peer(peer_id)?;
+        ip.map_or(Ok(()), |ip| self.check_ip(ip))
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        if let Some(ip) = ip_from_multiaddr(remote_addr) {
+            self.check_ip(ip).map_err(ConnectionDenied::new)?;
+        }
+        Ok(())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(peer, ip_from_multiaddr(remote_addr))
+            .map_err(ConnectionDenied::new)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _: &[Multiaddr],
+        _: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if let Some(peer) = maybe_peer {
+            self.check_peer(peer).map_err(ConnectionDenied::new)?;
+        }
+        Ok(vec![])
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(peer, ip_from_multiaddr(addr))
+            .map_err(ConnectionDenied::new)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished {
+                peer_id,
+                connection_id,
+                endpoint,
+                ..
+            }) => {
+                let ip = ip_from_multiaddr(endpoint.get_remote_address());
+                self.connections.insert(connection_id, (peer_id, ip));
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. }) => {
+                self.connections.remove(&connection_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if let Some((peer_id, connection_id)) = self.close_connections.pop_front() {
+            return Poll::Ready(ToSwarm::CloseConnection {
+                peer_id,
+                connection: CloseConnection::One(connection_id),
+            });
+        }
+        self.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn subnet(s: &str) -> IpSubnet {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn subnets_are_parsed_and_matched() {
+        assert_eq!(subnet("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(subnet("10.1.2.3").to_string(), "10.1.2.3/32");
+        assert_eq!(subnet("0.0.0.0/0").to_string(), "0.0.0.0/0");
+        assert!("10.0.0.0/33".parse::<IpSubnet>().is_err());
+        assert!("10.0.0/8".parse::<IpSubnet>().is_err());
+
+        assert!(subnet("10.0.0.0/8").contains(ip("10.255.0.1")));
+        assert!(!subnet("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(subnet("10.0.0.0/8").contains(ip("::ffff:10.0.0.1")));
+        assert!(subnet("fd00::/8").contains(ip("fd12::1")));
+        assert!(!subnet("fd00::/8").contains(ip("10.0.0.1")));
+        assert!(subnet("0.0.0.0/0").contains(ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn rules_deny_ips_and_peers() {
+        let network_dir = TempDir::new().unwrap();
+        let peer_id = PeerId::random();
+        let mut gater = Behaviour::new(
+            vec![GaterRule::DenySubnet(subnet("10.0.0.0/8"))],
+            network_dir.path(),
+        )
+        .unwrap();
+
+        assert!(gater.check(peer_id, Some(ip("10.0.0.1"))).is_err());
+        assert!(gater.check(peer_id, Some(ip("192.168.0.1"))).is_ok());
+
+        gater
+            .add_rule(GaterRule::AllowSubnet(subnet("192.168.0.0/16")))
+            .unwrap();
+        assert!(gater.check(peer_id, Some(ip("192.168.0.1"))).is_ok());
+        assert!(gater.check(peer_id, Some(ip("172.16.0.1"))).is_err());
+
+        gater.add_rule(GaterRule::DenyPeer(peer_id)).unwrap();
+        assert!(gater.check(peer_id, Some(ip("192.168.0.1"))).is_err());
+        assert!(gater.check(PeerId::random(), None).is_ok());
+    }
+
+    #[test]
+    fn runtime_rules_are_persisted() {
+        let network_dir = TempDir::new().unwrap();
+        let config_rule = GaterRule::DenySubnet(subnet("10.0.0.0/8"));
+        let banned_ip = GaterRule::DenySubnet(subnet("1.2.3.4"));
+        let banned_peer = GaterRule::DenyPeer(PeerId::random());
+
+        let mut gater = Behaviour::new(vec![config_rule.clone()], network_dir.path()).unwrap();
+        gater.add_rule(banned_ip.clone()).unwrap();
+        gater.add_rule(banned_peer.clone()).unwrap();
+        assert!(gater.remove_rule(&banned_peer).unwrap());
+        // Removing a rule of the config only lasts until restart.
+        assert!(gater.remove_rule(&config_rule).unwrap());
+        assert!(!gater.remove_rule(&config_rule).unwrap());
+
+        let gater = Behaviour::new(vec![config_rule.clone()], network_dir.path()).unwrap();
+        let mut rules = gater.rules();
+        rules.sort_by_key(|rule| rule.to_string());
+        assert_eq!(rules, vec![banned_ip, config_rule]);
+    }
+
+    #[test]
+    fn rules_are_not_applied_if_they_can_not_be_persisted() {
+        let network_dir = TempDir::new().unwrap();
+        let banned_ip = GaterRule::DenySubnet(subnet("1.2.3.4"));
+        let mut gater = Behaviour::new(vec![], network_dir.path()).unwrap();
+        gater.add_rule(banned_ip.clone()).unwrap();
+
+        // The rules file can't be written over a directory.
+        fs::remove_file(network_dir.path().join(GATER_RULES_FILENAME)).unwrap();
+        fs::create_dir(network_dir.path().join(GATER_RULES_FILENAME)).unwrap();
+        assert!(gater
+            .add_rule(GaterRule::DenyPeer(PeerId::random()))
+            .is_err());
+        assert!(gater.remove_rule(&banned_ip).is_err());
+        assert_eq!(gater.rules(), vec![banned_ip]);
+    }
+}
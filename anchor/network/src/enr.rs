@@ -0,0 +1,214 @@
+//! Builds and updates the local ENR.
+
+use crate::Config;
+use discv5::enr::CombinedKey;
+use discv5::Enr;
+use libp2p::identity::Keypair;
+use lighthouse_network::CombinedKeyExt;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU16;
+
+/// The ENR field specifying the QUIC IPv4 port.
+pub const QUIC_ENR_KEY: &str = "quic";
+/// The ENR field specifying the QUIC IPv6 port.
+pub const QUIC6_ENR_KEY: &str = "quic6";
+/// The ENR field specifying the domain type of the active fork.
+pub const DOMAIN_TYPE_ENR_KEY: &str = "domaintype";
+/// The ENR field specifying the domain type of the next scheduled fork.
+pub const NEXT_DOMAIN_TYPE_ENR_KEY: &str = "next_domaintype";
+
+/// Builds the local ENR from the configured ENR addresses and ports, falling back to the listening
+/// ports. The QUIC ports are only advertised if QUIC is enabled.
+pub fn build_enr(config: &Config, local_keypair: &Keypair) -> Result<(Enr, CombinedKey), String> {
+    let enr_key = CombinedKey::from_libp2p(local_keypair.clone())
+        .map_err(|e| format!("Unable to create the ENR key: {e}"))?;
+
+    let mut builder = Enr::builder();
+    let (maybe_ipv4, maybe_ipv6) = config.enr_address;
+    if let Some(ip) = maybe_ipv4 {
+        builder.ip4(ip);
+    }
+    if let Some(ip) = maybe_ipv6 {
+        builder.ip6(ip);
+    }
+
+    if let Some(listen_addr) = config.listen_addresses.v4() {
+        builder.tcp4(
+            config
+                .enr_tcp4_port
+                .map_or(listen_addr.tcp_port, NonZeroU16::get),
+        );
+        builder.udp4(
+            config
+                .enr_udp4_port
+                .map_or(listen_addr.disc_port, NonZeroU16::get),
+        );
+        if !config.disable_quic_support {
+            builder.add_value(
+                QUIC_ENR_KEY,
+                &config
+                    .enr_quic4_port
+                    .map_or(listen_addr.quic_port, NonZeroU16::get),
+            );
+        }
+    }
+    if let Some(listen_addr) = config.listen_addresses.v6() {
+        builder.tcp6(
+            config
+                .enr_tcp6_port
+                .map_or(listen_addr.tcp_port, NonZeroU16::get),
+        );
+        builder.udp6(
+            config
+                .enr_udp6_port
+                .map_or(listen_addr.disc_port, NonZeroU16::get),
+        );
+        if !config.disable_quic_support {
+            builder.add_value(
+                QUIC6_ENR_KEY,
+                &config
+                    .enr_quic6_port
+                    .map_or(listen_addr.quic_port, NonZeroU16::get),
+            );
+        }
+    }
+
+    let enr = builder
+        .build(&enr_key)
+        .map_err(|e| format!("Unable to build the local ENR: {e:?}"))?;
+    Ok((enr, enr_key))
+}
+
+/// Sets the TCP socket of the ENR for the IP version of `ip`. Returns true if the ENR changed.
+pub fn update_enr_tcp_socket(
+    enr: &mut Enr,
+    enr_key: &CombinedKey,
+    ip: IpAddr,
+    tcp_port: u16,
+) -> Result<bool, String> {
+    let socket = SocketAddr::new(ip, tcp_port);
+    let current = match ip {
+        IpAddr::V4(_) => enr.tcp4_socket().map(SocketAddr::V4),
+        IpAddr::V6(_) => enr.tcp6_socket().map(SocketAddr::V6),
+    };
+    if current == Some(socket) {
+        return Ok(false);
+    }
+
+    enr.set_tcp_socket(socket, enr_key)
+        .map_err(|e| format!("Unable to update the local ENR: {e:?}"))?;
+    Ok(true)
+}
+
+/// Sets the domain types of the active and next fork in the ENR, removing the next domain type
+/// if no fork is scheduled. Returns true if the ENR changed.
+pub fn update_enr_domain_types(
+    enr: &mut Enr,
+    enr_key: &CombinedKey,
+    domain_type: [u8; 4],
+    next_domain_type: Option<[u8; 4]>,
+) -> Result<bool, String> {
+    if enr_domain_type(enr, DOMAIN_TYPE_ENR_KEY) == Some(domain_type)
+        && enr_domain_type(enr, NEXT_DOMAIN_TYPE_ENR_KEY) == next_domain_type
+    {
+        return Ok(false);
+    }
+
+    let mut insert = vec![(DOMAIN_TYPE_ENR_KEY, domain_type.as_slice())];
+    let mut remove = vec![];
+    match &next_domain_type {
+        Some(next_domain_type) => {
+            insert.push((NEXT_DOMAIN_TYPE_ENR_KEY, next_domain_type.as_slice()))
+        }
+        None => remove.push(NEXT_DOMAIN_TYPE_ENR_KEY),
+    }
+    enr.remove_insert(remove.into_iter(), insert.into_iter(), enr_key)
+        .map_err(|e| format!("Unable to update the local ENR: {e:?}"))?;
+    Ok(true)
+}
+
+/// Reads a domain type from the ENR field, if set.
+pub fn enr_domain_type(enr: &Enr, key: &str) -> Option<[u8; 4]> {
+    // The domain type is RLP encoded as a 4 byte string.
+    match enr.get_raw_rlp(key)? {
+        [0x84, domain_type @ ..] => domain_type.try_into().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity::secp256k1;
+    use lighthouse_network::{EnrExt, ListenAddr, ListenAddress};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn update_tcp_socket() {
+        let keypair: Keypair = secp256k1::Keypair::generate().into();
+        let (mut enr, enr_key) = build_enr(&Config::default(), &keypair).unwrap();
+        assert_eq!(enr.ip4(), None);
+        let seq = enr.seq();
+
+        let ip: IpAddr = "8.8.8.8".parse().unwrap();
+        assert!(update_enr_tcp_socket(&mut enr, &enr_key, ip, 9100).unwrap());
+        assert_eq!(enr.tcp4_socket(), Some("8.8.8.8:9100".parse().unwrap()));
+        assert!(enr.seq() > seq);
+
+        // Setting the same socket again does not change the ENR.
+        assert!(!update_enr_tcp_socket(&mut enr, &enr_key, ip, 9100).unwrap());
+    }
+
+    #[test]
+    fn dual_stack_enr() {
+        let keypair: Keypair = secp256k1::Keypair::generate().into();
+        let config = Config {
+            listen_addresses: ListenAddress::DualStack(
+                ListenAddr {
+                    addr: Ipv4Addr::UNSPECIFIED,
+                    disc_port: 9100,
+                    quic_port: 9101,
+                    tcp_port: 9100,
+                },
+                ListenAddr {
+                    addr: Ipv6Addr::UNSPECIFIED,
+                    disc_port: 9110,
+                    quic_port: 9111,
+                    tcp_port: 9110,
+                },
+            ),
+            enr_address: (None, Some(Ipv6Addr::LOCALHOST)),
+            enr_tcp6_port: NonZeroU16::new(9120),
+            ..Config::default()
+        };
+
+        let (enr, _) = build_enr(&config, &keypair).unwrap();
+        assert_eq!(enr.tcp4(), Some(9100));
+        assert_eq!(enr.quic4(), Some(9101));
+        assert_eq!(enr.ip6(), Some(Ipv6Addr::LOCALHOST));
+        assert_eq!(enr.tcp6(), Some(9120));
+        assert_eq!(enr.udp6(), Some(9110));
+        assert_eq!(enr.quic6(), Some(9111));
+        assert_eq!(
+            enr.multiaddr_tcp(),
+            vec!["/ip6/::1/tcp/9120".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn update_domain_types() {
+        let keypair: Keypair = secp256k1::Keypair::generate().into();
+        let (mut enr, enr_key) = build_enr(&Config::default(), &keypair).unwrap();
+        let (current, next) = ([0, 0, 0, 1], [0, 0, 1, 1]);
+
+        assert!(update_enr_domain_types(&mut enr, &enr_key, current, Some(next)).unwrap());
+        assert_eq!(enr_domain_type(&enr, DOMAIN_TYPE_ENR_KEY), Some(current));
+        assert_eq!(enr_domain_type(&enr, NEXT_DOMAIN_TYPE_ENR_KEY), Some(next));
+        assert!(!update_enr_domain_types(&mut enr, &enr_key, current, Some(next)).unwrap());
+
+        // After the fork, no further fork is scheduled.
+        assert!(update_enr_domain_types(&mut enr, &enr_key, next, None).unwrap());
+        assert_eq!(enr_domain_type(&enr, DOMAIN_TYPE_ENR_KEY), Some(next));
+        assert_eq!(enr_domain_type(&enr, NEXT_DOMAIN_TYPE_ENR_KEY), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! The schedule of the SSV network forks.
+//!
+//! A fork changes the domain type of the network, which is part of every message id and is
+//! advertised in the ENR, and may change the naming of the subnet topics. Around a fork epoch the
+//! node stays subscribed to the topics of both forks for `FORK_GRACE_PERIOD_EPOCHS`, so that no
+//! message is missed while the peers transition.
+
+use crate::subnets::SubnetId;
+use libp2p::gossipsub::IdentTopic;
+use serde::{Deserialize, Serialize};
+use ssv_types::{MessageId, Role};
+
+/// The number of epochs before and after a fork during which the topics of both forks are used.
+pub const FORK_GRACE_PERIOD_EPOCHS: u64 = 2;
+/// The domain of the SSV mainnet.
+pub const MAINNET_DOMAIN_TYPE: [u8; 4] = [0, 0, 0, 1];
+/// The prefix of the subnet topics at genesis, followed by the subnet index.
+pub const GENESIS_TOPIC_PREFIX: &str = "ssv.v2.";
+
+/// A network fork, active from its epoch until the epoch of the next fork.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fork {
+    pub name: String,
+    pub epoch: u64,
+    /// The domain type of the messages and ENRs.
+    pub domain_type: [u8; 4],
+    /// The prefix of the subnet topics, followed by the subnet index.
+    pub topic_prefix: String,
+}
+
+impl Fork {
+    /// The id of the messages of a duty executor on this fork.
+    pub fn message_id(&self, role: Role, duty_executor_id: &[u8]) -> MessageId {
+        MessageId::new(self.domain_type, role, duty_executor_id)
+    }
+
+    /// Whether a message id is one of this fork, i.e. the id this fork derives for its role and
+    /// duty executor.
+    pub fn is_fork_message_id(&self, msg_id: &MessageId) -> bool {
+        msg_id
+            .role()
+            .is_some_and(|role| self.message_id(role, msg_id.duty_executor_id()) == *msg_id)
+    }
+
+    /// The topic of a subnet on this fork.
+    pub fn topic(&self, subnet: SubnetId) -> IdentTopic {
+        subnet.topic(&self.topic_prefix)
+    }
+}
+
+/// The forks of the network, by increasing epoch. The first fork starts at genesis.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Fork>", into = "Vec<Fork>")]
+pub struct ForkSchedule {
+    forks: Vec<Fork>,
+}
+
+impl ForkSchedule {
+    pub fn new(forks: Vec<Fork>) -> Result<Self, String> {
+        let first = forks.first().ok_or("The fork schedule is empty")?;
+        if first.epoch != 0 {
+            return Err(format!(
+                "The first fork {} must start at epoch 0",
+                first.name
+            ));
+        }
+        if let Some(pair) = forks.windows(2).find(|pair| pair[0].epoch >= pair[1].epoch) {
+            return Err(format!(
+                "Fork {} must start after fork {}",
+                pair[1].name, pair[0].name
+            ));
+        }
+        Ok(Self { forks })
+    }
+
+    /// A schedule without forks after genesis.
+    pub fn genesis(domain_type: [u8; 4]) -> Self {
+        Self {
+            forks: vec![Fork {
+                name: "genesis".to_string(),
+                epoch: 0,
+                domain_type,
+                topic_prefix: GENESIS_TOPIC_PREFIX.to_string(),
+            }],
+        }
+    }
+
+    pub fn forks(&self) -> &[Fork] {
+        &self.forks
+    }
+
+    /// The fork active at the epoch.
+    pub fn fork_at(&self, epoch: u64) -> &Fork {
+        self.forks
+            .iter()
+            .rev()
+            .find(|fork| fork.epoch <= epoch)
+            .unwrap_or(&self.forks[0])
+    }
+
+    /// The first fork after the epoch, if any is scheduled.
+    pub fn next_fork(&self, epoch: u64) -> Option<&Fork> {
+        self.forks.iter().find(|fork| fork.epoch > epoch)
+    }
+
+    /// The forks whose topics are used at the epoch: the active fork, the previous fork until the
+    /// grace period after the fork is over, and the next fork from the grace period before it.
+    pub fn forks_in_use(&self, epoch: u64) -> Vec<&Fork> {
+        self.forks
+            .iter()
+            .enumerate()
+            .filter(|(index, fork)| {
+                let start = fork.epoch.saturating_sub(FORK_GRACE_PERIOD_EPOCHS);
+                let end = self
+                    .forks
+                    .get(index + 1)
+                    .map(|next| next.epoch.saturating_add(FORK_GRACE_PERIOD_EPOCHS));
+                start <= epoch && end.map_or(true, |end| epoch < end)
+            })
+            .map(|(_, fork)| fork)
+            .collect()
+    }
+}
+
+impl Default for ForkSchedule {
+    fn default() -> Self {
+        Self::genesis(MAINNET_DOMAIN_TYPE)
+    }
+}
+
+impl TryFrom<Vec<Fork>> for ForkSchedule {
+    type Error = String;
+
+    fn try_from(forks: Vec<Fork>) -> Result<Self, Self::Error> {
+        Self::new(forks)
+    }
+}
+
+impl From<ForkSchedule> for Vec<Fork> {
+    fn from(schedule: ForkSchedule) -> Self {
+        schedule.forks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> ForkSchedule {
+        ForkSchedule::new(vec![
+            Fork {
+                name: "genesis".to_string(),
+                epoch: 0,
+                domain_type: [0, 0, 0, 1],
+                topic_prefix: "ssv.v2.".to_string(),
+            },
+            Fork {
+                name: "next".to_string(),
+                epoch: 100,
+                domain_type: [0, 0, 1, 1],
+                topic_prefix: "ssv.v3.".to_string(),
+            },
+        ])
+        .unwrap()
+    }
+
+    fn names(forks: Vec<&Fork>) -> Vec<&str> {
+        forks.iter().map(|fork| fork.name.as_str()).collect()
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        let mut forks = schedule().forks;
+        assert!(ForkSchedule::new(vec![]).is_err());
+        assert!(ForkSchedule::new(forks[1..].to_vec()).is_err());
+        forks[1].epoch = 0;
+        assert!(ForkSchedule::new(forks).is_err());
+    }
+
+    #[test]
+    fn active_and_next_fork() {
+        let schedule = schedule();
+        assert_eq!(schedule.fork_at(99).name, "genesis");
+        assert_eq!(schedule.fork_at(100).name, "next");
+        assert_eq!(schedule.next_fork(99).unwrap().name, "next");
+        assert!(schedule.next_fork(100).is_none());
+        assert_eq!(
+            schedule
+                .fork_at(100)
+                .message_id(Role::Committee, &[1; 32])
+                .domain(),
+            [0, 0, 1, 1]
+        );
+        let msg_id = schedule.fork_at(0).message_id(Role::Committee, &[1; 32]);
+        assert!(schedule.fork_at(0).is_fork_message_id(&msg_id));
+        assert!(!schedule.fork_at(100).is_fork_message_id(&msg_id));
+        assert_eq!(
+            schedule.fork_at(100).topic(SubnetId(3)).to_string(),
+            "ssv.v3.3"
+        );
+    }
+
+    #[test]
+    fn both_forks_are_used_during_the_grace_period() {
+        let schedule = schedule();
+        let start = 100 - FORK_GRACE_PERIOD_EPOCHS;
+        let end = 100 + FORK_GRACE_PERIOD_EPOCHS;
+        assert_eq!(names(schedule.forks_in_use(start - 1)), vec!["genesis"]);
+        assert_eq!(names(schedule.forks_in_use(start)), vec!["genesis", "next"]);
+        assert_eq!(
+            names(schedule.forks_in_use(end - 1)),
+            vec!["genesis", "next"]
+        );
+        assert_eq!(names(schedule.forks_in_use(end)), vec!["next"]);
+    }
+
+    #[test]
+    fn schedule_is_deserialized_and_validated() {
+        let json = serde_json::to_string(&schedule()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ForkSchedule>(&json).unwrap(),
+            schedule()
+        );
+        assert!(serde_json::from_str::<ForkSchedule>("[]").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! The tunable gossipsub parameters.
+//!
+//! The defaults match the parameters of the SSV network. Small networks such as devnets may want
+//! smaller meshes and a shorter heartbeat to lower the latency.
+//!
+//! Every subnet topic is scored with the same parameters: peers earn a bounded score for their
+//! time in the mesh and the messages they deliver first, and lose score quadratically for the
+//! invalid messages they deliver. Mesh delivery rates are not scored, as the expected rate of a
+//! subnet depends on its committees and would penalise honest peers of quiet subnets.
+
+use libp2p::gossipsub::{self, PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
+use serde::{Deserialize, Serialize};
+use ssv_types::message::MAX_SIGNED_SSV_MESSAGE_SIZE;
+use std::time::Duration;
+
+/// The maximum positive score a peer can get from the topics.
+const TOPIC_SCORE_CAP: f64 = 32.0;
+/// The number of invalid messages on a topic after which a peer is graylisted.
+const INVALID_MESSAGES_TO_GRAYLIST: f64 = 20.0;
+/// The time in the mesh a peer earns score for, in units of `TIME_IN_MESH_QUANTUM`.
+const TIME_IN_MESH_CAP: f64 = 300.0;
+/// The time in the mesh earning one unit of score, a slot.
+const TIME_IN_MESH_QUANTUM: Duration = Duration::from_secs(12);
+/// The number of first deliveries a peer earns score for.
+const FIRST_MESSAGE_DELIVERIES_CAP: f64 = 40.0;
+
+/// The gossipsub mesh, gossip and peer score parameters.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GossipsubConfig {
+    /// The target number of peers in the mesh of a topic (D).
+    pub mesh_n: usize,
+    /// The number of mesh peers below which more peers are grafted (D_lo).
+    pub mesh_n_low: usize,
+    /// The number of mesh peers above which peers are pruned (D_hi).
+    pub mesh_n_high: usize,
+    /// The number of peers outside the mesh gossip is emitted to (D_lazy).
+    pub gossip_lazy: usize,
+    /// The interval between two gossipsub heartbeats, in milliseconds.
+    pub heartbeat_interval_ms: u64,
+    /// The number of heartbeats messages are kept in the message cache for.
+    pub history_length: usize,
+    /// The number of heartbeats of the message cache that gossip is emitted about.
+    pub history_gossip: usize,
+    /// The maximum size of a gossipsub RPC, in bytes.
+    pub max_transmit_size: usize,
+    /// Publish our messages to all peers of a topic with a score above the publish threshold,
+    /// instead of to the mesh peers only.
+    pub flood_publish: bool,
+    /// The peer score below which no gossip is emitted to or accepted from a peer.
+    pub gossip_threshold: f64,
+    /// The peer score below which our messages are not flood published to a peer.
+    pub publish_threshold: f64,
+    /// The peer score below which all messages of a peer are ignored.
+    pub graylist_threshold: f64,
+    /// The peer score above which the peers exchanged on prune are accepted.
+    pub accept_px_threshold: f64,
+    /// The median mesh peer score below which peers with a higher score are grafted.
+    pub opportunistic_graft_threshold: f64,
+}
+
+impl Default for GossipsubConfig {
+    fn default() -> Self {
+        Self {
+            mesh_n: 8,
+            mesh_n_low: 6,
+            mesh_n_high: 12,
+            gossip_lazy: 6,
+            heartbeat_interval_ms: 700,
+            history_length: 6,
+            history_gossip: 4,
+            max_transmit_size: MAX_SIGNED_SSV_MESSAGE_SIZE,
+            flood_publish: true,
+            gossip_threshold: -4000.0,
+            publish_threshold: -8000.0,
+            graylist_threshold: -16000.0,
+            accept_px_threshold: 100.0,
+            opportunistic_graft_threshold: 5.0,
+        }
+    }
+}
+
+impl GossipsubConfig {
+    /// Checks that the parameters are consistent and large enough for the SSV messages.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1 <= self.mesh_n_low
+            && self.mesh_n_low <= self.mesh_n
+            && self.mesh_n <= self.mesh_n_high)
+        {
+            return Err(format!(
+                "Invalid gossipsub mesh sizes, 1 <= D_lo ({}) <= D ({}) <= D_hi ({}) is required",
+                self.mesh_n_low, self.mesh_n, self.mesh_n_high
+            ));
+        }
+        if self.heartbeat_interval_ms == 0 {
+            return Err("The gossipsub heartbeat interval must not be zero".to_string());
+        }
+        if self.history_gossip == 0 || self.history_gossip > self.history_length {
+            return Err(format!(
+                "Invalid gossipsub history, 1 <= gossip ({}) <= length ({}) is required",
+                self.history_gossip, self.history_length
+            ));
+        }
+        if self.max_transmit_size < MAX_SIGNED_SSV_MESSAGE_SIZE {
+            return Err(format!(
+                "The gossipsub max transmit size must be at least {} bytes",
+                MAX_SIGNED_SSV_MESSAGE_SIZE
+            ));
+        }
+        self.topic_score_params()
+            .validate()
+            .map_err(|e| format!("Invalid gossipsub topic score parameters: {e}"))?;
+        self.peer_score_thresholds()
+            .validate()
+            .map_err(|e| format!("Invalid gossipsub peer score thresholds: {e}"))
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_ms)
+    }
+
+    /// Applies the parameters to a gossipsub config.
+    pub fn apply(&self, builder: &mut gossipsub::ConfigBuilder) {
+        builder
+            .mesh_n(self.mesh_n)
+            .mesh_n_low(self.mesh_n_low)
+            .mesh_n_high(self.mesh_n_high)
+            // At most half of the mesh must be outbound peers, which small meshes cannot have.
+            .mesh_outbound_min((self.mesh_n / 2).min(self.mesh_n_low).min(2))
+            .gossip_lazy(self.gossip_lazy)
+            .heartbeat_interval(self.heartbeat_interval())
+            .history_length(self.history_length)
+            .history_gossip(self.history_gossip)
+            .max_transmit_size(self.max_transmit_size)
+            .flood_publish(self.flood_publish);
+    }
+
+    /// The global peer score parameters. The parameters of the subnet topics are set as they are
+    /// subscribed to, see `topic_score_params`.
+    pub fn peer_score_params(&self) -> PeerScoreParams {
+        PeerScoreParams {
+            topic_score_cap: TOPIC_SCORE_CAP,
+            ..PeerScoreParams::default()
+        }
+    }
+
+    /// The score parameters of a subnet topic.
+    pub fn topic_score_params(&self) -> TopicScoreParams {
+        TopicScoreParams {
+            topic_weight: 1.0,
+            time_in_mesh_weight: 10.0 / TIME_IN_MESH_CAP,
+            time_in_mesh_quantum: TIME_IN_MESH_QUANTUM,
+            time_in_mesh_cap: TIME_IN_MESH_CAP,
+            first_message_deliveries_weight: 0.5,
+            first_message_deliveries_decay: 0.9,
+            first_message_deliveries_cap: FIRST_MESSAGE_DELIVERIES_CAP,
+            mesh_message_deliveries_weight: 0.0,
+            mesh_failure_penalty_weight: 0.0,
+            // The penalty is the square of the number of invalid messages.
+            invalid_message_deliveries_weight: self.graylist_threshold
+                / INVALID_MESSAGES_TO_GRAYLIST.powi(2),
+            invalid_message_deliveries_decay: 0.99,
+            ..TopicScoreParams::default()
+        }
+    }
+
+    pub fn peer_score_thresholds(&self) -> PeerScoreThresholds {
+        PeerScoreThresholds {
+            gossip_threshold: self.gossip_threshold,
+            publish_threshold: self.publish_threshold,
+            graylist_threshold: self.graylist_threshold,
+            accept_px_threshold: self.accept_px_threshold,
+            opportunistic_graft_threshold: self.opportunistic_graft_threshold,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = GossipsubConfig::default();
+        assert!(config.validate().is_ok());
+
+        let mut builder = gossipsub::ConfigBuilder::default();
+        config.apply(&mut builder);
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn invalid_messages_graylist_peers() {
+        let config = GossipsubConfig::default();
+        assert!(config.peer_score_params().validate().is_ok());
+        let params = config.topic_score_params();
+        assert!(params.validate().is_ok());
+
+        let penalty = |invalid_messages: f64| {
+            params.topic_weight
+                * params.invalid_message_deliveries_weight
+                * invalid_messages.powi(2)
+        };
+        // The positive score of the topics can't make up for the invalid messages.
+        let max_score = TOPIC_SCORE_CAP;
+        assert!(
+            max_score + penalty(INVALID_MESSAGES_TO_GRAYLIST - 1.0) > config.graylist_threshold
+        );
+        assert!(
+            max_score + penalty(INVALID_MESSAGES_TO_GRAYLIST + 1.0) < config.graylist_threshold
+        );
+        assert!(max_score + penalty(1.0) > config.gossip_threshold);
+    }
+
+    #[test]
+    fn small_meshes_are_valid() {
+        let config = GossipsubConfig {
+            mesh_n: 2,
+            mesh_n_low: 1,
+            mesh_n_high: 3,
+            heartbeat_interval_ms: 100,
+            ..GossipsubConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let mut builder = gossipsub::ConfigBuilder::default();
+        config.apply(&mut builder);
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let invalid = |config: GossipsubConfig| config.validate().is_err();
+        let default = GossipsubConfig::default;
+        assert!(invalid(GossipsubConfig {
+            mesh_n_low: 9,
+            ..default()
+        }));
+        assert!(invalid(GossipsubConfig {
+            mesh_n_high: 7,
+            ..default()
+        }));
+        assert!(invalid(GossipsubConfig {
+            mesh_n_low: 0,
+            ..default()
+        }));
+        assert!(invalid(GossipsubConfig {
+            heartbeat_interval_ms: 0,
+            ..default()
+        }));
+        assert!(invalid(GossipsubConfig {
+            history_gossip: 7,
+            ..default()
+        }));
+        assert!(invalid(GossipsubConfig {
+            max_transmit_size: 1024,
+            ..default()
+        }));
+        assert!(invalid(GossipsubConfig {
+            publish_threshold: -1000.0,
+            ..default()
+        }));
+    }
+
+    #[test]
+    fn missing_values_take_the_defaults() {
+        let config: GossipsubConfig =
+            serde_json::from_str(r#"{"mesh_n": 4, "mesh_n_low": 3, "mesh_n_high": 6}"#).unwrap();
+        assert_eq!(
+            config,
+            GossipsubConfig {
+                mesh_n: 4,
+                mesh_n_low: 3,
+                mesh_n_high: 6,
+                ..GossipsubConfig::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! A request-response protocol to fetch the recent decided messages of a duty executor.
+//!
+//! Nodes keep the last decided messages they saw on gossip in a `DecidedStore` and serve them to
+//! peers requesting a range of heights, so that a node that restarts or joins late learns about
+//! the progress of its committees. Requests and responses are SSZ encoded and their size is
+//! limited on both ends. The encoding differs from the one of the SSV history protocol, so the
+//! protocol has its own id and is only spoken between Anchor nodes.
+//!
+//! Responses are not trusted: every message must be a decided message of the requested duty
+//! executor, signed by a quorum of its committee, to be accepted.
+
+use crate::message_validator::MessageValidator;
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{request_response, StreamProtocol};
+use ssv_types::message::MESSAGE_ID_LEN;
+use ssv_types::{MessageId, MsgType, QbftMessage, QbftMessageType, SignedSSVMessage};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode, Encode};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::time::Duration;
+
+/// The protocol decided messages are requested on.
+pub const PROTOCOL: StreamProtocol = StreamProtocol::new("/anchor/sync/decided/history/0.0.1");
+/// The maximum number of heights a single request may cover.
+pub const MAX_HEIGHTS_PER_REQUEST: u64 = 25;
+/// The size of an encoded request: the message id and two heights.
+const MAX_REQUEST_SIZE: usize = MESSAGE_ID_LEN + 2 * 8;
+/// The maximum size of an encoded response. Responses are truncated to fit.
+pub const MAX_RESPONSE_SIZE: usize = 8 * 1024 * 1024;
+/// The number of decided heights kept per duty executor.
+const MAX_STORED_HEIGHTS: usize = 64;
+/// The time a peer has to answer a request.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The maximum number of inbound and outbound requests in flight per connection.
+pub const MAX_CONCURRENT_STREAMS: usize = 8;
+
+/// Requests the decided messages of a duty executor for an inclusive range of heights.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct DecidedHistoryRequest {
+    pub msg_id: MessageId,
+    pub from_height: u64,
+    pub to_height: u64,
+}
+
+impl DecidedHistoryRequest {
+    /// Checks that the range of heights is not empty and not larger than allowed.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.to_height < self.from_height {
+            return Err(format!(
+                "Invalid height range {}..={}",
+                self.from_height, self.to_height
+            ));
+        }
+        if self.to_height - self.from_height >= MAX_HEIGHTS_PER_REQUEST {
+            return Err(format!(
+                "Height range {}..={} exceeds {MAX_HEIGHTS_PER_REQUEST} heights",
+                self.from_height, self.to_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The decided messages found for a request, by increasing height.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct DecidedHistoryResponse {
+    pub messages: Vec<SignedSSVMessage>,
+}
+
+/// Checks that the messages of a response are verified decided messages answering the request,
+/// and returns them.
+pub fn validate_response(
+    request: &DecidedHistoryRequest,
+    response: DecidedHistoryResponse,
+    validator: &MessageValidator,
+) -> Result<Vec<SignedSSVMessage>, String> {
+    for message in &response.messages {
+        if message.ssv_message.msg_id != request.msg_id {
+            return Err("Response contains a message of another duty executor".to_string());
+        }
+        let height = validator
+            .verify_decided(message)
+            .map_err(|e| format!("Response contains an invalid decided message: {e}"))?;
+        if height < request.from_height || height > request.to_height {
+            return Err(format!("Response contains unrequested height {height}"));
+        }
+    }
+    Ok(response.messages)
+}
+
+/// Returns the height of a decided message, i.e. a commit aggregated from several signers.
+pub fn decided_height(message: &SignedSSVMessage) -> Option<u64> {
+    if message.operator_ids.len() < 2 || message.ssv_message.msg_type() != Ok(MsgType::Consensus) {
+        return None;
+    }
+    let qbft_message = QbftMessage::from_ssz_bytes(&message.ssv_message.data).ok()?;
+    (qbft_message.qbft_message_type() == Ok(QbftMessageType::Commit)).then_some(qbft_message.height)
+}
+
+/// Keeps the last decided messages of each duty executor.
+#[derive(Debug, Default)]
+pub struct DecidedStore {
+    decided: HashMap<MessageId, BTreeMap<u64, SignedSSVMessage>>,
+}
+
+impl DecidedStore {
+    /// Stores the message if it is a decided message. Of several decided messages for a height,
+    /// the one with the most signers is kept. Returns true if the message was stored.
+    ///
+    /// The signatures are not verified here: only our own messages and the gossip messages
+    /// accepted by the `MessageValidator` may be inserted, so that a forged message can't replace
+    /// a genuine one.
+    pub fn insert(&mut self, message: &SignedSSVMessage) -> bool {
+        let Some(height) = decided_height(message) else {
+            return false;
+        };
+        let heights = self.decided.entry(message.ssv_message.msg_id).or_default();
+        if heights
+            .get(&height)
+            .is_some_and(|stored| stored.operator_ids.len() >= message.operator_ids.len())
+        {
+            return false;
+        }
+        heights.insert(height, message.clone());
+        while heights.len() > MAX_STORED_HEIGHTS {
+            heights.pop_first();
+        }
+        true
+    }
+
+    /// Returns the stored decided messages of the requested heights, truncated to fit in a
+    /// response.
+    pub fn get(&self, request: &DecidedHistoryRequest) -> DecidedHistoryResponse {
+        let mut response = DecidedHistoryResponse::default();
+        let Some(heights) = self.decided.get(&request.msg_id) else {
+            return response;
+        };
+        // The offset of each message in the list takes 4 bytes.
+        let mut size = 4;
+        for message in heights
+            .range(request.from_height..=request.to_height)
+            .map(|(_, message)| message)
+        {
+            size += 4 + message.ssz_bytes_len();
+            if size > MAX_RESPONSE_SIZE {
+                break;
+            }
+            response.messages.push(message.clone());
+        }
+        response
+    }
+}
+
+/// Encodes the requests and responses with SSZ.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HistorySyncCodec;
+
+#[async_trait]
+impl request_response::Codec for HistorySyncCodec {
+    type Protocol = StreamProtocol;
+    type Request = DecidedHistoryRequest;
+    type Response = DecidedHistoryResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_limited(io, MAX_REQUEST_SIZE).await?;
+        DecidedHistoryRequest::from_ssz_bytes(&data).map_err(invalid_data)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_limited(io, MAX_RESPONSE_SIZE).await?;
+        DecidedHistoryResponse::from_ssz_bytes(&data).map_err(invalid_data)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&request.as_ssz_bytes()).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&response.as_ssz_bytes()).await
+    }
+}
+
+/// Reads the whole stream, failing if it is longer than `limit` bytes.
+async fn read_limited<T>(io: &mut T, limit: usize) -> io::Result<Vec<u8>>
+where
+    T: AsyncRead + Unpin + Send,
+{
+    let mut data = Vec::new();
+    io.take(limit as u64 + 1).read_to_end(&mut data).await?;
+    if data.len() > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Message exceeds {limit} bytes"),
+        ));
+    }
+    Ok(data)
+}
+
+fn invalid_data(error: ssz::DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{error:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TestContext, TEST_OPERATOR_KEY};
+    use ssv_types::message::RSA_SIGNATURE_SIZE;
+    use ssv_types::{OperatorId, Role, SSVMessage};
+    use std::sync::Arc;
+
+    fn msg_id() -> MessageId {
+        MessageId::new([0, 0, 0, 1], Role::Committee, &[3; 32])
+    }
+
+    fn message(
+        qbft_message_type: QbftMessageType,
+        height: u64,
+        signers: &[u64],
+    ) -> SignedSSVMessage {
+        let qbft_message = QbftMessage {
+            qbft_message_type: qbft_message_type as u64,
+            height,
+            round: 1,
+            identifier: msg_id().as_bytes().to_vec(),
+            root: [1; 32],
+            data_round: 0,
+            round_change_justification: vec![],
+            prepare_justification: vec![],
+        };
+        SignedSSVMessage {
+            signatures: signers.iter().map(|_| vec![0; 256]).collect(),
+            operator_ids: signers.iter().copied().map(OperatorId).collect(),
+            ssv_message: SSVMessage {
+                msg_type: MsgType::Consensus as u64,
+                msg_id: msg_id(),
+                data: qbft_message.as_ssz_bytes(),
+            },
+            full_data: vec![],
+        }
+    }
+
+    fn request(from_height: u64, to_height: u64) -> DecidedHistoryRequest {
+        DecidedHistoryRequest {
+            msg_id: msg_id(),
+            from_height,
+            to_height,
+        }
+    }
+
+    #[test]
+    fn only_decided_messages_are_stored() {
+        let mut store = DecidedStore::default();
+        assert!(!store.insert(&message(QbftMessageType::Commit, 1, &[1])));
+        assert!(!store.insert(&message(QbftMessageType::Prepare, 1, &[1, 2, 3])));
+        assert!(store.insert(&message(QbftMessageType::Commit, 1, &[1, 2, 3])));
+        // A decided message with fewer signers does not replace the stored one.
+        assert!(!store.insert(&message(QbftMessageType::Commit, 1, &[1, 2])));
+        assert!(store.insert(&message(QbftMessageType::Commit, 1, &[1, 2, 3, 4])));
+
+        let response = store.get(&request(0, 5));
+        assert_eq!(
+            response.messages,
+            vec![message(QbftMessageType::Commit, 1, &[1, 2, 3, 4])]
+        );
+    }
+
+    #[test]
+    fn heights_are_served_by_range_and_pruned() {
+        let mut store = DecidedStore::default();
+        for height in 0..MAX_STORED_HEIGHTS as u64 + 10 {
+            store.insert(&message(QbftMessageType::Commit, height, &[1, 2, 3]));
+        }
+
+        let heights = |response: DecidedHistoryResponse| -> Vec<u64> {
+            response
+                .messages
+                .iter()
+                .filter_map(decided_height)
+                .collect()
+        };
+        assert_eq!(heights(store.get(&request(20, 22))), vec![20, 21, 22]);
+        // The oldest heights have been pruned.
+        assert!(heights(store.get(&request(0, 9))).is_empty());
+    }
+
+    #[test]
+    fn request_ranges_are_bounded() {
+        assert!(request(5, 5).validate().is_ok());
+        assert!(request(5, 4).validate().is_err());
+        assert!(request(0, MAX_HEIGHTS_PER_REQUEST - 1).validate().is_ok());
+        assert!(request(0, MAX_HEIGHTS_PER_REQUEST).validate().is_err());
+        assert_eq!(request(1, 2).as_ssz_bytes().len(), MAX_REQUEST_SIZE);
+    }
+
+    /// Signs a message as all its signers with the test operator key.
+    fn signed(mut message: SignedSSVMessage) -> SignedSSVMessage {
+        let signature = TEST_OPERATOR_KEY
+            .sign(&message.ssv_message.as_ssz_bytes())
+            .unwrap();
+        message.signatures = vec![signature; message.operator_ids.len()];
+        message
+    }
+
+    #[test]
+    fn responses_must_match_the_request() {
+        let validator = MessageValidator::new(Arc::new(TestContext), msg_id().domain());
+        let validate = |request, messages| {
+            validate_response(&request, DecidedHistoryResponse { messages }, &validator)
+        };
+        let decided = signed(message(QbftMessageType::Commit, 3, &[1, 2, 3]));
+        assert!(validate(request(1, 5), vec![decided.clone()]).is_ok());
+        assert!(validate(request(4, 5), vec![decided.clone()]).is_err());
+        assert!(validate(
+            request(1, 5),
+            vec![signed(message(QbftMessageType::Prepare, 3, &[1]))]
+        )
+        .is_err());
+
+        // Forged messages are not accepted.
+        let mut forged = decided;
+        forged.signatures[0] = vec![0; RSA_SIGNATURE_SIZE];
+        assert!(validate(request(1, 5), vec![forged]).is_err());
+        assert!(validate(
+            request(1, 5),
+            vec![message(QbftMessageType::Commit, 3, &[1, 2, 3])]
+        )
+        .is_err());
+    }
+}
@@ -1,51 +1,290 @@
+use crate::keystore::EncryptedKey;
+use crate::Config;
 use libp2p::identity::{secp256k1, Keypair};
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use tracing::{debug, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::{debug, info};
 
 pub const NETWORK_KEY_FILENAME: &str = "key";
+pub const ENCRYPTED_NETWORK_KEY_FILENAME: &str = "key.json";
 
-/// Loads a private key from disk. If this fails, a new key is
-/// generated and is then saved to disk.
+/// The PEM tag of a SEC1 encoded elliptic curve private key.
+const PEM_EC_PRIVATE_KEY_TAG: &str = "EC PRIVATE KEY";
+
+/// Loads the network private key.
+///
+/// If a key file is given in the config it is imported and must be valid. Otherwise, the key is
+/// loaded from the network directory, encrypted with the configured password if any. A plaintext
+/// key stored before a password was configured is encrypted in place of it, and an encrypted key
+/// without a password is an error. A new key is only generated and saved when no key exists yet:
+/// an existing key that cannot be read or parsed is an error, as silently rotating the node
+/// identity would change its peer id.
 ///
 /// Currently only secp256k1 keys are allowed, as these are the only keys supported by discv5.
-pub fn load_private_key(network_dir: &PathBuf) -> Keypair {
-    // check for key from disk
-    let network_key_f = network_dir.join(NETWORK_KEY_FILENAME);
-    if let Ok(mut network_key_file) = File::open(network_key_f.clone()) {
-        let mut key_bytes: Vec<u8> = Vec::with_capacity(36);
-        match network_key_file.read_to_end(&mut key_bytes) {
-            Err(_) => debug!("Could not read network key file"),
-            Ok(_) => {
-                // only accept secp256k1 keys for now
-                if let Ok(secret_key) = secp256k1::SecretKey::try_from_bytes(&mut key_bytes) {
-                    let kp: secp256k1::Keypair = secret_key.into();
-                    debug!("Loaded network key from disk.");
-                    return kp.into();
-                } else {
-                    debug!("Network key file is not a valid secp256k1 key");
-                }
+pub fn load_private_key(config: &Config) -> Result<Keypair, String> {
+    let password = config
+        .network_key_password_file
+        .as_deref()
+        .map(read_password_file)
+        .transpose()?;
+
+    if let Some(key_file) = &config.network_key_file {
+        let keypair = read_private_key(key_file, password.as_deref())?;
+        debug!(file = ?key_file, "Imported network key");
+        return Ok(keypair);
+    }
+
+    let plaintext_file = config.network_dir.join(NETWORK_KEY_FILENAME);
+    let encrypted_file = config.network_dir.join(ENCRYPTED_NETWORK_KEY_FILENAME);
+    let key_file = match password {
+        Some(_) => &encrypted_file,
+        None => &plaintext_file,
+    };
+    if key_file.exists() {
+        let keypair = read_private_key(key_file, password.as_deref())?;
+        debug!("Loaded network key from disk.");
+        return Ok(keypair);
+    }
+    match &password {
+        Some(password) if plaintext_file.exists() => {
+            // Keep the identity of the node, only encrypting its key.
+            let keypair = read_private_key(&plaintext_file, None)?;
+            export_private_key(&keypair, &encrypted_file, Some(password.as_slice()))?;
+            fs::remove_file(&plaintext_file).map_err(|e| {
+                format!("Unable to remove the plaintext network key {plaintext_file:?}: {e}")
+            })?;
+            info!(file = ?encrypted_file, "Encrypted the existing network key");
+            return Ok(keypair);
+        }
+        None if encrypted_file.exists() => {
+            return Err(format!(
+                "The network key {encrypted_file:?} is encrypted, a password file is required"
+            ));
+        }
+        _ => {}
+    }
+
+    // No key has been stored yet, generate a new one and save it
+    let keypair: Keypair = secp256k1::Keypair::generate().into();
+    fs::create_dir_all(&config.network_dir)
+        .map_err(|e| format!("Unable to create network directory: {e}"))?;
+    export_private_key(&keypair, key_file, password.as_deref())?;
+    info!(file = ?key_file, "New network key generated and written to disk");
+    Ok(keypair)
+}
+
+/// Reads a secp256k1 private key from a file.
+///
+/// The file can either contain a password-encrypted keystore, a PEM encoded SEC1 key, a hex
+/// encoded key (with an optional `0x` prefix) or the 32 raw bytes of the key.
+pub fn read_private_key(path: &Path, password: Option<&[u8]>) -> Result<Keypair, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("Unable to read network key file {path:?}: {e}"))?;
+    parse_private_key(&bytes, password)
+        .map_err(|e| format!("Invalid network key file {path:?}: {e}"))
+}
+
+/// Writes a private key to a file that is only readable by the current user. The key is written
+/// as an encrypted keystore if a password is given and hex encoded otherwise.
+pub fn export_private_key(
+    keypair: &Keypair,
+    path: &Path,
+    password: Option<&[u8]>,
+) -> Result<(), String> {
+    let secret = keypair
+        .clone()
+        .try_into_secp256k1()
+        .map_err(|_| "Only secp256k1 network keys are supported".to_string())?
+        .secret()
+        .to_bytes();
+
+    let contents = match password {
+        Some(password) => serde_json::to_vec_pretty(&EncryptedKey::encrypt(&secret, password)?)
+            .map_err(|e| format!("Unable to serialize network keystore: {e}"))?,
+        None => hex::encode(secret).into_bytes(),
+    };
+
+    create_private_file(path)
+        .and_then(|mut file| file.write_all(&contents))
+        .map_err(|e| format!("Unable to write network key to {path:?}: {e}"))
+}
+
+/// Writes the network key of the node to a file, encrypted with the password of `password_file`
+/// if given, e.g. to move the identity of the node to another host.
+pub fn export_network_key(
+    config: &Config,
+    path: &Path,
+    password_file: Option<&Path>,
+) -> Result<(), String> {
+    let keypair = load_private_key(config)?;
+    let password = password_file.map(read_password_file).transpose()?;
+    export_private_key(&keypair, path, password.as_deref())?;
+    info!(file = ?path, "Exported network key");
+    Ok(())
+}
+
+fn parse_private_key(bytes: &[u8], password: Option<&[u8]>) -> Result<Keypair, String> {
+    let secret_key = if let Ok(text) = std::str::from_utf8(bytes) {
+        let text = text.trim();
+        if text.starts_with('{') {
+            let password =
+                password.ok_or("The network key is encrypted but no password was given")?;
+            let encrypted: EncryptedKey =
+                serde_json::from_str(text).map_err(|e| format!("Unable to parse keystore: {e}"))?;
+            secp256k1::SecretKey::try_from_bytes(encrypted.decrypt(password)?)
+        } else if text.starts_with("-----BEGIN") {
+            let pem = pem::parse(text).map_err(|e| format!("Unable to parse PEM: {e}"))?;
+            if pem.tag() != PEM_EC_PRIVATE_KEY_TAG {
+                return Err(format!("Unsupported PEM tag {}", pem.tag()));
             }
+            secp256k1::SecretKey::from_der(pem.into_contents())
+        } else {
+            let mut decoded = hex::decode(text.strip_prefix("0x").unwrap_or(text))
+                .or_else(|_| raw_key_bytes(bytes))?;
+            secp256k1::SecretKey::try_from_bytes(&mut decoded)
         }
+    } else {
+        secp256k1::SecretKey::try_from_bytes(raw_key_bytes(bytes)?)
     }
+    .map_err(|e| format!("Not a valid secp256k1 key: {e}"))?;
+
+    Ok(secp256k1::Keypair::from(secret_key).into())
+}
 
-    // if a key could not be loaded from disk, generate a new one and save it
-    let local_private_key = secp256k1::Keypair::generate();
-    let _ = std::fs::create_dir_all(network_dir);
-    match File::create(network_key_f.clone())
-        .and_then(|mut f| f.write_all(&local_private_key.secret().to_bytes()))
+fn raw_key_bytes(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.len() == 32 {
+        Ok(bytes.to_vec())
+    } else {
+        Err("Unrecognized key format".to_string())
+    }
+}
+
+fn read_password_file(path: &Path) -> Result<Vec<u8>, String> {
+    let password = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read network key password file {path:?}: {e}"))?;
+    Ok(password.trim_end_matches(['\r', '\n']).as_bytes().to_vec())
+}
+
+fn create_private_file(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
     {
-        Ok(_) => {
-            debug!("New network key generated and written to disk");
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config(dir: &TempDir) -> Config {
+        Config {
+            network_dir: dir.path().to_path_buf(),
+            ..Config::default()
         }
-        Err(e) => {
-            warn!(
-                file = ?network_key_f,
-                error = ?e,
-                "Could not write node key to file"
-            );
+    }
+
+    #[test]
+    fn generated_key_is_persisted() {
+        let dir = TempDir::new().unwrap();
+        let config = config(&dir);
+
+        let keypair = load_private_key(&config).unwrap();
+        let reloaded = load_private_key(&config).unwrap();
+        assert_eq!(keypair.public(), reloaded.public());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(dir.path().join(NETWORK_KEY_FILENAME)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
         }
     }
-    local_private_key.into()
+
+    #[test]
+    fn corrupt_key_file_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join(NETWORK_KEY_FILENAME), b"not a key").unwrap();
+        assert!(load_private_key(&config(&dir)).is_err());
+    }
+
+    #[test]
+    fn import_hex_and_pem_keys() {
+        let dir = TempDir::new().unwrap();
+        let secret = secp256k1::SecretKey::generate();
+        let expected: Keypair = secp256k1::Keypair::from(secret.clone()).into();
+
+        let hex_file = dir.path().join("key.hex");
+        fs::write(&hex_file, format!("0x{}\n", hex::encode(secret.to_bytes()))).unwrap();
+        let keypair = read_private_key(&hex_file, None).unwrap();
+        assert_eq!(keypair.public(), expected.public());
+
+        // SEC1 DER structure of a secp256k1 private key without the optional public key.
+        let mut der = vec![0x30, 0x2e, 0x02, 0x01, 0x01, 0x04, 0x20];
+        der.extend_from_slice(&secret.to_bytes());
+        der.extend_from_slice(&[0xa0, 0x07, 0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a]);
+        let pem_file = dir.path().join("key.pem");
+        fs::write(
+            &pem_file,
+            pem::encode(&pem::Pem::new(PEM_EC_PRIVATE_KEY_TAG, der)),
+        )
+        .unwrap();
+        let keypair = read_private_key(&pem_file, None).unwrap();
+        assert_eq!(keypair.public(), expected.public());
+    }
+
+    #[test]
+    fn encrypted_key_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "secret\n").unwrap();
+        let config = Config {
+            network_key_password_file: Some(password_file),
+            ..config(&dir)
+        };
+
+        let keypair = load_private_key(&config).unwrap();
+        let key_file = dir.path().join(ENCRYPTED_NETWORK_KEY_FILENAME);
+        assert!(key_file.exists());
+        assert!(read_private_key(&key_file, None).is_err());
+        assert!(read_private_key(&key_file, Some(b"wrong")).is_err());
+        assert_eq!(
+            load_private_key(&config).unwrap().public(),
+            keypair.public()
+        );
+    }
+
+    #[test]
+    fn plaintext_key_is_encrypted_once_a_password_is_set() {
+        let dir = TempDir::new().unwrap();
+        let keypair = load_private_key(&config(&dir)).unwrap();
+
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "secret").unwrap();
+        let encrypted_config = Config {
+            network_key_password_file: Some(password_file.clone()),
+            ..config(&dir)
+        };
+        assert_eq!(
+            load_private_key(&encrypted_config).unwrap().public(),
+            keypair.public()
+        );
+        assert!(!dir.path().join(NETWORK_KEY_FILENAME).exists());
+        // The encrypted key is not replaced by a new plaintext key without the password.
+        assert!(load_private_key(&config(&dir)).is_err());
+
+        let exported = dir.path().join("exported.json");
+        export_network_key(&encrypted_config, &exported, Some(&password_file)).unwrap();
+        assert_eq!(
+            read_private_key(&exported, Some(b"secret"))
+                .unwrap()
+                .public(),
+            keypair.public()
+        );
+    }
 }
@@ -0,0 +1,149 @@
+//! A password-encrypted container for the node's network key.
+//!
+//! The format is specific to Anchor, though its scheme is the one of EIP-2335 keystores: the
+//! password is stretched with scrypt, the first half of the derived key encrypts the secret with
+//! AES-128-CTR and the second half is used to compute a checksum that detects a wrong password.
+//! EIP-2335 tools can not read it.
+
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+/// The current version of the keystore format.
+pub const KEYSTORE_VERSION: u32 = 1;
+
+/// The default scrypt cost parameter, as a power of two.
+const DEFAULT_SCRYPT_LOG_N: u8 = 15;
+const DEFAULT_SCRYPT_R: u32 = 8;
+const DEFAULT_SCRYPT_P: u32 = 1;
+const DERIVED_KEY_LEN: usize = 32;
+const SALT_LEN: usize = 32;
+const IV_LEN: usize = 16;
+
+/// The scrypt parameters used to derive the encryption key from the password.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    #[serde(with = "hex_bytes")]
+    pub salt: Vec<u8>,
+}
+
+/// A network key encrypted with a password.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedKey {
+    pub version: u32,
+    pub kdf: ScryptParams,
+    #[serde(with = "hex_bytes")]
+    pub iv: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub checksum: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedKey {
+    /// Encrypts `secret` with `password` using a random salt and IV.
+    pub fn encrypt(secret: &[u8], password: &[u8]) -> Result<Self, String> {
+        let mut rng = rand::thread_rng();
+        let mut salt = vec![0; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut iv = vec![0; IV_LEN];
+        rng.fill_bytes(&mut iv);
+
+        let kdf = ScryptParams {
+            log_n: DEFAULT_SCRYPT_LOG_N,
+            r: DEFAULT_SCRYPT_R,
+            p: DEFAULT_SCRYPT_P,
+            salt,
+        };
+        let derived_key = derive_key(password, &kdf)?;
+
+        let mut ciphertext = secret.to_vec();
+        Aes128Ctr::new(derived_key[..16].into(), iv.as_slice().into())
+            .apply_keystream(&mut ciphertext);
+        let checksum = checksum(&derived_key, &ciphertext);
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            kdf,
+            iv,
+            checksum,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts the secret, returning an error if the password is wrong.
+    pub fn decrypt(&self, password: &[u8]) -> Result<Vec<u8>, String> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(format!("Unsupported keystore version {}", self.version));
+        }
+        if self.iv.len() != IV_LEN {
+            return Err("Invalid keystore IV length".to_string());
+        }
+
+        let derived_key = derive_key(password, &self.kdf)?;
+        if checksum(&derived_key, &self.ciphertext) != self.checksum {
+            return Err("Invalid password for the network key".to_string());
+        }
+
+        let mut secret = self.ciphertext.clone();
+        Aes128Ctr::new(derived_key[..16].into(), self.iv.as_slice().into())
+            .apply_keystream(&mut secret);
+        Ok(secret)
+    }
+}
+
+fn derive_key(password: &[u8], kdf: &ScryptParams) -> Result<[u8; DERIVED_KEY_LEN], String> {
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, DERIVED_KEY_LEN)
+        .map_err(|e| format!("Invalid scrypt parameters: {e}"))?;
+    let mut derived_key = [0; DERIVED_KEY_LEN];
+    scrypt::scrypt(password, &kdf.salt, &params, &mut derived_key)
+        .map_err(|e| format!("Unable to derive key: {e}"))?;
+    Ok(derived_key)
+}
+
+fn checksum(derived_key: &[u8; DERIVED_KEY_LEN], ciphertext: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(&derived_key[16..]);
+    hasher.update(ciphertext);
+    hasher.finalize().to_vec()
+}
+
+/// Serializes byte vectors as hex strings.
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_roundtrip() {
+        let secret = [42u8; 32];
+        let encrypted = EncryptedKey::encrypt(&secret, b"password").unwrap();
+        assert_ne!(encrypted.ciphertext, secret);
+        assert_eq!(encrypted.decrypt(b"password").unwrap(), secret);
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let encrypted = EncryptedKey::encrypt(&[42u8; 32], b"password").unwrap();
+        assert!(encrypted.decrypt(b"wrong password").is_err());
+    }
+}
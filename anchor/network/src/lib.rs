@@ -2,11 +2,43 @@
 
 mod behaviour;
 mod config;
+mod connection_gater;
+mod enr;
+mod fork;
+mod gossipsub_config;
+mod history_sync;
 mod keypair_utils;
+mod keystore;
+mod message_validator;
+mod metrics;
+mod nat;
 mod network;
+mod network_info;
+mod peer_limits;
+mod peer_manager;
+mod rate_limiter;
+mod subnets;
+#[cfg(test)]
+mod test_utils;
 mod transport;
 mod types;
 
 pub use config::Config;
+pub use connection_gater::{GaterRule, IpSubnet};
+pub use discv5::Enr;
+pub use fork::{Fork, ForkSchedule, FORK_GRACE_PERIOD_EPOCHS, MAINNET_DOMAIN_TYPE};
+pub use gossipsub_config::GossipsubConfig;
+pub use history_sync::{DecidedHistoryRequest, MAX_HEIGHTS_PER_REQUEST};
+pub use keypair_utils::export_network_key;
+pub use libp2p::metrics::Registry;
+pub use libp2p::{Multiaddr, PeerId};
 pub use lighthouse_network::{ListenAddr, ListenAddress};
-pub use network::Network;
+pub use message_validator::{
+    MessageValidator, NoValidationContext, ValidationContext, ValidationError, ValidationResult,
+};
+pub use network::{DecidedHistoryReply, Network, NetworkCommand};
+pub use network_info::{
+    ConnectionDirection, Identity, NetworkInfo, PeerConnectionState, PeerInfo, TopicInfo,
+};
+pub use rate_limiter::{Quota, RateLimiterConfig};
+pub use subnets::{SubnetId, SUBNET_COUNT};
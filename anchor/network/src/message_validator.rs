@@ -0,0 +1,1046 @@
+//! Validation of the SSV messages received over gossipsub.
+//!
+//! Messages are validated before they are forwarded to peers or passed to the consumers of the
+//! network, and the result is reported back to gossipsub. Valid messages are accepted. Messages
+//! that may be valid but are of no use, such as duplicates or messages we lack the context to
+//! check, are ignored. Messages breaking the protocol are rejected, which penalises the peer that
+//! propagated them.
+
+use libp2p::gossipsub::MessageAcceptance;
+use operator_key::OperatorPublicKey;
+use parking_lot::{Mutex, RwLock};
+use ssv_types::message::{MAX_SIGNATURES, MAX_SIGNED_SSV_MESSAGE_SIZE, RSA_SIGNATURE_SIZE};
+use ssv_types::partial_sig::MAX_PARTIAL_SIGNATURE_MESSAGES;
+use ssv_types::{
+    MessageId, MsgType, OperatorId, PartialSignatureMessages, QbftMessage, QbftMessageType, Role,
+    SignedSSVMessage,
+};
+use ssz::{Decode, Encode};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// The number of slots after the slot of a duty during which its messages are still accepted.
+pub const LATE_SLOT_ALLOWANCE: u64 = 2;
+/// Attestations and aggregates can be included for an epoch, so their messages live longer.
+pub const SLOTS_PER_EPOCH: u64 = 32;
+
+/// Provides the chain and registry information messages are validated against.
+pub trait ValidationContext: Send + Sync {
+    /// The current slot, or `None` if it is not known, e.g. before genesis.
+    fn current_slot(&self) -> Option<u64>;
+
+    /// The sorted operators of the committee the message is addressed to, or `None` if the
+    /// committee is not known.
+    fn committee(&self, message_id: &MessageId) -> Option<Vec<OperatorId>>;
+
+    /// The RSA public key of an operator, or `None` if the operator is not known.
+    fn operator_public_key(&self, operator_id: OperatorId) -> Option<Arc<OperatorPublicKey>>;
+}
+
+/// A context without any chain or registry information. All messages are ignored, as none can be
+/// fully validated.
+pub struct NoValidationContext;
+
+impl ValidationContext for NoValidationContext {
+    fn current_slot(&self) -> Option<u64> {
+        None
+    }
+
+    fn committee(&self, _message_id: &MessageId) -> Option<Vec<OperatorId>> {
+        None
+    }
+
+    fn operator_public_key(&self, _operator_id: OperatorId) -> Option<Arc<OperatorPublicKey>> {
+        None
+    }
+}
+
+/// The reasons a message is ignored or rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The message exceeds the maximum message size.
+    TooLarge(usize),
+    /// The message could not be decoded.
+    Undecodable(String),
+    UnknownMessageType(u64),
+    UnknownRole,
+    /// The message is for a different network domain.
+    WrongDomain,
+    NoSigners,
+    TooManySigners(usize),
+    SignatureCountMismatch,
+    WrongSignatureSize(usize),
+    /// The signers are not sorted or contain duplicates.
+    SignersNotSorted,
+    /// The committee of the message is not known (yet).
+    UnknownCommittee,
+    SignerNotInCommittee(OperatorId),
+    /// The public key of the signer is not known (yet).
+    UnknownOperator(OperatorId),
+    /// The RSA signature of the signer does not match the message.
+    InvalidSignature(OperatorId),
+    /// The current slot is not known, so the message cannot be checked.
+    UnknownSlot,
+    SlotTooEarly(u64),
+    SlotTooLate(u64),
+    /// The role does not run consensus.
+    NoConsensusForRole(Role),
+    UnknownQbftMessageType(u64),
+    WrongIdentifier,
+    RoundOutOfRange(u64),
+    /// Only commit messages can aggregate several signers.
+    UnexpectedMultipleSigners,
+    /// A decided message not signed by a quorum of the committee.
+    NoQuorum(usize),
+    /// A message expected to be decided, i.e. a commit aggregated from a quorum, is not.
+    NotDecided,
+    /// Justifications on a message that takes none.
+    UnexpectedJustification,
+    /// A justifying message of another instance, step, round or value.
+    InvalidJustification(String),
+    /// A justification not signed by a quorum of the committee.
+    NoJustificationQuorum(usize),
+    /// Partial signature messages carry no full data.
+    UnexpectedFullData,
+    UnknownPartialSignatureKind(u64),
+    WrongPartialSignatureCount(usize),
+    /// A partial signature of another operator than the signer of the message.
+    PartialSignatureSignerMismatch(OperatorId),
+    /// The message has already been received.
+    Duplicate,
+    /// The signer sent different values for the same instance, round and step.
+    Equivocation(OperatorId),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooLarge(size) => write!(f, "Message too large: {size} bytes"),
+            Self::Undecodable(error) => write!(f, "Undecodable message: {error}"),
+            Self::UnknownMessageType(msg_type) => write!(f, "Unknown message type {msg_type}"),
+            Self::UnknownRole => write!(f, "Unknown role"),
+            Self::WrongDomain => write!(f, "Wrong domain"),
+            Self::NoSigners => write!(f, "No signers"),
+            Self::TooManySigners(count) => write!(f, "Too many signers: {count}"),
+            Self::SignatureCountMismatch => write!(f, "Signature and signer counts differ"),
+            Self::WrongSignatureSize(size) => write!(f, "Wrong signature size: {size} bytes"),
+            Self::SignersNotSorted => write!(f, "Signers not sorted or duplicated"),
+            Self::UnknownCommittee => write!(f, "Unknown committee"),
+            Self::SignerNotInCommittee(signer) => write!(f, "Signer {signer} not in committee"),
+            Self::UnknownOperator(signer) => write!(f, "Unknown operator {signer}"),
+            Self::InvalidSignature(signer) => write!(f, "Invalid signature of {signer}"),
+            Self::UnknownSlot => write!(f, "Current slot unknown"),
+            Self::SlotTooEarly(slot) => write!(f, "Slot {slot} is in the future"),
+            Self::SlotTooLate(slot) => write!(f, "Slot {slot} is too old"),
+            Self::NoConsensusForRole(role) => write!(f, "No consensus for role {role:?}"),
+            Self::UnknownQbftMessageType(msg_type) => {
+                write!(f, "Unknown QBFT message type {msg_type}")
+            }
+            Self::WrongIdentifier => write!(f, "QBFT identifier does not match the message id"),
+            Self::RoundOutOfRange(round) => write!(f, "Round {round} out of range"),
+            Self::UnexpectedMultipleSigners => write!(f, "Multiple signers on a non-commit"),
+            Self::NoQuorum(count) => write!(f, "Decided message with only {count} signers"),
+            Self::NotDecided => write!(f, "Not a decided message"),
+            Self::UnexpectedJustification => write!(f, "Unexpected justification"),
+            Self::InvalidJustification(error) => write!(f, "Invalid justification: {error}"),
+            Self::NoJustificationQuorum(count) => {
+                write!(f, "Justification with only {count} signers")
+            }
+            Self::UnexpectedFullData => write!(f, "Unexpected full data"),
+            Self::UnknownPartialSignatureKind(kind) => {
+                write!(f, "Unknown partial signature kind {kind}")
+            }
+            Self::WrongPartialSignatureCount(count) => {
+                write!(f, "Wrong partial signature count: {count}")
+            }
+            Self::PartialSignatureSignerMismatch(signer) => {
+                write!(f, "Partial signature of another signer {signer}")
+            }
+            Self::Duplicate => write!(f, "Duplicate message"),
+            Self::Equivocation(signer) => write!(f, "Equivocation by {signer}"),
+        }
+    }
+}
+
+/// The outcome of the validation of a message.
+#[derive(Debug)]
+pub enum ValidationResult {
+    /// The message is valid and is passed on.
+    Accept(SignedSSVMessage),
+    /// The message is dropped without penalising the peer.
+    Ignore(ValidationError),
+    /// The message is invalid, the peer is penalised.
+    Reject(ValidationError),
+}
+
+impl ValidationResult {
+    pub fn acceptance(&self) -> MessageAcceptance {
+        match self {
+            Self::Accept(_) => MessageAcceptance::Accept,
+            Self::Ignore(_) => MessageAcceptance::Ignore,
+            Self::Reject(_) => MessageAcceptance::Reject,
+        }
+    }
+}
+
+/// The messages already received for a duty, to detect duplicates and equivocation.
+#[derive(Default)]
+struct SeenMessages {
+    /// The root each signer sent per round and step.
+    consensus: HashMap<(OperatorId, u64, QbftMessageType), [u8; 32]>,
+    /// The signers of each decided message per round.
+    decided: HashSet<(u64, Vec<OperatorId>)>,
+    /// The partial signature kinds each signer sent.
+    partial_signatures: HashSet<(OperatorId, u64)>,
+}
+
+/// The messages seen for recent slots.
+#[derive(Default)]
+struct SeenSlots {
+    messages: HashMap<(MessageId, u64), SeenMessages>,
+    /// The slot the seen messages were last pruned at.
+    pruned_slot: u64,
+}
+
+/// Validates the messages received over gossipsub, keeping track of the messages seen for recent
+/// slots. Messages can be validated concurrently: the seen messages are only locked to record a
+/// message once its signatures are verified.
+pub struct MessageValidator {
+    context: Arc<dyn ValidationContext>,
+    /// The network domains messages are accepted for.
+    domains: RwLock<Vec<[u8; 4]>>,
+    seen: Mutex<SeenSlots>,
+}
+
+impl MessageValidator {
+    pub fn new(context: Arc<dyn ValidationContext>, domain: [u8; 4]) -> Self {
+        Self {
+            context,
+            domains: RwLock::new(vec![domain]),
+            seen: Mutex::new(SeenSlots::default()),
+        }
+    }
+
+    /// Sets the network domains messages are accepted for, i.e. those of the forks in use.
+    pub fn set_domains(&self, domains: Vec<[u8; 4]>) {
+        *self.domains.write() = domains;
+    }
+
+    /// Validates the raw data of a gossipsub message.
+    pub fn validate(&self, data: &[u8]) -> ValidationResult {
+        match self.validate_message(data) {
+            Ok(message) => ValidationResult::Accept(message),
+            Err(error) if is_ignored(&error) => ValidationResult::Ignore(error),
+            Err(error) => ValidationResult::Reject(error),
+        }
+    }
+
+    fn validate_message(&self, data: &[u8]) -> Result<SignedSSVMessage, ValidationError> {
+        if data.len() > MAX_SIGNED_SSV_MESSAGE_SIZE {
+            return Err(ValidationError::TooLarge(data.len()));
+        }
+        let message = SignedSSVMessage::from_ssz_bytes(data)
+            .map_err(|e| ValidationError::Undecodable(format!("{e:?}")))?;
+
+        let msg_type = message
+            .ssv_message
+            .msg_type()
+            .map_err(ValidationError::UnknownMessageType)?;
+        let msg_id = message.ssv_message.msg_id;
+        let role = msg_id.role().ok_or(ValidationError::UnknownRole)?;
+        if !self.domains.read().contains(&msg_id.domain()) {
+            return Err(ValidationError::WrongDomain);
+        }
+
+        validate_signatures(&message)?;
+        let committee = self.committee(&message)?;
+
+        let current_slot = self
+            .context
+            .current_slot()
+            .ok_or(ValidationError::UnknownSlot)?;
+        self.prune(current_slot);
+
+        match msg_type {
+            MsgType::Consensus => {
+                self.validate_consensus(&message, role, committee.len(), current_slot)?
+            }
+            MsgType::PartialSignature => {
+                self.validate_partial_signatures(&message, role, current_slot)?
+            }
+        }
+        Ok(message)
+    }
+
+    fn validate_consensus(
+        &self,
+        message: &SignedSSVMessage,
+        role: Role,
+        committee_size: usize,
+        current_slot: u64,
+    ) -> Result<(), ValidationError> {
+        let max_round = max_round(role).ok_or(ValidationError::NoConsensusForRole(role))?;
+        let qbft_message = QbftMessage::from_ssz_bytes(&message.ssv_message.data)
+            .map_err(|e| ValidationError::Undecodable(format!("{e:?}")))?;
+        let qbft_message_type = qbft_message
+            .qbft_message_type()
+            .map_err(ValidationError::UnknownQbftMessageType)?;
+        let msg_id = message.ssv_message.msg_id;
+        if qbft_message.identifier != msg_id.as_bytes() {
+            return Err(ValidationError::WrongIdentifier);
+        }
+        if qbft_message.round == 0 || qbft_message.round > max_round {
+            return Err(ValidationError::RoundOutOfRange(qbft_message.round));
+        }
+        validate_slot(qbft_message.height, role, current_slot)?;
+        if message.operator_ids.len() > 1 {
+            if qbft_message_type != QbftMessageType::Commit {
+                return Err(ValidationError::UnexpectedMultipleSigners);
+            }
+            if message.operator_ids.len() < quorum_size(committee_size) {
+                return Err(ValidationError::NoQuorum(message.operator_ids.len()));
+            }
+        }
+        self.verify_signatures(message)?;
+        self.validate_justifications(&qbft_message, qbft_message_type, committee_size)?;
+
+        let mut seen_slots = self.seen.lock();
+        let seen = seen_slots
+            .messages
+            .entry((msg_id, qbft_message.height))
+            .or_default();
+        if message.operator_ids.len() > 1 {
+            if !seen
+                .decided
+                .insert((qbft_message.round, message.operator_ids.clone()))
+            {
+                return Err(ValidationError::Duplicate);
+            }
+            return Ok(());
+        }
+
+        let signer = message.operator_ids[0];
+        match seen
+            .consensus
+            .entry((signer, qbft_message.round, qbft_message_type))
+        {
+            Entry::Occupied(entry) if *entry.get() == qbft_message.root => {
+                Err(ValidationError::Duplicate)
+            }
+            Entry::Occupied(_) => Err(ValidationError::Equivocation(signer)),
+            Entry::Vacant(entry) => {
+                entry.insert(qbft_message.root);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks the justifications of a consensus message. A round change of a prepared value
+    /// carries the prepares of a quorum for it. A proposal after the first round carries the
+    /// round changes of a quorum for its round, and the prepares of a quorum for the highest value
+    /// they prepared, which it must propose. Other messages carry none.
+    fn validate_justifications(
+        &self,
+        qbft_message: &QbftMessage,
+        qbft_message_type: QbftMessageType,
+        committee_size: usize,
+    ) -> Result<(), ValidationError> {
+        let quorum = quorum_size(committee_size);
+        match qbft_message_type {
+            QbftMessageType::Proposal if qbft_message.round > 1 => {
+                let round_changes = self.verify_justification(
+                    qbft_message,
+                    &qbft_message.round_change_justification,
+                    QbftMessageType::RoundChange,
+                    qbft_message.round,
+                    None,
+                    quorum,
+                )?;
+                let prepared = round_changes
+                    .iter()
+                    .filter(|round_change| round_change.data_round > 0)
+                    .max_by_key(|round_change| round_change.data_round);
+                match prepared {
+                    Some(prepared) => {
+                        if prepared.root != qbft_message.root {
+                            return Err(ValidationError::InvalidJustification(
+                                "The proposal is not the prepared value".to_string(),
+                            ));
+                        }
+                        self.verify_justification(
+                            qbft_message,
+                            &qbft_message.prepare_justification,
+                            QbftMessageType::Prepare,
+                            prepared.data_round,
+                            Some(prepared.root),
+                            quorum,
+                        )?;
+                    }
+                    None if !qbft_message.prepare_justification.is_empty() => {
+                        return Err(ValidationError::UnexpectedJustification)
+                    }
+                    None => {}
+                }
+            }
+            QbftMessageType::RoundChange if qbft_message.data_round > 0 => {
+                if !qbft_message.prepare_justification.is_empty() {
+                    return Err(ValidationError::UnexpectedJustification);
+                }
+                self.verify_justification(
+                    qbft_message,
+                    &qbft_message.round_change_justification,
+                    QbftMessageType::Prepare,
+                    qbft_message.data_round,
+                    Some(qbft_message.root),
+                    quorum,
+                )?;
+            }
+            _ => {
+                if !qbft_message.round_change_justification.is_empty()
+                    || !qbft_message.prepare_justification.is_empty()
+                {
+                    return Err(ValidationError::UnexpectedJustification);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies the messages justifying a consensus message: `qbft_message_type` messages of the
+    /// same instance for `round` and `root`, if any, each signed by another member of its
+    /// committee, and at least a quorum of them. Returns their QBFT messages.
+    fn verify_justification(
+        &self,
+        justified: &QbftMessage,
+        justification: &[Vec<u8>],
+        qbft_message_type: QbftMessageType,
+        round: u64,
+        root: Option<[u8; 32]>,
+        quorum: usize,
+    ) -> Result<Vec<QbftMessage>, ValidationError> {
+        let mut signers = HashSet::new();
+        let mut qbft_messages = Vec::with_capacity(justification.len());
+        for data in justification {
+            let message = SignedSSVMessage::from_ssz_bytes(data)
+                .map_err(|e| ValidationError::Undecodable(format!("{e:?}")))?;
+            validate_signatures(&message)?;
+            let [signer] = *message.operator_ids.as_slice() else {
+                return Err(ValidationError::UnexpectedMultipleSigners);
+            };
+            if message.ssv_message.msg_type() != Ok(MsgType::Consensus)
+                || justified.identifier != message.ssv_message.msg_id.as_bytes()
+            {
+                return Err(ValidationError::InvalidJustification(
+                    "Message of another instance".to_string(),
+                ));
+            }
+            let qbft_message = QbftMessage::from_ssz_bytes(&message.ssv_message.data)
+                .map_err(|e| ValidationError::Undecodable(format!("{e:?}")))?;
+            if qbft_message.qbft_message_type() != Ok(qbft_message_type)
+                || qbft_message.identifier != justified.identifier
+                || qbft_message.height != justified.height
+                || qbft_message.round != round
+                || root.is_some_and(|root| qbft_message.root != root)
+            {
+                return Err(ValidationError::InvalidJustification(format!(
+                    "Expected a {qbft_message_type:?} of round {round}"
+                )));
+            }
+            if !signers.insert(signer) {
+                return Err(ValidationError::InvalidJustification(format!(
+                    "Duplicate signer {signer}"
+                )));
+            }
+            self.committee(&message)?;
+            self.verify_signatures(&message)?;
+            qbft_messages.push(qbft_message);
+        }
+        if signers.len() < quorum {
+            return Err(ValidationError::NoJustificationQuorum(signers.len()));
+        }
+        Ok(qbft_messages)
+    }
+
+    fn validate_partial_signatures(
+        &self,
+        message: &SignedSSVMessage,
+        role: Role,
+        current_slot: u64,
+    ) -> Result<(), ValidationError> {
+        if !message.full_data.is_empty() {
+            return Err(ValidationError::UnexpectedFullData);
+        }
+        let [signer] = *message.operator_ids.as_slice() else {
+            return Err(ValidationError::TooManySigners(message.operator_ids.len()));
+        };
+
+        let partial_signatures =
+            PartialSignatureMessages::from_ssz_bytes(&message.ssv_message.data)
+                .map_err(|e| ValidationError::Undecodable(format!("{e:?}")))?;
+        partial_signatures
+            .kind()
+            .map_err(ValidationError::UnknownPartialSignatureKind)?;
+        let count = partial_signatures.messages.len();
+        if count == 0 || count > MAX_PARTIAL_SIGNATURE_MESSAGES {
+            return Err(ValidationError::WrongPartialSignatureCount(count));
+        }
+        if let Some(other) = partial_signatures
+            .messages
+            .iter()
+            .find(|partial_signature| partial_signature.signer != signer)
+        {
+            return Err(ValidationError::PartialSignatureSignerMismatch(
+                other.signer,
+            ));
+        }
+        validate_slot(partial_signatures.slot, role, current_slot)?;
+        self.verify_signatures(message)?;
+
+        let mut seen_slots = self.seen.lock();
+        let seen = seen_slots
+            .messages
+            .entry((message.ssv_message.msg_id, partial_signatures.slot))
+            .or_default();
+        if !seen
+            .partial_signatures
+            .insert((signer, partial_signatures.kind))
+        {
+            return Err(ValidationError::Duplicate);
+        }
+        Ok(())
+    }
+
+    /// Verifies a decided message received outside of gossip, e.g. in a decided history
+    /// response: it must be a commit of its duty executor signed by a quorum of the committee.
+    /// Its slot is not checked, as it may be old, and it is not recorded as seen. Returns the
+    /// height of the message.
+    pub fn verify_decided(&self, message: &SignedSSVMessage) -> Result<u64, ValidationError> {
+        validate_signatures(message)?;
+        let committee = self.committee(message)?;
+        if message.ssv_message.msg_type() != Ok(MsgType::Consensus) {
+            return Err(ValidationError::NotDecided);
+        }
+        let qbft_message = QbftMessage::from_ssz_bytes(&message.ssv_message.data)
+            .map_err(|e| ValidationError::Undecodable(format!("{e:?}")))?;
+        if qbft_message.qbft_message_type() != Ok(QbftMessageType::Commit) {
+            return Err(ValidationError::NotDecided);
+        }
+        if qbft_message.identifier != message.ssv_message.msg_id.as_bytes() {
+            return Err(ValidationError::WrongIdentifier);
+        }
+        if message.operator_ids.len() < quorum_size(committee.len()).max(2) {
+            return Err(ValidationError::NoQuorum(message.operator_ids.len()));
+        }
+        self.verify_signatures(message)?;
+        Ok(qbft_message.height)
+    }
+
+    /// Returns the committee of the message, checking that all its signers are part of it.
+    fn committee(&self, message: &SignedSSVMessage) -> Result<Vec<OperatorId>, ValidationError> {
+        let committee = self
+            .context
+            .committee(&message.ssv_message.msg_id)
+            .ok_or(ValidationError::UnknownCommittee)?;
+        if let Some(signer) = message
+            .operator_ids
+            .iter()
+            .find(|signer| !committee.contains(signer))
+        {
+            return Err(ValidationError::SignerNotInCommittee(*signer));
+        }
+        Ok(committee)
+    }
+
+    /// Verifies the RSA signature of every signer. Forged messages must not be recorded as seen,
+    /// or they would make the genuine messages look like duplicates or equivocations.
+    fn verify_signatures(&self, message: &SignedSSVMessage) -> Result<(), ValidationError> {
+        let signed_data = message.ssv_message.as_ssz_bytes();
+        for (signer, signature) in message.operator_ids.iter().zip(&message.signatures) {
+            let public_key = self
+                .context
+                .operator_public_key(*signer)
+                .ok_or(ValidationError::UnknownOperator(*signer))?;
+            if !public_key.verify(&signed_data, signature) {
+                return Err(ValidationError::InvalidSignature(*signer));
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets the messages of slots that are too old to be accepted anymore.
+    fn prune(&self, current_slot: u64) {
+        let mut seen = self.seen.lock();
+        if current_slot <= seen.pruned_slot {
+            return;
+        }
+        seen.pruned_slot = current_slot;
+        seen.messages.retain(|(msg_id, slot), _| {
+            let ttl = msg_id.role().map_or(0, slot_ttl);
+            slot + ttl >= current_slot
+        });
+    }
+}
+
+/// Checks the number of signers and signatures and that the signers are sorted and unique.
+fn validate_signatures(message: &SignedSSVMessage) -> Result<(), ValidationError> {
+    let signers = &message.operator_ids;
+    if signers.is_empty() {
+        return Err(ValidationError::NoSigners);
+    }
+    if signers.len() > MAX_SIGNATURES {
+        return Err(ValidationError::TooManySigners(signers.len()));
+    }
+    if message.signatures.len() != signers.len() {
+        return Err(ValidationError::SignatureCountMismatch);
+    }
+    if let Some(signature) = message
+        .signatures
+        .iter()
+        .find(|signature| signature.len() != RSA_SIGNATURE_SIZE)
+    {
+        return Err(ValidationError::WrongSignatureSize(signature.len()));
+    }
+    if signers.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(ValidationError::SignersNotSorted);
+    }
+    Ok(())
+}
+
+/// Checks that the slot of a duty is neither in the future nor too old for the role.
+fn validate_slot(slot: u64, role: Role, current_slot: u64) -> Result<(), ValidationError> {
+    // Allow one slot of clock disparity between the operators.
+    if slot > current_slot + 1 {
+        return Err(ValidationError::SlotTooEarly(slot));
+    }
+    if slot + slot_ttl(role) < current_slot {
+        return Err(ValidationError::SlotTooLate(slot));
+    }
+    Ok(())
+}
+
+/// The number of slots the messages of a duty are accepted for after the duty's slot.
+fn slot_ttl(role: Role) -> u64 {
+    match role {
+        Role::Committee | Role::Aggregator => SLOTS_PER_EPOCH + LATE_SLOT_ALLOWANCE,
+        Role::Proposer
+        | Role::SyncCommitteeContribution
+        | Role::ValidatorRegistration
+        | Role::VoluntaryExit => LATE_SLOT_ALLOWANCE,
+    }
+}
+
+/// The highest round a consensus instance of the role can reach, `None` for roles without
+/// consensus.
+fn max_round(role: Role) -> Option<u64> {
+    match role {
+        Role::Committee | Role::Aggregator => Some(12),
+        Role::Proposer | Role::SyncCommitteeContribution => Some(6),
+        Role::ValidatorRegistration | Role::VoluntaryExit => None,
+    }
+}
+
+/// The number of operators needed to decide in a committee tolerating `(n - 1) / 3` faults.
+fn quorum_size(committee_size: usize) -> usize {
+    committee_size - (committee_size.saturating_sub(1)) / 3
+}
+
+fn is_ignored(error: &ValidationError) -> bool {
+    matches!(
+        error,
+        ValidationError::UnknownCommittee
+            | ValidationError::UnknownOperator(_)
+            | ValidationError::UnknownSlot
+            | ValidationError::SlotTooEarly(_)
+            | ValidationError::SlotTooLate(_)
+            | ValidationError::Duplicate
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TEST_OPERATOR_KEY;
+    use ssv_types::{PartialSignature, PartialSignatureMessage, SSVMessage};
+
+    const DOMAIN: [u8; 4] = [0, 0, 5, 2];
+    const SLOT: u64 = 100;
+
+    struct TestContext {
+        current_slot: u64,
+    }
+
+    impl ValidationContext for TestContext {
+        fn current_slot(&self) -> Option<u64> {
+            Some(self.current_slot)
+        }
+
+        fn committee(&self, _message_id: &MessageId) -> Option<Vec<OperatorId>> {
+            Some((1..=4).map(OperatorId).collect())
+        }
+
+        fn operator_public_key(&self, operator_id: OperatorId) -> Option<Arc<OperatorPublicKey>> {
+            // All operators share the test key, except operator 4 whose key is not known yet.
+            (operator_id != OperatorId(4))
+                .then(|| Arc::new(OperatorPublicKey::from(&*TEST_OPERATOR_KEY)))
+        }
+    }
+
+    fn validator() -> MessageValidator {
+        MessageValidator::new(Arc::new(TestContext { current_slot: SLOT }), DOMAIN)
+    }
+
+    fn msg_id(role: Role) -> MessageId {
+        MessageId::new(DOMAIN, role, &[1; 32])
+    }
+
+    fn qbft_message(qbft_message_type: QbftMessageType, round: u64, root: [u8; 32]) -> QbftMessage {
+        QbftMessage {
+            qbft_message_type: qbft_message_type as u64,
+            height: SLOT,
+            round,
+            identifier: msg_id(Role::Committee).as_bytes().to_vec(),
+            root,
+            data_round: 0,
+            round_change_justification: vec![],
+            prepare_justification: vec![],
+        }
+    }
+
+    fn signed(msg_type: MsgType, data: Vec<u8>, signers: &[u64]) -> Vec<u8> {
+        let ssv_message = SSVMessage {
+            msg_type: msg_type as u64,
+            msg_id: msg_id(Role::Committee),
+            data,
+        };
+        let signature = TEST_OPERATOR_KEY.sign(&ssv_message.as_ssz_bytes()).unwrap();
+        SignedSSVMessage {
+            signatures: vec![signature; signers.len()],
+            operator_ids: signers.iter().copied().map(OperatorId).collect(),
+            ssv_message,
+            full_data: vec![],
+        }
+        .as_ssz_bytes()
+    }
+
+    /// Replaces the signature of the first signer of a message.
+    fn forged(data: &[u8]) -> Vec<u8> {
+        let mut message = SignedSSVMessage::from_ssz_bytes(data).unwrap();
+        message.signatures[0] = vec![0; RSA_SIGNATURE_SIZE];
+        message.as_ssz_bytes()
+    }
+
+    fn consensus(message: &QbftMessage, signers: &[u64]) -> Vec<u8> {
+        signed(MsgType::Consensus, message.as_ssz_bytes(), signers)
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Outcome {
+        Accept,
+        Ignore,
+        Reject,
+    }
+
+    fn outcome(validator: &MessageValidator, data: &[u8]) -> Outcome {
+        match validator.validate(data) {
+            ValidationResult::Accept(_) => Outcome::Accept,
+            ValidationResult::Ignore(_) => Outcome::Ignore,
+            ValidationResult::Reject(_) => Outcome::Reject,
+        }
+    }
+
+    #[test]
+    fn valid_consensus_message_is_accepted_once() {
+        let validator = validator();
+        let prepare = consensus(&qbft_message(QbftMessageType::Prepare, 1, [1; 32]), &[1]);
+        assert!(matches!(
+            validator.validate(&prepare),
+            ValidationResult::Accept(_)
+        ));
+        assert!(matches!(
+            validator.validate(&prepare),
+            ValidationResult::Ignore(ValidationError::Duplicate)
+        ));
+    }
+
+    #[test]
+    fn equivocation_is_rejected() {
+        let validator = validator();
+        let prepare = consensus(&qbft_message(QbftMessageType::Prepare, 1, [1; 32]), &[1]);
+        let conflicting = consensus(&qbft_message(QbftMessageType::Prepare, 1, [2; 32]), &[1]);
+        assert_eq!(outcome(&validator, &prepare), Outcome::Accept);
+        assert!(matches!(
+            validator.validate(&conflicting),
+            ValidationResult::Reject(ValidationError::Equivocation(OperatorId(1)))
+        ));
+        // The same root in another round is fine.
+        let next_round = consensus(&qbft_message(QbftMessageType::Prepare, 2, [2; 32]), &[1]);
+        assert_eq!(outcome(&validator, &next_round), Outcome::Accept);
+    }
+
+    #[test]
+    fn forged_messages_are_rejected_before_being_seen() {
+        let validator = validator();
+        let prepare = consensus(&qbft_message(QbftMessageType::Prepare, 1, [1; 32]), &[1]);
+        let conflicting = consensus(&qbft_message(QbftMessageType::Prepare, 1, [2; 32]), &[1]);
+        assert!(matches!(
+            validator.validate(&forged(&conflicting)),
+            ValidationResult::Reject(ValidationError::InvalidSignature(OperatorId(1)))
+        ));
+        // The forgery did not make the genuine message an equivocation.
+        assert_eq!(outcome(&validator, &prepare), Outcome::Accept);
+
+        let commit = consensus(
+            &qbft_message(QbftMessageType::Commit, 1, [1; 32]),
+            &[1, 2, 3],
+        );
+        assert_eq!(outcome(&validator, &forged(&commit)), Outcome::Reject);
+        assert_eq!(outcome(&validator, &commit), Outcome::Accept);
+
+        // Operators without a known public key can't be verified.
+        let unknown = consensus(&qbft_message(QbftMessageType::Prepare, 1, [1; 32]), &[4]);
+        assert!(matches!(
+            validator.validate(&unknown),
+            ValidationResult::Ignore(ValidationError::UnknownOperator(OperatorId(4)))
+        ));
+    }
+
+    #[test]
+    fn decided_messages_are_verified() {
+        let validator = validator();
+        let decode = |data: Vec<u8>| SignedSSVMessage::from_ssz_bytes(&data).unwrap();
+        let commit = qbft_message(QbftMessageType::Commit, 1, [1; 32]);
+        assert_eq!(
+            validator.verify_decided(&decode(consensus(&commit, &[1, 2, 3]))),
+            Ok(SLOT)
+        );
+        assert_eq!(
+            validator.verify_decided(&decode(forged(&consensus(&commit, &[1, 2, 3])))),
+            Err(ValidationError::InvalidSignature(OperatorId(1)))
+        );
+        assert_eq!(
+            validator.verify_decided(&decode(consensus(&commit, &[1, 2]))),
+            Err(ValidationError::NoQuorum(2))
+        );
+        assert_eq!(
+            validator.verify_decided(&decode(consensus(&commit, &[1, 2, 5]))),
+            Err(ValidationError::SignerNotInCommittee(OperatorId(5)))
+        );
+        let prepare = qbft_message(QbftMessageType::Prepare, 1, [1; 32]);
+        assert_eq!(
+            validator.verify_decided(&decode(consensus(&prepare, &[1, 2, 3]))),
+            Err(ValidationError::NotDecided)
+        );
+    }
+
+    #[test]
+    fn justifications_are_verified() {
+        let validator = validator();
+        let rejection = |message: &QbftMessage, signer| match validator
+            .validate(&consensus(message, &[signer]))
+        {
+            ValidationResult::Reject(error) => error,
+            result => panic!("Expected a rejection, got {result:?}"),
+        };
+        let prepares: Vec<_> = [1, 2, 3]
+            .map(|signer| {
+                consensus(
+                    &qbft_message(QbftMessageType::Prepare, 1, [1; 32]),
+                    &[signer],
+                )
+            })
+            .into();
+        let round_change = |prepare_justification: &[Vec<u8>]| QbftMessage {
+            data_round: 1,
+            round_change_justification: prepare_justification.to_vec(),
+            ..qbft_message(QbftMessageType::RoundChange, 2, [1; 32])
+        };
+
+        // A prepared round change carries the prepares of a quorum for its value.
+        assert_eq!(
+            rejection(&round_change(&prepares[..2]), 1),
+            ValidationError::NoJustificationQuorum(2)
+        );
+        let other_round = consensus(&qbft_message(QbftMessageType::Prepare, 2, [1; 32]), &[3]);
+        assert!(matches!(
+            rejection(&round_change(&[&prepares[..2], &[other_round]].concat()), 1),
+            ValidationError::InvalidJustification(_)
+        ));
+        let round_changes: Vec<_> = [1, 2, 3]
+            .map(|signer| consensus(&round_change(&prepares), &[signer]))
+            .into();
+        for round_change in &round_changes {
+            assert_eq!(outcome(&validator, round_change), Outcome::Accept);
+        }
+
+        // A proposal after the first round carries the round changes of a quorum, and the
+        // prepares of a quorum for the value they prepared.
+        let proposal = |root, round_changes: &[Vec<u8>], prepares: &[Vec<u8>]| QbftMessage {
+            round_change_justification: round_changes.to_vec(),
+            prepare_justification: prepares.to_vec(),
+            ..qbft_message(QbftMessageType::Proposal, 2, root)
+        };
+        assert_eq!(
+            rejection(&proposal([1; 32], &[], &[]), 1),
+            ValidationError::NoJustificationQuorum(0)
+        );
+        assert_eq!(
+            rejection(&proposal([1; 32], &round_changes, &[]), 1),
+            ValidationError::NoJustificationQuorum(0)
+        );
+        assert!(matches!(
+            rejection(&proposal([2; 32], &round_changes, &prepares), 1),
+            ValidationError::InvalidJustification(_)
+        ));
+        let duplicated = [&round_changes[..2], &round_changes[..1]].concat();
+        assert!(matches!(
+            rejection(&proposal([1; 32], &duplicated, &prepares), 1),
+            ValidationError::InvalidJustification(_)
+        ));
+        assert_eq!(
+            outcome(
+                &validator,
+                &consensus(&proposal([1; 32], &round_changes, &prepares), &[1])
+            ),
+            Outcome::Accept
+        );
+
+        // Other messages carry no justification.
+        let first_proposal = QbftMessage {
+            round: 1,
+            ..proposal([1; 32], &round_changes, &prepares)
+        };
+        let prepare = QbftMessage {
+            prepare_justification: prepares.clone(),
+            ..qbft_message(QbftMessageType::Prepare, 2, [1; 32])
+        };
+        for message in [first_proposal, prepare] {
+            assert_eq!(
+                rejection(&message, 2),
+                ValidationError::UnexpectedJustification
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_signers_are_rejected() {
+        let validator = validator();
+        let prepare = qbft_message(QbftMessageType::Prepare, 1, [1; 32]);
+        for signers in [&[][..], &[5][..], &[2, 1][..], &[1, 1][..]] {
+            assert_eq!(
+                outcome(&validator, &consensus(&prepare, signers)),
+                Outcome::Reject,
+                "signers {signers:?}"
+            );
+        }
+        // Only commits can be aggregated, and only by a quorum.
+        assert_eq!(
+            outcome(&validator, &consensus(&prepare, &[1, 2, 3])),
+            Outcome::Reject
+        );
+        let commit = qbft_message(QbftMessageType::Commit, 1, [1; 32]);
+        assert_eq!(
+            outcome(&validator, &consensus(&commit, &[1, 2])),
+            Outcome::Reject
+        );
+        assert_eq!(
+            outcome(&validator, &consensus(&commit, &[1, 2, 3])),
+            Outcome::Accept
+        );
+    }
+
+    #[test]
+    fn round_and_slot_bounds() {
+        let validator = validator();
+        for round in [0, 13] {
+            let message = qbft_message(QbftMessageType::Prepare, round, [1; 32]);
+            assert_eq!(
+                outcome(&validator, &consensus(&message, &[1])),
+                Outcome::Reject
+            );
+        }
+
+        for height in [SLOT + 2, SLOT - SLOTS_PER_EPOCH - LATE_SLOT_ALLOWANCE - 1] {
+            let message = QbftMessage {
+                height,
+                ..qbft_message(QbftMessageType::Prepare, 1, [1; 32])
+            };
+            assert_eq!(
+                outcome(&validator, &consensus(&message, &[1])),
+                Outcome::Ignore
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_and_malformed_messages_are_rejected() {
+        let validator = validator();
+        assert_eq!(
+            outcome(&validator, &vec![0; MAX_SIGNED_SSV_MESSAGE_SIZE + 1]),
+            Outcome::Reject
+        );
+        assert_eq!(outcome(&validator, &[1, 2, 3]), Outcome::Reject);
+        assert_eq!(
+            outcome(&validator, &signed(MsgType::Consensus, vec![1, 2, 3], &[1])),
+            Outcome::Reject
+        );
+    }
+
+    #[test]
+    fn messages_of_other_domains_are_rejected() {
+        let validator = validator();
+        let prepare = consensus(&qbft_message(QbftMessageType::Prepare, 1, [1; 32]), &[1]);
+        validator.set_domains(vec![[0, 0, 0, 1]]);
+        assert_eq!(outcome(&validator, &prepare), Outcome::Reject);
+        validator.set_domains(vec![[0, 0, 0, 1], DOMAIN]);
+        assert_eq!(outcome(&validator, &prepare), Outcome::Accept);
+    }
+
+    #[test]
+    fn unknown_committee_is_ignored() {
+        let validator = MessageValidator::new(Arc::new(NoValidationContext), DOMAIN);
+        let prepare = consensus(&qbft_message(QbftMessageType::Prepare, 1, [1; 32]), &[1]);
+        assert_eq!(outcome(&validator, &prepare), Outcome::Ignore);
+    }
+
+    #[test]
+    fn partial_signatures() {
+        let validator = validator();
+        let partial_signatures = |signer| PartialSignatureMessages {
+            kind: 0,
+            slot: SLOT,
+            messages: vec![PartialSignatureMessage {
+                partial_signature: PartialSignature([0; 96]),
+                signing_root: [0; 32],
+                signer: OperatorId(signer),
+                validator_index: 0,
+            }],
+        };
+
+        let valid = signed(
+            MsgType::PartialSignature,
+            partial_signatures(1).as_ssz_bytes(),
+            &[1],
+        );
+        assert_eq!(outcome(&validator, &valid), Outcome::Accept);
+        assert_eq!(outcome(&validator, &valid), Outcome::Ignore);
+
+        let other_signer = signed(
+            MsgType::PartialSignature,
+            partial_signatures(3).as_ssz_bytes(),
+            &[2],
+        );
+        assert_eq!(outcome(&validator, &other_signer), Outcome::Reject);
+
+        let forged_partial_signatures = forged(&signed(
+            MsgType::PartialSignature,
+            partial_signatures(2).as_ssz_bytes(),
+            &[2],
+        ));
+        assert!(matches!(
+            validator.validate(&forged_partial_signatures),
+            ValidationResult::Reject(ValidationError::InvalidSignature(OperatorId(2)))
+        ));
+    }
+}
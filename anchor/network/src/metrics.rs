@@ -0,0 +1,172 @@
+//! Metrics of the p2p network.
+//!
+//! The metrics are registered in a `prometheus_client` registry, which is encoded by the metrics
+//! server alongside the validator client metrics.
+
+use crate::rate_limiter::RateLimitedProtocol;
+use libp2p::metrics::{Metrics, Recorder, Registry};
+use libp2p::PeerId;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use std::collections::HashMap;
+
+/// The label used for peers whose client could not be determined.
+const UNKNOWN: &str = "unknown";
+/// The label used for peers running a client not in [`KNOWN_CLIENTS`].
+const OTHER: &str = "other";
+/// The clients reported with their own label. The agent version is chosen by the peer, so it
+/// is mapped to this fixed set to keep the number of series bounded.
+const KNOWN_CLIENTS: [&str; 2] = ["Anchor", "SSV-Node"];
+
+/// The client software of a peer, parsed from its identify agent version.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ClientLabels {
+    pub client: &'static str,
+}
+
+impl ClientLabels {
+    /// Parses agent versions of the form `<client>/<version>/...`, e.g.
+    /// `Anchor/v0.1.0-1a2b3c4/x86_64-linux`.
+    pub fn from_agent_version(agent_version: &str) -> Self {
+        let client = match agent_version.split('/').next() {
+            None | Some("") => UNKNOWN,
+            Some(client) => KNOWN_CLIENTS
+                .into_iter()
+                .find(|known| known.eq_ignore_ascii_case(client))
+                .unwrap_or(OTHER),
+        };
+        Self { client }
+    }
+}
+
+/// The protocol a peer exceeded its rate limit for.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ProtocolLabels {
+    pub protocol: &'static str,
+}
+
+/// Records the libp2p and peer metrics of the network.
+pub struct NetworkMetrics {
+    /// Swarm, identify, ping and gossipsub metrics provided by libp2p.
+    libp2p: Metrics,
+    /// The number of connected peers per client.
+    peers_per_client: Family<ClientLabels, Gauge>,
+    /// The client of each identified peer, used to update the gauge on disconnection.
+    peer_clients: HashMap<PeerId, ClientLabels>,
+    /// The number of requests denied because a peer exceeded its rate limit, per protocol.
+    rate_limited: Family<ProtocolLabels, Counter>,
+}
+
+impl NetworkMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let libp2p = Metrics::new(registry);
+
+        let peers_per_client = Family::default();
+        let rate_limited = Family::default();
+        let registry = registry.sub_registry_with_prefix("anchor");
+        registry.register(
+            "peers_per_client",
+            "The number of connected peers per client",
+            peers_per_client.clone(),
+        );
+        registry.register(
+            "rate_limited",
+            "The number of requests denied because a peer exceeded its rate limit",
+            rate_limited.clone(),
+        );
+
+        Self {
+            libp2p,
+            peers_per_client,
+            peer_clients: HashMap::new(),
+            rate_limited,
+        }
+    }
+
+    /// Records a swarm or behaviour event in the libp2p metrics.
+    pub fn record<E>(&self, event: &E)
+    where
+        Metrics: Recorder<E>,
+    {
+        self.libp2p.record(event)
+    }
+
+    /// Registers the client of an identified peer.
+    pub fn on_identified(&mut self, peer_id: PeerId, agent_version: &str) {
+        let labels = ClientLabels::from_agent_version(agent_version);
+        if let Some(previous) = self.peer_clients.insert(peer_id, labels.clone()) {
+            if previous == labels {
+                return;
+            }
+            self.remove_peer_client(&previous);
+        }
+        self.peers_per_client.get_or_create(&labels).inc();
+    }
+
+    /// Counts a request denied because the peer exceeded its rate limit.
+    pub fn on_rate_limited(&self, protocol: RateLimitedProtocol) {
+        self.rate_limited
+            .get_or_create(&ProtocolLabels {
+                protocol: protocol.as_str(),
+            })
+            .inc();
+    }
+
+    /// Removes a disconnected peer from the client counts.
+    pub fn on_peer_disconnected(&mut self, peer_id: &PeerId) {
+        if let Some(labels) = self.peer_clients.remove(peer_id) {
+            self.remove_peer_client(&labels);
+        }
+    }
+
+    /// Decrements the peer count of a client, removing its series once no peer runs it.
+    fn remove_peer_client(&self, labels: &ClientLabels) {
+        let previous = self.peers_per_client.get_or_create(labels).dec();
+        if previous <= 1 {
+            self.peers_per_client.remove(labels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_agent_versions() {
+        let client = |agent_version| ClientLabels::from_agent_version(agent_version).client;
+        assert_eq!(client("Anchor/v0.1.0-1a2b3c4/x86_64-linux"), "Anchor");
+        assert_eq!(client("SSV-Node/v1.3.8"), "SSV-Node");
+        assert_eq!(client("ssv-node/v1.3.8"), "SSV-Node");
+        assert_eq!(client("Spoofed-\u{1F600}-Client/v9"), OTHER);
+        assert_eq!(client(""), UNKNOWN);
+    }
+
+    #[test]
+    fn peers_per_client_follow_connections() {
+        let mut registry = Registry::default();
+        let mut metrics = NetworkMetrics::new(&mut registry);
+        let labels = ClientLabels::from_agent_version("Anchor/v0.1.0");
+        let peer_id = PeerId::random();
+
+        metrics.on_identified(peer_id, "Anchor/v0.1.0");
+        // Identify is received again on every push, it must not be counted twice.
+        metrics.on_identified(peer_id, "Anchor/v0.1.0");
+        metrics.on_identified(PeerId::random(), "Anchor/v0.1.0");
+        assert_eq!(metrics.peers_per_client.get_or_create(&labels).get(), 2);
+
+        metrics.on_peer_disconnected(&peer_id);
+        assert_eq!(metrics.peers_per_client.get_or_create(&labels).get(), 1);
+
+        // The series is removed once the last peer of a client disconnects.
+        let other_peer_id = PeerId::random();
+        metrics.on_identified(other_peer_id, "Custom/v1");
+        metrics.on_peer_disconnected(&other_peer_id);
+        let mut encoded = String::new();
+        prometheus_client::encoding::text::encode(&mut encoded, &registry).unwrap();
+        assert!(encoded.contains("client=\"Anchor\""));
+        assert!(!encoded.contains("client=\"other\""));
+    }
+}
@@ -0,0 +1,218 @@
+//! External address detection for nodes behind a NAT.
+//!
+//! Port mappings are requested from the gateway via UPnP (`libp2p-upnp`, which does not support
+//! NAT-PMP). Independently, the addresses peers observe us on are collected from identify and an
+//! IP address is confirmed once peers from enough distinct subnets report it. Counting subnets
+//! rather than peer ids prevents a single host with many identities from choosing our address.
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The number of distinct observer subnets that must observe the same IP before it is confirmed.
+pub const OBSERVATION_THRESHOLD: usize = 4;
+/// The prefix length of the IPv4 subnets observers are grouped by.
+const IPV4_OBSERVER_PREFIX: u32 = 16;
+/// The prefix length of the IPv6 subnets observers are grouped by.
+const IPV6_OBSERVER_PREFIX: u32 = 56;
+/// The maximum number of candidate IP addresses that are tracked at the same time.
+const MAX_CANDIDATES: usize = 16;
+
+/// Collects the IP addresses that peers observe us on.
+#[derive(Debug, Default)]
+pub struct ObservedAddresses {
+    /// The observer subnets that reported each candidate IP.
+    candidates: HashMap<IpAddr, HashSet<IpAddr>>,
+    /// The most recently confirmed IPv4 and IPv6 addresses.
+    confirmed: (Option<IpAddr>, Option<IpAddr>),
+}
+
+impl ObservedAddresses {
+    /// Records an address observed by a peer connected from `observer`. Returns the IP if this
+    /// observation confirmed a new external IP.
+    pub fn on_observed(&mut self, observer: IpAddr, observed: &Multiaddr) -> Option<IpAddr> {
+        let ip = ip_from_multiaddr(observed)?;
+        if !is_global(ip) {
+            return None;
+        }
+
+        if !self.candidates.contains_key(&ip) && self.candidates.len() >= MAX_CANDIDATES {
+            // Make room by dropping the least observed candidate.
+            let least_observed = self
+                .candidates
+                .iter()
+                .min_by_key(|(_, observers)| observers.len())
+                .map(|(ip, _)| *ip)?;
+            self.candidates.remove(&least_observed);
+        }
+
+        let observers = self.candidates.entry(ip).or_default();
+        observers.insert(observer_subnet(observer));
+        if observers.len() < OBSERVATION_THRESHOLD {
+            return None;
+        }
+
+        let confirmed = match ip {
+            IpAddr::V4(_) => &mut self.confirmed.0,
+            IpAddr::V6(_) => &mut self.confirmed.1,
+        };
+        if *confirmed == Some(ip) {
+            return None;
+        }
+        *confirmed = Some(ip);
+        // Observations of the previous address are stale now.
+        self.candidates
+            .retain(|candidate, _| *candidate == ip || candidate.is_ipv4() != ip.is_ipv4());
+        Some(ip)
+    }
+}
+
+/// Returns the IP address of a multiaddr, if any.
+pub fn ip_from_multiaddr(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+/// Returns the subnet of an observer, as its IP with the host bits cleared.
+fn observer_subnet(observer: IpAddr) -> IpAddr {
+    match observer {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX << (32 - IPV4_OBSERVER_PREFIX);
+            IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX << (128 - IPV6_OBSERVER_PREFIX);
+            IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+        }
+    }
+}
+
+/// Returns the TCP port of a multiaddr, if any.
+pub fn tcp_port_from_multiaddr(address: &Multiaddr) -> Option<u16> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Tcp(port) => Some(port),
+        _ => None,
+    })
+}
+
+/// Returns true if the IP is publicly routable.
+fn is_global(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation())
+        }
+        IpAddr::V6(ip) => {
+            let first_segment = ip.segments()[0];
+            // Excludes unique local (fc00::/7) and link local (fe80::/10) addresses.
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || (first_segment & 0xfe00) == 0xfc00
+                || (first_segment & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observed(ip: &str) -> Multiaddr {
+        format!("/ip4/{ip}/tcp/9100").parse().unwrap()
+    }
+
+    /// An observer in its own /16 subnet.
+    fn observer(subnet: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(100, subnet, 0, 1))
+    }
+
+    /// Observes `ip` from `count` distinct subnets, starting at `first_subnet`.
+    fn observe(
+        addresses: &mut ObservedAddresses,
+        ip: &str,
+        first_subnet: u8,
+        count: u8,
+    ) -> Option<IpAddr> {
+        (first_subnet..first_subnet + count)
+            .map(|subnet| addresses.on_observed(observer(subnet), &observed(ip)))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn ip_is_confirmed_by_distinct_subnets() {
+        let mut addresses = ObservedAddresses::default();
+        let threshold = OBSERVATION_THRESHOLD as u8;
+
+        assert_eq!(observe(&mut addresses, "8.8.8.8", 0, threshold - 1), None);
+        // The same subnet reporting again does not count, even from another host.
+        assert_eq!(
+            addresses.on_observed(
+                IpAddr::V4(Ipv4Addr::new(100, 0, 200, 7)),
+                &observed("8.8.8.8")
+            ),
+            None
+        );
+        assert_eq!(
+            addresses.on_observed(observer(threshold - 1), &observed("8.8.8.8")),
+            Some("8.8.8.8".parse().unwrap())
+        );
+        // An already confirmed address is not reported again.
+        assert_eq!(
+            addresses.on_observed(observer(threshold), &observed("8.8.8.8")),
+            None
+        );
+    }
+
+    #[test]
+    fn many_peers_from_one_subnet_do_not_confirm() {
+        let mut addresses = ObservedAddresses::default();
+        for host in 0..=255 {
+            let observer = IpAddr::V4(Ipv4Addr::new(100, 7, host, 1));
+            assert_eq!(addresses.on_observed(observer, &observed("8.8.8.8")), None);
+        }
+    }
+
+    #[test]
+    fn changed_ip_is_confirmed() {
+        let mut addresses = ObservedAddresses::default();
+        let threshold = OBSERVATION_THRESHOLD as u8;
+        observe(&mut addresses, "8.8.8.8", 0, threshold);
+
+        assert_eq!(
+            observe(&mut addresses, "1.1.1.1", 0, threshold),
+            Some("1.1.1.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn private_ips_are_ignored() {
+        let mut addresses = ObservedAddresses::default();
+        assert_eq!(
+            observe(
+                &mut addresses,
+                "192.168.1.10",
+                0,
+                OBSERVATION_THRESHOLD as u8
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn ipv6_observers_are_grouped_by_subnet() {
+        let first: IpAddr = "2001:db8:1:100::1".parse().unwrap();
+        let same_subnet: IpAddr = "2001:db8:1:1ff::2".parse().unwrap();
+        let other_subnet: IpAddr = "2001:db8:1:200::1".parse().unwrap();
+        assert_eq!(observer_subnet(first), observer_subnet(same_subnet));
+        assert_ne!(observer_subnet(first), observer_subnet(other_subnet));
+    }
+}
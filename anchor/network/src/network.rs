@@ -1,31 +1,198 @@
-use crate::behaviour::AnchorBehaviour;
+use crate::behaviour::{AnchorBehaviour, AnchorBehaviourEvent};
+use crate::connection_gater::{self, GaterRule};
+use crate::enr::{build_enr, update_enr_domain_types, update_enr_tcp_socket};
+use crate::fork::{Fork, ForkSchedule};
+use crate::history_sync::{
+    validate_response, DecidedHistoryRequest, DecidedHistoryResponse, DecidedStore,
+    HistorySyncCodec, MAX_CONCURRENT_STREAMS, PROTOCOL, REQUEST_TIMEOUT,
+};
 use crate::keypair_utils::load_private_key;
+use crate::message_validator::{
+    MessageValidator, ValidationContext, ValidationResult, SLOTS_PER_EPOCH,
+};
+use crate::metrics::NetworkMetrics;
+use crate::nat::{ip_from_multiaddr, tcp_port_from_multiaddr, ObservedAddresses};
+use crate::network_info::{ConnectionDirection, Identity, NetworkInfo, TopicInfo};
+use crate::peer_limits::{self, EstablishedLimits};
+use crate::peer_manager::{
+    peer_id_from_multiaddr, PeerAction, PeerManager, MIN_OUTBOUND_ONLY_FACTOR, PEER_EXCESS_FACTOR,
+    PRIORITY_PEER_EXCESS,
+};
+use crate::rate_limiter::{RateLimitedProtocol, RateLimiter};
+use crate::subnets::{SubnetId, SubnetSubscriptions, SubscriptionChanges};
 use crate::transport::build_transport;
 use crate::Config;
+use discv5::enr::CombinedKey;
+use discv5::Enr;
 use futures::StreamExt;
 use libp2p::core::muxing::StreamMuxerBox;
 use libp2p::core::transport::Boxed;
+use libp2p::gossipsub::{MessageAcceptance, MessageAuthenticity, TopicScoreParams, ValidationMode};
 use libp2p::identity::Keypair;
+use libp2p::metrics::Registry;
 use libp2p::multiaddr::Protocol;
-use libp2p::{futures, identify, ping, PeerId, Swarm, SwarmBuilder};
+use libp2p::request_response::{self, OutboundRequestId, ProtocolSupport, ResponseChannel};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{
+    connection_limits, futures, gossipsub, identify, memory_connection_limits, ping, upnp,
+    Multiaddr, PeerId, Swarm, SwarmBuilder,
+};
+use lighthouse_network::{EnrExt, ListenAddress};
+use processor::{Work, WorkKind};
+use sha2::{Digest, Sha256};
+use ssv_types::{CommitteeId, SignedSSVMessage};
+use ssz::Encode;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::num::{NonZeroU8, NonZeroUsize};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use task_executor::TaskExecutor;
-use tracing::info;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// The interval at which the network checks for static peers that need to be redialed, updates
+/// the peer scores and transitions to a new fork.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// The number of validated messages buffered for the consumers of the network.
+const INBOUND_CHANNEL_SIZE: usize = 1024;
+/// The number of commands buffered for the network.
+const COMMAND_CHANNEL_SIZE: usize = 64;
+/// The number of gossip messages validated by the processor buffered for the network.
+const VALIDATED_CHANNEL_SIZE: usize = 1024;
+
+/// Commands the other components send to the network.
+#[derive(Debug)]
+pub enum NetworkCommand {
+    /// Subscribe to the subnets of these committees, and unsubscribe from the others.
+    UpdateCommittees(Vec<CommitteeId>),
+    /// Publish a message on a subnet. Messages whose id is not one of a fork in use are dropped.
+    Publish {
+        subnet: SubnetId,
+        message: SignedSSVMessage,
+    },
+    /// Dial a peer.
+    Dial(Multiaddr),
+    /// Disconnect from a peer.
+    Disconnect(PeerId),
+    /// Reply with the addresses the network is listening on.
+    ListenAddresses(oneshot::Sender<Vec<Multiaddr>>),
+    /// Reply with the connected peers.
+    ConnectedPeers(oneshot::Sender<Vec<PeerId>>),
+    /// Reply with the peers subscribed to a subnet.
+    SubnetPeers(SubnetId, oneshot::Sender<Vec<PeerId>>),
+    /// Apply and persist a connection gater rule, closing the connections it denies. Replies
+    /// with the reason the rule could not be persisted, if any.
+    AddGaterRule(GaterRule, oneshot::Sender<Result<(), String>>),
+    /// Remove a connection gater rule, replying whether it was applied.
+    RemoveGaterRule(GaterRule, oneshot::Sender<Result<bool, String>>),
+    /// Reply with the connection gater rules currently applied.
+    GaterRules(oneshot::Sender<Vec<GaterRule>>),
+    /// Request decided messages from a peer, replying with the messages or the reason the
+    /// request failed.
+    RequestDecidedHistory {
+        peer_id: PeerId,
+        request: DecidedHistoryRequest,
+        reply: DecidedHistoryReply,
+    },
+}
+
+/// Receives the decided messages requested from a peer.
+pub type DecidedHistoryReply = oneshot::Sender<Result<Vec<SignedSSVMessage>, String>>;
+
+/// A gossip message validated by the processor, whose result is still to be reported.
+struct ValidatedMessage {
+    propagation_source: PeerId,
+    message_id: gossipsub::MessageId,
+    result: ValidationResult,
+}
 
 pub struct Network {
     swarm: Swarm<AnchorBehaviour>,
     peer_id: PeerId,
+    peer_manager: PeerManager,
+    /// Whether misbehaving peers are scored down and banned.
+    peer_scoring_enabled: bool,
+    /// The gossipsub score parameters of the subnet topics, if peer scoring is enabled.
+    topic_score_params: Option<TopicScoreParams>,
+    /// Limits the rate at which each peer may use our resources.
+    rate_limiter: RateLimiter,
+    metrics: Option<NetworkMetrics>,
+    /// The ENR advertising how to reach this node.
+    local_enr: Enr,
+    /// The information about the node and its peers shared with other components.
+    info: Arc<NetworkInfo>,
+    enr_key: CombinedKey,
+    /// Whether the IPv4 and IPv6 ENR addresses were set explicitly and must not be updated.
+    fixed_enr_address: (bool, bool),
+    /// The addresses we listen on, determining the IP versions we prefer to dial.
+    listen_addresses: ListenAddress,
+    quic_enabled: bool,
+    /// The addresses identify reports peers observe us on.
+    observed_addresses: ObservedAddresses,
+    /// Validates gossip messages before they are propagated, in the processor.
+    message_validator: Arc<MessageValidator>,
+    processor: processor::Sender,
+    /// Receives the gossip messages validated by the processor.
+    validated_tx: mpsc::Sender<ValidatedMessage>,
+    validated_rx: mpsc::Receiver<ValidatedMessage>,
+    /// Provides the current slot, determining the active fork.
+    validation_context: Arc<dyn ValidationContext>,
+    fork_schedule: ForkSchedule,
+    /// The fork active at the current epoch.
+    current_fork: Fork,
+    /// The forks whose topics we are subscribed to and whose messages we accept.
+    forks_in_use: Vec<Fork>,
+    /// Passes the validated messages on to the consumers of the network.
+    inbound_tx: mpsc::Sender<SignedSSVMessage>,
+    /// The subnets we are subscribed to.
+    subnet_subscriptions: SubnetSubscriptions,
+    /// The recent decided messages served to peers.
+    decided_store: DecidedStore,
+    /// The decided history requests awaiting a response from a peer.
+    pending_history_requests:
+        HashMap<OutboundRequestId, (DecidedHistoryRequest, DecidedHistoryReply)>,
+    command_tx: mpsc::Sender<NetworkCommand>,
+    command_rx: mpsc::Receiver<NetworkCommand>,
 }
 
 impl Network {
     // Creates an instance of the Network struct to start sending and receiving information on the
-    // p2p network.
-    pub async fn try_new(config: &Config, executor: TaskExecutor) -> Result<Network, String> {
-        let local_keypair: Keypair = load_private_key(&config.network_dir);
-        let transport = build_transport(local_keypair.clone(), !config.disable_quic_support);
-        let behaviour = build_anchor_behaviour(local_keypair.clone());
+    // p2p network. If a metrics registry is given, the network metrics are registered in it.
+    //
+    // Gossip messages are validated against the `validation_context` by the `processor` and the
+    // valid ones are received on the returned channel.
+    pub async fn try_new(
+        config: &Config,
+        validation_context: Arc<dyn ValidationContext>,
+        processor: processor::Sender,
+        mut registry: Option<&mut Registry>,
+        executor: TaskExecutor,
+    ) -> Result<(Network, mpsc::Receiver<SignedSSVMessage>), String> {
+        let local_keypair: Keypair = load_private_key(config)?;
+        let transport = build_transport(local_keypair.clone(), !config.disable_quic_support)?;
+        let behaviour =
+            build_anchor_behaviour(local_keypair.clone(), registry.as_deref_mut(), config)?;
         let peer_id = local_keypair.public().to_peer_id();
+        let (local_enr, enr_key) = build_enr(config, &local_keypair)?;
+        let peer_manager =
+            PeerManager::new(&config.static_peers, &config.trusted_peers, Instant::now())?;
+        let (inbound_tx, inbound_rx) = mpsc::channel(INBOUND_CHANNEL_SIZE);
+        let (command_tx, command_rx) = mpsc::channel(COMMAND_CHANNEL_SIZE);
+        let (validated_tx, validated_rx) = mpsc::channel(VALIDATED_CHANNEL_SIZE);
+        let info = Arc::new(NetworkInfo::new(Identity {
+            peer_id,
+            enr: local_enr.clone(),
+            listen_addresses: vec![],
+        }));
+        let current_fork = config
+            .fork_schedule
+            .fork_at(current_epoch(&*validation_context))
+            .clone();
 
         let mut network = Network {
             swarm: build_swarm(
@@ -33,12 +200,46 @@ impl Network {
                 local_keypair,
                 transport,
                 behaviour,
+                registry.as_deref_mut(),
                 config,
             ),
             peer_id,
+            peer_manager,
+            peer_scoring_enabled: !config.disable_peer_scoring,
+            topic_score_params: (!config.disable_peer_scoring)
+                .then(|| config.gossipsub.topic_score_params()),
+            rate_limiter: RateLimiter::new(config.rate_limits.clone()),
+            metrics: registry.map(NetworkMetrics::new),
+            local_enr,
+            info,
+            enr_key,
+            fixed_enr_address: (
+                config.enr_address.0.is_some(),
+                config.enr_address.1.is_some(),
+            ),
+            listen_addresses: config.listen_addresses.clone(),
+            quic_enabled: !config.disable_quic_support,
+            observed_addresses: ObservedAddresses::default(),
+            message_validator: Arc::new(MessageValidator::new(
+                validation_context.clone(),
+                current_fork.domain_type,
+            )),
+            processor,
+            validated_tx,
+            validated_rx,
+            validation_context,
+            fork_schedule: config.fork_schedule.clone(),
+            current_fork,
+            forks_in_use: vec![],
+            inbound_tx,
+            subnet_subscriptions: SubnetSubscriptions::new(config.subscribe_all_subnets),
+            decided_store: DecidedStore::default(),
+            pending_history_requests: HashMap::new(),
+            command_tx,
+            command_rx,
         };
 
-        info!(%peer_id, "Network starting");
+        info!(%peer_id, enr = %network.local_enr.to_base64(), "Network starting");
 
         for listen_multiaddr in config.listen_addresses.libp2p_addresses() {
             // If QUIC is disabled, ignore listening on QUIC ports
@@ -60,31 +261,750 @@ impl Network {
             log_address.push(Protocol::P2p(peer_id));
             info!(address = %log_address, "Listening established");
         }
-        /*
-        TODO
-        - Dial peers
-        - Subscribe gossip topics
-         */
 
-        // TODO: Return channels for input/output
-        Ok(network)
+        // Dial the boot nodes
+        for enr in &config.boot_nodes_enr {
+            network.dial_enr(enr);
+        }
+        for multiaddr in &config.boot_nodes_multiaddr {
+            network.dial(multiaddr.clone());
+        }
+
+        // Dial the static peers
+        network.dial_static_peers();
+
+        // Subscribe to all subnets if configured, the committee subnets follow once known
+        network.update_fork();
+        let changes = network.subnet_subscriptions.initial();
+        network.apply_subscription_changes(changes);
+
+        Ok((network, inbound_rx))
     }
 
     /// Main loop for polling and handling swarm and channels.
     pub async fn run(mut self) {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
         loop {
             tokio::select! {
-                _swarm_message = self.swarm.select_next_some() => {
-                    // TODO handle and match swarm messages
+                swarm_message = self.swarm.select_next_some() => {
+                    self.on_swarm_event(swarm_message);
+                }
+                _ = heartbeat.tick() => {
+                    self.dial_static_peers();
+                    let now = Instant::now();
+                    self.peer_manager.update_scores(now);
+                    self.rate_limiter.prune(now);
+                    self.swarm.behaviour_mut().peer_limits.prune(now);
+                    self.update_fork();
+                }
+                Some(command) = self.command_rx.recv() => {
+                    self.on_command(command);
+                }
+                Some(validated) = self.validated_rx.recv() => {
+                    self.on_validated_message(validated);
+                }
+            }
+        }
+    }
+
+    pub fn local_peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    pub fn local_enr(&self) -> Enr {
+        self.local_enr.clone()
+    }
+
+    /// Returns a read-only handle to the information about the node and its peers.
+    pub fn info(&self) -> Arc<NetworkInfo> {
+        self.info.clone()
+    }
+
+    /// Returns a sender for commands to the network. It must be taken before running the network.
+    pub fn command_sender(&self) -> mpsc::Sender<NetworkCommand> {
+        self.command_tx.clone()
+    }
+
+    fn on_command(&mut self, command: NetworkCommand) {
+        match command {
+            NetworkCommand::UpdateCommittees(committees) => {
+                let changes = self.subnet_subscriptions.update(&committees);
+                self.apply_subscription_changes(changes);
+            }
+            NetworkCommand::Publish { subnet, message } => {
+                // The id is covered by the signatures, so a message of a fork that is not in use
+                // can't be converted and would be ignored by the peers.
+                let msg_id = message.ssv_message.msg_id;
+                if !self
+                    .forks_in_use
+                    .iter()
+                    .any(|fork| fork.is_fork_message_id(&msg_id))
+                {
+                    warn!(
+                        subnet = subnet.0,
+                        domain = ?msg_id.domain(),
+                        "Not publishing a message of a fork not in use"
+                    );
+                    return;
                 }
-                // TODO match input channels
+                self.decided_store.insert(&message);
+                if let Err(error) = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(self.current_fork.topic(subnet), message.as_ssz_bytes())
+                {
+                    warn!(subnet = subnet.0, %error, "Could not publish message");
+                }
+            }
+            NetworkCommand::Dial(multiaddr) => self.dial(multiaddr),
+            NetworkCommand::Disconnect(peer_id) => {
+                if self.swarm.disconnect_peer_id(peer_id).is_err() {
+                    debug!(%peer_id, "Peer to disconnect is not connected");
+                }
+            }
+            NetworkCommand::ListenAddresses(reply) => {
+                let _ = reply.send(self.swarm.listeners().cloned().collect());
+            }
+            NetworkCommand::ConnectedPeers(reply) => {
+                let _ = reply.send(self.swarm.connected_peers().copied().collect());
+            }
+            NetworkCommand::SubnetPeers(subnet, reply) => {
+                let topic = self.current_fork.topic(subnet).hash();
+                let peers = self
+                    .swarm
+                    .behaviour()
+                    .gossipsub
+                    .all_peers()
+                    .filter(|(_, topics)| topics.contains(&&topic))
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect();
+                let _ = reply.send(peers);
+            }
+            NetworkCommand::AddGaterRule(rule, reply) => {
+                info!(%rule, "Adding connection gater rule");
+                let result = self.swarm.behaviour_mut().connection_gater.add_rule(rule);
+                let _ = reply.send(result);
+            }
+            NetworkCommand::RemoveGaterRule(rule, reply) => {
+                info!(%rule, "Removing connection gater rule");
+                let result = self
+                    .swarm
+                    .behaviour_mut()
+                    .connection_gater
+                    .remove_rule(&rule);
+                let _ = reply.send(result);
             }
+            NetworkCommand::GaterRules(reply) => {
+                let _ = reply.send(self.swarm.behaviour().connection_gater.rules());
+            }
+            NetworkCommand::RequestDecidedHistory {
+                peer_id,
+                request,
+                reply,
+            } => {
+                if let Err(error) = request.validate() {
+                    let _ = reply.send(Err(error));
+                    return;
+                }
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .history_sync
+                    .send_request(&peer_id, request.clone());
+                self.pending_history_requests
+                    .insert(request_id, (request, reply));
+            }
+        }
+    }
+
+    /// Subscribes to and unsubscribes from the gossipsub topics of the changed subnets.
+    fn apply_subscription_changes(&mut self, changes: SubscriptionChanges) {
+        if changes.subscribe.is_empty() && changes.unsubscribe.is_empty() {
+            return;
+        }
+        debug!(
+            subscribe = ?changes.subscribe,
+            unsubscribe = ?changes.unsubscribe,
+            "Updating subnet subscriptions"
+        );
+
+        let prefixes = self.topic_prefixes();
+        self.update_topics(&prefixes, &changes.subscribe, &changes.unsubscribe);
+    }
+
+    /// Subscribes to and unsubscribes from the topics of the subnets, named with each prefix.
+    fn update_topics(
+        &mut self,
+        prefixes: &[String],
+        subscribe: &[SubnetId],
+        unsubscribe: &[SubnetId],
+    ) {
+        let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+        for prefix in prefixes {
+            for subnet in subscribe {
+                let topic = subnet.topic(prefix);
+                if let Err(error) = gossipsub.subscribe(&topic) {
+                    warn!(subnet = subnet.0, %error, "Could not subscribe to subnet");
+                }
+                if let Some(params) = &self.topic_score_params {
+                    if let Err(error) = gossipsub.set_topic_params(topic, params.clone()) {
+                        warn!(
+                            subnet = subnet.0,
+                            error, "Could not set the subnet score params"
+                        );
+                    }
+                }
+            }
+            for subnet in unsubscribe {
+                if let Err(error) = gossipsub.unsubscribe(&subnet.topic(prefix)) {
+                    warn!(subnet = subnet.0, %error, "Could not unsubscribe from subnet");
+                }
+            }
+        }
+        self.refresh_topics();
+    }
+
+    /// Shares the topics we are subscribed to and their peers.
+    fn refresh_topics(&self) {
+        let gossipsub = &self.swarm.behaviour().gossipsub;
+        let topics = gossipsub
+            .topics()
+            .map(|topic| TopicInfo {
+                topic: topic.to_string(),
+                peers: gossipsub
+                    .all_peers()
+                    .filter(|(_, topics)| topics.contains(&topic))
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect(),
+            })
+            .collect();
+        self.info.set_topics(topics);
+    }
+
+    /// The distinct topic prefixes of the forks in use.
+    fn topic_prefixes(&self) -> Vec<String> {
+        let mut prefixes: Vec<String> = self
+            .forks_in_use
+            .iter()
+            .map(|fork| fork.topic_prefix.clone())
+            .collect();
+        prefixes.sort();
+        prefixes.dedup();
+        prefixes
+    }
+
+    /// Transitions to the forks in use at the current epoch: subscribes to the topics of the
+    /// forks coming into use, unsubscribes from those of the forks no longer in use, and updates
+    /// the accepted domains and the ENR.
+    fn update_fork(&mut self) {
+        let epoch = current_epoch(&*self.validation_context);
+        let forks_in_use: Vec<Fork> = self
+            .fork_schedule
+            .forks_in_use(epoch)
+            .into_iter()
+            .cloned()
+            .collect();
+        let current_fork = self.fork_schedule.fork_at(epoch).clone();
+        if forks_in_use == self.forks_in_use && current_fork == self.current_fork {
+            return;
+        }
+        if current_fork != self.current_fork {
+            info!(fork = current_fork.name, epoch, "Network fork activated");
+        }
+
+        let previous_prefixes = self.topic_prefixes();
+        self.forks_in_use = forks_in_use;
+        self.current_fork = current_fork;
+        let prefixes = self.topic_prefixes();
+        let added: Vec<String> = prefixes
+            .iter()
+            .filter(|prefix| !previous_prefixes.contains(prefix))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = previous_prefixes
+            .into_iter()
+            .filter(|prefix| !prefixes.contains(prefix))
+            .collect();
+        let subnets: Vec<SubnetId> = self.subnet_subscriptions.active().collect();
+        debug!(?added, ?removed, "Updating the topics of the forks in use");
+        self.update_topics(&added, &subnets, &[]);
+        self.update_topics(&removed, &[], &subnets);
+
+        let mut domains: Vec<[u8; 4]> = self
+            .forks_in_use
+            .iter()
+            .map(|fork| fork.domain_type)
+            .collect();
+        domains.sort();
+        domains.dedup();
+        self.message_validator.set_domains(domains);
+
+        let next_domain_type = self
+            .fork_schedule
+            .next_fork(epoch)
+            .map(|fork| fork.domain_type);
+        match update_enr_domain_types(
+            &mut self.local_enr,
+            &self.enr_key,
+            self.current_fork.domain_type,
+            next_domain_type,
+        ) {
+            Ok(true) => {
+                debug!(
+                    enr = %self.local_enr.to_base64(),
+                    "Updated local ENR with the fork domain types"
+                );
+                self.info.set_enr(self.local_enr.clone());
+            }
+            Ok(false) => {}
+            Err(error) => warn!(error, "Could not update local ENR"),
+        }
+    }
+
+    fn on_swarm_event(&mut self, event: SwarmEvent<AnchorBehaviourEvent>) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&event);
+        }
+
+        match event {
+            SwarmEvent::Behaviour(behaviour_event) => self.on_behaviour_event(behaviour_event),
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
+                debug!(%peer_id, "Peer connected");
+                if self.peer_manager.is_banned(&peer_id, Instant::now()) {
+                    debug!(%peer_id, "Disconnecting banned peer");
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    return;
+                }
+                let direction = if endpoint.is_dialer() {
+                    ConnectionDirection::Outbound
+                } else {
+                    ConnectionDirection::Inbound
+                };
+                self.info
+                    .on_connected(peer_id, endpoint.get_remote_address().clone(), direction);
+                self.peer_manager.on_connection_established(&peer_id);
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } => {
+                debug!(%peer_id, "Peer disconnected");
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.on_peer_disconnected(&peer_id);
+                }
+                self.peer_manager
+                    .on_connection_closed(&peer_id, Instant::now());
+                self.info.on_disconnected(&peer_id);
+                self.refresh_topics();
+            }
+            SwarmEvent::NewListenAddr { .. } | SwarmEvent::ExpiredListenAddr { .. } => {
+                self.info
+                    .set_listen_addresses(self.swarm.listeners().cloned().collect());
+            }
+            SwarmEvent::ExternalAddrConfirmed { address } => {
+                self.on_external_address_confirmed(&address);
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id: Some(peer_id),
+                error,
+                ..
+            } => {
+                debug!(%peer_id, %error, "Failed to dial peer");
+                self.peer_manager.on_dial_failure(&peer_id, Instant::now());
+            }
+            // TODO handle and match swarm messages
+            _ => {}
+        }
+    }
+
+    fn on_behaviour_event(&mut self, event: AnchorBehaviourEvent) {
+        match event {
+            AnchorBehaviourEvent::Identify(event) => {
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.record(&event);
+                }
+                if let identify::Event::Received { peer_id, info, .. } = event {
+                    if let Some(metrics) = &mut self.metrics {
+                        metrics.on_identified(peer_id, &info.agent_version);
+                    }
+                    self.info
+                        .on_identified(&peer_id, info.agent_version.clone());
+                    let observer = self
+                        .info
+                        .peer(&peer_id)
+                        .and_then(|peer| ip_from_multiaddr(&peer.last_seen_address));
+                    if let Some(ip) = observer.and_then(|observer| {
+                        self.observed_addresses
+                            .on_observed(observer, &info.observed_addr)
+                    }) {
+                        self.on_external_ip_observed(ip);
+                    }
+                }
+            }
+            AnchorBehaviourEvent::Ping(event) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record(&event);
+                }
+            }
+            AnchorBehaviourEvent::Gossipsub(event) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record(&event);
+                }
+                match event {
+                    gossipsub::Event::Message {
+                        propagation_source,
+                        message_id,
+                        message,
+                    } => self.on_gossip_message(propagation_source, message_id, message.data),
+                    gossipsub::Event::Subscribed { .. } | gossipsub::Event::Unsubscribed { .. } => {
+                        self.refresh_topics()
+                    }
+                    _ => {}
+                }
+            }
+            AnchorBehaviourEvent::HistorySync(event) => self.on_history_sync_event(event),
+            AnchorBehaviourEvent::ConnectionGater(event) => match event {},
+            AnchorBehaviourEvent::PeerLimits(peer_limits::Event::HandshakeRateLimited(ip)) => {
+                debug!(%ip, "IP exceeded the handshake rate limit");
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_rate_limited(RateLimitedProtocol::Handshake);
+                }
+            }
+            AnchorBehaviourEvent::ConnectionLimits(event) => match event {},
+            AnchorBehaviourEvent::MemoryLimits(event) => match event {},
+            AnchorBehaviourEvent::Upnp(event) => match event {
+                upnp::Event::NewExternalAddr(address) => {
+                    info!(%address, "UPnP mapped external address");
+                }
+                upnp::Event::ExpiredExternalAddr(address) => {
+                    debug!(%address, "UPnP mapping expired");
+                }
+                upnp::Event::GatewayNotFound => {
+                    info!("No UPnP gateway found, ports are not mapped");
+                }
+                upnp::Event::NonRoutableGateway => {
+                    warn!("UPnP gateway is not exposed to the public network");
+                }
+            },
+        }
+    }
+
+    fn on_history_sync_event(
+        &mut self,
+        event: request_response::Event<DecidedHistoryRequest, DecidedHistoryResponse>,
+    ) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request, channel, ..
+                    },
+            } => self.on_history_request(peer, request, channel),
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Response {
+                        request_id,
+                        response,
+                    },
+            } => {
+                let Some((request, reply)) = self.pending_history_requests.remove(&request_id)
+                else {
+                    return;
+                };
+                let result = validate_response(&request, response, &self.message_validator);
+                if let Err(error) = &result {
+                    debug!(peer_id = %peer, error, "Invalid decided history response");
+                    self.report_peer(peer, PeerAction::LowToleranceError);
+                }
+                let _ = reply.send(result);
+            }
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                if let Some((_, reply)) = self.pending_history_requests.remove(&request_id) {
+                    let _ = reply.send(Err(format!(
+                        "Decided history request to {peer} failed: {error}"
+                    )));
+                }
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                debug!(peer_id = %peer, %error, "Failed to serve decided history request");
+            }
+            request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
+    /// Serves the decided messages requested by a peer within its rate limit.
+    fn on_history_request(
+        &mut self,
+        peer_id: PeerId,
+        request: DecidedHistoryRequest,
+        channel: ResponseChannel<DecidedHistoryResponse>,
+    ) {
+        // Dropping the channel without responding closes the stream.
+        if !self.peer_manager.is_trusted(&peer_id)
+            && !self.rate_limiter.allows(
+                peer_id,
+                RateLimitedProtocol::DecidedHistory,
+                Instant::now(),
+            )
+        {
+            self.on_rate_limited(peer_id, RateLimitedProtocol::DecidedHistory);
+            return;
+        }
+        if let Err(error) = request.validate() {
+            debug!(%peer_id, error, "Invalid decided history request");
+            self.report_peer(peer_id, PeerAction::LowToleranceError);
+            return;
+        }
+
+        let response = self.decided_store.get(&request);
+        if self
+            .swarm
+            .behaviour_mut()
+            .history_sync
+            .send_response(channel, response)
+            .is_err()
+        {
+            debug!(%peer_id, "Peer closed the decided history request");
+        }
+    }
+
+    /// Handles a peer exceeding its quota for a protocol by reporting it.
+    fn on_rate_limited(&mut self, peer_id: PeerId, protocol: RateLimitedProtocol) {
+        debug!(%peer_id, protocol = protocol.as_str(), "Peer exceeded its rate limit");
+        if let Some(metrics) = &self.metrics {
+            metrics.on_rate_limited(protocol);
+        }
+        self.report_peer(peer_id, PeerAction::MidToleranceError);
+    }
+
+    /// Lowers the score of a misbehaving peer, disconnecting it if it gets banned.
+    fn report_peer(&mut self, peer_id: PeerId, action: PeerAction) {
+        if !self.peer_scoring_enabled {
+            return;
+        }
+        if self
+            .peer_manager
+            .report_peer(&peer_id, action, Instant::now())
+        {
+            info!(%peer_id, "Banned misbehaving peer");
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+    }
+
+    /// Hands a gossip message to the processor, which verifies its signatures and validates it
+    /// off the swarm loop. Gossipsub forgets the messages the processor drops without
+    /// penalising their peers.
+    fn on_gossip_message(
+        &mut self,
+        propagation_source: PeerId,
+        message_id: gossipsub::MessageId,
+        data: Vec<u8>,
+    ) {
+        let validator = self.message_validator.clone();
+        let validated_tx = self.validated_tx.clone();
+        let id = message_id.clone();
+        let slot = self.validation_context.current_slot().unwrap_or(0);
+        let work = Work::new(WorkKind::GossipMessage, slot, move || {
+            let validated = ValidatedMessage {
+                propagation_source,
+                message_id: id,
+                result: validator.validate(&data),
+            };
+            // The network only stops with the client.
+            let _ = validated_tx.blocking_send(validated);
+        });
+        if let Err(error) = self.processor.send(work) {
+            debug!(%message_id, error, "Ignoring gossip message");
+            self.report_validation_result(
+                &message_id,
+                &propagation_source,
+                MessageAcceptance::Ignore,
+            );
+        }
+    }
+
+    fn report_validation_result(
+        &mut self,
+        message_id: &gossipsub::MessageId,
+        propagation_source: &PeerId,
+        acceptance: MessageAcceptance,
+    ) {
+        if let Err(error) = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(message_id, propagation_source, acceptance)
+        {
+            warn!(%message_id, ?error, "Could not report message validation result");
+        }
+    }
+
+    /// Reports the result of the validation of a gossip message to gossipsub so that it is only
+    /// propagated if valid, and passes valid messages on.
+    fn on_validated_message(&mut self, validated: ValidatedMessage) {
+        let ValidatedMessage {
+            propagation_source,
+            message_id,
+            result,
+        } = validated;
+        // Invalid messages of trusted peers are dropped without lowering their gossipsub score.
+        let acceptance = match result.acceptance() {
+            MessageAcceptance::Reject if self.peer_manager.is_trusted(&propagation_source) => {
+                MessageAcceptance::Ignore
+            }
+            acceptance => acceptance,
+        };
+        self.report_validation_result(&message_id, &propagation_source, acceptance);
+
+        match result {
+            ValidationResult::Accept(message) => {
+                self.decided_store.insert(&message);
+                match self.inbound_tx.try_send(message) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        warn!(%message_id, "Inbound message channel full, dropping message")
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        debug!(%message_id, "No consumer for inbound messages")
+                    }
+                }
+            }
+            ValidationResult::Ignore(error) => {
+                debug!(
+                    %message_id,
+                    peer_id = %propagation_source,
+                    %error,
+                    "Ignoring gossip message"
+                )
+            }
+            ValidationResult::Reject(error) => {
+                debug!(
+                    %message_id,
+                    peer_id = %propagation_source,
+                    %error,
+                    "Rejecting gossip message"
+                )
+            }
+        }
+    }
+
+    /// Confirms an external IP observed by enough peers, advertising it on the listening TCP port.
+    fn on_external_ip_observed(&mut self, ip: IpAddr) {
+        let tcp_port = match ip {
+            IpAddr::V4(_) => self.local_enr.tcp4(),
+            IpAddr::V6(_) => self.local_enr.tcp6(),
+        };
+        if let Some(tcp_port) = tcp_port {
+            let address = Multiaddr::empty()
+                .with(Protocol::from(ip))
+                .with(Protocol::Tcp(tcp_port));
+            debug!(%address, "External address observed by peers");
+            self.swarm.add_external_address(address);
+        }
+    }
+
+    /// Updates the local ENR when a new external address has been confirmed, unless the ENR
+    /// address was set explicitly.
+    fn on_external_address_confirmed(&mut self, address: &Multiaddr) {
+        let (Some(ip), Some(tcp_port)) =
+            (ip_from_multiaddr(address), tcp_port_from_multiaddr(address))
+        else {
+            return;
+        };
+        let fixed = match ip {
+            IpAddr::V4(_) => self.fixed_enr_address.0,
+            IpAddr::V6(_) => self.fixed_enr_address.1,
+        };
+        if fixed {
+            return;
+        }
+
+        match update_enr_tcp_socket(&mut self.local_enr, &self.enr_key, ip, tcp_port) {
+            Ok(true) => {
+                info!(
+                    %address,
+                    enr = %self.local_enr.to_base64(),
+                    "Updated local ENR with the external address"
+                );
+                self.info.set_enr(self.local_enr.clone());
+            }
+            Ok(false) => {}
+            Err(error) => warn!(%address, error, "Could not update local ENR"),
+        }
+    }
+
+    /// Dials the static peers that are due for a (re)dial.
+    fn dial_static_peers(&mut self) {
+        for (peer_id, addresses) in self.peer_manager.peers_to_dial(Instant::now()) {
+            let opts = DialOpts::peer_id(peer_id).addresses(addresses).build();
+            if let Err(error) = self.swarm.dial(opts) {
+                warn!(%peer_id, %error, "Could not dial static peer");
+                self.peer_manager.on_dial_failure(&peer_id, Instant::now());
+            }
+        }
+    }
+
+    /// Dials the peer of an ENR on the addresses it advertises, trying the IP versions we listen
+    /// on first.
+    fn dial_enr(&mut self, enr: &Enr) {
+        let mut addresses = enr.multiaddr_tcp();
+        if self.quic_enabled {
+            addresses.extend(enr.multiaddr_quic());
+        }
+        if addresses.is_empty() {
+            warn!(enr = %enr.to_base64(), "ENR has no address to dial");
+            return;
+        }
+        addresses.sort_by_key(|address| !self.listens_on_ip_version_of(address));
+
+        let peer_id = enr.peer_id();
+        debug!(%peer_id, ?addresses, "Dialing peer");
+        let opts = DialOpts::peer_id(peer_id).addresses(addresses).build();
+        if let Err(error) = self.swarm.dial(opts) {
+            warn!(%peer_id, %error, "Could not dial peer");
+        }
+    }
+
+    /// Returns true if we listen on the IP version of the address.
+    fn listens_on_ip_version_of(&self, address: &Multiaddr) -> bool {
+        match ip_from_multiaddr(address) {
+            Some(IpAddr::V4(_)) => self.listen_addresses.v4().is_some(),
+            Some(IpAddr::V6(_)) => self.listen_addresses.v6().is_some(),
+            None => false,
+        }
+    }
+
+    /// Dials a single multiaddr, logging any immediate failure.
+    fn dial(&mut self, multiaddr: Multiaddr) {
+        debug!(address = %multiaddr, "Dialing peer");
+        if let Err(error) = self.swarm.dial(multiaddr.clone()) {
+            warn!(address = %multiaddr, %error, "Could not dial peer");
         }
     }
 }
 
-fn build_anchor_behaviour(local_keypair: Keypair) -> AnchorBehaviour {
-    // setup gossipsub
+/// The current epoch, or genesis if the current slot is not known.
+fn current_epoch(context: &dyn ValidationContext) -> u64 {
+    context
+        .current_slot()
+        .map_or(0, |slot| slot / SLOTS_PER_EPOCH)
+}
+
+fn build_anchor_behaviour(
+    local_keypair: Keypair,
+    registry: Option<&mut Registry>,
+    config: &Config,
+) -> Result<AnchorBehaviour, String> {
     // discv5
     let identify = {
         let local_public_key = local_keypair.public();
@@ -94,11 +1014,114 @@ fn build_anchor_behaviour(local_keypair: Keypair) -> AnchorBehaviour {
         identify::Behaviour::new(identify_config)
     };
 
-    AnchorBehaviour {
+    let gossipsub = {
+        config.gossipsub.validate()?;
+        // SSV messages are not signed by libp2p, they are identified by their content. Messages
+        // are only propagated once the application validated them.
+        let mut builder = gossipsub::ConfigBuilder::default();
+        builder
+            .validation_mode(ValidationMode::Anonymous)
+            .validate_messages()
+            .message_id_fn(|message| Sha256::digest(&message.data)[..20].to_vec().into());
+        config.gossipsub.apply(&mut builder);
+        let gossipsub_config = builder
+            .build()
+            .map_err(|e| format!("Invalid gossipsub config: {e}"))?;
+        let mut gossipsub = match registry {
+            Some(registry) => gossipsub::Behaviour::new_with_metrics(
+                MessageAuthenticity::Anonymous,
+                gossipsub_config,
+                registry.sub_registry_with_prefix("gossipsub"),
+                gossipsub::MetricsConfig::default(),
+            ),
+            None => gossipsub::Behaviour::new(MessageAuthenticity::Anonymous, gossipsub_config),
+        }
+        .map_err(|e| format!("Unable to create gossipsub behaviour: {e}"))?;
+        if !config.disable_peer_scoring {
+            gossipsub
+                .with_peer_score(
+                    config.gossipsub.peer_score_params(),
+                    config.gossipsub.peer_score_thresholds(),
+                )
+                .map_err(|e| format!("Unable to enable gossipsub peer scoring: {e}"))?;
+        }
+        // Messages are always exchanged with the trusted peers, whatever their score.
+        for peer_id in &config.trusted_peers {
+            gossipsub.add_explicit_peer(peer_id);
+        }
+        gossipsub
+    };
+
+    let upnp = Toggle::from(config.upnp_enabled.then(upnp::tokio::Behaviour::default));
+
+    // The established connection counts are limited by `peer_limits`, which exempts the
+    // protected peers.
+    let connection_limits = {
+        let limits = connection_limits::ConnectionLimits::default()
+            .with_max_pending_incoming(Some(config.max_pending_incoming))
+            .with_max_pending_outgoing(Some(config.max_pending_outgoing))
+            .with_max_established_per_peer(Some(config.max_connections_per_peer));
+        connection_limits::Behaviour::new(limits)
+    };
+
+    let peer_limits = {
+        let target_peers = config.target_peers as f32;
+        let limits = EstablishedLimits {
+            max_incoming: (target_peers * (1.0 + PEER_EXCESS_FACTOR - MIN_OUTBOUND_ONLY_FACTOR))
+                .ceil() as u32,
+            max_outgoing: (target_peers * (1.0 + PEER_EXCESS_FACTOR)).ceil() as u32,
+            max_total: (target_peers * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS)).ceil()
+                as u32,
+        };
+        let protected_peers = config
+            .static_peers
+            .iter()
+            .filter_map(peer_id_from_multiaddr)
+            .chain(config.trusted_peers.iter().copied())
+            .collect();
+        peer_limits::Behaviour::new(limits, protected_peers, config.rate_limits.clone())
+    };
+
+    let history_sync = request_response::Behaviour::with_codec(
+        HistorySyncCodec,
+        [(PROTOCOL, ProtocolSupport::Full)],
+        request_response::Config::default()
+            .with_request_timeout(REQUEST_TIMEOUT)
+            .with_max_concurrent_streams(MAX_CONCURRENT_STREAMS),
+    );
+
+    let connection_gater = {
+        let rules = config
+            .allowed_subnets
+            .iter()
+            .copied()
+            .map(GaterRule::AllowSubnet)
+            .chain(
+                config
+                    .denied_subnets
+                    .iter()
+                    .copied()
+                    .map(GaterRule::DenySubnet),
+            )
+            .chain(config.denied_peers.iter().copied().map(GaterRule::DenyPeer))
+            .collect();
+        connection_gater::Behaviour::new(rules, &config.network_dir)?
+    };
+
+    let memory_limits =
+        memory_connection_limits::Behaviour::with_max_percentage(config.max_memory_usage);
+
+    Ok(AnchorBehaviour {
+        connection_gater,
+        peer_limits,
+        connection_limits,
+        memory_limits,
         identify,
         ping: ping::Behaviour::default(),
-        // gossipsub: gossipsub::Behaviour::default(),
-    }
+        gossipsub,
+        history_sync,
+        upnp,
+    })
 }
 
 fn build_swarm(
@@ -106,6 +1129,7 @@ fn build_swarm(
     local_keypair: Keypair,
     transport: Boxed<(PeerId, StreamMuxerBox)>,
     behaviour: AnchorBehaviour,
+    registry: Option<&mut Registry>,
     _config: &Config,
 ) -> Swarm<AnchorBehaviour> {
     // use the executor for libp2p
@@ -116,48 +1140,46 @@ fn build_swarm(
         }
     }
 
-    // TODO: revisit once peer manager is integrated
-    // let connection_limits = {
-    //     let limits = libp2p::connection_limits::ConnectionLimits::default()
-    //         .with_max_pending_incoming(Some(5))
-    //         .with_max_pending_outgoing(Some(16))
-    //         .with_max_established_incoming(Some(
-    //             (config.target_peers as f32
-    //                 * (1.0 + PEER_EXCESS_FACTOR - MIN_OUTBOUND_ONLY_FACTOR))
-    //                 .ceil() as u32,
-    //         ))
-    //         .with_max_established_outgoing(Some(
-    //             (config.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR)).ceil() as u32,
-    //         ))
-    //         .with_max_established(Some(
-    //             (config.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS))
-    //                 .ceil() as u32,
-    //         ))
-    //         .with_max_established_per_peer(Some(1));
-    //
-    //     libp2p::connection_limits::Behaviour::new(limits)
-    // };
-
     let swarm_config = libp2p::swarm::Config::with_executor(Executor(executor))
         .with_notify_handler_buffer_size(NonZeroUsize::new(7).expect("Not zero"))
         .with_per_connection_event_buffer_size(4)
         .with_dial_concurrency_factor(NonZeroU8::new(1).unwrap());
 
-    // TODO Add metrics later
-    SwarmBuilder::with_existing_identity(local_keypair)
+    let builder = SwarmBuilder::with_existing_identity(local_keypair)
         .with_tokio()
         .with_other_transport(|_key| transport)
-        .expect("infalible")
-        .with_behaviour(|_| behaviour)
-        .expect("infalible")
-        .with_swarm_config(|_| swarm_config)
-        .build()
+        .expect("infalible");
+
+    match registry {
+        Some(registry) => builder
+            .with_bandwidth_metrics(registry)
+            .with_behaviour(|_| behaviour)
+            .expect("infalible")
+            .with_swarm_config(|_| swarm_config)
+            .build(),
+        None => builder
+            .with_behaviour(|_| behaviour)
+            .expect("infalible")
+            .with_swarm_config(|_| swarm_config)
+            .build(),
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::network::Network;
-    use crate::Config;
+    use crate::connection_gater::GaterRule;
+    use crate::network::{Network, NetworkCommand};
+    use crate::network_info::{ConnectionDirection, PeerConnectionState};
+    use crate::test_utils::{
+        localhost_dual_stack, localhost_v4, localhost_v6, spawn_processor, test_config,
+        test_decided_message, test_message, wait_until, TestNetwork, TEST_SLOT,
+    };
+    use crate::{Config, NoValidationContext};
+    use libp2p::multiaddr::Protocol;
+    use lighthouse_network::{ListenAddr, ListenAddress};
+    use std::net::Ipv6Addr;
+    use std::sync::Arc;
+    use std::time::Duration;
     use task_executor::TaskExecutor;
 
     #[tokio::test]
@@ -166,8 +1188,296 @@ mod test {
         let (_signal, exit) = async_channel::bounded(1);
         let (shutdown_tx, _) = futures::channel::mpsc::channel(1);
         let task_executor = TaskExecutor::new(handle, exit, shutdown_tx);
-        assert!(Network::try_new(&Config::default(), task_executor)
+        assert!(Network::try_new(
+            &Config::default(),
+            Arc::new(NoValidationContext),
+            spawn_processor(),
+            None,
+            task_executor
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn gossip_is_delivered_to_all_nodes() {
+        let mut network = TestNetwork::new(3).await;
+        network.connect_all().await;
+        network.set_subscribed(&[0, 1, 2], true).await;
+        network.wait_for_subnet_peers(0, &[1, 2]).await;
+
+        let message = test_message(1, 1);
+        network.publish(0, message.clone()).await;
+        assert_eq!(network.nodes[1].receive().await, message);
+        assert_eq!(network.nodes[2].receive().await, message);
+    }
+
+    #[tokio::test]
+    async fn gossip_is_relayed() {
+        // The nodes are connected in a line, so messages from one end reach the other end
+        // through the middle node.
+        let mut network = TestNetwork::new(3).await;
+        network.connect(1, 0).await;
+        network.connect(2, 1).await;
+        network.set_subscribed(&[0, 1, 2], true).await;
+        network.wait_for_subnet_peers(1, &[0, 2]).await;
+
+        let message = test_message(1, 1);
+        network.publish(0, message.clone()).await;
+        assert_eq!(network.nodes[2].receive().await, message);
+    }
+
+    #[tokio::test]
+    async fn disconnected_peer_receives_after_reconnecting() {
+        let mut network = TestNetwork::new(2).await;
+        network.connect_all().await;
+        network.set_subscribed(&[0, 1], true).await;
+        network.wait_for_subnet_peers(0, &[1]).await;
+
+        let peer_id = network.nodes[1].peer_id;
+        network.nodes[0]
+            .command(NetworkCommand::Disconnect(peer_id))
+            .await;
+        network.wait_for_subnet_peers(0, &[]).await;
+        network.publish(0, test_message(1, 1)).await;
+        assert!(network.nodes[1].receives_nothing().await);
+
+        network.connect(1, 0).await;
+        network.wait_for_subnet_peers(0, &[1]).await;
+        let message = test_message(1, 2);
+        network.publish(0, message.clone()).await;
+        assert_eq!(network.nodes[1].receive().await, message);
+    }
+
+    #[tokio::test]
+    async fn resubscribed_node_receives_again() {
+        let mut network = TestNetwork::new(3).await;
+        network.connect_all().await;
+        network.set_subscribed(&[0, 1, 2], true).await;
+        network.wait_for_subnet_peers(0, &[1, 2]).await;
+
+        network.set_subscribed(&[1], false).await;
+        network.wait_for_subnet_peers(0, &[2]).await;
+        network.wait_for_subnet_peers(2, &[0]).await;
+        let message = test_message(1, 1);
+        network.publish(0, message.clone()).await;
+        assert_eq!(network.nodes[2].receive().await, message);
+        assert!(network.nodes[1].receives_nothing().await);
+
+        network.set_subscribed(&[1], true).await;
+        network.wait_for_subnet_peers(0, &[1, 2]).await;
+        let message = test_message(1, 2);
+        network.publish(0, message.clone()).await;
+        assert_eq!(network.nodes[1].receive().await, message);
+    }
+
+    #[tokio::test]
+    async fn trusted_peer_is_not_graylisted_for_invalid_messages() {
+        let mut network = TestNetwork::empty();
+        let trusted = network.add_node(localhost_v4()).await;
+        let trusted_peer_id = network.nodes[trusted].peer_id;
+        let node = network
+            .add_node_with_config(|network_dir| Config {
+                trusted_peers: vec![trusted_peer_id],
+                ..test_config(localhost_v4(), network_dir)
+            })
+            .await;
+        network.connect(node, trusted).await;
+        network.set_subscribed(&[node, trusted], true).await;
+        network.wait_for_subnet_peers(trusted, &[node]).await;
+
+        // Operator 9 is not part of the committee, so its messages are invalid. More of them
+        // than needed to graylist an untrusted peer are sent.
+        for round in 1..=30 {
+            network.publish(trusted, test_message(9, round)).await;
+        }
+        assert!(network.nodes[node].receives_nothing().await);
+
+        let message = test_message(1, 1);
+        network.publish(trusted, message.clone()).await;
+        assert_eq!(network.nodes[node].receive().await, message);
+        network.wait_for_subnet_peers(node, &[trusted]).await;
+        assert!(network.nodes[node]
+            .connected_peers()
             .await
-            .is_ok());
+            .contains(&trusted_peer_id));
+    }
+
+    #[tokio::test]
+    async fn ipv6_nodes_exchange_gossip() {
+        let mut network = TestNetwork::empty();
+        network.add_node(localhost_v6()).await;
+        network.add_node(localhost_v6()).await;
+        network.connect_all().await;
+        network.set_subscribed(&[0, 1], true).await;
+        network.wait_for_subnet_peers(0, &[1]).await;
+
+        let message = test_message(1, 1);
+        network.publish(0, message.clone()).await;
+        assert_eq!(network.nodes[1].receive().await, message);
+    }
+
+    #[tokio::test]
+    async fn decided_history_is_served_to_late_nodes() {
+        let mut network = TestNetwork::new(2).await;
+        network.connect_all().await;
+        network.set_subscribed(&[0, 1], true).await;
+        network.wait_for_subnet_peers(0, &[1]).await;
+
+        let decided = [
+            test_decided_message(TEST_SLOT - 1),
+            test_decided_message(TEST_SLOT),
+        ];
+        for message in &decided {
+            network.publish(0, message.clone()).await;
+            assert_eq!(network.nodes[1].receive().await, *message);
+        }
+
+        // A node joining late fetches the decided messages node 1 received over gossip.
+        let late_node = network.add_node(localhost_v4()).await;
+        network.connect(late_node, 1).await;
+        let peer_id = network.nodes[1].peer_id;
+        let history = network.nodes[late_node]
+            .decided_history(peer_id, TEST_SLOT - 5, TEST_SLOT + 5)
+            .await
+            .unwrap();
+        assert_eq!(history, decided);
+        let history = network.nodes[late_node]
+            .decided_history(peer_id, TEST_SLOT, TEST_SLOT)
+            .await
+            .unwrap();
+        assert_eq!(history, decided[1..]);
+
+        // Requests over too many heights are not sent.
+        assert!(network.nodes[late_node]
+            .decided_history(peer_id, 0, TEST_SLOT)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn network_info_follows_peers_and_topics() {
+        let network = TestNetwork::new(2).await;
+        network.connect(1, 0).await;
+        network.set_subscribed(&[0, 1], true).await;
+        network.wait_for_subnet_peers(0, &[1]).await;
+
+        let info = &network.nodes[0].info;
+        let identity = info.identity();
+        assert_eq!(identity.peer_id, network.nodes[0].peer_id);
+        assert_eq!(
+            identity.listen_addresses,
+            vec![network.nodes[0].listen_address().await]
+        );
+
+        let peer_id = network.nodes[1].peer_id;
+        let peer = info.peer(&peer_id).unwrap();
+        assert_eq!(peer.state, PeerConnectionState::Connected);
+        assert_eq!(peer.direction, ConnectionDirection::Inbound);
+        // The topic peers are shared once gossipsub reported the subscription.
+        wait_until(|| async {
+            let topics = info.topics();
+            (topics.len() == 1 && topics[0].peers == vec![peer_id]).then_some(())
+        })
+        .await;
+
+        network.nodes[0]
+            .command(NetworkCommand::Disconnect(peer_id))
+            .await;
+        network.wait_for_subnet_peers(0, &[]).await;
+        assert_eq!(
+            info.peer(&peer_id).unwrap().state,
+            PeerConnectionState::Disconnected
+        );
+    }
+
+    #[tokio::test]
+    async fn denied_peer_is_disconnected_until_allowed_again() {
+        let network = TestNetwork::new(2).await;
+        network.connect(1, 0).await;
+
+        let rule = GaterRule::DenyPeer(network.nodes[1].peer_id);
+        network.nodes[0].add_gater_rule(rule.clone()).await.unwrap();
+        wait_until(|| async {
+            network.nodes[0]
+                .connected_peers()
+                .await
+                .is_empty()
+                .then_some(())
+        })
+        .await;
+
+        // The denied peer cannot connect again.
+        let address = network.nodes[0].listen_address().await;
+        network.nodes[1]
+            .command(NetworkCommand::Dial(address))
+            .await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(network.nodes[0].connected_peers().await.is_empty());
+
+        assert!(network.nodes[0].remove_gater_rule(rule).await.unwrap());
+        network.connect(1, 0).await;
+    }
+
+    #[tokio::test]
+    async fn dual_stack_node_is_reachable_over_ipv4_and_ipv6() {
+        let mut network = TestNetwork::empty();
+        let dual_stack = network.add_node(localhost_dual_stack()).await;
+        let ipv4_only = network.add_node(localhost_v4()).await;
+        let ipv6_only = network.add_node(localhost_v6()).await;
+
+        let ipv4_address = network.nodes[dual_stack]
+            .listen_address_matching(|address| {
+                address.iter().any(|p| matches!(p, Protocol::Ip4(_)))
+            })
+            .await;
+        let ipv6_address = network.nodes[dual_stack]
+            .listen_address_matching(|address| {
+                address.iter().any(|p| matches!(p, Protocol::Ip6(_)))
+            })
+            .await;
+        network.dial(ipv4_only, dual_stack, ipv4_address).await;
+        network.dial(ipv6_only, dual_stack, ipv6_address).await;
+
+        network
+            .set_subscribed(&[dual_stack, ipv4_only, ipv6_only], true)
+            .await;
+        network
+            .wait_for_subnet_peers(dual_stack, &[ipv4_only, ipv6_only])
+            .await;
+        let message = test_message(1, 1);
+        network.publish(dual_stack, message.clone()).await;
+        assert_eq!(network.nodes[ipv4_only].receive().await, message);
+        assert_eq!(network.nodes[ipv6_only].receive().await, message);
+    }
+
+    #[tokio::test]
+    async fn ipv6_boot_node_enr_is_dialed() {
+        // The ENR must advertise a reachable port, so the boot node does not use a zero port.
+        let tcp_port = unused_port::unused_tcp6_port().unwrap();
+        let mut network = TestNetwork::empty();
+        let boot_node = network
+            .add_node_with_config(|network_dir| Config {
+                enr_address: (None, Some(Ipv6Addr::LOCALHOST)),
+                ..test_config(
+                    ListenAddress::V6(ListenAddr {
+                        addr: Ipv6Addr::LOCALHOST,
+                        disc_port: tcp_port,
+                        quic_port: 0,
+                        tcp_port,
+                    }),
+                    network_dir,
+                )
+            })
+            .await;
+
+        let boot_enr = network.nodes[boot_node].enr.clone();
+        let node = network
+            .add_node_with_config(|network_dir| Config {
+                boot_nodes_enr: vec![boot_enr],
+                ..test_config(localhost_v6(), network_dir)
+            })
+            .await;
+        network.wait_until_connected(node, boot_node).await;
     }
 }
@@ -0,0 +1,226 @@
+//! Information about the node and its peers, shared with the other components.
+//!
+//! The network keeps a `NetworkInfo` up to date as the swarm changes. Other components, such as
+//! the HTTP API, get a read-only handle to it with `Network::info`.
+
+use discv5::Enr;
+use libp2p::{Multiaddr, PeerId};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The number of disconnected peers remembered.
+const MAX_DISCONNECTED_PEERS: usize = 100;
+
+/// How the node can be reached.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub peer_id: PeerId,
+    pub enr: Enr,
+    /// The addresses the node is listening on.
+    pub listen_addresses: Vec<Multiaddr>,
+}
+
+/// Whether the connection to a peer was initiated by the peer or by us.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionDirection {
+    Inbound,
+    Outbound,
+}
+
+impl fmt::Display for ConnectionDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Inbound => write!(f, "inbound"),
+            Self::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerConnectionState {
+    Connected,
+    Disconnected,
+}
+
+impl fmt::Display for PeerConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Connected => write!(f, "connected"),
+            Self::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
+
+/// A peer we are or were connected to.
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+    pub peer_id: PeerId,
+    /// The address of the last connection to the peer.
+    pub last_seen_address: Multiaddr,
+    pub direction: ConnectionDirection,
+    pub state: PeerConnectionState,
+    /// The agent version reported by identify, if received.
+    pub agent_version: Option<String>,
+}
+
+/// A gossipsub topic we are subscribed to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicInfo {
+    pub topic: String,
+    /// The peers subscribed to the topic.
+    pub peers: Vec<PeerId>,
+}
+
+/// Information about the node and its peers, written by the network only.
+#[derive(Debug)]
+pub struct NetworkInfo {
+    identity: RwLock<Identity>,
+    peers: RwLock<HashMap<PeerId, PeerInfo>>,
+    /// The disconnected peers, from the least to the most recently disconnected.
+    disconnected: RwLock<Vec<PeerId>>,
+    topics: RwLock<Vec<TopicInfo>>,
+}
+
+impl NetworkInfo {
+    pub(crate) fn new(identity: Identity) -> Self {
+        Self {
+            identity: RwLock::new(identity),
+            peers: RwLock::new(HashMap::new()),
+            disconnected: RwLock::new(vec![]),
+            topics: RwLock::new(vec![]),
+        }
+    }
+
+    pub fn identity(&self) -> Identity {
+        self.identity.read().clone()
+    }
+
+    /// The connected and recently disconnected peers.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.peers.read().values().cloned().collect()
+    }
+
+    pub fn peer(&self, peer_id: &PeerId) -> Option<PeerInfo> {
+        self.peers.read().get(peer_id).cloned()
+    }
+
+    /// The topics we are subscribed to.
+    pub fn topics(&self) -> Vec<TopicInfo> {
+        self.topics.read().clone()
+    }
+
+    pub(crate) fn set_enr(&self, enr: Enr) {
+        self.identity.write().enr = enr;
+    }
+
+    pub(crate) fn set_listen_addresses(&self, listen_addresses: Vec<Multiaddr>) {
+        self.identity.write().listen_addresses = listen_addresses;
+    }
+
+    pub(crate) fn set_topics(&self, topics: Vec<TopicInfo>) {
+        *self.topics.write() = topics;
+    }
+
+    pub(crate) fn on_connected(
+        &self,
+        peer_id: PeerId,
+        address: Multiaddr,
+        direction: ConnectionDirection,
+    ) {
+        self.disconnected.write().retain(|peer| *peer != peer_id);
+        let mut peers = self.peers.write();
+        let agent_version = peers.remove(&peer_id).and_then(|peer| peer.agent_version);
+        peers.insert(
+            peer_id,
+            PeerInfo {
+                peer_id,
+                last_seen_address: address,
+                direction,
+                state: PeerConnectionState::Connected,
+                agent_version,
+            },
+        );
+    }
+
+    pub(crate) fn on_identified(&self, peer_id: &PeerId, agent_version: String) {
+        if let Some(peer) = self.peers.write().get_mut(peer_id) {
+            peer.agent_version = Some(agent_version);
+        }
+    }
+
+    /// Marks a peer as disconnected, forgetting the least recently disconnected peers beyond
+    /// `MAX_DISCONNECTED_PEERS`.
+    pub(crate) fn on_disconnected(&self, peer_id: &PeerId) {
+        let mut peers = self.peers.write();
+        let Some(peer) = peers
+            .get_mut(peer_id)
+            .filter(|peer| peer.state == PeerConnectionState::Connected)
+        else {
+            return;
+        };
+        peer.state = PeerConnectionState::Disconnected;
+
+        let mut disconnected = self.disconnected.write();
+        disconnected.push(*peer_id);
+        if disconnected.len() > MAX_DISCONNECTED_PEERS {
+            let forgotten = disconnected.remove(0);
+            peers.remove(&forgotten);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discv5::enr::CombinedKey;
+
+    fn network_info() -> NetworkInfo {
+        let enr = Enr::builder()
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        NetworkInfo::new(Identity {
+            peer_id: PeerId::random(),
+            enr,
+            listen_addresses: vec![],
+        })
+    }
+
+    #[test]
+    fn peers_follow_connections() {
+        let info = network_info();
+        let peer_id = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9100".parse().unwrap();
+
+        info.on_connected(peer_id, address.clone(), ConnectionDirection::Inbound);
+        info.on_identified(&peer_id, "Anchor/v0.1.0".to_string());
+        info.on_disconnected(&peer_id);
+        let peer = info.peer(&peer_id).unwrap();
+        assert_eq!(peer.state, PeerConnectionState::Disconnected);
+        assert_eq!(peer.last_seen_address, address);
+
+        // The agent version is kept when the peer reconnects.
+        info.on_connected(peer_id, address, ConnectionDirection::Outbound);
+        let peer = info.peer(&peer_id).unwrap();
+        assert_eq!(peer.state, PeerConnectionState::Connected);
+        assert_eq!(peer.direction, ConnectionDirection::Outbound);
+        assert_eq!(peer.agent_version.as_deref(), Some("Anchor/v0.1.0"));
+    }
+
+    #[test]
+    fn disconnected_peers_are_forgotten() {
+        let info = network_info();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9100".parse().unwrap();
+        let peer_ids: Vec<PeerId> = (0..=MAX_DISCONNECTED_PEERS)
+            .map(|_| PeerId::random())
+            .collect();
+        for peer_id in &peer_ids {
+            info.on_connected(*peer_id, address.clone(), ConnectionDirection::Inbound);
+            info.on_disconnected(peer_id);
+        }
+
+        assert_eq!(info.peers().len(), MAX_DISCONNECTED_PEERS);
+        assert!(info.peer(&peer_ids[0]).is_none());
+        assert!(info.peer(&peer_ids[1]).is_some());
+    }
+}
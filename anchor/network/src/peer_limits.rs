@@ -0,0 +1,277 @@
+//! Limits the connections of the peers that are not protected.
+//!
+//! Inbound connections are rate limited per remote IP before the transport handshake, so that a
+//! peer can't make us perform handshakes faster than its quota by changing its identity. The
+//! established connections are capped per direction and in total, but the trusted and static
+//! peers are always accepted: they are the peers we rely on most when the node is at capacity.
+
+use crate::nat::ip_from_multiaddr;
+use crate::rate_limiter::{RateLimitedProtocol, RateLimiter, RateLimiterConfig};
+use libp2p::core::transport::PortUse;
+use libp2p::core::Endpoint;
+use libp2p::swarm::behaviour::ConnectionEstablished;
+use libp2p::swarm::{
+    dummy, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler,
+    THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+/// The maximum number of established connections of the peers that are not protected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EstablishedLimits {
+    pub max_incoming: u32,
+    pub max_outgoing: u32,
+    pub max_total: u32,
+}
+
+/// The reason a connection is denied.
+#[derive(Debug)]
+pub struct LimitExceeded(String);
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+#[derive(Debug)]
+pub enum Event {
+    /// An inbound connection was denied because its IP exceeded the handshake rate limit.
+    HandshakeRateLimited(IpAddr),
+}
+
+pub struct Behaviour {
+    limits: EstablishedLimits,
+    /// The trusted and static peers, which are exempt from the established limits.
+    protected_peers: HashSet<PeerId>,
+    handshake_limiter: RateLimiter<IpAddr>,
+    /// The direction of each established connection.
+    connections: HashMap<ConnectionId, Endpoint>,
+    events: VecDeque<Event>,
+    waker: Option<Waker>,
+}
+
+impl Behaviour {
+    pub fn new(
+        limits: EstablishedLimits,
+        protected_peers: HashSet<PeerId>,
+        rate_limits: RateLimiterConfig,
+    ) -> Self {
+        Self {
+            limits,
+            protected_peers,
+            handshake_limiter: RateLimiter::new(rate_limits),
+            connections: HashMap::new(),
+            events: VecDeque::new(),
+            waker: None,
+        }
+    }
+
+    /// Drops the rate limits of the IPs whose quota is full again.
+    pub fn prune(&mut self, now: Instant) {
+        self.handshake_limiter.prune(now);
+    }
+
+    fn count(&self, endpoint: Endpoint) -> u32 {
+        self.connections
+            .values()
+            .filter(|direction| **direction == endpoint)
+            .count() as u32
+    }
+
+    fn check_established(&self, peer_id: PeerId, endpoint: Endpoint) -> Result<(), LimitExceeded> {
+        if self.protected_peers.contains(&peer_id) {
+            return Ok(());
+        }
+        let (count, limit) = match endpoint {
+            Endpoint::Listener => (self.count(Endpoint::Listener), self.limits.max_incoming),
+            Endpoint::Dialer => (self.count(Endpoint::Dialer), self.limits.max_outgoing),
+        };
+        if count >= limit {
+            return Err(LimitExceeded(format!(
+                "{count} {} connections established",
+                match endpoint {
+                    Endpoint::Listener => "incoming",
+                    Endpoint::Dialer => "outgoing",
+                }
+            )));
+        }
+        let total = self.connections.len() as u32;
+        if total >= self.limits.max_total {
+            return Err(LimitExceeded(format!("{total} connections established")));
+        }
+        Ok(())
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Event;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        let Some(ip) = ip_from_multiaddr(remote_addr) else {
+            return Ok(());
+        };
+        if !self
+            .handshake_limiter
+            .allows(ip, RateLimitedProtocol::Handshake, Instant::now())
+        {
+            self.events.push_back(Event::HandshakeRateLimited(ip));
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+            return Err(ConnectionDenied::new(LimitExceeded(format!(
+                "IP {ip} exceeded the handshake rate limit"
+            ))));
+        }
+        Ok(())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_established(peer, Endpoint::Listener)
+            .map_err(ConnectionDenied::new)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_established(peer, Endpoint::Dialer)
+            .map_err(ConnectionDenied::new)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished {
+                connection_id,
+                endpoint,
+                ..
+            }) => {
+                let direction = if endpoint.is_dialer() {
+                    Endpoint::Dialer
+                } else {
+                    Endpoint::Listener
+                };
+                self.connections.insert(connection_id, direction);
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. }) => {
+                self.connections.remove(&connection_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(ToSwarm::GenerateEvent(event));
+        }
+        self.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limiter::Quota;
+
+    fn behaviour(protected_peer: PeerId) -> Behaviour {
+        Behaviour::new(
+            EstablishedLimits {
+                max_incoming: 1,
+                max_outgoing: 1,
+                max_total: 2,
+            },
+            HashSet::from([protected_peer]),
+            RateLimiterConfig {
+                handshake_quota: Quota::n_every(2, 60),
+                ..RateLimiterConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn handshakes_are_limited_per_ip() {
+        let mut limits = behaviour(PeerId::random());
+        let address: Multiaddr = "/ip4/1.2.3.4/tcp/9000".parse().unwrap();
+        let other_port: Multiaddr = "/ip4/1.2.3.4/tcp/9001".parse().unwrap();
+        let other_ip: Multiaddr = "/ip4/1.2.3.5/tcp/9000".parse().unwrap();
+        let local: Multiaddr = "/ip4/0.0.0.0/tcp/9000".parse().unwrap();
+        let mut pending = |remote: &Multiaddr| {
+            limits
+                .handle_pending_inbound_connection(ConnectionId::new_unchecked(0), &local, remote)
+                .is_ok()
+        };
+
+        assert!(pending(&address));
+        assert!(pending(&other_port));
+        // The port of the connection does not matter, nor the peer id it will authenticate as.
+        assert!(!pending(&address));
+        assert!(pending(&other_ip));
+    }
+
+    #[test]
+    fn protected_peers_are_exempt_from_the_established_limits() {
+        let protected_peer = PeerId::random();
+        let mut limits = behaviour(protected_peer);
+        limits
+            .connections
+            .insert(ConnectionId::new_unchecked(1), Endpoint::Listener);
+
+        assert!(limits
+            .check_established(PeerId::random(), Endpoint::Listener)
+            .is_err());
+        assert!(limits
+            .check_established(protected_peer, Endpoint::Listener)
+            .is_ok());
+        assert!(limits
+            .check_established(PeerId::random(), Endpoint::Dialer)
+            .is_ok());
+
+        limits
+            .connections
+            .insert(ConnectionId::new_unchecked(2), Endpoint::Dialer);
+        assert!(limits
+            .check_established(PeerId::random(), Endpoint::Dialer)
+            .is_err());
+        assert!(limits
+            .check_established(protected_peer, Endpoint::Dialer)
+            .is_ok());
+    }
+}
@@ -0,0 +1,362 @@
+//! Keeps track of the peers we want to stay connected to regardless of discovery.
+//!
+//! Static peers are dialed on startup and redialed with an exponential backoff whenever the
+//! connection drops or a dial fails. Trusted peers are never disconnected or scored down by the
+//! node, which makes small private clusters and devnets usable without discovery.
+//!
+//! Misbehaving peers are reported with a `PeerAction` lowering their score. The score decays back
+//! towards zero over time, and a peer whose score drops below `BAN_THRESHOLD` is banned for
+//! `BAN_DURATION`.
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// The initial delay before redialing a static peer that disconnected.
+pub const INITIAL_REDIAL_BACKOFF: Duration = Duration::from_secs(5);
+/// The maximum delay between two redial attempts of a static peer.
+pub const MAX_REDIAL_BACKOFF: Duration = Duration::from_secs(300);
+/// The fraction of peers we accept in excess of the target peer count.
+pub const PEER_EXCESS_FACTOR: f32 = 0.1;
+/// The additional fraction of peers we accept for priority connections.
+pub const PRIORITY_PEER_EXCESS: f32 = 0.2;
+/// The fraction of the target peer count kept for outbound-only connections.
+pub const MIN_OUTBOUND_ONLY_FACTOR: f32 = 0.2;
+/// The lowest score a peer can have.
+pub const MIN_SCORE: f64 = -100.0;
+/// Peers whose score drops below this threshold are banned.
+pub const BAN_THRESHOLD: f64 = -50.0;
+/// The time a peer stays banned.
+pub const BAN_DURATION: Duration = Duration::from_secs(30 * 60);
+/// The time after which a score has decayed to half its value.
+const SCORE_HALFLIFE: Duration = Duration::from_secs(600);
+/// Scores closer to zero than this are forgotten.
+const SCORE_EPSILON: f64 = 0.1;
+
+/// The misbehaviour a peer is reported for, from the least to the most tolerated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerAction {
+    /// The peer must be banned immediately.
+    Fatal,
+    /// Misbehaviour tolerated a few times.
+    LowToleranceError,
+    /// Misbehaviour tolerated about ten times.
+    MidToleranceError,
+    /// Misbehaviour tolerated about fifty times.
+    HighToleranceError,
+}
+
+impl PeerAction {
+    fn penalty(&self) -> f64 {
+        match self {
+            PeerAction::Fatal => MIN_SCORE,
+            PeerAction::LowToleranceError => -10.0,
+            PeerAction::MidToleranceError => -5.0,
+            PeerAction::HighToleranceError => -1.0,
+        }
+    }
+}
+
+/// The score of a peer that has been reported.
+#[derive(Debug)]
+struct PeerScore {
+    score: f64,
+    last_updated: Instant,
+    banned_until: Option<Instant>,
+}
+
+impl PeerScore {
+    /// Decays the score towards zero.
+    fn update(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_updated);
+        let halflives = elapsed.as_secs_f64() / SCORE_HALFLIFE.as_secs_f64();
+        self.score *= 0.5f64.powf(halflives);
+        self.last_updated = now;
+        if self.banned_until.is_some_and(|until| until <= now) {
+            self.banned_until = None;
+        }
+    }
+}
+
+/// The dial state of a single static peer.
+#[derive(Debug)]
+struct StaticPeer {
+    /// The addresses we attempt to dial the peer on.
+    addresses: Vec<Multiaddr>,
+    /// Whether we currently hold at least one connection to the peer.
+    connected: bool,
+    /// The delay applied after the next failure.
+    backoff: Duration,
+    /// The earliest instant at which we may dial the peer again. `None` while connected or while
+    /// a dial is in flight.
+    next_dial: Option<Instant>,
+}
+
+/// Tracks static and trusted peers and decides when they need to be (re)dialed.
+#[derive(Debug, Default)]
+pub struct PeerManager {
+    /// Peers that are never disconnected or scored down.
+    trusted_peers: HashSet<PeerId>,
+    /// Peers that are kept connected at all times.
+    static_peers: HashMap<PeerId, StaticPeer>,
+    /// The scores of the peers that have been reported.
+    scores: HashMap<PeerId, PeerScore>,
+}
+
+impl PeerManager {
+    /// Creates a new `PeerManager`. Every static peer address must end with a `/p2p/<peer-id>`
+    /// component so that the connection can be tracked.
+    pub fn new(
+        static_peers: &[Multiaddr],
+        trusted_peers: &[PeerId],
+        now: Instant,
+    ) -> Result<Self, String> {
+        let mut peers: HashMap<PeerId, StaticPeer> = HashMap::new();
+        for address in static_peers {
+            let peer_id = peer_id_from_multiaddr(address)
+                .ok_or_else(|| format!("Static peer address must contain a peer id: {address}"))?;
+            peers
+                .entry(peer_id)
+                .or_insert_with(|| StaticPeer {
+                    addresses: vec![],
+                    connected: false,
+                    backoff: INITIAL_REDIAL_BACKOFF,
+                    next_dial: Some(now),
+                })
+                .addresses
+                .push(address.clone());
+        }
+
+        Ok(Self {
+            trusted_peers: trusted_peers.iter().copied().collect(),
+            static_peers: peers,
+            scores: HashMap::new(),
+        })
+    }
+
+    /// Returns true if the peer is trusted and must not be disconnected or penalised.
+    pub fn is_trusted(&self, peer_id: &PeerId) -> bool {
+        self.trusted_peers.contains(peer_id)
+    }
+
+    /// Returns true if the peer is a static peer.
+    pub fn is_static(&self, peer_id: &PeerId) -> bool {
+        self.static_peers.contains_key(peer_id)
+    }
+
+    /// Returns true if the peer should be exempt from any disconnection logic.
+    pub fn is_protected(&self, peer_id: &PeerId) -> bool {
+        self.is_trusted(peer_id) || self.is_static(peer_id)
+    }
+
+    /// Returns the static peers that are due to be dialed and marks them as being dialed.
+    pub fn peers_to_dial(&mut self, now: Instant) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        self.static_peers
+            .iter_mut()
+            .filter(|(_, peer)| !peer.connected && peer.next_dial.is_some_and(|t| t <= now))
+            .map(|(peer_id, peer)| {
+                peer.next_dial = None;
+                (*peer_id, peer.addresses.clone())
+            })
+            .collect()
+    }
+
+    /// Registers an established connection and resets the backoff of static peers.
+    pub fn on_connection_established(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.static_peers.get_mut(peer_id) {
+            peer.connected = true;
+            peer.next_dial = None;
+            peer.backoff = INITIAL_REDIAL_BACKOFF;
+        }
+    }
+
+    /// Registers that all connections to a peer have been closed. Static peers are scheduled for
+    /// a redial after the current backoff.
+    pub fn on_connection_closed(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(peer) = self.static_peers.get_mut(peer_id) {
+            peer.connected = false;
+            Self::schedule_redial(peer_id, peer, now);
+        }
+    }
+
+    /// Registers a failed dial. Static peers are scheduled for a redial with an increased
+    /// backoff.
+    pub fn on_dial_failure(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(peer) = self.static_peers.get_mut(peer_id) {
+            if !peer.connected {
+                Self::schedule_redial(peer_id, peer, now);
+            }
+        }
+    }
+
+    /// Lowers the score of a peer for a misbehaviour. Returns true if the peer is banned as a
+    /// result and must be disconnected. Trusted peers are never penalised.
+    pub fn report_peer(&mut self, peer_id: &PeerId, action: PeerAction, now: Instant) -> bool {
+        if self.is_trusted(peer_id) {
+            return false;
+        }
+        let score = self.scores.entry(*peer_id).or_insert(PeerScore {
+            score: 0.0,
+            last_updated: now,
+            banned_until: None,
+        });
+        score.update(now);
+        score.score = (score.score + action.penalty()).max(MIN_SCORE);
+        debug!(%peer_id, ?action, score = score.score, "Peer reported");
+
+        if score.banned_until.is_none() && score.score <= BAN_THRESHOLD {
+            debug!(%peer_id, "Banning peer");
+            score.banned_until = Some(now + BAN_DURATION);
+            return true;
+        }
+        false
+    }
+
+    /// Returns true if the peer is banned and must not be connected.
+    pub fn is_banned(&self, peer_id: &PeerId, now: Instant) -> bool {
+        self.scores
+            .get(peer_id)
+            .and_then(|score| score.banned_until)
+            .is_some_and(|until| until > now)
+    }
+
+    /// Decays the scores and forgets the peers that are neither banned nor penalised anymore.
+    pub fn update_scores(&mut self, now: Instant) {
+        self.scores.retain(|_, score| {
+            score.update(now);
+            score.banned_until.is_some() || score.score.abs() > SCORE_EPSILON
+        });
+    }
+
+    fn schedule_redial(peer_id: &PeerId, peer: &mut StaticPeer, now: Instant) {
+        debug!(%peer_id, backoff = ?peer.backoff, "Scheduling static peer redial");
+        peer.next_dial = Some(now + peer.backoff);
+        peer.backoff = (peer.backoff * 2).min(MAX_REDIAL_BACKOFF);
+    }
+}
+
+/// Extracts the peer id from the `/p2p/` component of a multiaddr, if any.
+pub fn peer_id_from_multiaddr(address: &Multiaddr) -> Option<PeerId> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(peer_id) => Some(peer_id),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn static_peer_address(peer_id: PeerId) -> Multiaddr {
+        let mut address: Multiaddr = "/ip4/127.0.0.1/tcp/9100".parse().unwrap();
+        address.push(Protocol::P2p(peer_id));
+        address
+    }
+
+    #[test]
+    fn static_peer_without_peer_id_is_rejected() {
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9100".parse().unwrap();
+        assert!(PeerManager::new(&[address], &[], Instant::now()).is_err());
+    }
+
+    #[test]
+    fn static_peers_are_dialed_once_on_startup() {
+        let now = Instant::now();
+        let peer_id = PeerId::random();
+        let mut manager = PeerManager::new(&[static_peer_address(peer_id)], &[], now).unwrap();
+
+        let to_dial = manager.peers_to_dial(now);
+        assert_eq!(to_dial.len(), 1);
+        assert_eq!(to_dial[0].0, peer_id);
+        // A dial is in flight, we must not dial again.
+        assert!(manager.peers_to_dial(now).is_empty());
+    }
+
+    #[test]
+    fn redial_backoff_doubles_and_resets() {
+        let now = Instant::now();
+        let peer_id = PeerId::random();
+        let mut manager = PeerManager::new(&[static_peer_address(peer_id)], &[], now).unwrap();
+        manager.peers_to_dial(now);
+
+        manager.on_dial_failure(&peer_id, now);
+        assert!(manager.peers_to_dial(now).is_empty());
+        let now = now + INITIAL_REDIAL_BACKOFF;
+        assert_eq!(manager.peers_to_dial(now).len(), 1);
+
+        // The second failure waits twice as long.
+        manager.on_dial_failure(&peer_id, now);
+        assert!(manager
+            .peers_to_dial(now + INITIAL_REDIAL_BACKOFF)
+            .is_empty());
+        let now = now + INITIAL_REDIAL_BACKOFF * 2;
+        assert_eq!(manager.peers_to_dial(now).len(), 1);
+
+        // A successful connection resets the backoff.
+        manager.on_connection_established(&peer_id);
+        manager.on_connection_closed(&peer_id, now);
+        assert_eq!(manager.peers_to_dial(now + INITIAL_REDIAL_BACKOFF).len(), 1);
+    }
+
+    #[test]
+    fn trusted_and_static_peers_are_protected() {
+        let now = Instant::now();
+        let static_peer = PeerId::random();
+        let trusted_peer = PeerId::random();
+        let manager =
+            PeerManager::new(&[static_peer_address(static_peer)], &[trusted_peer], now).unwrap();
+
+        assert!(manager.is_protected(&static_peer));
+        assert!(manager.is_protected(&trusted_peer));
+        assert!(manager.is_trusted(&trusted_peer));
+        assert!(!manager.is_protected(&PeerId::random()));
+    }
+
+    #[test]
+    fn reported_peers_are_banned_until_the_ban_expires() {
+        let now = Instant::now();
+        let peer_id = PeerId::random();
+        let mut manager = PeerManager::new(&[], &[], now).unwrap();
+
+        for _ in 0..4 {
+            assert!(!manager.report_peer(&peer_id, PeerAction::LowToleranceError, now));
+        }
+        assert!(manager.report_peer(&peer_id, PeerAction::LowToleranceError, now));
+        assert!(manager.is_banned(&peer_id, now));
+        // A banned peer is only banned once.
+        assert!(!manager.report_peer(&peer_id, PeerAction::LowToleranceError, now));
+
+        let now = now + BAN_DURATION;
+        manager.update_scores(now);
+        assert!(!manager.is_banned(&peer_id, now));
+    }
+
+    #[test]
+    fn scores_decay() {
+        let now = Instant::now();
+        let peer_id = PeerId::random();
+        let mut manager = PeerManager::new(&[], &[], now).unwrap();
+
+        for _ in 0..4 {
+            manager.report_peer(&peer_id, PeerAction::LowToleranceError, now);
+        }
+        // After one halflife, the penalties so far only count half.
+        let now = now + SCORE_HALFLIFE;
+        assert!(!manager.report_peer(&peer_id, PeerAction::LowToleranceError, now));
+
+        manager.update_scores(now + SCORE_HALFLIFE * 20);
+        assert!(manager.scores.is_empty());
+    }
+
+    #[test]
+    fn trusted_peers_are_not_penalised() {
+        let now = Instant::now();
+        let trusted_peer = PeerId::random();
+        let mut manager = PeerManager::new(&[], &[trusted_peer], now).unwrap();
+
+        assert!(!manager.report_peer(&trusted_peer, PeerAction::Fatal, now));
+        assert!(!manager.is_banned(&trusted_peer, now));
+    }
+}
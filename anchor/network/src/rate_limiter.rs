@@ -0,0 +1,223 @@
+//! Limits the rate at which a peer may use our resources.
+//!
+//! Every peer, or remote IP for the handshakes, gets a token bucket per rate limited protocol.
+//! Each request takes a token from the bucket and the bucket is refilled continuously, so that a
+//! peer can send a burst of `max_tokens` requests and then one request every
+//! `replenish_all_every / max_tokens`.
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The protocols whose use is rate limited per peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitedProtocol {
+    /// Establishing an inbound connection, i.e. the transport handshake followed by identify.
+    /// It is limited per remote IP, as the peer id is only known after the handshake.
+    Handshake,
+    /// Requesting decided messages with the history sync protocol.
+    DecidedHistory,
+}
+
+impl RateLimitedProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitedProtocol::Handshake => "handshake",
+            RateLimitedProtocol::DecidedHistory => "decided_history",
+        }
+    }
+}
+
+/// The number of requests a peer may send within a period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    /// The number of requests a peer may send at once.
+    pub max_tokens: u32,
+    /// The time it takes for an empty bucket to be full again.
+    pub replenish_all_every: Duration,
+}
+
+impl Quota {
+    pub const fn n_every(max_tokens: u32, seconds: u64) -> Self {
+        Self {
+            max_tokens,
+            replenish_all_every: Duration::from_secs(seconds),
+        }
+    }
+
+    /// The number of tokens refilled during `elapsed`.
+    fn tokens_refilled(&self, elapsed: Duration) -> f64 {
+        if self.replenish_all_every.is_zero() {
+            return f64::from(self.max_tokens);
+        }
+        elapsed.as_secs_f64() / self.replenish_all_every.as_secs_f64() * f64::from(self.max_tokens)
+    }
+}
+
+/// Parses a quota written as `TOKENS/SECONDS`, e.g. `5/60`.
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (max_tokens, seconds) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid quota {s}, expected TOKENS/SECONDS"))?;
+        let max_tokens = max_tokens
+            .parse()
+            .map_err(|e| format!("Invalid number of tokens in quota {s}: {e}"))?;
+        let seconds = seconds
+            .parse()
+            .map_err(|e| format!("Invalid period in quota {s}: {e}"))?;
+        if max_tokens == 0 {
+            return Err(format!("Invalid quota {s}, at least one token is required"));
+        }
+        Ok(Self::n_every(max_tokens, seconds))
+    }
+}
+
+/// The quota of each rate limited protocol.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimiterConfig {
+    pub handshake_quota: Quota,
+    pub decided_history_quota: Quota,
+}
+
+impl RateLimiterConfig {
+    pub const DEFAULT_HANDSHAKE_QUOTA: Quota = Quota::n_every(5, 60);
+    pub const DEFAULT_DECIDED_HISTORY_QUOTA: Quota = Quota::n_every(10, 60);
+
+    fn quota(&self, protocol: RateLimitedProtocol) -> &Quota {
+        match protocol {
+            RateLimitedProtocol::Handshake => &self.handshake_quota,
+            RateLimitedProtocol::DecidedHistory => &self.decided_history_quota,
+        }
+    }
+}
+
+impl Default for RateLimiterConfig {
+    fn default() -> Self {
+        Self {
+            handshake_quota: Self::DEFAULT_HANDSHAKE_QUOTA,
+            decided_history_quota: Self::DEFAULT_DECIDED_HISTORY_QUOTA,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Keeps a token bucket per key, a peer id by default, and protocol.
+#[derive(Debug)]
+pub struct RateLimiter<K = PeerId> {
+    config: RateLimiterConfig,
+    buckets: HashMap<(K, RateLimitedProtocol), TokenBucket>,
+}
+
+impl<K: Copy + Eq + Hash> RateLimiter<K> {
+    pub fn new(config: RateLimiterConfig) -> Self {
+        Self {
+            config,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from the bucket of the key for the protocol. Returns false if the bucket is
+    /// empty, i.e. the key exceeded its quota.
+    pub fn allows(&mut self, key: K, protocol: RateLimitedProtocol, now: Instant) -> bool {
+        let quota = self.config.quota(protocol);
+        let max_tokens = f64::from(quota.max_tokens);
+        let bucket = self.buckets.entry((key, protocol)).or_insert(TokenBucket {
+            tokens: max_tokens,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + quota.tokens_refilled(elapsed)).min(max_tokens);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops the buckets that are full again, as they are equivalent to new ones.
+    pub fn prune(&mut self, now: Instant) {
+        let config = &self.config;
+        self.buckets.retain(|(_, protocol), bucket| {
+            let quota = config.quota(*protocol);
+            let elapsed = now.saturating_duration_since(bucket.last_refill);
+            bucket.tokens + quota.tokens_refilled(elapsed) < f64::from(quota.max_tokens)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(quota: Quota) -> RateLimiter {
+        RateLimiter::new(RateLimiterConfig {
+            handshake_quota: quota,
+            ..RateLimiterConfig::default()
+        })
+    }
+
+    #[test]
+    fn bucket_is_refilled_over_time() {
+        let mut limiter = limiter(Quota::n_every(2, 10));
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        assert!(limiter.allows(peer_id, RateLimitedProtocol::Handshake, now));
+        assert!(limiter.allows(peer_id, RateLimitedProtocol::Handshake, now));
+        assert!(!limiter.allows(peer_id, RateLimitedProtocol::Handshake, now));
+
+        // A token is refilled every 5 seconds.
+        let now = now + Duration::from_secs(4);
+        assert!(!limiter.allows(peer_id, RateLimitedProtocol::Handshake, now));
+        let now = now + Duration::from_secs(2);
+        assert!(limiter.allows(peer_id, RateLimitedProtocol::Handshake, now));
+        assert!(!limiter.allows(peer_id, RateLimitedProtocol::Handshake, now));
+    }
+
+    #[test]
+    fn peers_have_separate_buckets() {
+        let mut limiter = limiter(Quota::n_every(1, 10));
+        let now = Instant::now();
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+
+        assert!(limiter.allows(peer_a, RateLimitedProtocol::Handshake, now));
+        assert!(!limiter.allows(peer_a, RateLimitedProtocol::Handshake, now));
+        assert!(limiter.allows(peer_b, RateLimitedProtocol::Handshake, now));
+    }
+
+    #[test]
+    fn quotas_are_parsed() {
+        assert_eq!("5/60".parse(), Ok(Quota::n_every(5, 60)));
+        assert!("5".parse::<Quota>().is_err());
+        assert!("0/60".parse::<Quota>().is_err());
+        assert!("5/a".parse::<Quota>().is_err());
+    }
+
+    #[test]
+    fn full_buckets_are_pruned() {
+        let mut limiter = limiter(Quota::n_every(2, 10));
+        let now = Instant::now();
+        let peer_id = PeerId::random();
+        limiter.allows(peer_id, RateLimitedProtocol::Handshake, now);
+
+        limiter.prune(now + Duration::from_secs(4));
+        assert_eq!(limiter.buckets.len(), 1);
+        limiter.prune(now + Duration::from_secs(5));
+        assert!(limiter.buckets.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! The gossipsub subnets the SSV messages are published on.
+//!
+//! The messages of a committee are published on one of the `SUBNET_COUNT` subnets, derived from
+//! the committee id. A node subscribes to the subnets of the committees its validators are part
+//! of, or to all subnets if configured to do so. The topic names depend on the active fork, see
+//! `fork`.
+
+use libp2p::gossipsub::IdentTopic;
+use ssv_types::CommitteeId;
+use std::collections::BTreeSet;
+
+/// The number of subnets the SSV network is split into.
+pub const SUBNET_COUNT: u64 = 128;
+
+/// The index of a subnet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubnetId(pub u64);
+
+impl SubnetId {
+    /// The subnet of a committee: the committee id as a big endian integer modulo the number of
+    /// subnets.
+    pub fn from_committee(committee_id: &CommitteeId) -> Self {
+        // The subnet count divides 256, so only the last byte matters.
+        Self(u64::from(committee_id.0[31]) % SUBNET_COUNT)
+    }
+
+    /// The topic of the subnet, named with the topic prefix of a fork.
+    pub fn topic(&self, prefix: &str) -> IdentTopic {
+        IdentTopic::new(format!("{prefix}{}", self.0))
+    }
+}
+
+/// The subnets to subscribe to and unsubscribe from after the committees changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SubscriptionChanges {
+    pub subscribe: Vec<SubnetId>,
+    pub unsubscribe: Vec<SubnetId>,
+}
+
+/// Tracks the subnets the node is subscribed to.
+#[derive(Debug)]
+pub struct SubnetSubscriptions {
+    subscribe_all: bool,
+    active: BTreeSet<SubnetId>,
+}
+
+impl SubnetSubscriptions {
+    pub fn new(subscribe_all: bool) -> Self {
+        Self {
+            subscribe_all,
+            active: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the subnets to subscribe to on startup, i.e. all subnets if subscribing to all.
+    pub fn initial(&mut self) -> SubscriptionChanges {
+        if self.subscribe_all {
+            self.diff((0..SUBNET_COUNT).map(SubnetId).collect())
+        } else {
+            SubscriptionChanges::default()
+        }
+    }
+
+    /// Updates the subscriptions to the subnets of the given committees, returning the changes to
+    /// apply.
+    pub fn update<'a>(
+        &mut self,
+        committees: impl IntoIterator<Item = &'a CommitteeId>,
+    ) -> SubscriptionChanges {
+        if self.subscribe_all {
+            return SubscriptionChanges::default();
+        }
+        let subnets = committees
+            .into_iter()
+            .map(SubnetId::from_committee)
+            .collect();
+        self.diff(subnets)
+    }
+
+    /// The subnets the node is subscribed to.
+    pub fn active(&self) -> impl Iterator<Item = SubnetId> + '_ {
+        self.active.iter().copied()
+    }
+
+    /// Whether the node is subscribed to the subnet.
+    pub fn is_subscribed(&self, subnet: &SubnetId) -> bool {
+        self.active.contains(subnet)
+    }
+
+    fn diff(&mut self, subnets: BTreeSet<SubnetId>) -> SubscriptionChanges {
+        let changes = SubscriptionChanges {
+            subscribe: subnets.difference(&self.active).copied().collect(),
+            unsubscribe: self.active.difference(&subnets).copied().collect(),
+        };
+        self.active = subnets;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn committee(last_byte: u8) -> CommitteeId {
+        let mut id = [0xab; 32];
+        id[31] = last_byte;
+        CommitteeId(id)
+    }
+
+    #[test]
+    fn subnet_of_committee() {
+        assert_eq!(SubnetId::from_committee(&committee(5)), SubnetId(5));
+        assert_eq!(SubnetId::from_committee(&committee(133)), SubnetId(5));
+        assert_eq!(SubnetId::from_committee(&committee(255)), SubnetId(127));
+        assert_eq!(SubnetId(5).topic("ssv.v2.").to_string(), "ssv.v2.5");
+    }
+
+    #[test]
+    fn subscriptions_follow_committees() {
+        let mut subscriptions = SubnetSubscriptions::new(false);
+        assert_eq!(subscriptions.initial(), SubscriptionChanges::default());
+
+        let changes = subscriptions.update(&[committee(1), committee(2), committee(129)]);
+        assert_eq!(changes.subscribe, vec![SubnetId(1), SubnetId(2)]);
+        assert!(changes.unsubscribe.is_empty());
+
+        let changes = subscriptions.update(&[committee(2), committee(3)]);
+        assert_eq!(changes.subscribe, vec![SubnetId(3)]);
+        assert_eq!(changes.unsubscribe, vec![SubnetId(1)]);
+        assert!(subscriptions.is_subscribed(&SubnetId(2)));
+        assert!(!subscriptions.is_subscribed(&SubnetId(1)));
+    }
+
+    #[test]
+    fn subscribe_all_subnets() {
+        let mut subscriptions = SubnetSubscriptions::new(true);
+        assert_eq!(
+            subscriptions.initial().subscribe.len(),
+            SUBNET_COUNT as usize
+        );
+        assert_eq!(
+            subscriptions.update(&[committee(1)]),
+            SubscriptionChanges::default()
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! A harness running several `Network` instances on localhost, to test the network end-to-end.
+
+use crate::connection_gater::GaterRule;
+use crate::history_sync::DecidedHistoryRequest;
+use crate::message_validator::SLOTS_PER_EPOCH;
+use crate::network::{Network, NetworkCommand};
+use crate::network_info::NetworkInfo;
+use crate::subnets::SubnetId;
+use crate::{Config, ValidationContext};
+use discv5::Enr;
+use libp2p::{Multiaddr, PeerId};
+use lighthouse_network::{ListenAddr, ListenAddress};
+use operator_key::{OperatorKey, OperatorPublicKey};
+use processor::Processor;
+use slot_clock::{ManualSlotClock, Slot};
+use ssv_types::{
+    CommitteeId, MessageId, MsgType, OperatorId, QbftMessage, QbftMessageType, Role, SSVMessage,
+    SignedSSVMessage,
+};
+use ssz::Encode;
+use std::future::Future;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use task_executor::TaskExecutor;
+use tempfile::TempDir;
+use tokio::sync::{mpsc, oneshot};
+
+/// The maximum time to wait for the nodes to reach an expected state.
+const TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The slot the test messages are sent at.
+pub const TEST_SLOT: u64 = 100;
+/// The committee all test nodes are part of.
+pub const TEST_COMMITTEE: CommitteeId = CommitteeId([7; 32]);
+/// The key all test operators sign their messages with. Generating RSA keys is slow, so the
+/// operators share it.
+pub static TEST_OPERATOR_KEY: LazyLock<OperatorKey> =
+    LazyLock::new(|| OperatorKey::generate().unwrap());
+
+/// A context in which every message is for `TEST_COMMITTEE` at `TEST_SLOT`.
+pub struct TestContext;
+
+impl ValidationContext for TestContext {
+    fn current_slot(&self) -> Option<u64> {
+        Some(TEST_SLOT)
+    }
+
+    fn committee(&self, _message_id: &MessageId) -> Option<Vec<OperatorId>> {
+        Some((1..=4).map(OperatorId).collect())
+    }
+
+    fn operator_public_key(&self, _operator_id: OperatorId) -> Option<Arc<OperatorPublicKey>> {
+        Some(Arc::new(OperatorPublicKey::from(&*TEST_OPERATOR_KEY)))
+    }
+}
+
+/// Starts a processor to validate the gossip messages of a node.
+pub fn spawn_processor() -> processor::Sender {
+    let slot_clock = ManualSlotClock::new(Slot::new(0), Duration::ZERO, Duration::from_secs(12));
+    let (sender, processor) = Processor::new(processor::Config::default(), slot_clock, None);
+    tokio::spawn(processor.run());
+    sender
+}
+
+/// Listens on a random localhost port over IPv4.
+pub fn localhost_v4() -> ListenAddress {
+    ListenAddress::V4(ListenAddr {
+        addr: Ipv4Addr::LOCALHOST,
+        disc_port: 0,
+        quic_port: 0,
+        tcp_port: 0,
+    })
+}
+
+/// Listens on a random localhost port over IPv6.
+pub fn localhost_v6() -> ListenAddress {
+    ListenAddress::V6(ListenAddr {
+        addr: Ipv6Addr::LOCALHOST,
+        disc_port: 0,
+        quic_port: 0,
+        tcp_port: 0,
+    })
+}
+
+/// Listens on random localhost ports over IPv4 and IPv6.
+pub fn localhost_dual_stack() -> ListenAddress {
+    match (localhost_v4(), localhost_v6()) {
+        (ListenAddress::V4(v4), ListenAddress::V6(v6)) => ListenAddress::DualStack(v4, v6),
+        _ => unreachable!(),
+    }
+}
+
+/// A configuration for a test node listening on `listen_addresses`, storing its key in
+/// `network_dir`.
+pub fn test_config(listen_addresses: ListenAddress, network_dir: &TempDir) -> Config {
+    Config {
+        network_dir: network_dir.path().to_path_buf(),
+        listen_addresses,
+        disable_quic_support: true,
+        upnp_enabled: false,
+        ..Config::default()
+    }
+}
+
+/// A running node.
+pub struct TestNode {
+    pub peer_id: PeerId,
+    pub enr: Enr,
+    pub info: Arc<NetworkInfo>,
+    commands: mpsc::Sender<NetworkCommand>,
+    messages: mpsc::Receiver<SignedSSVMessage>,
+    _network_dir: TempDir,
+}
+
+impl TestNode {
+    /// Starts a node with the given configuration, see `test_config`.
+    pub async fn spawn(executor: TaskExecutor, config: Config, network_dir: TempDir) -> Self {
+        let (network, messages) = Network::try_new(
+            &config,
+            Arc::new(TestContext),
+            spawn_processor(),
+            None,
+            executor,
+        )
+        .await
+        .unwrap();
+        let peer_id = network.local_peer_id();
+        let enr = network.local_enr();
+        let info = network.info();
+        let commands = network.command_sender();
+        tokio::spawn(network.run());
+
+        Self {
+            peer_id,
+            enr,
+            info,
+            commands,
+            messages,
+            _network_dir: network_dir,
+        }
+    }
+
+    pub async fn command(&self, command: NetworkCommand) {
+        self.commands.send(command).await.unwrap();
+    }
+
+    async fn query<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> NetworkCommand) -> T {
+        let (reply, response) = oneshot::channel();
+        self.command(command(reply)).await;
+        response.await.unwrap()
+    }
+
+    /// Waits until the node listens on an address and returns it.
+    pub async fn listen_address(&self) -> Multiaddr {
+        self.listen_address_matching(|_| true).await
+    }
+
+    /// Waits until the node listens on an address matching `filter` and returns it.
+    pub async fn listen_address_matching(&self, filter: impl Fn(&Multiaddr) -> bool) -> Multiaddr {
+        let filter = &filter;
+        wait_until(|| async move {
+            self.query(NetworkCommand::ListenAddresses)
+                .await
+                .into_iter()
+                .find(filter)
+        })
+        .await
+    }
+
+    pub async fn connected_peers(&self) -> Vec<PeerId> {
+        self.query(NetworkCommand::ConnectedPeers).await
+    }
+
+    pub async fn subnet_peers(&self, subnet: SubnetId) -> Vec<PeerId> {
+        self.query(|reply| NetworkCommand::SubnetPeers(subnet, reply))
+            .await
+    }
+
+    pub async fn add_gater_rule(&self, rule: GaterRule) -> Result<(), String> {
+        self.query(|reply| NetworkCommand::AddGaterRule(rule, reply))
+            .await
+    }
+
+    pub async fn remove_gater_rule(&self, rule: GaterRule) -> Result<bool, String> {
+        self.query(|reply| NetworkCommand::RemoveGaterRule(rule, reply))
+            .await
+    }
+
+    /// Requests the decided messages of `TEST_COMMITTEE` for the heights from a peer.
+    pub async fn decided_history(
+        &self,
+        peer_id: PeerId,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<SignedSSVMessage>, String> {
+        let request = DecidedHistoryRequest {
+            msg_id: test_msg_id(),
+            from_height,
+            to_height,
+        };
+        self.query(|reply| NetworkCommand::RequestDecidedHistory {
+            peer_id,
+            request,
+            reply,
+        })
+        .await
+    }
+
+    /// Waits for the next message received by the node.
+    pub async fn receive(&mut self) -> SignedSSVMessage {
+        tokio::time::timeout(TIMEOUT, self.messages.recv())
+            .await
+            .expect("No message received in time")
+            .unwrap()
+    }
+
+    /// Returns true if no message is received for a while.
+    pub async fn receives_nothing(&mut self) -> bool {
+        tokio::time::timeout(Duration::from_secs(1), self.messages.recv())
+            .await
+            .is_err()
+    }
+}
+
+/// Several nodes on localhost.
+pub struct TestNetwork {
+    pub nodes: Vec<TestNode>,
+    executor: TaskExecutor,
+    _exit_signal: async_channel::Sender<()>,
+}
+
+impl TestNetwork {
+    /// Starts `node_count` nodes listening over IPv4.
+    pub async fn new(node_count: usize) -> Self {
+        let mut network = Self::empty();
+        for _ in 0..node_count {
+            network.add_node(localhost_v4()).await;
+        }
+        network
+    }
+
+    /// A network without nodes, see `add_node` and `add_node_with_config`.
+    pub fn empty() -> Self {
+        let (exit_signal, exit) = async_channel::bounded(1);
+        let (shutdown_tx, _) = futures::channel::mpsc::channel(1);
+        let executor = TaskExecutor::new(tokio::runtime::Handle::current(), exit, shutdown_tx);
+        Self {
+            nodes: vec![],
+            executor,
+            _exit_signal: exit_signal,
+        }
+    }
+
+    /// Starts a node listening on `listen_addresses`, returning its index.
+    pub async fn add_node(&mut self, listen_addresses: ListenAddress) -> usize {
+        self.add_node_with_config(|network_dir| test_config(listen_addresses, network_dir))
+            .await
+    }
+
+    /// Starts a node with the configuration built from its network directory, returning its
+    /// index.
+    pub async fn add_node_with_config(&mut self, config: impl FnOnce(&TempDir) -> Config) -> usize {
+        let network_dir = TempDir::new().unwrap();
+        let config = config(&network_dir);
+        let node = TestNode::spawn(self.executor.clone(), config, network_dir).await;
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Connects every node to all other nodes.
+    pub async fn connect_all(&self) {
+        for from in 0..self.nodes.len() {
+            for to in 0..from {
+                self.connect(from, to).await;
+            }
+        }
+    }
+
+    /// Makes node `from` dial node `to` and waits until they are connected.
+    pub async fn connect(&self, from: usize, to: usize) {
+        let address = self.nodes[to].listen_address().await;
+        self.dial(from, to, address).await;
+    }
+
+    /// Makes node `from` dial node `to` on `address` and waits until they are connected.
+    pub async fn dial(&self, from: usize, to: usize, address: Multiaddr) {
+        self.nodes[from]
+            .command(NetworkCommand::Dial(address))
+            .await;
+        self.wait_until_connected(from, to).await;
+    }
+
+    /// Waits until node `from` is connected to node `to`.
+    pub async fn wait_until_connected(&self, from: usize, to: usize) {
+        let peer_id = self.nodes[to].peer_id;
+        wait_until(|| async move {
+            self.nodes[from]
+                .connected_peers()
+                .await
+                .contains(&peer_id)
+                .then_some(())
+        })
+        .await
+    }
+
+    /// Subscribes the nodes to the subnet of `TEST_COMMITTEE`, or unsubscribes them from all
+    /// subnets.
+    pub async fn set_subscribed(&self, nodes: &[usize], subscribed: bool) {
+        let committees = if subscribed {
+            vec![TEST_COMMITTEE]
+        } else {
+            vec![]
+        };
+        for &node in nodes {
+            self.nodes[node]
+                .command(NetworkCommand::UpdateCommittees(committees.clone()))
+                .await;
+        }
+    }
+
+    /// Waits until `node` knows exactly `peers` to be subscribed to the test subnet.
+    pub async fn wait_for_subnet_peers(&self, node: usize, peers: &[usize]) {
+        let mut expected: Vec<_> = peers.iter().map(|&peer| self.nodes[peer].peer_id).collect();
+        expected.sort();
+        let expected = &expected;
+        wait_until(|| async move {
+            let mut subnet_peers = self.nodes[node].subnet_peers(test_subnet()).await;
+            subnet_peers.sort();
+            (subnet_peers == *expected).then_some(())
+        })
+        .await
+    }
+
+    pub async fn publish(&self, node: usize, message: SignedSSVMessage) {
+        self.nodes[node]
+            .command(NetworkCommand::Publish {
+                subnet: test_subnet(),
+                message,
+            })
+            .await
+    }
+}
+
+pub fn test_subnet() -> SubnetId {
+    SubnetId::from_committee(&TEST_COMMITTEE)
+}
+
+/// A valid prepare message of `signer` for `TEST_COMMITTEE`. Messages of different rounds are
+/// distinct messages.
+pub fn test_message(signer: u64, round: u64) -> SignedSSVMessage {
+    consensus_message(QbftMessageType::Prepare, TEST_SLOT, round, &[signer])
+}
+
+/// A valid decided message of `TEST_COMMITTEE` for the height, signed by a quorum.
+pub fn test_decided_message(height: u64) -> SignedSSVMessage {
+    consensus_message(QbftMessageType::Commit, height, 1, &[1, 2, 3])
+}
+
+fn test_msg_id() -> MessageId {
+    Config::default()
+        .fork_schedule
+        .fork_at(TEST_SLOT / SLOTS_PER_EPOCH)
+        .message_id(Role::Committee, &TEST_COMMITTEE.0)
+}
+
+fn consensus_message(
+    qbft_message_type: QbftMessageType,
+    height: u64,
+    round: u64,
+    signers: &[u64],
+) -> SignedSSVMessage {
+    let msg_id = test_msg_id();
+    let qbft_message = QbftMessage {
+        qbft_message_type: qbft_message_type as u64,
+        height,
+        round,
+        identifier: msg_id.as_bytes().to_vec(),
+        root: [1; 32],
+        data_round: 0,
+        round_change_justification: vec![],
+        prepare_justification: vec![],
+    };
+    let ssv_message = SSVMessage {
+        msg_type: MsgType::Consensus as u64,
+        msg_id,
+        data: qbft_message.as_ssz_bytes(),
+    };
+    let signature = TEST_OPERATOR_KEY.sign(&ssv_message.as_ssz_bytes()).unwrap();
+    SignedSSVMessage {
+        signatures: vec![signature; signers.len()],
+        operator_ids: signers.iter().copied().map(OperatorId).collect(),
+        ssv_message,
+        full_data: vec![],
+    }
+}
+
+/// Polls `condition` until it returns a value, panicking after `TIMEOUT`.
+pub async fn wait_until<T, F, Fut>(mut condition: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Some(value) = condition().await {
+                return value;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .expect("Timed out waiting for the nodes")
+}
@@ -1,16 +1,29 @@
 use futures::future::Either;
 use libp2p::core::muxing::StreamMuxerBox;
 use libp2p::core::transport::Boxed;
+use libp2p::dns::{ResolverConfig, ResolverOpts};
 use libp2p::identity::Keypair;
-use libp2p::{noise, quic, tcp, yamux, PeerId, Transport};
+use libp2p::{dns, noise, quic, tcp, yamux, PeerId, Transport};
 use std::time::Duration;
+use tracing::warn;
 
 /// The implementation supports TCP/IP, QUIC over UDP, noise as the encryption layer, and
-/// yamux as the multiplexing layer (when using TCP).
+/// yamux as the multiplexing layer (when using TCP). DNS multiaddrs are resolved using the
+/// system's resolver configuration, falling back to the default resolvers if it can't be read.
 pub(crate) fn build_transport(
     local_private_key: Keypair,
     quic_support: bool,
-) -> Boxed<(PeerId, StreamMuxerBox)> {
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, String> {
+    build_transport_with_resolver(local_private_key, quic_support, None)
+}
+
+/// Builds the transport, resolving DNS multiaddrs with the given resolver configuration or the
+/// system's configuration if `None`.
+pub(crate) fn build_transport_with_resolver(
+    local_private_key: Keypair,
+    quic_support: bool,
+    resolver: Option<(ResolverConfig, ResolverOpts)>,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, String> {
     let yamux_config = yamux::Config::default();
 
     let tcp = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
@@ -19,7 +32,7 @@ pub(crate) fn build_transport(
         .multiplex(yamux_config)
         .timeout(Duration::from_secs(10));
 
-    if quic_support {
+    let transport = if quic_support {
         let quic_config = quic::Config::new(&local_private_key);
         let quic = quic::tokio::Transport::new(quic_config);
         let transport = tcp
@@ -31,11 +44,142 @@ pub(crate) fn build_transport(
         transport.boxed()
     } else {
         tcp.boxed()
-    }
-    // TODO: Enable DNS over the transport
+    };
+
+    // Enables DNS over the transport.
+    let transport = match resolver {
+        Some((config, opts)) => dns::tokio::Transport::custom(transport, config, opts),
+        None => match hickory_resolver::system_conf::read_system_conf() {
+            Ok((config, opts)) => dns::tokio::Transport::custom(transport, config, opts),
+            Err(e) => {
+                warn!(error = %e, "Unable to read the system DNS configuration, using the default resolvers");
+                dns::tokio::Transport::custom(
+                    transport,
+                    ResolverConfig::default(),
+                    ResolverOpts::default(),
+                )
+            }
+        },
+    };
+
+    Ok(transport.boxed())
 }
 
 /// Generate authenticated XX Noise config from identity keys
 fn generate_noise_config(identity_keypair: &Keypair) -> noise::Config {
     noise::Config::new(identity_keypair).expect("signing can fail only once during starting a node")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use hickory_resolver::config::{NameServerConfig, Protocol as DnsProtocol};
+    use libp2p::core::transport::{DialOpts, ListenerId, PortUse, TransportEvent};
+    use libp2p::core::Endpoint;
+    use libp2p::multiaddr::Protocol;
+    use libp2p::Multiaddr;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use tokio::net::UdpSocket;
+
+    const TEST_DOMAIN: &str = "boot.anchor.test";
+
+    /// A stand-in DNS server that answers every A query with `address`.
+    async fn serve_dns(socket: UdpSocket, address: Ipv4Addr) {
+        let mut buf = [0u8; 512];
+        while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+            let query = &buf[..len];
+            if query.len() < 12 {
+                continue;
+            }
+
+            // Find the end of the question: the name labels followed by the type and class.
+            let mut end = 12;
+            while end < query.len() && query[end] != 0 {
+                end += query[end] as usize + 1;
+            }
+            let question_end = end + 5;
+            if question_end > query.len() {
+                continue;
+            }
+            let is_a_query = query[end + 1..end + 3] == [0, 1];
+
+            let mut response = Vec::with_capacity(question_end + 16);
+            // Copy the id, then flag the message as a response with recursion available.
+            response.extend_from_slice(&query[..2]);
+            response.extend_from_slice(&[0x81, 0x80]);
+            // One question, one or zero answers, no authority or additional records.
+            response.extend_from_slice(&[0, 1, 0, is_a_query as u8, 0, 0, 0, 0]);
+            response.extend_from_slice(&query[12..question_end]);
+            if is_a_query {
+                // A pointer to the question name, type A, class IN, a TTL of 60s and the address.
+                response.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+                response.extend_from_slice(&address.octets());
+            }
+            let _ = socket.send_to(&response, from).await;
+        }
+    }
+
+    fn local_resolver(name_server: SocketAddr) -> (ResolverConfig, ResolverOpts) {
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig::new(name_server, DnsProtocol::Udp));
+        let mut opts = ResolverOpts::default();
+        opts.use_hosts_file = false;
+        (config, opts)
+    }
+
+    #[tokio::test]
+    async fn dial_dns_multiaddr() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let resolver = local_resolver(socket.local_addr().unwrap());
+        tokio::spawn(serve_dns(socket, Ipv4Addr::LOCALHOST));
+
+        let mut listener = build_transport_with_resolver(
+            Keypair::generate_secp256k1(),
+            false,
+            Some(resolver.clone()),
+        )
+        .unwrap();
+        listener
+            .listen_on(ListenerId::next(), "/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let port = match listener.select_next_some().await {
+            TransportEvent::NewAddress { listen_addr, .. } => listen_addr
+                .iter()
+                .find_map(|protocol| match protocol {
+                    Protocol::Tcp(port) => Some(port),
+                    _ => None,
+                })
+                .unwrap(),
+            event => panic!("Unexpected transport event: {event:?}"),
+        };
+
+        let dialer_keypair = Keypair::generate_secp256k1();
+        let dialer_peer_id = dialer_keypair.public().to_peer_id();
+        let mut dialer =
+            build_transport_with_resolver(dialer_keypair, false, Some(resolver)).unwrap();
+        let address: Multiaddr = format!("/dns4/{TEST_DOMAIN}/tcp/{port}").parse().unwrap();
+        let dial = dialer
+            .dial(
+                address,
+                DialOpts {
+                    role: Endpoint::Dialer,
+                    port_use: PortUse::New,
+                },
+            )
+            .unwrap();
+
+        let accept = async {
+            loop {
+                if let TransportEvent::Incoming { upgrade, .. } = listener.select_next_some().await
+                {
+                    return upgrade.await;
+                }
+            }
+        };
+
+        let (dialed, accepted) = futures::join!(dial, accept);
+        assert!(dialed.is_ok());
+        assert_eq!(accepted.unwrap().0, dialer_peer_id);
+    }
+}
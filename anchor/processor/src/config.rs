@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::thread;
+
+/// The default maximum number of items waiting in each queue.
+pub const DEFAULT_QUEUE_SIZE: usize = 16_384;
+
+/// Configuration of the processor.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// The maximum number of items waiting in each queue. Once a queue is full, its oldest item is
+    /// dropped for each new one.
+    pub queue_size: usize,
+    /// The maximum number of items processed at once on the blocking thread pool.
+    pub max_workers: usize,
+    /// The number of slots an item may be late before it is dropped as stale, which tolerates
+    /// the messages of a duty sent around the end of its slot.
+    pub stale_slots: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            queue_size: DEFAULT_QUEUE_SIZE,
+            max_workers: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            stale_slots: 1,
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! The processor runs the CPU intensive work of the client, such as verifying the signatures of
+//! the messages of other operators and validating them, on a blocking thread pool.
+//!
+//! Work waits in a bounded queue per kind, and the queues are served in priority order so that
+//! the messages completing a consensus instance are handled before the ones progressing it, and
+//! the messages already validated before new gossip messages. Work for a slot that has passed is
+//! dropped, as its duty can no longer be performed.
+
+mod config;
+mod metrics;
+
+pub use config::{Config, DEFAULT_QUEUE_SIZE};
+pub use metrics::{DropReason, ProcessorMetrics};
+
+use prometheus_client::registry::Registry;
+use slot_clock::SlotClock;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{debug, trace};
+
+/// The kinds of work, from the highest priority to the lowest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WorkKind {
+    /// Messages aggregating the commits of a quorum.
+    Decided,
+    Commit,
+    Prepare,
+    Proposal,
+    RoundChange,
+    PartialSignature,
+    /// Messages received over gossip, to validate before they are propagated.
+    GossipMessage,
+}
+
+impl WorkKind {
+    /// All the kinds, in priority order.
+    pub const ALL: [Self; 7] = [
+        Self::Decided,
+        Self::Commit,
+        Self::Prepare,
+        Self::Proposal,
+        Self::RoundChange,
+        Self::PartialSignature,
+        Self::GossipMessage,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Decided => "decided",
+            Self::Commit => "commit",
+            Self::Prepare => "prepare",
+            Self::Proposal => "proposal",
+            Self::RoundChange => "round_change",
+            Self::PartialSignature => "partial_signature",
+            Self::GossipMessage => "gossip_message",
+        }
+    }
+}
+
+/// A task for the slot of a duty.
+pub struct Work {
+    pub kind: WorkKind,
+    pub slot: u64,
+    pub task: Box<dyn FnOnce() + Send>,
+}
+
+impl Work {
+    pub fn new(kind: WorkKind, slot: u64, task: impl FnOnce() + Send + 'static) -> Self {
+        Self {
+            kind,
+            slot,
+            task: Box::new(task),
+        }
+    }
+}
+
+/// Queues work in the processor.
+#[derive(Clone)]
+pub struct Sender(mpsc::Sender<Work>);
+
+impl Sender {
+    /// Queues work without waiting, failing if the processor is overloaded or stopped.
+    pub fn send(&self, work: Work) -> Result<(), String> {
+        self.0.try_send(work).map_err(|e| match e {
+            TrySendError::Full(work) => {
+                format!("Processor overloaded, dropping {} work", work.kind.as_str())
+            }
+            TrySendError::Closed(_) => "Processor stopped".to_string(),
+        })
+    }
+}
+
+pub struct Processor<T> {
+    config: Config,
+    slot_clock: T,
+    receiver: mpsc::Receiver<Work>,
+    /// The queue of each kind, in priority order.
+    queues: [VecDeque<Work>; WorkKind::ALL.len()],
+    workers: Arc<Semaphore>,
+    metrics: ProcessorMetrics,
+}
+
+impl<T: SlotClock> Processor<T> {
+    /// Creates the processor, registering its metrics if a registry is given.
+    pub fn new(config: Config, slot_clock: T, registry: Option<&mut Registry>) -> (Sender, Self) {
+        let (sender, receiver) = mpsc::channel(config.queue_size);
+        let processor = Self {
+            workers: Arc::new(Semaphore::new(config.max_workers)),
+            config,
+            slot_clock,
+            receiver,
+            queues: Default::default(),
+            metrics: ProcessorMetrics::new(registry),
+        };
+        (Sender(sender), processor)
+    }
+
+    pub fn metrics(&self) -> ProcessorMetrics {
+        self.metrics.clone()
+    }
+
+    /// Processes the queued work until every sender is dropped and the queues are empty.
+    pub async fn run(mut self) {
+        let mut closed = false;
+        loop {
+            let has_work = self.queues.iter().any(|queue| !queue.is_empty());
+            if closed && !has_work {
+                return;
+            }
+            tokio::select! {
+                biased;
+                permit = self.workers.clone().acquire_owned(), if has_work => {
+                    let permit = permit.expect("The semaphore is never closed");
+                    // Queue the work received meanwhile, which may have a higher priority.
+                    while let Ok(work) = self.receiver.try_recv() {
+                        self.enqueue(work);
+                    }
+                    if let Some(work) = self.next_work() {
+                        trace!(kind = work.kind.as_str(), slot = work.slot, "Processing work");
+                        tokio::task::spawn_blocking(move || {
+                            (work.task)();
+                            drop(permit);
+                        });
+                    }
+                }
+                work = self.receiver.recv(), if !closed => match work {
+                    Some(work) => self.enqueue(work),
+                    None => closed = true,
+                },
+            }
+        }
+    }
+
+    fn enqueue(&mut self, work: Work) {
+        let kind = work.kind;
+        if self.is_stale(work.slot) {
+            debug!(
+                kind = kind.as_str(),
+                slot = work.slot,
+                "Dropping stale work"
+            );
+            self.metrics.on_dropped(kind, DropReason::Stale);
+            return;
+        }
+        let queue = &mut self.queues[kind as usize];
+        if queue.len() >= self.config.queue_size {
+            if let Some(dropped) = queue.pop_front() {
+                debug!(
+                    kind = kind.as_str(),
+                    slot = dropped.slot,
+                    "Queue full, dropping its oldest work"
+                );
+                self.metrics.on_dropped(kind, DropReason::Full);
+            }
+        }
+        queue.push_back(work);
+        self.metrics.set_queue_length(kind, queue.len());
+    }
+
+    /// The next work of the highest priority, dropping the stale work met on the way.
+    fn next_work(&mut self) -> Option<Work> {
+        for kind in WorkKind::ALL {
+            while let Some(work) = self.queues[kind as usize].pop_front() {
+                self.metrics
+                    .set_queue_length(kind, self.queues[kind as usize].len());
+                if !self.is_stale(work.slot) {
+                    return Some(work);
+                }
+                debug!(
+                    kind = kind.as_str(),
+                    slot = work.slot,
+                    "Dropping stale work"
+                );
+                self.metrics.on_dropped(kind, DropReason::Stale);
+            }
+        }
+        None
+    }
+
+    fn is_stale(&self, slot: u64) -> bool {
+        self.slot_clock
+            .now()
+            .is_some_and(|now| slot + self.config.stale_slots < now.as_u64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slot_clock::{ManualSlotClock, Slot};
+    use std::time::Duration;
+    use tokio::sync::mpsc::UnboundedSender;
+
+    fn slot_clock(slot: u64) -> ManualSlotClock {
+        let slot_clock = ManualSlotClock::new(
+            Slot::new(0),
+            Duration::from_secs(1_606_824_023),
+            Duration::from_secs(12),
+        );
+        slot_clock.set_slot(slot);
+        slot_clock
+    }
+
+    fn config(queue_size: usize) -> Config {
+        Config {
+            queue_size,
+            max_workers: 1,
+            stale_slots: 1,
+        }
+    }
+
+    /// Work reporting its kind and slot once processed.
+    fn work(kind: WorkKind, slot: u64, done: &UnboundedSender<(WorkKind, u64)>) -> Work {
+        let done = done.clone();
+        Work::new(kind, slot, move || done.send((kind, slot)).unwrap())
+    }
+
+    #[tokio::test]
+    async fn work_is_processed_in_priority_order() {
+        let (sender, processor) = Processor::new(config(16), slot_clock(10), None);
+        let (done, mut processed) = mpsc::unbounded_channel();
+        for kind in WorkKind::ALL.into_iter().rev() {
+            sender.send(work(kind, 10, &done)).unwrap();
+        }
+        drop(sender);
+        processor.run().await;
+
+        for kind in WorkKind::ALL {
+            assert_eq!(processed.recv().await, Some((kind, 10)));
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_work_is_dropped() {
+        let (sender, processor) = Processor::new(config(16), slot_clock(10), None);
+        let metrics = processor.metrics();
+        let (done, mut processed) = mpsc::unbounded_channel();
+        for slot in [8, 9, 10] {
+            sender.send(work(WorkKind::Prepare, slot, &done)).unwrap();
+        }
+        drop(sender);
+        processor.run().await;
+
+        assert_eq!(processed.recv().await, Some((WorkKind::Prepare, 9)));
+        assert_eq!(processed.recv().await, Some((WorkKind::Prepare, 10)));
+        assert_eq!(metrics.dropped(WorkKind::Prepare, DropReason::Stale), 1);
+    }
+
+    #[tokio::test]
+    async fn full_queues_drop_their_oldest_work() {
+        let (sender, processor) = Processor::new(config(2), slot_clock(10), None);
+        let metrics = processor.metrics();
+        let (done, mut processed) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(processor.run());
+
+        // Keep the only worker busy until the queue overflowed.
+        let (started, mut wait_started) = mpsc::unbounded_channel();
+        let (release, wait_release) = std::sync::mpsc::channel::<()>();
+        sender
+            .send(Work::new(WorkKind::Decided, 10, move || {
+                started.send(()).unwrap();
+                wait_release.recv().unwrap();
+            }))
+            .unwrap();
+        wait_started.recv().await.unwrap();
+
+        for (slot, queued) in [(10, 1), (11, 2), (12, 2)] {
+            sender.send(work(WorkKind::Commit, slot, &done)).unwrap();
+            while metrics.queue_length(WorkKind::Commit) != queued
+                || (slot == 12 && metrics.dropped(WorkKind::Commit, DropReason::Full) == 0)
+            {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+
+        release.send(()).unwrap();
+        drop(sender);
+        handle.await.unwrap();
+        assert_eq!(processed.recv().await, Some((WorkKind::Commit, 11)));
+        assert_eq!(processed.recv().await, Some((WorkKind::Commit, 12)));
+        assert_eq!(metrics.queue_length(WorkKind::Commit), 0);
+    }
+}
@@ -0,0 +1,102 @@
+//! Metrics of the processor queues, registered in the `prometheus_client` registry encoded by
+//! the metrics server.
+
+use crate::WorkKind;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct QueueLabels {
+    pub queue: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DroppedLabels {
+    pub queue: &'static str,
+    pub reason: &'static str,
+}
+
+/// Why an item was dropped without being processed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropReason {
+    /// Its queue was full.
+    Full,
+    /// Its slot has passed.
+    Stale,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Stale => "stale",
+        }
+    }
+}
+
+/// The metrics are shared by the clones.
+#[derive(Clone, Default)]
+pub struct ProcessorMetrics {
+    /// The number of items waiting in each queue.
+    queue_length: Family<QueueLabels, Gauge>,
+    /// The number of items dropped from each queue, per reason.
+    dropped: Family<DroppedLabels, Counter>,
+}
+
+impl ProcessorMetrics {
+    /// Creates the metrics, registering them if a registry is given.
+    pub fn new(registry: Option<&mut Registry>) -> Self {
+        let metrics = Self::default();
+        if let Some(registry) = registry {
+            let registry = registry.sub_registry_with_prefix("anchor_processor");
+            registry.register(
+                "queue_length",
+                "The number of items waiting in each processor queue",
+                metrics.queue_length.clone(),
+            );
+            registry.register(
+                "dropped",
+                "The number of items dropped without being processed, per queue and reason",
+                metrics.dropped.clone(),
+            );
+        }
+        metrics
+    }
+
+    pub fn set_queue_length(&self, kind: WorkKind, length: usize) {
+        self.queue_length
+            .get_or_create(&QueueLabels {
+                queue: kind.as_str(),
+            })
+            .set(length as i64);
+    }
+
+    pub fn queue_length(&self, kind: WorkKind) -> i64 {
+        self.queue_length
+            .get_or_create(&QueueLabels {
+                queue: kind.as_str(),
+            })
+            .get()
+    }
+
+    pub fn on_dropped(&self, kind: WorkKind, reason: DropReason) {
+        self.dropped
+            .get_or_create(&DroppedLabels {
+                queue: kind.as_str(),
+                reason: reason.as_str(),
+            })
+            .inc();
+    }
+
+    pub fn dropped(&self, kind: WorkKind, reason: DropReason) -> u64 {
+        self.dropped
+            .get_or_create(&DroppedLabels {
+                queue: kind.as_str(),
+                reason: reason.as_str(),
+            })
+            .get()
+    }
+}
@@ -5,7 +5,10 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tracing::{debug, instrument, warn, Level};
-pub use validation::{validate_consensus_data, ValidatedData, ValidationError};
+pub use validation::{
+    validate_consensus_data, validate_data, DataValidator, DefaultDataValidator, ValidatedData,
+    ValidationError,
+};
 
 pub use types::{
     Completed, ConsensusData, InMessage, InstanceHeight, InstanceState, LeaderFunction, OperatorId,
@@ -27,15 +30,18 @@ type RoundChangeMap<D> = HashMap<OperatorId, Option<ConsensusData<ValidatedData<
 /// This builds and runs an entire QBFT process until it completes. It can complete either
 /// successfully (i.e that it has successfully come to consensus, or through a timeout where enough
 /// round changes have elapsed before coming to consensus.
-pub struct Qbft<F, D>
+pub struct Qbft<F, D, V = DefaultDataValidator>
 where
     F: LeaderFunction + Clone,
     D: Debug + Clone + Eq + Hash,
+    V: DataValidator<D>,
 {
     /// The initial configuration used to establish this instance of QBFT.
     config: Config<F>,
     /// Initial data that we will propose if we are the leader.
     start_data: ValidatedData<D>,
+    /// Checks the data proposed by the other operators.
+    data_validator: V,
     /// The instance height acts as an ID for the current instance and helps distinguish it from
     /// other instances.
     instance_height: InstanceHeight,
@@ -58,14 +64,16 @@ where
     state: InstanceState,
 }
 
-impl<F, D> Qbft<F, D>
+impl<F, D, V> Qbft<F, D, V>
 where
     F: LeaderFunction + Clone,
     D: Debug + Clone + Hash + Eq,
+    V: DataValidator<D>,
 {
     pub fn new(
         config: Config<F>,
         start_data: ValidatedData<D>,
+        data_validator: V,
     ) -> (
         UnboundedSender<InMessage<D>>,
         UnboundedReceiver<OutMessage<D>>,
@@ -81,6 +89,7 @@ where
             instance_height: config.instance_height,
             config,
             start_data,
+            data_validator,
             past_consensus: HashMap::with_capacity(2),
             prepare_messages: HashMap::with_capacity(estimated_map_size),
             commit_messages: HashMap::with_capacity(estimated_map_size),
@@ -96,7 +105,10 @@ where
     // This adds the fields to all our logs for this instance.
     #[instrument(name = "QBFT",skip_all, fields(operator_id=*self.config.operator_id,instance_height=*self.config.instance_height), level= Level::ERROR)]
     pub async fn start_instance(mut self) {
-        let mut round_end = tokio::time::interval(self.config.round_time);
+        // The first round also lasts a whole round time.
+        let round_time = self.config.round_time;
+        let mut round_end =
+            tokio::time::interval_at(tokio::time::Instant::now() + round_time, round_time);
         self.start_round();
         loop {
             // If we reached a critical error, end gracefully
@@ -293,7 +305,8 @@ where
         }
 
         // Validate the data
-        let Ok(consensus_data) = validate_consensus_data(consensus_data) else {
+        let Ok(consensus_data) = validate_consensus_data(consensus_data, &self.data_validator)
+        else {
             warn!(
                 from = *operator_id,
                 current_round = *self.current_round,
@@ -353,7 +366,8 @@ where
         }
 
         // Validate the data
-        let Ok(consensus_data) = validate_consensus_data(consensus_data) else {
+        let Ok(consensus_data) = validate_consensus_data(consensus_data, &self.data_validator)
+        else {
             warn!(
                 from = *operator_id,
                 current_round = *self.current_round,
@@ -430,7 +444,8 @@ where
         }
 
         // Validate the data
-        let Ok(consensus_data) = validate_consensus_data(consensus_data) else {
+        let Ok(consensus_data) = validate_consensus_data(consensus_data, &self.data_validator)
+        else {
             warn!(
                 from = *operator_id,
                 current_round = *self.current_round,
@@ -509,7 +524,9 @@ where
         // Validate the data, if it exists
         let maybe_past_consensus_data = match maybe_past_consensus_data {
             Some(consensus_data) => {
-                let Ok(consensus_data) = validate_consensus_data(consensus_data) else {
+                let Ok(consensus_data) =
+                    validate_consensus_data(consensus_data, &self.data_validator)
+                else {
                     warn!(
                         from = *operator_id,
                         current_round = *self.current_round,
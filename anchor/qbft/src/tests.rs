@@ -3,12 +3,13 @@
 //! These test individual components and also provide full end-to-end tests of the entire protocol.
 
 use super::*;
-use crate::validation::{validate_data, ValidatedData};
+use crate::validation::{validate_data, DefaultDataValidator, ValidatedData};
 use futures::stream::select_all;
 use futures::StreamExt;
 use std::cmp::Eq;
 use std::hash::Hash;
 use std::pin::Pin;
+use std::time::Duration;
 use std::task::{Context, Poll};
 use tracing::debug;
 use tracing_subscriber::filter::EnvFilter;
@@ -88,7 +89,7 @@ impl TestQBFTCommitteeBuilder {
         }
 
         // Validate the data
-        let validated_data = validate_data(data).unwrap();
+        let validated_data = validate_data(data, &DefaultDataValidator {}).unwrap();
 
         let (senders, mut receivers) = construct_and_run_committee(self.config, validated_data);
 
@@ -189,7 +190,11 @@ fn construct_and_run_committee<D: Debug + Default + Clone + Send + Sync + 'stati
     for id in 0..config.committee_size {
         // Creates a new instance
         config.operator_id = OperatorId::from(id);
-        let (sender, receiver, instance) = Qbft::new(config.clone(), validated_data.clone());
+        let (sender, receiver, instance) = Qbft::new(
+            config.clone(),
+            validated_data.clone(),
+            DefaultDataValidator {},
+        );
         senders.insert(config.operator_id, sender);
         receivers.insert(config.operator_id, receiver);
 
@@ -376,3 +381,27 @@ async fn test_basic_committee() {
     // Wait until consensus is reached or all the instances have ended
     test_instance.wait_until_end().await;
 }
+
+#[tokio::test(start_paused = true)]
+async fn test_first_round_lasts_a_whole_round_time() {
+    // Operator 1 does not lead the first round, so it sends nothing until the round ends.
+    let config = Config::<DefaultLeaderFunction> {
+        operator_id: OperatorId::from(1),
+        committee_size: 5,
+        committee_members: (0..5).map(OperatorId::from).collect::<HashSet<_>>(),
+        ..Default::default()
+    };
+    let round_time = config.round_time;
+    let start_data = validate_data(21, &DefaultDataValidator {}).unwrap();
+    let (_sender, mut receiver, instance) = Qbft::new(config, start_data, DefaultDataValidator {});
+    tokio::spawn(instance.start_instance());
+
+    tokio::time::sleep(round_time - Duration::from_millis(1)).await;
+    assert!(receiver.try_recv().is_err());
+
+    tokio::time::sleep(Duration::from_millis(2)).await;
+    match receiver.try_recv() {
+        Ok(OutMessage::RoundChange(round, None)) => assert_eq!(*round, 1),
+        other => panic!("Expected a round change, got {other:?}"),
+    }
+}
@@ -33,7 +33,7 @@ impl LeaderFunction for DefaultLeaderFunction {
 }
 
 /// This represents an individual round, these change on regular time intervals
-#[derive(Clone, Copy, Debug, Deref, Default, Add, PartialEq, Eq, Hash, PartialOrd)]
+#[derive(Clone, Copy, Debug, Deref, Default, Add, PartialEq, Eq, Hash, PartialOrd, From)]
 pub struct Round(usize);
 
 impl Round {
@@ -54,7 +54,7 @@ pub struct OperatorId(usize);
 
 /// The instance height behaves like an "ID" for the QBFT instance. It is used to uniquely identify
 /// different instances, that have the same operator id.
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, From)]
 pub struct InstanceHeight(usize);
 
 impl Deref for InstanceHeight {
@@ -81,7 +81,7 @@ pub enum InstanceState {
 
 /// Generic Data trait to allow for future implementations of the QBFT module
 // Messages that can be received from the message_in channel
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InMessage<D: Debug + Clone + Eq + Hash> {
     /// A PROPOSE message to be sent on the network.
     Propose(OperatorId, ConsensusData<D>),
@@ -109,7 +109,7 @@ pub enum OutMessage<D: Debug + Clone + Eq + Hash> {
 }
 /// Type definitions for the allowable messages
 /// This holds the consensus data for a given round.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ConsensusData<D> {
     /// The round that this data corresponds to
     pub round: Round,
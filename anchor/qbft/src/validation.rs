@@ -8,6 +8,23 @@ pub enum ValidationError {
     Invalid,
 }
 
+/// Checks the data of an instance, e.g. against the duty it is for, before the instance agrees to
+/// it.
+pub trait DataValidator<D> {
+    /// Returns true if the data is acceptable
+    fn validate(&self, data: &D) -> bool;
+}
+
+/// Accepts any data.
+#[derive(Debug, Clone)]
+pub struct DefaultDataValidator {}
+
+impl<D> DataValidator<D> for DefaultDataValidator {
+    fn validate(&self, _data: &D) -> bool {
+        true
+    }
+}
+
 /// Data that has been validated by our validation function.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct ValidatedData<D> {
@@ -15,16 +32,23 @@ pub struct ValidatedData<D> {
 }
 
 /// This verifies the data is correct an appropriate to use for consensus.
-pub fn validate_data<D>(data: D) -> Result<ValidatedData<D>, ValidationError> {
+pub fn validate_data<D>(
+    data: D,
+    validator: &impl DataValidator<D>,
+) -> Result<ValidatedData<D>, ValidationError> {
+    if !validator.validate(&data) {
+        return Err(ValidationError::Invalid);
+    }
     Ok(ValidatedData { data })
 }
 
 // Validates consensus data
 pub fn validate_consensus_data<D>(
     consensus_data: ConsensusData<D>,
+    validator: &impl DataValidator<D>,
 ) -> Result<ConsensusData<ValidatedData<D>>, ValidationError> {
     let round = consensus_data.round;
-    let validated_data = validate_data(consensus_data.data)?;
+    let validated_data = validate_data(consensus_data.data, validator)?;
     Ok(ConsensusData {
         round,
         data: validated_data,
@@ -0,0 +1,189 @@
+//! Lagrange interpolation of BLS signature shares.
+//!
+//! The secret key of a validator is the value at zero of a polynomial whose value at each
+//! operator id is the key share of that operator. As signing is linear in the secret key, the
+//! signature of the validator is interpolated the same way from the signatures of any threshold
+//! of the operators.
+
+use blst::min_pk::{AggregateSignature, SecretKey, Signature};
+use blst::{
+    blst_fr, blst_fr_add, blst_fr_eucl_inverse, blst_fr_from_scalar, blst_fr_from_uint64,
+    blst_fr_mul, blst_fr_sub, blst_p2, blst_p2_add_or_double, blst_p2_affine, blst_p2_from_affine,
+    blst_p2_mult, blst_scalar, blst_scalar_from_fr,
+};
+use rand::RngCore;
+use ssv_types::OperatorId;
+use std::collections::HashSet;
+
+/// The number of bits of the scalars of the BLS12-381 curve.
+const SCALAR_BITS: usize = 255;
+
+/// Reconstructs the signature of the shared secret key from the signatures of the operators,
+/// which must be at least as many as the threshold of the shares.
+pub fn reconstruct_signature(partials: &[(OperatorId, Signature)]) -> Result<Signature, String> {
+    let ids: Vec<u64> = partials
+        .iter()
+        .map(|(operator_id, _)| **operator_id)
+        .collect();
+    if ids.contains(&0) {
+        return Err("Operator id 0 can not hold a key share".to_string());
+    }
+    if ids.iter().collect::<HashSet<_>>().len() != ids.len() {
+        return Err("Duplicate partial signature".to_string());
+    }
+
+    let mut signature = blst_p2::default();
+    for (i, (_, partial)) in partials.iter().enumerate() {
+        let coefficient = lagrange_coefficient(&ids, i);
+        let affine: &blst_p2_affine = partial.into();
+        let mut point = blst_p2::default();
+        let mut scalar = blst_scalar::default();
+        let mut term = blst_p2::default();
+        // SAFETY: every pointer refers to an initialized value living for the whole block, and
+        // the scalar is 32 bytes long, covering the bits read by the multiplication.
+        unsafe {
+            blst_p2_from_affine(&mut point, affine);
+            blst_scalar_from_fr(&mut scalar, &coefficient);
+            blst_p2_mult(&mut term, &point, scalar.b.as_ptr(), SCALAR_BITS);
+            blst_p2_add_or_double(&mut signature, &signature, &term);
+        }
+    }
+    Ok(AggregateSignature::from(signature).to_signature())
+}
+
+/// Splits `secret_key` into shares for `operator_ids`, any `threshold` of which reconstruct it, by
+/// evaluating a random polynomial of degree `threshold - 1` at each operator id.
+pub fn split_secret_key(
+    secret_key: &SecretKey,
+    operator_ids: &[OperatorId],
+    threshold: usize,
+) -> Result<Vec<SecretKey>, String> {
+    if threshold == 0 || threshold > operator_ids.len() {
+        return Err(format!(
+            "Invalid threshold {threshold} for {} shares",
+            operator_ids.len()
+        ));
+    }
+    if operator_ids.contains(&OperatorId(0)) {
+        return Err("Operator id 0 can not hold a key share".to_string());
+    }
+    let mut coefficients = vec![scalar_fr(secret_key)];
+    for _ in 1..threshold {
+        let mut ikm = [0; 32];
+        rand::thread_rng().fill_bytes(&mut ikm);
+        let random = SecretKey::key_gen(&ikm, &[])
+            .map_err(|e| format!("Unable to generate a coefficient: {e:?}"))?;
+        coefficients.push(scalar_fr(&random));
+    }
+
+    operator_ids
+        .iter()
+        .map(|operator_id| {
+            // Horner's method, from the highest degree coefficient.
+            let x = fr(**operator_id);
+            let mut share = blst_fr::default();
+            let mut scalar = blst_scalar::default();
+            // SAFETY: the pointers refer to initialized values, and blst allows the output to
+            // alias an input.
+            unsafe {
+                for coefficient in coefficients.iter().rev() {
+                    blst_fr_mul(&mut share, &share, &x);
+                    blst_fr_add(&mut share, &share, coefficient);
+                }
+                blst_scalar_from_fr(&mut scalar, &share);
+            }
+            <&SecretKey>::try_from(&scalar)
+                .cloned()
+                .map_err(|e| format!("Invalid key share: {e:?}"))
+        })
+        .collect()
+}
+
+/// The coefficient of the share at `ids[i]` in the interpolation at zero: the product of
+/// `x_j / (x_j - x_i)` over the other ids.
+fn lagrange_coefficient(ids: &[u64], i: usize) -> blst_fr {
+    let x_i = fr(ids[i]);
+    let mut numerator = fr(1);
+    let mut denominator = fr(1);
+    for (j, id) in ids.iter().enumerate() {
+        if j == i {
+            continue;
+        }
+        let x_j = fr(*id);
+        let mut difference = blst_fr::default();
+        // SAFETY: the pointers refer to initialized values, and blst allows the output to alias
+        // an input.
+        unsafe {
+            blst_fr_mul(&mut numerator, &numerator, &x_j);
+            blst_fr_sub(&mut difference, &x_j, &x_i);
+            blst_fr_mul(&mut denominator, &denominator, &difference);
+        }
+    }
+    let mut coefficient = blst_fr::default();
+    // SAFETY: as above. The denominator is not zero as the ids are distinct.
+    unsafe {
+        blst_fr_eucl_inverse(&mut denominator, &denominator);
+        blst_fr_mul(&mut coefficient, &numerator, &denominator);
+    }
+    coefficient
+}
+
+fn fr(value: u64) -> blst_fr {
+    let mut fr = blst_fr::default();
+    // SAFETY: blst reads the four 64-bit limbs of the value, which are all provided.
+    unsafe { blst_fr_from_uint64(&mut fr, [value, 0, 0, 0].as_ptr()) };
+    fr
+}
+
+fn scalar_fr(secret_key: &SecretKey) -> blst_fr {
+    let scalar: &blst_scalar = secret_key.into();
+    let mut fr = blst_fr::default();
+    // SAFETY: the pointers refer to initialized values.
+    unsafe { blst_fr_from_scalar(&mut fr, scalar) };
+    fr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_threshold_of_shares_reconstructs_the_signature() {
+        let secret_key = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let operator_ids: Vec<OperatorId> = [3, 8, 21, 42].map(OperatorId).to_vec();
+        let shares = split_secret_key(&secret_key, &operator_ids, 3).unwrap();
+        let message = [7; 32];
+        let expected = secret_key.sign(&message, crate::ETH_DST, &[]);
+
+        for skipped in 0..operator_ids.len() {
+            let partials: Vec<(OperatorId, Signature)> = operator_ids
+                .iter()
+                .zip(&shares)
+                .enumerate()
+                .filter(|(i, _)| *i != skipped)
+                .map(|(_, (id, share))| (*id, share.sign(&message, crate::ETH_DST, &[])))
+                .collect();
+            assert_eq!(reconstruct_signature(&partials).unwrap(), expected);
+            // Below the threshold, the signature can not be reconstructed.
+            assert_ne!(reconstruct_signature(&partials[1..]).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn duplicate_and_zero_ids_are_rejected() {
+        let signature =
+            SecretKey::key_gen(&[1; 32], &[])
+                .unwrap()
+                .sign(&[7; 32], crate::ETH_DST, &[]);
+        assert!(
+            reconstruct_signature(&[(OperatorId(1), signature), (OperatorId(1), signature)])
+                .is_err()
+        );
+        assert!(reconstruct_signature(&[(OperatorId(0), signature)]).is_err());
+
+        let secret_key = SecretKey::key_gen(&[1; 32], &[]).unwrap();
+        let operator_ids = [OperatorId(1), OperatorId(2)];
+        assert!(split_secret_key(&secret_key, &operator_ids, 3).is_err());
+        assert!(split_secret_key(&secret_key, &[OperatorId(0), OperatorId(1)], 2).is_err());
+    }
+}
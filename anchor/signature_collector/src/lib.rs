@@ -0,0 +1,318 @@
+//! Collects the partial signatures of the operators of a validator and reconstructs the
+//! signature of the validator once a threshold of them is verified.
+//!
+//! After the committee decided on the data of a duty, each operator signs it with its key share
+//! and broadcasts its partial signature. Any threshold of the partial signatures reconstructs the
+//! signature of the validator.
+
+mod lagrange;
+
+pub use lagrange::{reconstruct_signature, split_secret_key};
+
+use blst::min_pk::{PublicKey, Signature};
+use blst::BLST_ERROR;
+use ssv_types::{CommitteeId, OperatorId, PartialSignature, PartialSignatureKind, Share};
+use std::collections::{BTreeMap, HashMap};
+use tracing::debug;
+
+/// The domain separation tag of the Ethereum BLS signatures.
+pub const ETH_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The public keys of a validator and of the key shares of its operators.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorCommittee {
+    pub validator_public_key: PublicKey,
+    pub share_public_keys: BTreeMap<OperatorId, PublicKey>,
+}
+
+impl ValidatorCommittee {
+    /// Parses the public keys of a validator and of the shares of its operators.
+    pub fn from_shares(validator_public_key: &[u8], shares: &[Share]) -> Result<Self, String> {
+        let parse = |public_key: &[u8]| {
+            PublicKey::key_validate(public_key)
+                .map_err(|e| format!("Invalid BLS public key {}: {e:?}", to_hex(public_key)))
+        };
+        Ok(Self {
+            validator_public_key: parse(validator_public_key)?,
+            share_public_keys: shares
+                .iter()
+                .map(|share| Ok((share.operator_id, parse(&share.public_key)?)))
+                .collect::<Result<_, String>>()?,
+        })
+    }
+
+    /// The number of partial signatures reconstructing the signature: the quorum of `2f + 1`
+    /// operators of a committee of `3f + 1`, which the key is shared with.
+    pub fn threshold(&self) -> usize {
+        let size = self.share_public_keys.len();
+        size - size.saturating_sub(1) / 3
+    }
+
+    /// The id of the committee of the operators, which runs the duties of its validators.
+    pub fn committee_id(&self) -> CommitteeId {
+        let operator_ids: Vec<OperatorId> = self.share_public_keys.keys().copied().collect();
+        CommitteeId::from_operators(&operator_ids)
+    }
+}
+
+/// Identifies a signature to reconstruct: a validator's signature of a signing root for a duty.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CollectionKey {
+    pub validator_public_key: Vec<u8>,
+    pub kind: PartialSignatureKind,
+    pub slot: u64,
+    pub signing_root: [u8; 32],
+}
+
+#[derive(Default)]
+struct Collection {
+    /// The verified partial signatures, by signer.
+    partials: BTreeMap<OperatorId, Signature>,
+    reconstructed: bool,
+}
+
+/// The partial signatures collected for each signature to reconstruct.
+#[derive(Default)]
+pub struct SignatureCollector {
+    collections: HashMap<CollectionKey, Collection>,
+}
+
+impl SignatureCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the partial signature of `signer`, after verifying it against the public key of the
+    /// signer's share. Returns the signature of the validator once the threshold of partial
+    /// signatures is reached, only for the partial signature reaching it.
+    pub fn add(
+        &mut self,
+        key: CollectionKey,
+        committee: &ValidatorCommittee,
+        signer: OperatorId,
+        partial_signature: &PartialSignature,
+    ) -> Result<Option<Signature>, String> {
+        let share_public_key = committee
+            .share_public_keys
+            .get(&signer)
+            .ok_or_else(|| format!("Operator {signer} does not hold a share of the validator"))?;
+        let collection = self.collections.entry(key.clone()).or_default();
+        if collection.partials.contains_key(&signer) {
+            return Ok(None);
+        }
+        let signature = Signature::sig_validate(&partial_signature.0, true)
+            .map_err(|e| format!("Invalid partial signature of operator {signer}: {e:?}"))?;
+        let result = signature.verify(
+            false,
+            &key.signing_root,
+            ETH_DST,
+            &[],
+            share_public_key,
+            false,
+        );
+        if result != BLST_ERROR::BLST_SUCCESS {
+            return Err(format!(
+                "Wrong partial signature of operator {signer}: {result:?}"
+            ));
+        }
+        collection.partials.insert(signer, signature);
+
+        if collection.reconstructed || collection.partials.len() < committee.threshold() {
+            return Ok(None);
+        }
+        let partials: Vec<(OperatorId, Signature)> = collection
+            .partials
+            .iter()
+            .map(|(operator_id, signature)| (*operator_id, *signature))
+            .collect();
+        let signature = reconstruct_signature(&partials)?;
+        let result = signature.verify(
+            false,
+            &key.signing_root,
+            ETH_DST,
+            &[],
+            &committee.validator_public_key,
+            false,
+        );
+        if result != BLST_ERROR::BLST_SUCCESS {
+            return Err(format!(
+                "The reconstructed signature does not match the validator: {result:?}"
+            ));
+        }
+        debug!(
+            validator = to_hex(&key.validator_public_key),
+            slot = key.slot,
+            kind = ?key.kind,
+            "Reconstructed validator signature"
+        );
+        collection.reconstructed = true;
+        Ok(Some(signature))
+    }
+
+    /// Forgets the partial signatures of the duties before `slot`.
+    pub fn prune(&mut self, slot: u64) {
+        self.collections.retain(|key, _| key.slot >= slot);
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blst::min_pk::SecretKey;
+
+    /// A validator whose key is shared with `size` operators, with its key shares.
+    fn committee(size: u64) -> (SecretKey, Vec<(OperatorId, SecretKey)>, ValidatorCommittee) {
+        let secret_key = SecretKey::key_gen(&[size as u8; 32], &[]).unwrap();
+        // The operator ids of a committee are not contiguous.
+        let operator_ids: Vec<OperatorId> = (1..=size).map(|i| OperatorId(i * 7 + 2)).collect();
+        let validator_public_key = secret_key.sk_to_pk();
+        let mut committee = ValidatorCommittee {
+            validator_public_key,
+            share_public_keys: BTreeMap::new(),
+        };
+        let threshold = size as usize - (size as usize - 1) / 3;
+        let shares = split_secret_key(&secret_key, &operator_ids, threshold).unwrap();
+        for (operator_id, share) in operator_ids.iter().zip(&shares) {
+            committee
+                .share_public_keys
+                .insert(*operator_id, share.sk_to_pk());
+        }
+        (
+            secret_key,
+            operator_ids.into_iter().zip(shares).collect(),
+            committee,
+        )
+    }
+
+    fn key(committee: &ValidatorCommittee) -> CollectionKey {
+        CollectionKey {
+            validator_public_key: committee.validator_public_key.to_bytes().to_vec(),
+            kind: PartialSignatureKind::PostConsensus,
+            slot: 100,
+            signing_root: [9; 32],
+        }
+    }
+
+    fn partial(share: &SecretKey, signing_root: &[u8; 32]) -> PartialSignature {
+        PartialSignature(share.sign(signing_root, ETH_DST, &[]).to_bytes())
+    }
+
+    #[test]
+    fn threshold_of_partial_signatures_reconstructs_the_signature() {
+        for (size, threshold) in [(4, 3), (7, 5), (10, 7), (13, 9)] {
+            let (secret_key, shares, committee) = committee(size);
+            assert_eq!(committee.threshold(), threshold);
+            let key = key(&committee);
+            let expected = secret_key.sign(&key.signing_root, ETH_DST, &[]);
+
+            // The last operators sign first, so that another subset than the first operators
+            // reconstructs the signature.
+            let mut collector = SignatureCollector::new();
+            for (i, (operator_id, share)) in shares.iter().rev().enumerate() {
+                let result = collector
+                    .add(
+                        key.clone(),
+                        &committee,
+                        *operator_id,
+                        &partial(share, &key.signing_root),
+                    )
+                    .unwrap();
+                if i + 1 == threshold {
+                    assert_eq!(result, Some(expected), "{threshold} of {size}");
+                } else {
+                    assert_eq!(result, None, "{} of {size}", i + 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_partial_signatures_are_rejected() {
+        let (_, shares, committee) = committee(4);
+        let key = key(&committee);
+        let mut collector = SignatureCollector::new();
+        let (operator_id, share) = &shares[0];
+
+        // Signed with the share of another operator, or another signing root.
+        let wrong_share = partial(&shares[1].1, &key.signing_root);
+        assert!(collector
+            .add(key.clone(), &committee, *operator_id, &wrong_share)
+            .is_err());
+        let wrong_root = partial(share, &[8; 32]);
+        assert!(collector
+            .add(key.clone(), &committee, *operator_id, &wrong_root)
+            .is_err());
+        assert!(collector
+            .add(
+                key.clone(),
+                &committee,
+                *operator_id,
+                &PartialSignature([0xff; 96])
+            )
+            .is_err());
+        // An operator outside of the committee.
+        assert!(collector
+            .add(
+                key.clone(),
+                &committee,
+                OperatorId(1000),
+                &partial(share, &key.signing_root)
+            )
+            .is_err());
+
+        // The rejected signatures were not counted.
+        for (operator_id, share) in &shares[..2] {
+            let partial = partial(share, &key.signing_root);
+            assert_eq!(
+                collector
+                    .add(key.clone(), &committee, *operator_id, &partial)
+                    .unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn signatures_are_reconstructed_once() {
+        let (_, shares, committee) = committee(4);
+        let key = key(&committee);
+        let mut collector = SignatureCollector::new();
+        let mut reconstructed = 0;
+        for (operator_id, share) in &shares {
+            let partial = partial(share, &key.signing_root);
+            for _ in 0..2 {
+                let result = collector.add(key.clone(), &committee, *operator_id, &partial);
+                reconstructed += result.unwrap().iter().count();
+            }
+        }
+        assert_eq!(reconstructed, 1);
+
+        // Pruning forgets the signatures of past slots.
+        collector.prune(key.slot + 1);
+        assert!(collector.collections.is_empty());
+    }
+
+    #[test]
+    fn committee_from_shares() {
+        let (_, shares, committee) = committee(4);
+        let validator_public_key = committee.validator_public_key.to_bytes();
+        let shares: Vec<Share> = shares
+            .iter()
+            .map(|(operator_id, share)| Share {
+                validator_public_key: validator_public_key.to_vec(),
+                operator_id: *operator_id,
+                public_key: share.sk_to_pk().to_bytes().to_vec(),
+                encrypted_key: vec![],
+            })
+            .collect();
+        assert_eq!(
+            ValidatorCommittee::from_shares(&validator_public_key, &shares).unwrap(),
+            committee
+        );
+        assert!(ValidatorCommittee::from_shares(&[0; 48], &shares).is_err());
+    }
+}
@@ -0,0 +1,22 @@
+-- The root of the chain of the validators, checked against the beacon nodes and the imports.
+CREATE TABLE metadata (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    genesis_validators_root BLOB NOT NULL
+);
+
+CREATE TABLE signed_blocks (
+    validator_public_key BLOB NOT NULL,
+    slot INTEGER NOT NULL,
+    -- Unknown for the blocks imported without it.
+    signing_root BLOB,
+    PRIMARY KEY (validator_public_key, slot)
+);
+
+CREATE TABLE signed_attestations (
+    validator_public_key BLOB NOT NULL,
+    source_epoch INTEGER NOT NULL,
+    target_epoch INTEGER NOT NULL,
+    -- Unknown for the attestations imported without it.
+    signing_root BLOB,
+    PRIMARY KEY (validator_public_key, target_epoch)
+);
@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// An error of the slashing protection database.
+#[derive(Debug)]
+pub enum Error {
+    /// An error of SQLite.
+    Sqlite(rusqlite::Error),
+    /// Signing could get the validator slashed.
+    Slashable(Violation),
+    /// The database protects the validators of another chain.
+    WrongChain { expected: [u8; 32], found: [u8; 32] },
+    /// The database does not know the chain of its validators yet.
+    UnknownChain,
+    /// An interchange file could not be imported.
+    InvalidInterchange(String),
+    /// The database was created by a more recent version of Anchor.
+    UnknownSchemaVersion(usize),
+}
+
+/// Why signing a block or an attestation is refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// Another block was signed at the slot.
+    DoubleBlockProposal { slot: u64 },
+    /// The slot is not after the first slot of the signed blocks, which are not all known when
+    /// they were imported from another client.
+    BlockBelowLowerBound { slot: u64, lower_bound: u64 },
+    /// The source of the attestation is after its target.
+    InvalidAttestation {
+        source_epoch: u64,
+        target_epoch: u64,
+    },
+    /// Another attestation was signed for the target.
+    DoubleVote { target_epoch: u64 },
+    /// The attestation surrounds the signed attestation of these epochs.
+    SurroundingVote {
+        source_epoch: u64,
+        target_epoch: u64,
+    },
+    /// The attestation is surrounded by the signed attestation of these epochs.
+    SurroundedVote {
+        source_epoch: u64,
+        target_epoch: u64,
+    },
+    /// The source is before the first signed source, or the target not after the first signed
+    /// target.
+    AttestationBelowLowerBound {
+        source_epoch: u64,
+        target_epoch: u64,
+    },
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sqlite(e) => write!(f, "SQLite error: {e}"),
+            Self::Slashable(violation) => write!(f, "Slashable signature refused: {violation:?}"),
+            Self::WrongChain { expected, found } => write!(
+                f,
+                "The slashing protection database is for genesis validators root 0x{}, not 0x{}",
+                hex::encode(expected),
+                hex::encode(found)
+            ),
+            Self::UnknownChain => {
+                write!(
+                    f,
+                    "The slashing protection database does not know its chain yet"
+                )
+            }
+            Self::InvalidInterchange(e) => write!(f, "Invalid interchange: {e}"),
+            Self::UnknownSchemaVersion(version) => {
+                write!(f, "Unknown database schema version {version}")
+            }
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl From<Violation> for Error {
+    fn from(violation: Violation) -> Self {
+        Self::Slashable(violation)
+    }
+}
@@ -0,0 +1,100 @@
+//! The slashing protection interchange format of EIP-3076, to move validators between clients
+//! without signing twice what they signed before.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The only version of the format supported.
+pub const INTERCHANGE_FORMAT_VERSION: u64 = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interchange {
+    pub metadata: InterchangeMetadata,
+    pub data: Vec<InterchangeData>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeMetadata {
+    #[serde(with = "quoted_u64")]
+    pub interchange_format_version: u64,
+    pub genesis_validators_root: Root,
+}
+
+/// The blocks and attestations signed by a validator.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeData {
+    #[serde(with = "hex_bytes")]
+    pub pubkey: Vec<u8>,
+    pub signed_blocks: Vec<SignedBlock>,
+    pub signed_attestations: Vec<SignedAttestation>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedBlock {
+    #[serde(with = "quoted_u64")]
+    pub slot: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_root: Option<Root>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    #[serde(with = "quoted_u64")]
+    pub source_epoch: u64,
+    #[serde(with = "quoted_u64")]
+    pub target_epoch: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_root: Option<Root>,
+}
+
+/// A root, serialized as a `0x` prefixed hex string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Root(pub [u8; 32]);
+
+impl Serialize for Root {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(self.0)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Root {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = hex_bytes::deserialize(deserializer)?;
+        let root = bytes
+            .try_into()
+            .map_err(|_| D::Error::custom("A root is 32 bytes long"))?;
+        Ok(Self(root))
+    }
+}
+
+/// Serializes integers as decimal strings.
+mod quoted_u64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes bytes as `0x` prefixed hex strings.
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let value = value
+            .strip_prefix("0x")
+            .ok_or_else(|| serde::de::Error::custom("Missing 0x prefix"))?;
+        hex::decode(value).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,656 @@
+//! The slashing protection database of the validators we hold shares of.
+//!
+//! Our key shares sign for real validators, so signing two conflicting blocks or attestations,
+//! e.g. across a restart, could get the validators slashed once a threshold of operators did the
+//! same. Every block and attestation is recorded before its partial signature is produced, and
+//! the ones conflicting with a recorded one are refused, as specified by EIP-3076. The records
+//! are kept apart from the registry database, so that resyncing the registry does not lose them.
+
+mod error;
+pub mod interchange;
+
+pub use error::{Error, Violation};
+
+use interchange::{
+    Interchange, InterchangeData, InterchangeMetadata, Root, SignedAttestation, SignedBlock,
+    INTERCHANGE_FORMAT_VERSION,
+};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::info;
+
+/// The name of the database file within the data directory.
+pub const SLASHING_PROTECTION_FILENAME: &str = "slashing_protection.sqlite";
+/// The length of the public key of a validator.
+const PUBLIC_KEY_LENGTH: usize = 48;
+
+/// The migrations of the schema, in order. The schema version of a database is the number of
+/// migrations applied to it.
+const MIGRATIONS: &[&str] = &[include_str!("../migrations/001_slashing_protection.sql")];
+
+pub struct SlashingDatabase {
+    connection: Mutex<Connection>,
+}
+
+impl SlashingDatabase {
+    /// Opens the database at `path`, creating it if needed and migrating it to the latest schema.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Opens a database that only lives in memory.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut connection: Connection) -> Result<Self, Error> {
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Checks that the database protects the validators of the chain of
+    /// `genesis_validators_root`, recording the chain on first use.
+    pub fn check_genesis_validators_root(
+        &self,
+        genesis_validators_root: &[u8; 32],
+    ) -> Result<(), Error> {
+        let mut connection = self.connection.lock();
+        let transaction = connection.transaction()?;
+        check_genesis_validators_root(&transaction, genesis_validators_root)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Records the block of a validator at `slot`, unless it conflicts with a signed block.
+    /// Signing the same block again is allowed.
+    pub fn check_and_insert_block(
+        &self,
+        validator_public_key: &[u8],
+        slot: u64,
+        signing_root: &[u8; 32],
+    ) -> Result<(), Error> {
+        let mut connection = self.connection.lock();
+        let transaction = connection.transaction()?;
+        let existing: Option<Option<[u8; 32]>> = transaction
+            .query_row(
+                "SELECT signing_root FROM signed_blocks
+                 WHERE validator_public_key = ?1 AND slot = ?2",
+                params![validator_public_key, slot],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match existing {
+            Some(Some(existing)) if existing == *signing_root => return Ok(()),
+            Some(_) => return Err(Violation::DoubleBlockProposal { slot }.into()),
+            None => {}
+        }
+        let lower_bound: Option<u64> = transaction.query_row(
+            "SELECT MIN(slot) FROM signed_blocks WHERE validator_public_key = ?1",
+            [validator_public_key],
+            |row| row.get(0),
+        )?;
+        if let Some(lower_bound) = lower_bound.filter(|lower_bound| slot <= *lower_bound) {
+            return Err(Violation::BlockBelowLowerBound { slot, lower_bound }.into());
+        }
+
+        transaction.execute(
+            "INSERT INTO signed_blocks (validator_public_key, slot, signing_root)
+             VALUES (?1, ?2, ?3)",
+            params![validator_public_key, slot, signing_root],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Checks that signing the attestation of a validator is safe, without recording it.
+    pub fn check_attestation(
+        &self,
+        validator_public_key: &[u8],
+        source_epoch: u64,
+        target_epoch: u64,
+        signing_root: &[u8; 32],
+    ) -> Result<(), Error> {
+        let connection = self.connection.lock();
+        check_attestation(
+            &connection,
+            validator_public_key,
+            source_epoch,
+            target_epoch,
+            signing_root,
+        )?;
+        Ok(())
+    }
+
+    /// Records the attestation of a validator, unless it is a double vote, surrounds or is
+    /// surrounded by a signed attestation. Signing the same attestation again is allowed.
+    pub fn check_and_insert_attestation(
+        &self,
+        validator_public_key: &[u8],
+        source_epoch: u64,
+        target_epoch: u64,
+        signing_root: &[u8; 32],
+    ) -> Result<(), Error> {
+        let mut connection = self.connection.lock();
+        let transaction = connection.transaction()?;
+        let signed = check_attestation(
+            &transaction,
+            validator_public_key,
+            source_epoch,
+            target_epoch,
+            signing_root,
+        )?;
+        if signed {
+            return Ok(());
+        }
+
+        transaction.execute(
+            "INSERT INTO signed_attestations
+             (validator_public_key, source_epoch, target_epoch, signing_root)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                validator_public_key,
+                source_epoch,
+                target_epoch,
+                signing_root
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Imports the blocks and attestations signed by validators with another client. When a
+    /// signed block or attestation is already recorded with another signing root, its root is
+    /// forgotten so that neither is signed again. A conflicting attestation also keeps the lower
+    /// of the two source epochs.
+    pub fn import_interchange(&self, interchange: &Interchange) -> Result<(), Error> {
+        let version = interchange.metadata.interchange_format_version;
+        if version != INTERCHANGE_FORMAT_VERSION {
+            return Err(Error::InvalidInterchange(format!(
+                "Unsupported format version {version}"
+            )));
+        }
+        let mut connection = self.connection.lock();
+        let transaction = connection.transaction()?;
+        check_genesis_validators_root(
+            &transaction,
+            &interchange.metadata.genesis_validators_root.0,
+        )?;
+
+        for data in &interchange.data {
+            if data.pubkey.len() != PUBLIC_KEY_LENGTH {
+                return Err(Error::InvalidInterchange(format!(
+                    "Invalid public key 0x{}",
+                    hex::encode(&data.pubkey)
+                )));
+            }
+            for block in &data.signed_blocks {
+                transaction.execute(
+                    "INSERT INTO signed_blocks (validator_public_key, slot, signing_root)
+                     VALUES (?1, ?2, ?3)
+                     ON CONFLICT (validator_public_key, slot) DO UPDATE SET signing_root = NULL
+                     WHERE signing_root IS NOT excluded.signing_root",
+                    params![
+                        data.pubkey,
+                        block.slot,
+                        block.signing_root.map(|root| root.0)
+                    ],
+                )?;
+            }
+            for attestation in &data.signed_attestations {
+                if attestation.source_epoch > attestation.target_epoch {
+                    return Err(Error::InvalidInterchange(format!(
+                        "Attestation with source epoch {} after its target epoch {}",
+                        attestation.source_epoch, attestation.target_epoch
+                    )));
+                }
+                transaction.execute(
+                    "INSERT INTO signed_attestations
+                     (validator_public_key, source_epoch, target_epoch, signing_root)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT (validator_public_key, target_epoch) DO UPDATE
+                     SET source_epoch = MIN(source_epoch, excluded.source_epoch),
+                         signing_root = NULL
+                     WHERE signing_root IS NOT excluded.signing_root
+                         OR source_epoch != excluded.source_epoch",
+                    params![
+                        data.pubkey,
+                        attestation.source_epoch,
+                        attestation.target_epoch,
+                        attestation.signing_root.map(|root| root.0)
+                    ],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        info!(
+            validators = interchange.data.len(),
+            "Imported slashing protection interchange"
+        );
+        Ok(())
+    }
+
+    /// Exports the blocks and attestations signed by all the validators.
+    pub fn export_interchange(&self) -> Result<Interchange, Error> {
+        let connection = self.connection.lock();
+        let genesis_validators_root =
+            genesis_validators_root(&connection)?.ok_or(Error::UnknownChain)?;
+        let mut data = BTreeMap::new();
+
+        let mut statement = connection.prepare(
+            "SELECT validator_public_key, slot, signing_root FROM signed_blocks ORDER BY slot",
+        )?;
+        let blocks = statement.query_map([], |row| {
+            let block = SignedBlock {
+                slot: row.get(1)?,
+                signing_root: row.get::<_, Option<[u8; 32]>>(2)?.map(Root),
+            };
+            Ok((row.get(0)?, block))
+        })?;
+        for block in blocks {
+            let (pubkey, block) = block?;
+            validator_data(&mut data, pubkey).signed_blocks.push(block);
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT validator_public_key, source_epoch, target_epoch, signing_root
+             FROM signed_attestations ORDER BY target_epoch",
+        )?;
+        let attestations = statement.query_map([], |row| {
+            let attestation = SignedAttestation {
+                source_epoch: row.get(1)?,
+                target_epoch: row.get(2)?,
+                signing_root: row.get::<_, Option<[u8; 32]>>(3)?.map(Root),
+            };
+            Ok((row.get(0)?, attestation))
+        })?;
+        for attestation in attestations {
+            let (pubkey, attestation) = attestation?;
+            validator_data(&mut data, pubkey)
+                .signed_attestations
+                .push(attestation);
+        }
+
+        Ok(Interchange {
+            metadata: InterchangeMetadata {
+                interchange_format_version: INTERCHANGE_FORMAT_VERSION,
+                genesis_validators_root: Root(genesis_validators_root),
+            },
+            data: data.into_values().collect(),
+        })
+    }
+}
+
+/// The exported data of a validator, by public key.
+fn validator_data(
+    data: &mut BTreeMap<Vec<u8>, InterchangeData>,
+    pubkey: Vec<u8>,
+) -> &mut InterchangeData {
+    data.entry(pubkey.clone())
+        .or_insert_with(|| InterchangeData {
+            pubkey,
+            signed_blocks: vec![],
+            signed_attestations: vec![],
+        })
+}
+
+/// Checks that signing an attestation is safe, returning whether it was already signed.
+fn check_attestation(
+    connection: &Connection,
+    validator_public_key: &[u8],
+    source_epoch: u64,
+    target_epoch: u64,
+    signing_root: &[u8; 32],
+) -> Result<bool, Error> {
+    if source_epoch > target_epoch {
+        return Err(Violation::InvalidAttestation {
+            source_epoch,
+            target_epoch,
+        }
+        .into());
+    }
+    let existing: Option<Option<[u8; 32]>> = connection
+        .query_row(
+            "SELECT signing_root FROM signed_attestations
+             WHERE validator_public_key = ?1 AND target_epoch = ?2",
+            params![validator_public_key, target_epoch],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match existing {
+        Some(Some(existing)) if existing == *signing_root => return Ok(true),
+        Some(_) => return Err(Violation::DoubleVote { target_epoch }.into()),
+        None => {}
+    }
+
+    let signed_attestation = |condition: &str| {
+        connection
+            .query_row(
+                &format!(
+                    "SELECT source_epoch, target_epoch FROM signed_attestations
+                     WHERE validator_public_key = ?1 AND {condition} LIMIT 1"
+                ),
+                params![validator_public_key, source_epoch, target_epoch],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    };
+    if let Some((source_epoch, target_epoch)) =
+        signed_attestation("source_epoch > ?2 AND target_epoch < ?3")?
+    {
+        return Err(Violation::SurroundingVote {
+            source_epoch,
+            target_epoch,
+        }
+        .into());
+    }
+    if let Some((source_epoch, target_epoch)) =
+        signed_attestation("source_epoch < ?2 AND target_epoch > ?3")?
+    {
+        return Err(Violation::SurroundedVote {
+            source_epoch,
+            target_epoch,
+        }
+        .into());
+    }
+    let (min_source_epoch, min_target_epoch): (Option<u64>, Option<u64>) = connection.query_row(
+        "SELECT MIN(source_epoch), MIN(target_epoch) FROM signed_attestations
+             WHERE validator_public_key = ?1",
+        [validator_public_key],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if min_source_epoch.is_some_and(|min| source_epoch < min)
+        || min_target_epoch.is_some_and(|min| target_epoch <= min)
+    {
+        return Err(Violation::AttestationBelowLowerBound {
+            source_epoch,
+            target_epoch,
+        }
+        .into());
+    }
+    Ok(false)
+}
+
+/// Checks the chain of the validators, recording it if the database does not know it yet.
+fn check_genesis_validators_root(
+    connection: &Connection,
+    genesis_validators_root: &[u8; 32],
+) -> Result<(), Error> {
+    match self::genesis_validators_root(connection)? {
+        Some(expected) if expected != *genesis_validators_root => Err(Error::WrongChain {
+            expected,
+            found: *genesis_validators_root,
+        }),
+        Some(_) => Ok(()),
+        None => {
+            connection.execute(
+                "INSERT INTO metadata (id, genesis_validators_root) VALUES (0, ?1)",
+                [genesis_validators_root],
+            )?;
+            Ok(())
+        }
+    }
+}
+
+fn genesis_validators_root(connection: &Connection) -> Result<Option<[u8; 32]>, Error> {
+    let root = connection
+        .query_row(
+            "SELECT genesis_validators_root FROM metadata WHERE id = 0",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(root)
+}
+
+/// Applies the migrations the database has not been migrated with yet.
+fn migrate(connection: &mut Connection) -> Result<(), Error> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(Error::UnknownSchemaVersion(version));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!(
+            version = index + 1,
+            "Migrating the slashing protection database schema"
+        );
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const VALIDATOR: [u8; 48] = [0xaa; 48];
+    const GENESIS_VALIDATORS_ROOT: [u8; 32] = [0x4b; 32];
+
+    fn database() -> SlashingDatabase {
+        let database = SlashingDatabase::open_in_memory().unwrap();
+        database
+            .check_genesis_validators_root(&GENESIS_VALIDATORS_ROOT)
+            .unwrap();
+        database
+    }
+
+    fn violation(result: Result<(), Error>) -> Violation {
+        match result {
+            Err(Error::Slashable(violation)) => violation,
+            result => panic!("Expected a violation, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn double_block_proposals_are_refused() {
+        let database = database();
+        database
+            .check_and_insert_block(&VALIDATOR, 100, &[1; 32])
+            .unwrap();
+        // Signing the same block again is safe, not another one.
+        database
+            .check_and_insert_block(&VALIDATOR, 100, &[1; 32])
+            .unwrap();
+        assert_eq!(
+            violation(database.check_and_insert_block(&VALIDATOR, 100, &[2; 32])),
+            Violation::DoubleBlockProposal { slot: 100 }
+        );
+        database
+            .check_and_insert_block(&VALIDATOR, 102, &[2; 32])
+            .unwrap();
+        database
+            .check_and_insert_block(&VALIDATOR, 101, &[3; 32])
+            .unwrap();
+        assert_eq!(
+            violation(database.check_and_insert_block(&VALIDATOR, 99, &[3; 32])),
+            Violation::BlockBelowLowerBound {
+                slot: 99,
+                lower_bound: 100
+            }
+        );
+        // Other validators are not affected.
+        database
+            .check_and_insert_block(&[0xbb; 48], 100, &[2; 32])
+            .unwrap();
+    }
+
+    #[test]
+    fn slashable_attestations_are_refused() {
+        let database = database();
+        database
+            .check_and_insert_attestation(&VALIDATOR, 10, 20, &[1; 32])
+            .unwrap();
+        database
+            .check_and_insert_attestation(&VALIDATOR, 10, 20, &[1; 32])
+            .unwrap();
+
+        let attest = |source_epoch, target_epoch| {
+            violation(database.check_and_insert_attestation(
+                &VALIDATOR,
+                source_epoch,
+                target_epoch,
+                &[2; 32],
+            ))
+        };
+        assert_eq!(attest(10, 20), Violation::DoubleVote { target_epoch: 20 });
+        assert_eq!(
+            attest(9, 21),
+            Violation::SurroundingVote {
+                source_epoch: 10,
+                target_epoch: 20
+            }
+        );
+        assert_eq!(
+            attest(11, 19),
+            Violation::SurroundedVote {
+                source_epoch: 10,
+                target_epoch: 20
+            }
+        );
+        assert_eq!(
+            attest(22, 21),
+            Violation::InvalidAttestation {
+                source_epoch: 22,
+                target_epoch: 21
+            }
+        );
+        assert_eq!(
+            attest(9, 9),
+            Violation::AttestationBelowLowerBound {
+                source_epoch: 9,
+                target_epoch: 9
+            }
+        );
+        database
+            .check_and_insert_attestation(&VALIDATOR, 20, 21, &[2; 32])
+            .unwrap();
+
+        // Checking an attestation does not record it.
+        assert_eq!(
+            violation(database.check_attestation(&VALIDATOR, 20, 21, &[3; 32])),
+            Violation::DoubleVote { target_epoch: 21 }
+        );
+        database
+            .check_attestation(&VALIDATOR, 21, 22, &[3; 32])
+            .unwrap();
+        database
+            .check_and_insert_attestation(&VALIDATOR, 21, 22, &[4; 32])
+            .unwrap();
+    }
+
+    #[test]
+    fn interchanges_are_imported_and_exported() {
+        let interchange = format!(
+            r#"{{
+                "metadata": {{
+                    "interchange_format_version": "5",
+                    "genesis_validators_root": "0x{}"
+                }},
+                "data": [{{
+                    "pubkey": "0x{}",
+                    "signed_blocks": [
+                        {{"slot": "81952", "signing_root": "0x{}"}},
+                        {{"slot": "81951"}}
+                    ],
+                    "signed_attestations": [
+                        {{"source_epoch": "2290", "target_epoch": "3007", "signing_root": "0x{}"}},
+                        {{"source_epoch": "2290", "target_epoch": "3008"}}
+                    ]
+                }}]
+            }}"#,
+            hex::encode(GENESIS_VALIDATORS_ROOT),
+            hex::encode(VALIDATOR),
+            hex::encode([1; 32]),
+            hex::encode([2; 32]),
+        );
+        let interchange: Interchange = serde_json::from_str(&interchange).unwrap();
+        let database = SlashingDatabase::open_in_memory().unwrap();
+        database.import_interchange(&interchange).unwrap();
+        // Importing twice changes nothing.
+        database.import_interchange(&interchange).unwrap();
+
+        // The blocks and attestations are sorted by slot and target epoch.
+        let mut expected = interchange.clone();
+        expected.data[0].signed_blocks.reverse();
+        assert_eq!(database.export_interchange().unwrap(), expected);
+
+        // The imported records protect the validator, even without their signing roots.
+        database
+            .check_and_insert_block(&VALIDATOR, 81952, &[1; 32])
+            .unwrap();
+        assert_eq!(
+            violation(database.check_and_insert_block(&VALIDATOR, 81951, &[1; 32])),
+            Violation::DoubleBlockProposal { slot: 81951 }
+        );
+        assert_eq!(
+            violation(database.check_and_insert_attestation(&VALIDATOR, 2290, 3008, &[2; 32])),
+            Violation::DoubleVote { target_epoch: 3008 }
+        );
+
+        // A conflicting signing root is forgotten.
+        let mut conflicting = interchange.clone();
+        conflicting.data[0].signed_blocks[0].signing_root = Some(Root([3; 32]));
+        database.import_interchange(&conflicting).unwrap();
+        assert_eq!(
+            violation(database.check_and_insert_block(&VALIDATOR, 81952, &[1; 32])),
+            Violation::DoubleBlockProposal { slot: 81952 }
+        );
+
+        // A conflicting attestation keeps the lower source epoch, whichever is imported first.
+        let mut conflicting = interchange.clone();
+        conflicting.data[0].signed_attestations[0].source_epoch = 2280;
+        database.import_interchange(&conflicting).unwrap();
+        database.import_interchange(&interchange).unwrap();
+        assert_eq!(
+            database.export_interchange().unwrap().data[0].signed_attestations[0],
+            SignedAttestation {
+                source_epoch: 2280,
+                target_epoch: 3007,
+                signing_root: None
+            }
+        );
+
+        // The interchange of another chain is refused.
+        let mut other_chain = interchange;
+        other_chain.metadata.genesis_validators_root = Root([0; 32]);
+        assert!(matches!(
+            database.import_interchange(&other_chain),
+            Err(Error::WrongChain { .. })
+        ));
+    }
+
+    #[test]
+    fn records_and_chain_are_persisted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(SLASHING_PROTECTION_FILENAME);
+        let database = SlashingDatabase::open(&path).unwrap();
+        assert!(matches!(
+            database.export_interchange(),
+            Err(Error::UnknownChain)
+        ));
+        database
+            .check_genesis_validators_root(&GENESIS_VALIDATORS_ROOT)
+            .unwrap();
+        database
+            .check_and_insert_attestation(&VALIDATOR, 10, 20, &[1; 32])
+            .unwrap();
+        drop(database);
+
+        let database = SlashingDatabase::open(&path).unwrap();
+        assert!(matches!(
+            database.check_genesis_validators_root(&[0; 32]),
+            Err(Error::WrongChain { .. })
+        ));
+        assert_eq!(
+            violation(database.check_and_insert_attestation(&VALIDATOR, 10, 20, &[2; 32])),
+            Violation::DoubleVote { target_epoch: 20 }
+        );
+    }
+}
@@ -2,7 +2,7 @@ use clap::Parser;
 use tracing::{error, info};
 
 mod environment;
-use client::{config, Anchor, Client};
+use client::{config, Anchor, AnchorSubcommand, Client};
 use environment::Environment;
 use task_executor::ShutdownReason;
 
@@ -15,9 +15,7 @@ fn main() {
     // Obtain the CLI and build the config
     let anchor_config: Anchor = Anchor::parse();
 
-    // Currently the only binary is the client. We build the client config, but later this will
-    // generalise to other sub commands
-    // Build the client config
+    // Build the client config, which the subcommands also read the data directory from
     let config = match config::from_cli(&anchor_config) {
         Ok(config) => config,
         Err(e) => {
@@ -28,6 +26,23 @@ fn main() {
 
     // Construct the task executor and exit signals
     let mut environment = Environment::default();
+
+    // Run the subcommand instead of the client, if one was given.
+    if let Some(subcommand) = &anchor_config.subcommand {
+        let result = match subcommand {
+            AnchorSubcommand::SlashingProtection(command) => {
+                client::run_slashing_protection(command, &config)
+            }
+            AnchorSubcommand::NetworkKey(command) => client::run_network_key(command, &config),
+            AnchorSubcommand::OperatorKey(command) => client::run_operator_key(command, &config),
+        };
+        if let Err(e) = result {
+            error!(e, "Command failed");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Build the core task executor
     let core_executor = environment.executor();
 